@@ -5,6 +5,10 @@
 // you may not use this file except in compliance with the License.
 // A copy of the License has been included in the root of the repository.
 
+// The library builds on stable Rust, but these benchmarks use the unstable
+// `test` crate, so they need a nightly compiler: `cargo +nightly bench`. The
+// `bench_decode` example measures decoding on stable, see tools/benchmark.sh.
+
 #![feature(test)]
 
 extern crate claxon;
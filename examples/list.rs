@@ -0,0 +1,34 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+// This file contains an example of listing all metadata blocks in a flac file,
+// in a format similar to what `metaflac --list <file>` prints.
+
+extern crate claxon;
+
+use std::env;
+use std::fs::File;
+
+use claxon::input::{BufferedReader, ReadBytes};
+use claxon::metadata::MetadataBlockReader;
+
+fn main() {
+    for fname in env::args().skip(1) {
+        let file = File::open(&fname).expect("failed to open file");
+        let mut input = BufferedReader::new(file);
+
+        // Metadata blocks follow the "fLaC" stream marker.
+        let marker = input.read_be_u32().expect("failed to read stream marker");
+        assert_eq!(marker, 0x66_4c_61_43, "not a FLAC stream");
+
+        for (i, block) in MetadataBlockReader::new(input).enumerate() {
+            let block = block.expect("failed to read metadata block");
+            println!("METADATA block #{}", i);
+            println!("{}", block);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+// This program plays a flac file through the default audio output device,
+// with cpal. Run it with `cargo run --example play --features cpal <file>`.
+//
+// Decoding happens on a separate thread, which sends blocks of interleaved
+// samples to the audio callback through a bounded channel. When the channel
+// is full, the decoder waits, so it never runs far ahead of playback. The
+// callback runs under real-time constraints, so it never blocks: if no
+// decoded audio is available in time, it plays silence. Used buffers are
+// sent back to the decoder, so the callback does not free memory either.
+// The audio is not resampled, the device must support the sample rate and
+// number of channels of the file.
+
+extern crate claxon;
+extern crate cpal;
+
+use claxon::FlacReader;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+use std::env;
+use std::fs::File;
+use std::mem;
+use std::process;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// The number of decoded blocks that can be queued ahead of playback.
+const QUEUE_LEN: usize = 8;
+
+/// Decodes the stream, and sends the blocks, as interleaved samples, to `queue`.
+///
+/// Buffers that the callback is done with come back through `recycle`.
+fn decode(mut reader: FlacReader<File>, queue: SyncSender<Vec<f32>>, recycle: Receiver<Vec<f32>>) {
+    let bits_per_sample = reader.streaminfo().bits_per_sample;
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+    loop {
+        let block = match blocks.read_next_or_eof(buffer) {
+            Ok(Some(block)) => block,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("failed to decode: {}", err);
+                break
+            }
+        };
+        let mut samples = recycle.try_recv().unwrap_or_else(|_| Vec::new());
+        block.interleaved_f32_into(bits_per_sample, &mut samples);
+        buffer = block.into_buffer();
+
+        // This waits while the queue is full. It fails when playback stopped.
+        if queue.send(samples).is_err() {
+            break
+        }
+    }
+}
+
+/// The state of the audio callback.
+struct Playback {
+    queue: Receiver<Vec<f32>>,
+    recycle: SyncSender<Vec<f32>>,
+    done: SyncSender<()>,
+    /// The block that is being played, and the position in it.
+    current: Vec<f32>,
+    pos: usize,
+}
+
+impl Playback {
+    /// Fills `out` with the next samples, or with silence if none are available.
+    fn fill<T: SizedSample + FromSample<f32>>(&mut self, out: &mut [T]) {
+        for sample in out.iter_mut() {
+            if self.pos == self.current.len() && !self.next_block() {
+                *sample = T::EQUILIBRIUM;
+                continue
+            }
+            *sample = T::from_sample(self.current[self.pos]);
+            self.pos += 1;
+        }
+    }
+
+    /// Takes the next block from the queue, if there is one, without waiting.
+    fn next_block(&mut self) -> bool {
+        match self.queue.try_recv() {
+            Ok(block) => {
+                let used = mem::replace(&mut self.current, block);
+                // If the decoder is gone, the buffer is dropped here, but
+                // then the stream is done anyway.
+                let _ = self.recycle.try_send(used);
+                self.pos = 0;
+                true
+            }
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                let _ = self.done.try_send(());
+                false
+            }
+        }
+    }
+}
+
+/// Returns a supported output configuration for the stream, preferring floating point samples.
+fn find_config(device: &cpal::Device, channels: u16, sample_rate: u32)
+               -> Option<(StreamConfig, SampleFormat)> {
+    let ranges = match device.supported_output_configs() {
+        Ok(ranges) => ranges,
+        Err(..) => return None,
+    };
+    let mut ranges: Vec<_> = ranges.filter(|r| r.channels() == channels &&
+                                               r.contains_rate(sample_rate))
+                                   .collect();
+    ranges.sort_by_key(|r| r.sample_format() != SampleFormat::F32);
+    ranges.into_iter().next().map(|range| {
+        let config = range.with_sample_rate(sample_rate);
+        (config.config(), config.sample_format())
+    })
+}
+
+fn build_stream<T>(device: &cpal::Device,
+                   config: StreamConfig,
+                   mut playback: Playback)
+                   -> cpal::Stream
+    where T: SizedSample + FromSample<f32>
+{
+    device.build_output_stream(config,
+                               move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
+                                   playback.fill(out)
+                               },
+                               |err| eprintln!("playback error: {}", err),
+                               None)
+          .expect("failed to open output stream")
+}
+
+fn main() {
+    let fname = match env::args().nth(1) {
+        Some(fname) => fname,
+        None => {
+            eprintln!("usage: play <file>");
+            process::exit(2);
+        }
+    };
+    let reader = FlacReader::open(&fname).expect("failed to open FLAC stream");
+    let channels = reader.streaminfo().channels as u16;
+    let sample_rate = reader.streaminfo().sample_rate;
+
+    let host = cpal::default_host();
+    let device = host.default_output_device().expect("no output device available");
+    let (config, format) = match find_config(&device, channels, sample_rate) {
+        Some(x) => x,
+        None => {
+            eprintln!("the output device does not support {} channels at {} Hz",
+                      channels, sample_rate);
+            process::exit(1);
+        }
+    };
+
+    let (queue_tx, queue_rx) = mpsc::sync_channel(QUEUE_LEN);
+    let (recycle_tx, recycle_rx) = mpsc::sync_channel(QUEUE_LEN + 1);
+    let (done_tx, done_rx) = mpsc::sync_channel(1);
+    let decoder = thread::spawn(move || decode(reader, queue_tx, recycle_rx));
+
+    let playback = Playback {
+        queue: queue_rx,
+        recycle: recycle_tx,
+        done: done_tx,
+        current: Vec::new(),
+        pos: 0,
+    };
+    let stream = match format {
+        SampleFormat::I8 => build_stream::<i8>(&device, config, playback),
+        SampleFormat::I16 => build_stream::<i16>(&device, config, playback),
+        SampleFormat::I24 => build_stream::<cpal::I24>(&device, config, playback),
+        SampleFormat::I32 => build_stream::<i32>(&device, config, playback),
+        SampleFormat::U8 => build_stream::<u8>(&device, config, playback),
+        SampleFormat::U16 => build_stream::<u16>(&device, config, playback),
+        SampleFormat::U32 => build_stream::<u32>(&device, config, playback),
+        SampleFormat::F32 => build_stream::<f32>(&device, config, playback),
+        SampleFormat::F64 => build_stream::<f64>(&device, config, playback),
+        other => {
+            eprintln!("unsupported output sample format {}", other);
+            process::exit(1);
+        }
+    };
+    stream.play().expect("failed to start playback");
+
+    // The callback signals when it played the last block. Give the device
+    // some time to play what it has buffered, before closing the stream.
+    done_rx.recv().expect("playback stopped unexpectedly");
+    thread::sleep(Duration::from_millis(500));
+    drop(stream);
+    decoder.join().unwrap();
+}
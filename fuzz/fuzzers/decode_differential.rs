@@ -0,0 +1,18 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+#![no_main]
+
+extern crate libfuzzer_sys;
+extern crate claxon;
+
+#[export_name="rust_fuzzer_test_input"]
+pub extern fn go(data: &[u8]) {
+    if let Err(difference) = claxon::libflac::compare_decoders(data) {
+        panic!("Claxon and libFLAC disagree: {:?}", difference);
+    }
+}
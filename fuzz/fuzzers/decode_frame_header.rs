@@ -0,0 +1,29 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+#![no_main]
+
+extern crate libfuzzer_sys;
+extern crate claxon;
+
+use std::io::Cursor;
+
+use claxon::frame::read_frame_info;
+
+#[export_name="rust_fuzzer_test_input"]
+pub extern fn go(data: &[u8]) {
+    // The first two bytes are the fixed block size of the stream.
+    if data.len() < 2 {
+        return
+    }
+    let fixed_block_size = (data[0] as u16) << 8 | data[1] as u16;
+    let mut input = Cursor::new(&data[2..]);
+    match read_frame_info(&mut input, fixed_block_size) {
+        Ok(..) => { }
+        Err(..) => { }
+    }
+}
@@ -0,0 +1,259 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Decodes streams built from the fuzz input, rather than the input itself.
+//!
+//! Random bytes rarely get past the stream header or a frame header CRC, so
+//! this fuzzer uses the input to choose the fields of a stream that is valid
+//! up to the subframe payloads: a correct streaminfo block, and frames with a
+//! valid header, CRC-8 and CRC-16. The payloads themselves are semi-valid:
+//! every subframe has a valid type and parameters, followed by arbitrary bits.
+
+#![no_main]
+
+extern crate arbitrary;
+extern crate libfuzzer_sys;
+extern crate claxon;
+
+use std::io::Cursor;
+
+use arbitrary::{Arbitrary, BufferError, FiniteBuffer};
+
+/// Accumulates bits, most significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// The number of bits in the last byte that are used, 0 if it is full.
+    used: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), used: 0 }
+    }
+
+    fn write(&mut self, bits: u32, value: u64) {
+        for i in (0..bits).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write(8, b as u64);
+        }
+    }
+
+    /// Pads with zero bits to the next byte boundary.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in bytes {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in bytes {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn take<T: Arbitrary>(u: &mut FiniteBuffer) -> Result<T, BufferError> {
+    T::arbitrary(u)
+}
+
+struct Format {
+    channels: u32,
+    bits_per_sample: u32,
+    /// The code for the bits per sample in a frame header.
+    bits_per_sample_code: u32,
+    block_size: u16,
+}
+
+fn write_stream_header(u: &mut FiniteBuffer, out: &mut BitWriter) -> Result<Format, BufferError> {
+    let sample_rates = [8000, 22050, 44100, 48000, 96000, 192000];
+    let bit_depths = [(8, 1), (12, 2), (16, 4), (20, 5), (24, 6)];
+    let (bits_per_sample, bits_per_sample_code) =
+        bit_depths[try!(take::<u8>(u)) as usize % bit_depths.len()];
+    let format = Format {
+        channels: try!(take::<u8>(u)) as u32 % 8 + 1,
+        bits_per_sample: bits_per_sample,
+        bits_per_sample_code: bits_per_sample_code,
+        block_size: ::std::cmp::max(16, try!(take::<u16>(u))),
+    };
+    let sample_rate = sample_rates[try!(take::<u8>(u)) as usize % sample_rates.len()];
+
+    out.write_bytes(b"fLaC");
+    // A last-metadata-block flag, type 0 (streaminfo), and length 34.
+    out.write(1, 1);
+    out.write(7, 0);
+    out.write(24, 34);
+    out.write(16, format.block_size as u64);
+    out.write(16, format.block_size as u64);
+    out.write(24, 0);
+    out.write(24, 0);
+    out.write(20, sample_rate);
+    out.write(3, format.channels as u64 - 1);
+    out.write(5, format.bits_per_sample as u64 - 1);
+    // An unknown number of samples, and an unknown MD5 signature.
+    out.write(36, 0);
+    out.write_bytes(&[0; 16]);
+    Ok(format)
+}
+
+fn write_subframe(u: &mut FiniteBuffer,
+                  out: &mut BitWriter,
+                  bps: u32,
+                  block_size: u16)
+                  -> Result<(), BufferError> {
+    let kind = try!(take::<u8>(u)) % 4;
+    let fixed_order = try!(take::<u8>(u)) as u32 % 5;
+    let lpc_order = try!(take::<u8>(u)) as u32 % 32 + 1;
+    let order = match kind { 2 => fixed_order, 3 => lpc_order, _ => 0 };
+    let type_code = match kind { 0 => 0, 1 => 1, 2 => 8 + fixed_order, _ => 31 + lpc_order };
+
+    // A zero padding bit, the type, and no wasted bits.
+    out.write(1, 0);
+    out.write(6, type_code as u64);
+    out.write(1, 0);
+
+    let samples = match kind { 0 => 1, 1 => block_size as u32, _ => order };
+    for _ in 0..samples {
+        out.write(bps, try!(take::<u32>(u)) as u64);
+    }
+
+    if kind == 3 {
+        // The precision of the coefficients, the shift, and the coefficients.
+        let precision = try!(take::<u8>(u)) as u32 % 15 + 1;
+        out.write(4, precision as u64 - 1);
+        out.write(5, try!(take::<u8>(u)) as u64 % 16);
+        for _ in 0..order {
+            out.write(precision, try!(take::<u32>(u)) as u64);
+        }
+    }
+
+    if kind >= 2 {
+        // Rice coding with 4-bit parameters, and a partition order for which
+        // the block size is divisible, followed by arbitrary residual bits.
+        let max_order = ::std::cmp::min(8, (block_size as u32).trailing_zeros());
+        let partition_order = try!(take::<u8>(u)) as u32 % (max_order + 1);
+        out.write(2, 0);
+        out.write(4, partition_order as u64);
+        for _ in 0..1 << partition_order {
+            out.write(4, try!(take::<u8>(u)) as u64 % 15);
+        }
+        let residual: Vec<u8> = try!(take(u));
+        out.write_bytes(&residual);
+    }
+
+    Ok(())
+}
+
+fn write_frame(u: &mut FiniteBuffer,
+               out: &mut BitWriter,
+               format: &Format,
+               frame_number: u8)
+               -> Result<(), BufferError> {
+    let block_size = try!(take::<u16>(u)) % format.block_size + 1;
+    let channel_assignment = if format.channels == 2 {
+        [1, 8, 9, 10][try!(take::<u8>(u)) as usize % 4]
+    } else {
+        format.channels - 1
+    };
+
+    let mut frame = BitWriter::new();
+    // The sync code for a fixed block size, a 16-bit block size after the
+    // header, and the sample rate of the streaminfo.
+    frame.write(16, 0xfff8);
+    frame.write(4, 0b0111);
+    frame.write(4, 0);
+    frame.write(4, channel_assignment as u64);
+    frame.write(3, format.bits_per_sample_code as u64);
+    frame.write(1, 0);
+    frame.write(8, frame_number as u64);
+    frame.write(16, block_size as u64 - 1);
+    let crc = crc8(&frame.bytes);
+    frame.write(8, crc as u64);
+
+    for ch in 0..format.channels {
+        // The side channel has one more bit.
+        let is_side = match (channel_assignment, ch) {
+            (8, 1) | (9, 0) | (10, 1) => true,
+            _ => false,
+        };
+        let bps = format.bits_per_sample + if is_side { 1 } else { 0 };
+        let result = write_subframe(u, &mut frame, bps, block_size);
+        if result.is_err() {
+            // Out of input; emit the truncated frame as it is.
+            out.write_bytes(&frame.bytes);
+            return result
+        }
+    }
+
+    frame.align();
+    let crc = crc16(&frame.bytes);
+    frame.write(16, crc as u64);
+    out.write_bytes(&frame.bytes);
+    Ok(())
+}
+
+fn generate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::new();
+    let mut u = match FiniteBuffer::new(data, data.len()) {
+        Ok(u) => u,
+        Err(..) => return out.bytes,
+    };
+    let format = match write_stream_header(&mut u, &mut out) {
+        Ok(format) => format,
+        Err(..) => return out.bytes,
+    };
+    // The frame number is a single byte in the UTF-8-like encoding.
+    for frame_number in 0..128 {
+        match take::<bool>(&mut u) {
+            Ok(true) => {}
+            _ => break,
+        }
+        if write_frame(&mut u, &mut out, &format, frame_number).is_err() {
+            break
+        }
+    }
+    out.bytes
+}
+
+#[export_name="rust_fuzzer_test_input"]
+pub extern fn go(data: &[u8]) {
+    let stream = generate(data);
+    let mut reader = match claxon::FlacReader::new(Cursor::new(stream)) {
+        Ok(r) => r,
+        Err(..) => return,
+    };
+
+    for sample in reader.samples() {
+        match sample {
+            Ok(..) => { }
+            Err(..) => return,
+        }
+    }
+}
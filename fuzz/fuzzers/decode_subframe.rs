@@ -0,0 +1,33 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+#![no_main]
+
+extern crate libfuzzer_sys;
+extern crate claxon;
+
+use std::io::Cursor;
+
+use claxon::input::Bitstream;
+use claxon::subframe;
+
+#[export_name="rust_fuzzer_test_input"]
+pub extern fn go(data: &[u8]) {
+    // The first byte selects the bits per sample, the next two the block
+    // size, and the remainder is the subframe.
+    if data.len() < 3 {
+        return
+    }
+    let bps = (data[0] as u32 % 32) + 1;
+    let block_size = ((data[1] as usize) << 8 | data[2] as usize) + 1;
+    let mut buffer = vec![0; block_size];
+    let mut input = Bitstream::new(Cursor::new(&data[3..]));
+    match subframe::decode(&mut input, bps, &mut buffer) {
+        Ok(..) => { }
+        Err(..) => { }
+    }
+}
@@ -0,0 +1,168 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `background` module decodes ahead on a worker thread.
+//!
+//! A playback thread should not wait for the disk, or for a frame that is
+//! slow to decode. `BackgroundDecoder` moves a `FlacReader` to a worker
+//! thread, which decodes blocks into a bounded queue, while the caller takes
+//! blocks from the front of the queue, without blocking if it so wishes.
+
+use std::io;
+use std::panic;
+use std::sync::mpsc;
+use std::thread;
+
+use error::Result;
+use frame::Block;
+use metadata::StreamInfo;
+use {FlacReader, FlacReaderState};
+
+/// The outcome of `BackgroundDecoder::try_next_block()`.
+#[derive(Debug)]
+pub enum TryNext {
+    /// The next block, in stream order.
+    Block(Block),
+    /// The worker has not decoded the next block yet.
+    NotReady,
+    /// The end of the stream was reached.
+    End,
+}
+
+/// A decoder that decodes blocks on a worker thread, ahead of when they are needed.
+///
+/// Blocks are decoded from the position of the reader onwards. Like
+/// `FlacReader::blocks()`, after a seek the first block starts at the frame
+/// that contains the target sample. When the decoder is dropped, the worker
+/// stops after the block it is decoding. If the worker panics, the panic is
+/// propagated to the caller when it asks for the next block.
+pub struct BackgroundDecoder {
+    streaminfo: StreamInfo,
+    /// Decoded blocks, followed by `Ok(None)` at the end of the stream.
+    blocks: mpsc::Receiver<Result<Option<Block>>>,
+    buffers: mpsc::Sender<Vec<i32>>,
+    worker: Option<thread::JoinHandle<()>>,
+    is_done: bool,
+}
+
+impl BackgroundDecoder {
+    /// Starts a worker thread that decodes at most `queue_len` blocks ahead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn new<R>(mut reader: FlacReader<R>, queue_len: usize) -> BackgroundDecoder
+        where R: io::Read + Send + 'static
+    {
+        // Fail here rather than on the worker thread.
+        if let FlacReaderState::MetadataOnly(..) = reader.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use BackgroundDecoder")
+        }
+
+        let streaminfo = reader.streaminfo();
+        let (block_sender, block_receiver) = mpsc::sync_channel(queue_len);
+        let (buffer_sender, buffer_receiver) = mpsc::channel::<Vec<i32>>();
+
+        let worker = thread::spawn(move || {
+            loop {
+                let buffer = buffer_receiver.try_recv().unwrap_or_else(|_| Vec::new());
+                let result = reader.read_next_block(buffer);
+                let is_last = match result {
+                    Ok(Some(..)) => false,
+                    Ok(None) | Err(..) => true,
+                };
+                // When the receiving end is gone, nobody needs more blocks.
+                if block_sender.send(result).is_err() || is_last {
+                    break
+                }
+            }
+        });
+
+        BackgroundDecoder {
+            streaminfo: streaminfo,
+            blocks: block_receiver,
+            buffers: buffer_sender,
+            worker: Some(worker),
+            is_done: false,
+        }
+    }
+
+    /// Returns the streaminfo of the stream.
+    pub fn streaminfo(&self) -> StreamInfo {
+        self.streaminfo
+    }
+
+    /// Returns the next block, waiting for the worker if it is not decoded yet.
+    ///
+    /// Returns `None` at the end of the stream. After an error, the worker
+    /// stops, and subsequent calls return `None`.
+    ///
+    /// # Panics
+    ///
+    /// Resumes the panic of the worker thread, if it panicked.
+    pub fn next_block(&mut self) -> Result<Option<Block>> {
+        if self.is_done {
+            return Ok(None)
+        }
+        match self.blocks.recv() {
+            Ok(result) => self.done_on_last(result),
+            Err(mpsc::RecvError) => {
+                self.propagate_panic();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the next block if it has been decoded, without waiting.
+    ///
+    /// # Panics
+    ///
+    /// Resumes the panic of the worker thread, if it panicked.
+    pub fn try_next_block(&mut self) -> Result<TryNext> {
+        if self.is_done {
+            return Ok(TryNext::End)
+        }
+        match self.blocks.try_recv() {
+            Ok(result) => match try!(self.done_on_last(result)) {
+                Some(block) => Ok(TryNext::Block(block)),
+                None => Ok(TryNext::End),
+            },
+            Err(mpsc::TryRecvError::Empty) => Ok(TryNext::NotReady),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.propagate_panic();
+                Ok(TryNext::End)
+            }
+        }
+    }
+
+    /// Hands back the buffer of a block, so the worker can decode into it again.
+    pub fn recycle(&self, buffer: Vec<i32>) {
+        // If the worker has stopped, the buffer is not needed any more.
+        let _ = self.buffers.send(buffer);
+    }
+
+    fn done_on_last(&mut self, result: Result<Option<Block>>) -> Result<Option<Block>> {
+        match result {
+            Ok(Some(..)) => {}
+            Ok(None) | Err(..) => self.is_done = true,
+        }
+        result
+    }
+
+    /// Handles the worker hanging up without sending the end of the stream.
+    ///
+    /// The worker only does that when it panics, so this resumes the panic.
+    fn propagate_panic(&mut self) {
+        self.is_done = true;
+        if let Some(worker) = self.worker.take() {
+            if let Err(payload) = worker.join() {
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `batch` module decodes many files in parallel.
+//!
+//! Scanning or verifying a music library is embarrassingly parallel: every
+//! file can be decoded independently. `decode_files()` distributes files over
+//! a number of worker threads, and every worker reuses its block buffer for
+//! the files it decodes.
+//!
+//! With the `rayon` feature, the workers are a `rayon` thread pool, which
+//! balances the work between them. Otherwise, Claxon starts its own threads,
+//! which take the next file from a shared counter.
+
+use std::cmp;
+use std::mem;
+#[cfg(not(feature = "rayon"))]
+use std::panic;
+use std::path::{Path, PathBuf};
+#[cfg(not(feature = "rayon"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "rayon"))]
+use std::sync::mpsc;
+#[cfg(not(feature = "rayon"))]
+use std::sync::Arc;
+#[cfg(not(feature = "rayon"))]
+use std::thread;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use error::Result;
+use frame::Block;
+use metadata::StreamInfo;
+use FlacReader;
+
+/// Decodes the files on `threads` worker threads, calling `f` for every block.
+///
+/// `f` receives the index of the file in `paths`, the streaminfo of the file,
+/// and the block. Blocks of one file are passed in order, but blocks of
+/// different files are interleaved arbitrarily. Decoding a file stops at its
+/// first error. Returns the result for every file, in the order of `paths`.
+pub fn decode_files<P, F>(paths: &[P], threads: usize, f: F) -> Vec<Result<()>>
+    where P: AsRef<Path>,
+          F: Fn(usize, &StreamInfo, &Block) + Send + Sync + 'static
+{
+    let paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+    let num_threads = cmp::max(1, cmp::min(threads, paths.len()));
+    decode_paths(paths, num_threads, f)
+}
+
+/// Implements `decode_files()` with a `rayon` thread pool.
+#[cfg(feature = "rayon")]
+fn decode_paths<F>(paths: Vec<PathBuf>, num_threads: usize, f: F) -> Vec<Result<()>>
+    where F: Fn(usize, &StreamInfo, &Block) + Send + Sync
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .expect("failed to start the worker threads");
+    // A panic in `f` is propagated by `install()`.
+    pool.install(|| {
+        paths.par_iter().enumerate().map_init(Vec::new, |buffer, (index, path)| {
+            decode_file(path, buffer, |streaminfo, block| f(index, streaminfo, block))
+        }).collect()
+    })
+}
+
+/// Implements `decode_files()` with threads that take the next file from a shared counter.
+#[cfg(not(feature = "rayon"))]
+fn decode_paths<F>(paths: Vec<PathBuf>, num_threads: usize, f: F) -> Vec<Result<()>>
+    where F: Fn(usize, &StreamInfo, &Block) + Send + Sync + 'static
+{
+    let paths = Arc::new(paths);
+    let next_index = Arc::new(AtomicUsize::new(0));
+    let f = Arc::new(f);
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..num_threads).map(|_| {
+        let paths = paths.clone();
+        let next_index = next_index.clone();
+        let f = f.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                if index >= paths.len() {
+                    break
+                }
+                let result = decode_file(&paths[index], &mut buffer, |streaminfo, block| {
+                    f(index, streaminfo, block)
+                });
+                // The receiver lives until all workers are done.
+                sender.send((index, result)).unwrap();
+            }
+        })
+    }).collect();
+    drop(sender);
+
+    let mut results: Vec<Option<Result<()>>> = paths.iter().map(|_| None).collect();
+    for (index, result) in receiver.iter() {
+        results[index] = Some(result);
+    }
+
+    // A worker that panicked in `f` leaves a result missing; propagate the panic.
+    for worker in workers {
+        if let Err(panic) = worker.join() {
+            panic::resume_unwind(panic)
+        }
+    }
+
+    results.into_iter().map(|r| r.expect("every file was decoded")).collect()
+}
+
+fn decode_file<F>(path: &Path, buffer: &mut Vec<i32>, mut f: F) -> Result<()>
+    where F: FnMut(&StreamInfo, &Block)
+{
+    let mut reader = try!(FlacReader::open(path));
+    let streaminfo = reader.streaminfo();
+    loop {
+        let taken = mem::replace(buffer, Vec::new());
+        match try!(reader.read_next_block(taken)) {
+            Some(block) => {
+                f(&streaminfo, &block);
+                *buffer = block.into_buffer();
+            }
+            None => return Ok(()),
+        }
+    }
+}
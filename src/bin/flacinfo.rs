@@ -0,0 +1,239 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+// This program prints the metadata blocks of flac files, in a format similar
+// to `metaflac --list`, followed by the duration and statistics about the
+// frames. With `--machine`, it prints `key=value` lines instead, which are
+// easier to process in scripts.
+
+extern crate claxon;
+
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
+use std::time::Duration;
+
+use claxon::FlacReader;
+use claxon::frame::ChannelAssignment;
+use claxon::metadata::MetadataBlock;
+
+/// Statistics about the frames in a stream.
+struct FrameStats {
+    frames: u64,
+    min_block_size: u32,
+    max_block_size: u32,
+    min_frame_len: u64,
+    max_frame_len: u64,
+    total_len: u64,
+    total_samples: u64,
+    /// Frame counts for independent, left/side, right/side, and mid/side coding.
+    assignments: [u64; 4],
+}
+
+impl FrameStats {
+    fn new() -> FrameStats {
+        FrameStats {
+            frames: 0,
+            min_block_size: 0,
+            max_block_size: 0,
+            min_frame_len: 0,
+            max_frame_len: 0,
+            total_len: 0,
+            total_samples: 0,
+            assignments: [0; 4],
+        }
+    }
+
+    fn add(&mut self, block_size: u32, len: u64, assignment: ChannelAssignment) {
+        if self.frames == 0 {
+            self.min_block_size = block_size;
+            self.min_frame_len = len;
+        }
+        self.frames += 1;
+        self.min_block_size = cmp::min(self.min_block_size, block_size);
+        self.max_block_size = cmp::max(self.max_block_size, block_size);
+        self.min_frame_len = cmp::min(self.min_frame_len, len);
+        self.max_frame_len = cmp::max(self.max_frame_len, len);
+        self.total_len += len;
+        self.total_samples += block_size as u64;
+        let index = match assignment {
+            ChannelAssignment::Independent(..) => 0,
+            ChannelAssignment::LeftSideStereo => 1,
+            ChannelAssignment::RightSideStereo => 2,
+            ChannelAssignment::MidSideStereo => 3,
+        };
+        self.assignments[index] += 1;
+    }
+
+    /// Returns the average bitrate in bits per second, if it can be computed.
+    fn bitrate(&self, sample_rate: u32) -> Option<u64> {
+        if self.total_samples == 0 {
+            return None
+        }
+        Some(self.total_len * 8 * sample_rate as u64 / self.total_samples)
+    }
+}
+
+/// Decodes all frames of the stream, and collects statistics about them.
+fn collect_frame_stats(reader: &mut FlacReader<File>) -> claxon::Result<FrameStats> {
+    let mut stats = FrameStats::new();
+    let mut frames = reader.blocks();
+    let mut buffer = Vec::new();
+    let mut start = frames.get_ref().position();
+    while let Some((info, block)) = try!(frames.read_next_with_info_or_eof(buffer)) {
+        let end = frames.get_ref().position();
+        stats.add(block.duration(), end - start, info.channel_assignment);
+        start = end;
+        buffer = block.into_buffer();
+    }
+    Ok(stats)
+}
+
+/// Returns the duration in whole milliseconds.
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+/// Formats a duration as minutes, seconds, and milliseconds, as in `3:07.250`.
+fn format_duration(duration: Duration) -> String {
+    let ms = millis(duration);
+    format!("{}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+fn block_type_name(block: &MetadataBlock) -> &'static str {
+    match *block {
+        MetadataBlock::StreamInfo(..) => "STREAMINFO",
+        MetadataBlock::Padding { .. } => "PADDING",
+        MetadataBlock::Application { .. } => "APPLICATION",
+        MetadataBlock::SeekTable(..) => "SEEKTABLE",
+        MetadataBlock::VorbisComment(..) => "VORBIS_COMMENT",
+        MetadataBlock::CueSheet(..) => "CUESHEET",
+        MetadataBlock::Picture(..) => "PICTURE",
+        MetadataBlock::Reserved => "UNKNOWN",
+    }
+}
+
+fn print_human<W: Write>(out: &mut W,
+                         fname: &str,
+                         reader: &FlacReader<File>,
+                         stats: &FrameStats)
+                         -> io::Result<()> {
+    let streaminfo = reader.streaminfo();
+    try!(writeln!(out, "{}", fname));
+    for (i, block) in reader.metadata().enumerate() {
+        try!(writeln!(out, "METADATA block #{}", i));
+        try!(writeln!(out, "{}", block));
+    }
+    match streaminfo.duration() {
+        Some(duration) => try!(writeln!(out, "duration: {}", format_duration(duration))),
+        None => try!(writeln!(out, "duration: unknown")),
+    }
+    try!(writeln!(out, "frames: {}", stats.frames));
+    if stats.frames > 0 {
+        try!(writeln!(out, "  block size: min {}, max {} samples",
+                      stats.min_block_size, stats.max_block_size));
+        try!(writeln!(out, "  frame size: min {}, max {} bytes",
+                      stats.min_frame_len, stats.max_frame_len));
+    }
+    if let Some(bitrate) = stats.bitrate(streaminfo.sample_rate) {
+        try!(writeln!(out, "  average bitrate: {} kbps", bitrate / 1000));
+    }
+    if streaminfo.channels == 2 {
+        try!(writeln!(out, "  channel coding: {} independent, {} left/side, \
+                            {} right/side, {} mid/side",
+                      stats.assignments[0], stats.assignments[1],
+                      stats.assignments[2], stats.assignments[3]));
+    }
+    Ok(())
+}
+
+fn print_machine<W: Write>(out: &mut W,
+                           fname: &str,
+                           reader: &FlacReader<File>,
+                           stats: &FrameStats)
+                           -> io::Result<()> {
+    let streaminfo = reader.streaminfo();
+    try!(writeln!(out, "file={}", fname));
+    try!(writeln!(out, "sample_rate={}", streaminfo.sample_rate));
+    try!(writeln!(out, "channels={}", streaminfo.channels));
+    try!(writeln!(out, "bits_per_sample={}", streaminfo.bits_per_sample));
+    if let Some(samples) = streaminfo.samples {
+        try!(writeln!(out, "total_samples={}", samples));
+    }
+    if let Some(duration) = streaminfo.duration() {
+        try!(writeln!(out, "duration_ms={}", millis(duration)));
+    }
+    let md5: Vec<String> = streaminfo.md5sum.iter().map(|b| format!("{:02x}", b)).collect();
+    try!(writeln!(out, "md5={}", md5.concat()));
+    for (i, block) in reader.metadata().enumerate() {
+        try!(writeln!(out, "block.{}={}", i, block_type_name(block)));
+    }
+    for (name, value) in reader.tags() {
+        try!(writeln!(out, "tag.{}={}", name, value));
+    }
+    try!(writeln!(out, "frames={}", stats.frames));
+    try!(writeln!(out, "min_block_size={}", stats.min_block_size));
+    try!(writeln!(out, "max_block_size={}", stats.max_block_size));
+    try!(writeln!(out, "min_frame_size={}", stats.min_frame_len));
+    try!(writeln!(out, "max_frame_size={}", stats.max_frame_len));
+    if let Some(bitrate) = stats.bitrate(streaminfo.sample_rate) {
+        try!(writeln!(out, "bitrate={}", bitrate));
+    }
+    try!(writeln!(out, "frames_independent={}", stats.assignments[0]));
+    try!(writeln!(out, "frames_left_side={}", stats.assignments[1]));
+    try!(writeln!(out, "frames_right_side={}", stats.assignments[2]));
+    try!(writeln!(out, "frames_mid_side={}", stats.assignments[3]));
+    Ok(())
+}
+
+fn main() {
+    let mut machine = false;
+    let mut fnames = Vec::new();
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--machine" => machine = true,
+            _ => fnames.push(arg),
+        }
+    }
+    let mut stderr = io::stderr();
+    if fnames.is_empty() {
+        writeln!(stderr, "usage: flacinfo [--machine] <file>...").unwrap();
+        process::exit(2);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+    for (i, fname) in fnames.iter().enumerate() {
+        let result = FlacReader::open(fname).and_then(|mut reader| {
+            let stats = try!(collect_frame_stats(&mut reader));
+            Ok((reader, stats))
+        });
+        let (reader, stats) = match result {
+            Ok(x) => x,
+            Err(err) => {
+                writeln!(stderr, "{}: {}", fname, err).unwrap();
+                failed = true;
+                continue
+            }
+        };
+        // Separate the output of multiple files with an empty line.
+        if i > 0 {
+            out.write_all(b"\n").unwrap();
+        }
+        if machine {
+            print_machine(&mut out, fname, &reader, &stats).unwrap();
+        } else {
+            print_human(&mut out, fname, &reader, &stats).unwrap();
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
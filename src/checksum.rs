@@ -0,0 +1,156 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `checksum` module hashes the encoded stream while it is being decoded.
+//!
+//! Wrap the input in a `HashingReader` before constructing the `FlacReader`,
+//! and every byte that the reader consumes is fed into the hasher. When
+//! decoding is done, `FlacReader::into_inner()` returns the hashing reader,
+//! and `finish()` returns the hasher. This fingerprints the original file in
+//! the same pass that decodes it, and verifies it against the streaminfo MD5
+//! signature if that is enabled, so the file need not be read twice.
+//!
+//! Claxon does not include hash functions. Any hash function can be used by
+//! implementing `StreamHasher`, or by passing a closure that feeds the bytes
+//! into it, as in this example with SHA-256 from the `sha2` crate:
+//!
+//! ```
+//! # extern crate claxon;
+//! # extern crate sha2;
+//! use claxon::checksum::HashingReader;
+//! use sha2::{Digest, Sha256};
+//!
+//! # fn main() {
+//! let mut hasher = Sha256::new();
+//! {
+//!     let file = std::fs::File::open("testsamples/short.flac").unwrap();
+//!     let input = HashingReader::new(file, |bytes: &[u8]| hasher.update(bytes));
+//!     let mut reader = claxon::FlacReader::new(input).unwrap();
+//!     for sample in reader.samples() {
+//!         sample.unwrap();
+//!     }
+//!     let _ = reader.into_inner().finish().unwrap();
+//! }
+//! let digest = hasher.finalize();
+//! # assert_eq!(digest.len(), 32);
+//! # }
+//! ```
+
+use std::io;
+
+/// Something that bytes can be fed into, such as a hash function.
+pub trait StreamHasher {
+    /// Feeds bytes into the hasher.
+    fn update(&mut self, bytes: &[u8]);
+}
+
+impl<F: FnMut(&[u8])> StreamHasher for F {
+    fn update(&mut self, bytes: &[u8]) {
+        self(bytes)
+    }
+}
+
+/// A reader that feeds everything read from the inner reader into a hasher.
+///
+/// The `FlacReader` buffers its input, so the bytes are hashed when they are
+/// read into the buffer, slightly ahead of decoding. After the last frame has
+/// been decoded, all bytes up to the end of the input have been hashed.
+///
+/// The hashing reader does not implement `Seek`: bytes that are skipped over
+/// would not be hashed, and bytes that are read twice would be hashed twice.
+pub struct HashingReader<R: io::Read, H: StreamHasher> {
+    inner: R,
+    hasher: H,
+    /// The number of bytes fed into the hasher so far.
+    len: u64,
+}
+
+impl<R: io::Read, H: StreamHasher> HashingReader<R, H> {
+    /// Wraps the reader, so that everything read from it is fed into the hasher.
+    pub fn new(inner: R, hasher: H) -> HashingReader<R, H> {
+        HashingReader {
+            inner: inner,
+            hasher: hasher,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes that were fed into the hasher so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no bytes were fed into the hasher yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the hasher.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Reads the remainder of the input, and returns the hasher.
+    ///
+    /// Data after the last frame, such as an ID3v1 tag, is not read by the
+    /// decoder. Reading it here makes the hash cover the entire input.
+    pub fn finish(mut self) -> io::Result<H> {
+        try!(io::copy(&mut self, &mut io::sink()));
+        Ok(self.hasher)
+    }
+
+    /// Returns the inner reader and the hasher, without reading the remainder of the input.
+    pub fn into_parts(self) -> (R, H) {
+        (self.inner, self.hasher)
+    }
+}
+
+impl<R: io::Read, H: StreamHasher> io::Read for HashingReader<R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+#[test]
+fn verify_hashing_reader_hashes_entire_input() {
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::io::Read;
+    use FlacReader;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/wasted_bits.flac").unwrap().read_to_end(&mut data).unwrap();
+    let mut input = data.clone();
+    // Trailing data, which the decoder does not read, is hashed by `finish()`.
+    input.extend_from_slice(b"TAG");
+    let expected = Sha256::digest(&input);
+
+    let mut hasher = Sha256::new();
+    {
+        let hashing_reader = HashingReader::new(io::Cursor::new(&input[..]),
+                                                |b: &[u8]| hasher.update(b));
+        let mut reader = FlacReader::new(hashing_reader).unwrap();
+        for sample in reader.samples() {
+            sample.unwrap();
+        }
+        let hashing_reader = reader.into_inner();
+        assert!(hashing_reader.len() <= input.len() as u64);
+        let _ = hashing_reader.finish().unwrap();
+    }
+    assert_eq!(hasher.finalize(), expected);
+
+    // Reading the input directly feeds every byte into the hasher once.
+    let mut len = 0;
+    {
+        let mut reader = HashingReader::new(io::Cursor::new(&data[..]), |b: &[u8]| len += b.len());
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+    }
+    assert_eq!(len, data.len());
+}
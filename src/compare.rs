@@ -0,0 +1,142 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `compare` module compares the audio of two streams.
+
+use std::cmp;
+use std::io;
+
+use error::Result;
+use frame::Block;
+use {FlacReader, FlacReaderState};
+
+/// The outcome of `compare()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    /// Both streams decode to the same samples.
+    Equal,
+    /// The streams have a different number of channels, or bits per sample.
+    FormatDiffers,
+    /// The streams differ first at the given inter-channel sample, in the given channel.
+    SampleDiffers {
+        /// The position of the first differing sample, counted from where the comparison started.
+        sample: u64,
+        /// The lowest channel that differs at that position.
+        channel: u32,
+    },
+    /// One stream ends after the given number of inter-channel samples, and the other continues.
+    LengthDiffers(u64),
+}
+
+/// A block, and the position of the first sample in it that was not compared yet.
+struct Cursor {
+    block: Block,
+    pos: u32,
+}
+
+impl Cursor {
+    fn remaining(&self) -> u32 {
+        self.block.duration() - self.pos
+    }
+}
+
+fn is_metadata_only<R: io::Read>(reader: &FlacReader<R>) -> bool {
+    match reader.input {
+        FlacReaderState::Full(..) => false,
+        FlacReaderState::MetadataOnly(..) => true,
+    }
+}
+
+/// Returns the next block that has samples left, or `None` at the end of the stream.
+fn advance<R: io::Read>(reader: &mut FlacReader<R>,
+                        cursor: Option<Cursor>)
+                        -> Result<Option<Cursor>> {
+    let mut buffer = match cursor {
+        Some(c) => {
+            if c.remaining() > 0 {
+                return Ok(Some(c))
+            }
+            c.block.into_buffer()
+        }
+        None => Vec::new(),
+    };
+    loop {
+        match try!(reader.read_next_block(buffer)) {
+            Some(block) => {
+                if block.duration() > 0 {
+                    return Ok(Some(Cursor { block: block, pos: 0 }))
+                }
+                buffer = block.into_buffer();
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Decodes two streams in lockstep, and compares their samples.
+///
+/// Decoding starts at the current position of both readers, so seek them
+/// first to compare only part of the streams. The block sizes of the streams
+/// may differ, so this can prove that a re-encoded stream is lossless. The
+/// comparison stops at the first difference, and decoding errors in either
+/// stream are returned as errors.
+///
+/// # Panics
+///
+/// Panics if a reader was constructed with `metadata_only` set.
+pub fn compare<A, B>(a: &mut FlacReader<A>, b: &mut FlacReader<B>) -> Result<Comparison>
+    where A: io::Read,
+          B: io::Read
+{
+    if is_metadata_only(a) || is_metadata_only(b) {
+        panic!("FlacReaderOptions::metadata_only must be false to be able to compare streams")
+    }
+
+    let (info_a, info_b) = (a.streaminfo(), b.streaminfo());
+    if info_a.channels != info_b.channels || info_a.bits_per_sample != info_b.bits_per_sample {
+        return Ok(Comparison::FormatDiffers)
+    }
+
+    let mut position = 0;
+    let mut cursor_a = None;
+    let mut cursor_b = None;
+    loop {
+        cursor_a = try!(advance(a, cursor_a));
+        cursor_b = try!(advance(b, cursor_b));
+        let (ca, cb) = match (cursor_a.as_mut(), cursor_b.as_mut()) {
+            (Some(ca), Some(cb)) => (ca, cb),
+            (None, None) => return Ok(Comparison::Equal),
+            _ => return Ok(Comparison::LengthDiffers(position)),
+        };
+
+        // Compare the samples that both blocks have left. If several
+        // channels differ, report the earliest sample.
+        let n = cmp::min(ca.remaining(), cb.remaining());
+        let mut first_difference: Option<(u32, u32)> = None;
+        for ch in 0..info_a.channels {
+            let xs = &ca.block.channel(ch)[ca.pos as usize..(ca.pos + n) as usize];
+            let ys = &cb.block.channel(ch)[cb.pos as usize..(cb.pos + n) as usize];
+            if let Some(i) = xs.iter().zip(ys).position(|(x, y)| x != y) {
+                let i = i as u32;
+                match first_difference {
+                    Some((j, _)) if j <= i => {}
+                    _ => first_difference = Some((i, ch)),
+                }
+            }
+        }
+        if let Some((i, ch)) = first_difference {
+            return Ok(Comparison::SampleDiffers {
+                sample: position + i as u64,
+                channel: ch,
+            })
+        }
+
+        ca.pos += n;
+        cb.pos += n;
+        position += n as u64;
+    }
+}
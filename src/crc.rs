@@ -70,6 +70,10 @@ pub struct Crc8Reader<R: ReadBytes> {
 pub struct Crc16Reader<R: ReadBytes> {
     inner: R,
     state: u16,
+    /// Whether to compute the CRC at all, it is not needed if it is not verified.
+    enabled: bool,
+    /// The number of bytes read so far.
+    bytes_read: u32,
 }
 
 impl<R: ReadBytes> Crc8Reader<R> {
@@ -98,6 +102,18 @@ impl<R: ReadBytes> Crc16Reader<R> {
         Crc16Reader {
             inner: inner,
             state: 0,
+            enabled: true,
+            bytes_read: 0,
+        }
+    }
+
+    /// Wraps the reader without computing the CRC, so `crc()` is always 0.
+    pub fn without_crc(inner: R) -> Crc16Reader<R> {
+        Crc16Reader {
+            inner: inner,
+            state: 0,
+            enabled: false,
+            bytes_read: 0,
         }
     }
 
@@ -106,9 +122,17 @@ impl<R: ReadBytes> Crc16Reader<R> {
         self.state
     }
 
+    /// Returns the number of bytes read thus far, also if the CRC is not computed.
+    pub fn bytes_read(&self) -> u32 {
+        self.bytes_read
+    }
+
     #[inline(always)]
     fn update_state(&mut self, byte: u8) {
-        self.state = (self.state << 8) ^ CRC16_TABLE[((self.state >> 8) as u8 ^ byte) as usize];
+        self.bytes_read = self.bytes_read.wrapping_add(1);
+        if self.enabled {
+            self.state = (self.state << 8) ^ CRC16_TABLE[((self.state >> 8) as u8 ^ byte) as usize];
+        }
     }
 }
 
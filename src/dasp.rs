@@ -0,0 +1,157 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `dasp` module yields decoded audio as `dasp` frames and samples.
+//!
+//! This module is only available with the `dasp` feature. Claxon decodes
+//! samples as `i32` values of the bit depth of the stream, whereas a `dasp`
+//! sample type spans its full range regardless of the bit depth. The samples
+//! are therefore scaled to the full `i32` range first, and then converted
+//! with `dasp::sample::FromSample`, so a 16-bit stream yields `i16` samples
+//! unchanged, and `f32` samples in the range [-1.0, 1.0).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate claxon;
+//! # fn main() {
+//! let reader = claxon::FlacReader::open("stereo.flac").unwrap();
+//! let frames = claxon::dasp::frames::<[f32; 2], _>(reader).expect("not a stereo file");
+//! for frame in frames {
+//!     let [left, right] = frame.unwrap();
+//!     // Process the frame here.
+//! #   let _ = (left, right);
+//! }
+//! # }
+//! ```
+
+extern crate dasp;
+
+use std::io;
+use std::marker::PhantomData;
+
+use self::dasp::frame::Frame;
+use self::dasp::sample::{FromSample, Sample};
+use error::Result;
+use {FlacIntoSamples, FlacReader};
+
+/// Converts a sample of the given bit depth into a `dasp` sample type.
+///
+/// The sample is scaled to the full range of `S`, see the module
+/// documentation.
+#[inline]
+pub fn to_sample<S: FromSample<i32>>(sample: i32, bits_per_sample: u32) -> S {
+    debug_assert!(bits_per_sample > 0 && bits_per_sample <= 32);
+    S::from_sample_(sample << (32 - bits_per_sample))
+}
+
+/// An iterator that yields the decoded audio as `dasp` frames.
+///
+/// Every frame holds one sample per channel, and has as many channels as the
+/// stream. It is returned by `frames()`.
+pub struct Frames<F, R: io::Read> {
+    samples: FlacIntoSamples<R>,
+    bits_per_sample: u32,
+    frame: PhantomData<F>,
+}
+
+/// Returns an iterator over the frames of the stream, that takes ownership of the reader.
+///
+/// Returns `None` if the number of channels of the frame type `F` differs from
+/// that of the stream. Like `FlacReader::into_samples()`, iteration starts at
+/// the current position of the reader, and it panics if the reader was
+/// constructed with `metadata_only` set.
+pub fn frames<F, R>(reader: FlacReader<R>) -> Option<Frames<F, R>>
+    where F: Frame,
+          F::Sample: FromSample<i32>,
+          R: io::Read
+{
+    if reader.streaminfo().channels as usize != F::CHANNELS {
+        return None
+    }
+    Some(Frames {
+        bits_per_sample: reader.streaminfo().bits_per_sample,
+        samples: reader.into_samples(),
+        frame: PhantomData,
+    })
+}
+
+impl<F, R: io::Read> Frames<F, R> {
+    /// Returns the reader, to continue decoding after the current block.
+    pub fn into_reader(self) -> FlacReader<R> {
+        self.samples.into_reader()
+    }
+}
+
+impl<F, R> Iterator for Frames<F, R>
+    where F: Frame,
+          F::Sample: FromSample<i32>,
+          R: io::Read
+{
+    type Item = Result<F>;
+
+    fn next(&mut self) -> Option<Result<F>> {
+        let bits_per_sample = self.bits_per_sample;
+        let samples = &mut self.samples;
+        let mut error = None;
+        let mut ended = false;
+        // A block holds all channels of an inter-channel sample, so the
+        // stream can only end, or fail, before the first channel.
+        let frame = F::from_fn(|_| {
+            match samples.next() {
+                Some(Ok(sample)) => to_sample(sample, bits_per_sample),
+                Some(Err(err)) => {
+                    error = Some(err);
+                    F::Sample::EQUILIBRIUM
+                }
+                None => {
+                    ended = true;
+                    F::Sample::EQUILIBRIUM
+                }
+            }
+        });
+        match error {
+            Some(err) => Some(Err(err)),
+            None if ended => None,
+            None => Some(Ok(frame)),
+        }
+    }
+
+    /// Returns the number of frames left, see `FlacSamples::size_hint()`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.samples.size_hint();
+        (lower / F::CHANNELS, upper.map(|n| n / F::CHANNELS))
+    }
+}
+
+#[test]
+fn verify_to_sample_scales_to_full_range() {
+    assert_eq!(to_sample::<i16>(-32768, 16), -32768);
+    assert_eq!(to_sample::<i16>(1, 16), 1);
+    assert_eq!(to_sample::<i16>(1 << 8, 24), 1);
+    assert_eq!(to_sample::<f32>(-128, 8), -1.0);
+    assert_eq!(to_sample::<f32>(1 << 22, 24), 0.5);
+}
+
+#[test]
+fn verify_frames_match_samples() {
+    let reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    assert!(frames::<[i32; 2], _>(reader).is_none());
+
+    let mut reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let bits_per_sample = reader.streaminfo().bits_per_sample;
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let frames = frames::<[i32; 1], _>(reader).unwrap();
+    assert_eq!(frames.size_hint(), (samples.len(), Some(samples.len())));
+    let decoded: Vec<[i32; 1]> = frames.map(|f| f.unwrap()).collect();
+    assert_eq!(decoded.len(), samples.len());
+    for (frame, &sample) in decoded.iter().zip(samples.iter()) {
+        assert_eq!(frame[0], sample << (32 - bits_per_sample));
+    }
+}
@@ -0,0 +1,182 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `dither` module reduces the bit depth of samples, for output to 16-bit sinks.
+//!
+//! Simply dropping the least significant bits of a sample introduces
+//! distortion that is correlated with the signal. Adding a small amount of
+//! random noise before rounding, called dither, turns that distortion into
+//! a constant noise floor, which is far less audible. `Dither` adds noise with
+//! a triangular probability density function (TPDF) of two least significant
+//! bits of the target bit depth wide, and can optionally shape the noise to
+//! move it towards higher frequencies.
+
+use error::Result;
+
+/// An adapter that reduces the bit depth of interleaved samples with dither.
+///
+/// The samples are typically `FlacReader::samples()`. Noise shaping keeps
+/// state per channel, so the adapter must see the samples of all channels,
+/// interleaved, starting at the first channel. The noise is pseudo-random,
+/// with a fixed seed, so the output is reproducible.
+pub struct Dither<I> {
+    samples: I,
+    channels: usize,
+    /// The channel of the next sample.
+    channel: usize,
+    /// The value of one least significant bit of the output, in input units.
+    step: f64,
+    min: f64,
+    max: f64,
+    /// The number of bits to shift left if the target is wider than the input.
+    shift_left: u32,
+    noise_shaping: bool,
+    /// The quantization error of the previous sample, per channel.
+    error: [f64; 8],
+    rng: u32,
+}
+
+impl<I: Iterator<Item = Result<i32>>> Dither<I> {
+    /// Wraps `samples` of `bits_per_sample` bits, to reduce them to `target_bits` bits.
+    ///
+    /// `channels` is the number of interleaved channels, at most 8. If
+    /// `noise_shaping` is true, first-order noise shaping is applied: the
+    /// quantization error of a sample is subtracted from the next sample of
+    /// the same channel, which moves the noise towards higher frequencies,
+    /// where the ear is less sensitive. If the target is at least as wide as
+    /// the input, the samples are only shifted, without dither.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is not between 1 and 8, or if `target_bits` is
+    /// not between 1 and 32.
+    pub fn new(samples: I,
+               channels: u32,
+               bits_per_sample: u32,
+               target_bits: u32,
+               noise_shaping: bool)
+               -> Dither<I> {
+        assert!(channels >= 1 && channels <= 8, "channels must be between 1 and 8");
+        assert!(target_bits >= 1 && target_bits <= 32, "target_bits must be between 1 and 32");
+        let max = ((1_i64 << (target_bits - 1)) - 1) as f64;
+        let (step, shift_left) = if bits_per_sample > target_bits {
+            ((1_u64 << (bits_per_sample - target_bits)) as f64, 0)
+        } else {
+            (1.0, target_bits - bits_per_sample)
+        };
+        Dither {
+            samples: samples,
+            channels: channels as usize,
+            channel: 0,
+            step: step,
+            min: -max - 1.0,
+            max: max,
+            shift_left: shift_left,
+            noise_shaping: noise_shaping,
+            error: [0.0; 8],
+            rng: 0x2545_f491,
+        }
+    }
+
+    /// Returns a pseudo-random number uniformly distributed in [0.0, 1.0).
+    fn next_uniform(&mut self) -> f64 {
+        // A xorshift generator, which is fast, and good enough for dither.
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f64 / (1 << 24) as f64
+    }
+
+    /// Reduces a single sample of the current channel.
+    fn reduce(&mut self, sample: i32) -> i32 {
+        let ch = self.channel;
+        self.channel = (self.channel + 1) % self.channels;
+
+        if self.step == 1.0 {
+            return sample << self.shift_left
+        }
+
+        let target = if self.noise_shaping {
+            sample as f64 - self.error[ch]
+        } else {
+            sample as f64
+        };
+
+        // The difference of two uniform variables has a triangular density,
+        // between -1 and 1 least significant bit of the output.
+        let noise = self.next_uniform() - self.next_uniform();
+        let quantized = (target / self.step + noise).round().max(self.min).min(self.max);
+
+        if self.noise_shaping {
+            self.error[ch] = quantized * self.step - target;
+        }
+        quantized as i32
+    }
+}
+
+impl<I: Iterator<Item = Result<i32>>> Iterator for Dither<I> {
+    type Item = Result<i32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<i32>> {
+        match self.samples.next() {
+            Some(Ok(sample)) => Some(Ok(self.reduce(sample))),
+            other => other,
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
+}
+
+#[test]
+fn verify_dither_stays_within_one_step() {
+    // A 24-bit signal with a constant value halfway between two 16-bit values.
+    let samples = vec![0x12_3480; 10000];
+    let out: Vec<i32> = Dither::new(samples.iter().map(|&s| Ok(s)), 1, 24, 16, false)
+        .map(|s| s.unwrap()).collect();
+
+    assert!(out.iter().all(|&s| s >= 0x1233 && s <= 0x1235));
+
+    // The dither must make the output average to the input value.
+    let mean = out.iter().map(|&s| s as f64).sum::<f64>() / out.len() as f64;
+    assert!((mean - 0x1234 as f64 - 0.5).abs() < 0.05);
+}
+
+#[test]
+fn verify_dither_clamps_and_passes_through() {
+    // Full scale must not wrap around, with or without noise shaping.
+    let samples = [0x7f_ffff, -0x80_0000, 0x7f_ffff, -0x80_0000];
+    for &shaping in &[false, true] {
+        let out: Vec<i32> = Dither::new(samples.iter().map(|&s| Ok(s)), 2, 24, 16, shaping)
+            .map(|s| s.unwrap()).collect();
+        assert!(out.iter().all(|&s| s >= -0x8000 && s <= 0x7fff));
+        assert!(out[0] > 0x7ff0 && out[1] < -0x7ff0);
+    }
+
+    // No dither is added when the bit depth is not reduced.
+    let samples = [1, -2, 3];
+    let out: Vec<i32> = Dither::new(samples.iter().map(|&s| Ok(s)), 1, 16, 16, true)
+        .map(|s| s.unwrap()).collect();
+    assert_eq!(out, vec![1, -2, 3]);
+    let out: Vec<i32> = Dither::new(samples.iter().map(|&s| Ok(s)), 1, 8, 16, true)
+        .map(|s| s.unwrap()).collect();
+    assert_eq!(out, vec![256, -512, 768]);
+}
+
+#[test]
+fn verify_noise_shaping_keeps_the_mean() {
+    // With noise shaping, the error is fed back, so the average of the output
+    // tracks the input even more closely than without.
+    let samples = vec![0x12_3440; 10000];
+    let out: Vec<i32> = Dither::new(samples.iter().map(|&s| Ok(s)), 1, 24, 16, true)
+        .map(|s| s.unwrap()).collect();
+    let mean = out.iter().map(|&s| s as f64).sum::<f64>() / out.len() as f64;
+    assert!((mean - 0x1234 as f64 - 0.25).abs() < 0.01);
+}
@@ -14,13 +14,34 @@ use std::result;
 use std::string;
 
 /// An error that prevents succesful decoding of the FLAC stream.
+///
+/// The variant tells what kind of problem occurred: a stream that ends
+/// prematurely, such as an interrupted upload, is an `IoError` (see
+/// `is_truncated()`), a damaged or invalid stream is a `FormatError`, and a
+/// valid stream that Claxon cannot decode is `Unsupported`. `BufferTooSmall`
+/// is a mistake of the caller, rather than a problem with the stream.
 #[derive(Debug)]
 pub enum Error {
     /// Not a decoding error, but a problem with the underlying IO.
     IoError(io::Error),
 
     /// An ill-formed FLAC stream was encountered.
-    FormatError(&'static str),
+    FormatError {
+        /// What is wrong with the stream.
+        reason: &'static str,
+        /// The offset in the input at which the ill-formed frame starts.
+        ///
+        /// This is only known for frames decoded through a `FlacReader`, it
+        /// is `None` for errors in the metadata, and for frames decoded with
+        /// a standalone `FrameReader`.
+        byte_offset: Option<u64>,
+        /// The index of the ill-formed frame in the stream, counting from 0.
+        ///
+        /// In addition to the cases where `byte_offset` is unknown, this is
+        /// `None` after a seek, or after frames were decoded through
+        /// `FlacReader::blocks()`.
+        frame_index: Option<u64>,
+    },
 
     /// A currently unsupported feature of the FLAC format was encountered.
     ///
@@ -28,18 +49,51 @@ pub enum Error {
     /// specification that are marked as reserved will cause a `FormatError`;
     /// `Unsupported` is used for features that are in the specification, but
     /// which are not implemented by Claxon.
-    Unsupported(&'static str),
+    Unsupported {
+        /// The feature that is not supported.
+        feature: &'static str,
+    },
+
+    /// A block does not fit in the buffer provided by the caller.
+    ///
+    /// This is returned by `FrameReader::read_next_into()`, where the frame is
+    /// consumed, so it cannot be decoded again with a larger buffer. It is also
+    /// returned by `FlacReader::decode_block_interleaved_into()`, which checks
+    /// the buffer before decoding anything.
+    BufferTooSmall {
+        /// The number of samples needed to hold all channels of the block.
+        required: usize,
+    },
+}
+
+impl Error {
+    /// Returns whether the error is caused by the input ending prematurely.
+    ///
+    /// A truncated stream is reported as an `IoError` of kind
+    /// `UnexpectedEof`. This tells it apart from a damaged stream or a
+    /// limitation of the decoder.
+    pub fn is_truncated(&self) -> bool {
+        match *self {
+            Error::IoError(ref err) => err.kind() == io::ErrorKind::UnexpectedEof,
+            _ => false,
+        }
+    }
 }
 
 impl PartialEq for Error {
     fn eq(&self, other: &Error) -> bool {
-        use error::Error::{IoError, FormatError, Unsupported};
+        use error::Error::{IoError, FormatError, Unsupported, BufferTooSmall};
         match (self, other) {
-            (&FormatError(r1), &FormatError(r2)) => r1 == r2,
-            (&Unsupported(f1), &Unsupported(f2)) => f1 == f2,
+            (&FormatError { reason: r1, byte_offset: o1, frame_index: i1 },
+             &FormatError { reason: r2, byte_offset: o2, frame_index: i2 }) => {
+                r1 == r2 && o1 == o2 && i1 == i2
+            }
+            (&Unsupported { feature: f1 }, &Unsupported { feature: f2 }) => f1 == f2,
+            (&BufferTooSmall { required: r1 }, &BufferTooSmall { required: r2 }) => r1 == r2,
             (&IoError(_), _) => false,
-            (&FormatError(_), _) => false,
-            (&Unsupported(_), _) => false,
+            (&FormatError { .. }, _) => false,
+            (&Unsupported { .. }, _) => false,
+            (&BufferTooSmall { .. }, _) => false,
         }
     }
 }
@@ -48,33 +102,35 @@ impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
         match *self {
             Error::IoError(ref err) => err.fmt(formatter),
-            Error::FormatError(reason) => {
+            Error::FormatError { reason, byte_offset, frame_index } => {
                 try!(formatter.write_str("Ill-formed FLAC stream: "));
-                formatter.write_str(reason)
+                try!(formatter.write_str(reason));
+                match (frame_index, byte_offset) {
+                    (Some(i), Some(o)) => write!(formatter, " (frame {} at byte {})", i, o),
+                    (None, Some(o)) => write!(formatter, " (frame at byte {})", o),
+                    _ => Ok(()),
+                }
             }
-            Error::Unsupported(feature) => {
+            Error::Unsupported { feature } => {
                 try!(formatter.write_str("A currently unsupported feature of the FLAC format \
                                           was encountered: "));
                 formatter.write_str(feature)
             }
+            Error::BufferTooSmall { required } => {
+                write!(formatter, "The block does not fit in the buffer, it needs {} samples",
+                       required)
+            }
         }
     }
 }
 
 impl error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::IoError(ref err) => err.description(),
-            Error::FormatError(reason) => reason,
-            Error::Unsupported(_) => "unsupported feature",
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
         match *self {
             Error::IoError(ref err) => Some(err),
-            Error::FormatError(_) => None,
-            Error::Unsupported(_) => None,
+            Error::FormatError { .. } => None,
+            Error::Unsupported { .. } => None,
+            Error::BufferTooSmall { .. } => None,
         }
     }
 }
@@ -89,7 +145,11 @@ impl From<string::FromUtf8Error> for Error {
     fn from(_: string::FromUtf8Error) -> Error {
         // Vendor strings and Vorbis cmments are the only place where UTF-8 is
         // parsed into a String.
-        Error::FormatError("Vorbis comment or vendor string is not valid UTF-8")
+        Error::FormatError {
+            reason: "Vorbis comment or vendor string is not valid UTF-8",
+            byte_offset: None,
+            frame_index: None,
+        }
     }
 }
 
@@ -98,5 +158,42 @@ pub type Result<T> = result::Result<T, Error>;
 
 /// Shorthand for producing a format error with reason.
 pub fn fmt_err<T>(reason: &'static str) -> Result<T> {
-    Err(Error::FormatError(reason))
+    Err(Error::FormatError {
+        reason: reason,
+        byte_offset: None,
+        frame_index: None,
+    })
+}
+
+/// Records the position of the frame in which a format error occurred.
+pub fn locate_frame(err: Error, byte_offset: u64, frame_index: Option<u64>) -> Error {
+    match err {
+        Error::FormatError { reason, .. } => Error::FormatError {
+            reason: reason,
+            byte_offset: Some(byte_offset),
+            frame_index: frame_index,
+        },
+        other => other,
+    }
+}
+
+#[test]
+fn verify_error_source_and_display() {
+    use std::error::Error as StdError;
+
+    let err = Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "eof"));
+    assert!(err.is_truncated());
+    assert!(err.source().is_some());
+
+    let err = locate_frame(fmt_err::<()>("invalid frame header").unwrap_err(), 1234, Some(5));
+    assert!(!err.is_truncated());
+    assert!(err.source().is_none());
+    assert_eq!(format!("{}", err),
+               "Ill-formed FLAC stream: invalid frame header (frame 5 at byte 1234)");
+
+    let err = Error::Unsupported { feature: "unencoded binary" };
+    assert_eq!(locate_frame(err, 0, None), Error::Unsupported { feature: "unencoded binary" });
+
+    let err = Error::BufferTooSmall { required: 8192 };
+    assert_eq!(format!("{}", err), "The block does not fit in the buffer, it needs 8192 samples");
 }
@@ -0,0 +1,332 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `ffi` module exports a C API, when the `ffi` feature is enabled.
+//!
+//! Data is read through callbacks, so the caller decides where it comes from.
+//! A decoder is created with `claxon_open()`, which returns a null pointer on
+//! failure, and destroyed with `claxon_free()`. All other functions return
+//! `CLAXON_OK` or a negative error code. Panics do not unwind into C: they
+//! make `claxon_open()` return null, and the other functions return
+//! `CLAXON_ERROR_PANIC`. To build a C library, compile the
+//! crate as a `cdylib` or `staticlib` with this feature, for instance with
+//! `cargo rustc --release --features ffi -- --crate-type cdylib`.
+//!
+//! The corresponding C declarations are:
+//!
+//! ```c
+//! typedef intptr_t (*claxon_read_fn)(void *user_data, uint8_t *buffer, size_t len);
+//! typedef int64_t (*claxon_seek_fn)(void *user_data, int64_t offset, int whence);
+//!
+//! typedef struct {
+//!     uint32_t sample_rate;
+//!     uint32_t channels;
+//!     uint32_t bits_per_sample;
+//!     uint32_t max_block_size;
+//!     uint64_t samples;
+//! } claxon_streaminfo;
+//!
+//! typedef struct claxon_decoder claxon_decoder;
+//!
+//! claxon_decoder *claxon_open(claxon_read_fn read, claxon_seek_fn seek, void *user_data);
+//! int claxon_streaminfo_get(const claxon_decoder *decoder, claxon_streaminfo *out);
+//! int claxon_decode_next(claxon_decoder *decoder, int32_t *buffer, size_t len, size_t *n_samples);
+//! int claxon_seek(claxon_decoder *decoder, uint64_t sample);
+//! void claxon_free(claxon_decoder *decoder);
+//! ```
+
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::panic;
+use std::ptr;
+use std::slice;
+
+use error::Error;
+use {DecodeScratch, FlacReader, FlacReaderOptions};
+
+/// The function succeeded.
+pub const CLAXON_OK: c_int = 0;
+/// Reading or seeking failed.
+pub const CLAXON_ERROR_IO: c_int = -1;
+/// The stream is not a valid FLAC stream.
+pub const CLAXON_ERROR_FORMAT: c_int = -2;
+/// The stream uses a feature that Claxon does not support.
+pub const CLAXON_ERROR_UNSUPPORTED: c_int = -3;
+/// A pointer argument was null, or a buffer was too small.
+pub const CLAXON_ERROR_ARGUMENT: c_int = -4;
+/// Claxon panicked, which is a bug. The decoder may only be freed afterwards.
+pub const CLAXON_ERROR_PANIC: c_int = -5;
+
+/// Reads at most `len` bytes into `buffer`.
+///
+/// Returns the number of bytes read, 0 at the end of the stream, or a negative
+/// number on error.
+pub type ReadFn = extern "C" fn(user_data: *mut c_void, buffer: *mut u8, len: usize) -> isize;
+
+/// Seeks like `fseek`, with `whence` 0, 1 or 2 for the start, the current
+/// position, or the end. Returns the new position, or a negative number on error.
+pub type SeekFn = extern "C" fn(user_data: *mut c_void, offset: i64, whence: c_int) -> i64;
+
+/// Adapts the callbacks to `io::Read` and `io::Seek`.
+pub struct CallbackReader {
+    read: ReadFn,
+    seek: Option<SeekFn>,
+    user_data: *mut c_void,
+}
+
+impl io::Read for CallbackReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let n = (self.read)(self.user_data, buffer.as_mut_ptr(), buffer.len());
+        if n < 0 || n as usize > buffer.len() {
+            Err(io::Error::new(io::ErrorKind::Other, "read callback failed"))
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
+impl io::Seek for CallbackReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let seek = match self.seek {
+            Some(seek) => seek,
+            None => return Err(io::Error::new(io::ErrorKind::Other, "input is not seekable")),
+        };
+        let (offset, whence) = match pos {
+            io::SeekFrom::Start(offset) => (offset as i64, 0),
+            io::SeekFrom::Current(offset) => (offset, 1),
+            io::SeekFrom::End(offset) => (offset, 2),
+        };
+        let position = seek(self.user_data, offset, whence);
+        if position < 0 {
+            Err(io::Error::new(io::ErrorKind::Other, "seek callback failed"))
+        } else {
+            Ok(position as u64)
+        }
+    }
+}
+
+/// The streaminfo, as exposed to C.
+#[repr(C)]
+pub struct StreamInfo {
+    /// The sample rate in Hz.
+    pub sample_rate: u32,
+    /// The number of channels.
+    pub channels: u32,
+    /// The number of bits per sample.
+    pub bits_per_sample: u32,
+    /// The maximum block size, in inter-channel samples.
+    pub max_block_size: u32,
+    /// The number of inter-channel samples in the stream, or 0 if unknown.
+    pub samples: u64,
+}
+
+/// A decoder, which is opaque to C.
+pub struct Decoder {
+    reader: FlacReader<CallbackReader>,
+    scratch: DecodeScratch,
+}
+
+fn error_code(error: &Error) -> c_int {
+    match *error {
+        Error::IoError(..) => CLAXON_ERROR_IO,
+        Error::FormatError { .. } => CLAXON_ERROR_FORMAT,
+        Error::Unsupported { .. } => CLAXON_ERROR_UNSUPPORTED,
+        Error::BufferTooSmall { .. } => CLAXON_ERROR_ARGUMENT,
+    }
+}
+
+/// Runs `f`, returning `CLAXON_ERROR_PANIC` if it panics, as unwinding into C is undefined.
+fn catch_panic<F: FnOnce() -> c_int>(f: F) -> c_int {
+    panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_or(CLAXON_ERROR_PANIC)
+}
+
+/// Creates a decoder, and reads the header and metadata of the stream.
+///
+/// `seek` may be null if the input cannot seek; `claxon_seek()` fails then.
+/// Returns null if the stream could not be read.
+#[no_mangle]
+pub extern "C" fn claxon_open(read: Option<ReadFn>,
+                              seek: Option<SeekFn>,
+                              user_data: *mut c_void)
+                              -> *mut Decoder {
+    let open = panic::AssertUnwindSafe(|| open(read, seek, user_data));
+    panic::catch_unwind(open).unwrap_or(ptr::null_mut())
+}
+
+fn open(read: Option<ReadFn>, seek: Option<SeekFn>, user_data: *mut c_void) -> *mut Decoder {
+    let read = match read {
+        Some(read) => read,
+        None => return ptr::null_mut(),
+    };
+    let input = CallbackReader {
+        read: read,
+        seek: seek,
+        user_data: user_data,
+    };
+    let options = FlacReaderOptions::default();
+    let result = if seek.is_some() {
+        FlacReader::new_seekable(input, options)
+    } else {
+        FlacReader::new_ext(input, options)
+    };
+    match result {
+        Ok(reader) => {
+            let scratch = DecodeScratch::for_streaminfo(&reader.streaminfo());
+            Box::into_raw(Box::new(Decoder {
+                reader: reader,
+                scratch: scratch,
+            }))
+        }
+        Err(..) => ptr::null_mut(),
+    }
+}
+
+/// Writes the streaminfo of the stream to `out`.
+///
+/// # Safety
+///
+/// `decoder` must be returned by `claxon_open()`, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn claxon_streaminfo_get(decoder: *const Decoder,
+                                               out: *mut StreamInfo)
+                                               -> c_int {
+    if decoder.is_null() || out.is_null() {
+        return CLAXON_ERROR_ARGUMENT
+    }
+    let streaminfo = (*decoder).reader.streaminfo();
+    *out = StreamInfo {
+        sample_rate: streaminfo.sample_rate,
+        channels: streaminfo.channels,
+        bits_per_sample: streaminfo.bits_per_sample,
+        max_block_size: streaminfo.max_block_size as u32,
+        samples: streaminfo.samples.unwrap_or(0),
+    };
+    CLAXON_OK
+}
+
+/// Decodes the next block into `buffer`, with the channels interleaved.
+///
+/// The buffer must hold `len` samples, at least the maximum block size times
+/// the number of channels. The number of samples written, for all channels
+/// together, is stored in `n_samples`; it is 0 at the end of the stream.
+///
+/// # Safety
+///
+/// `decoder` must be returned by `claxon_open()`, `buffer` must be valid for
+/// writing `len` samples, and `n_samples` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn claxon_decode_next(decoder: *mut Decoder,
+                                            buffer: *mut i32,
+                                            len: usize,
+                                            n_samples: *mut usize)
+                                            -> c_int {
+    if decoder.is_null() || buffer.is_null() || n_samples.is_null() {
+        return CLAXON_ERROR_ARGUMENT
+    }
+    let decoder = &mut *decoder;
+    if len < decoder.reader.streaminfo().max_block_len() {
+        return CLAXON_ERROR_ARGUMENT
+    }
+    let out = slice::from_raw_parts_mut(buffer, len);
+    catch_panic(|| {
+        match decoder.reader.decode_block_interleaved_into(&mut decoder.scratch, out) {
+            Ok(n) => {
+                *n_samples = n.unwrap_or(0);
+                CLAXON_OK
+            }
+            Err(err) => error_code(&err),
+        }
+    })
+}
+
+/// Seeks to the given inter-channel sample, so that decoding continues there.
+///
+/// The next block that `claxon_decode_next()` returns is the block that
+/// contains the target sample; it may start before the target.
+///
+/// # Safety
+///
+/// `decoder` must be returned by `claxon_open()`.
+#[no_mangle]
+pub unsafe extern "C" fn claxon_seek(decoder: *mut Decoder, sample: u64) -> c_int {
+    if decoder.is_null() {
+        return CLAXON_ERROR_ARGUMENT
+    }
+    let decoder = &mut *decoder;
+    catch_panic(|| {
+        match decoder.reader.seek_to_sample(sample) {
+            Ok(()) => CLAXON_OK,
+            Err(err) => error_code(&err),
+        }
+    })
+}
+
+/// Destroys a decoder. Passing null does nothing.
+///
+/// # Safety
+///
+/// `decoder` must be returned by `claxon_open()`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn claxon_free(decoder: *mut Decoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+#[cfg(test)]
+extern "C" fn read_cursor(user_data: *mut c_void, buffer: *mut u8, len: usize) -> isize {
+    use std::io::Read;
+    let cursor = unsafe { &mut *(user_data as *mut io::Cursor<Vec<u8>>) };
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer, len) };
+    cursor.read(buffer).map(|n| n as isize).unwrap_or(-1)
+}
+
+#[test]
+fn verify_decode_through_c_api() {
+    use std::fs;
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut data).unwrap();
+    let mut cursor = io::Cursor::new(data);
+    let user_data = &mut cursor as *mut io::Cursor<Vec<u8>> as *mut c_void;
+
+    let decoder = claxon_open(Some(read_cursor), None, user_data);
+    assert!(!decoder.is_null());
+    unsafe {
+        let mut streaminfo = StreamInfo {
+            sample_rate: 0,
+            channels: 0,
+            bits_per_sample: 0,
+            max_block_size: 0,
+            samples: 0,
+        };
+        assert_eq!(claxon_streaminfo_get(decoder, &mut streaminfo), CLAXON_OK);
+        assert_eq!(streaminfo.samples, 4);
+
+        let mut buffer = vec![0; streaminfo.max_block_size as usize * streaminfo.channels as usize];
+        let mut n = 0;
+        assert_eq!(claxon_decode_next(decoder, buffer.as_mut_ptr(), 1, &mut n),
+                   CLAXON_ERROR_ARGUMENT);
+        assert_eq!(claxon_decode_next(decoder, buffer.as_mut_ptr(), buffer.len(), &mut n),
+                   CLAXON_OK);
+        assert_eq!(n, 4 * streaminfo.channels as usize);
+        assert_eq!(claxon_decode_next(decoder, buffer.as_mut_ptr(), buffer.len(), &mut n),
+                   CLAXON_OK);
+        assert_eq!(n, 0);
+
+        // Without a seek callback, seeking fails.
+        assert_eq!(claxon_seek(decoder, 0), CLAXON_ERROR_IO);
+        claxon_free(decoder);
+    }
+}
+
+#[test]
+fn verify_panic_becomes_error_code() {
+    assert_eq!(catch_panic(|| CLAXON_ERROR_IO), CLAXON_ERROR_IO);
+    assert_eq!(catch_panic(|| panic!("bug")), CLAXON_ERROR_PANIC);
+}
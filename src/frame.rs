@@ -7,12 +7,22 @@
 
 //! The `frame` module deals with the frames that make up a FLAC stream.
 
+use std::cmp;
 use std::i32;
+use std::io;
+use std::mem;
+use std::slice;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 
 use crc::{Crc8Reader, Crc16Reader};
 use error::{Error, Result, fmt_err};
 use input::{Bitstream, ReadBytes};
+use layout::ChannelLayout;
+use metadata::StreamInfo;
+use metrics::{self, Metrics};
 use subframe;
+use subframe::SubframeInfo;
 
 #[derive(Clone, Copy)]
 enum BlockingStrategy {
@@ -20,14 +30,42 @@ enum BlockingStrategy {
     Variable,
 }
 
-#[derive(Clone, Copy)]
-enum BlockTime {
+/// The position of a frame in the stream, as stored in its header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockTime {
+    /// The frame number, in a fixed-blocksize stream.
     FrameNumber(u32),
+    /// The sample number of the first sample, in a variable-blocksize stream.
     SampleNumber(u64),
 }
 
-#[derive(Clone, Copy, Debug)]
-enum ChannelAssignment {
+/// A checksum of a frame that does not match its contents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CrcMismatch {
+    /// The position of the frame, as stored in its header.
+    ///
+    /// In a fixed-blocksize stream, this is the index of the frame.
+    pub block_time: BlockTime,
+    /// Which checksum did not match.
+    pub checksum: Checksum,
+    /// The checksum as stored in the stream.
+    pub expected: u16,
+    /// The checksum computed over the data that was read.
+    pub computed: u16,
+}
+
+/// The checksums that protect a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    /// The CRC-8 at the end of the frame header, computed over the header.
+    HeaderCrc8,
+    /// The CRC-16 at the end of the frame, computed over the entire frame.
+    FrameCrc16,
+}
+
+/// How the channels of a frame are coded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelAssignment {
     /// The `n: u8` channels are coded as-is.
     Independent(u8),
     /// Channel 0 is the left channel, channel 1 is the side channel.
@@ -121,14 +159,206 @@ fn verify_read_var_length_int() {
 
     // Two-byte integer with invalid continuation byte should fail.
     assert_eq!(read_var_length_int(&mut reader).err().unwrap(),
-               Error::FormatError("invalid variable-length integer"));
+               Error::FormatError {
+                   reason: "invalid variable-length integer",
+                   byte_offset: None,
+                   frame_index: None,
+               });
 
     // Continuation byte can never be the first byte.
     assert_eq!(read_var_length_int(&mut reader).err().unwrap(),
-               Error::FormatError("invalid variable-length integer"));
+               Error::FormatError {
+                   reason: "invalid variable-length integer",
+                   byte_offset: None,
+                   frame_index: None,
+               });
+}
+
+/// Returns whether the input continues with an ID3v1 or APEv2 tag.
+///
+/// The first two bytes have been read already, and are passed in as `first`.
+/// This consumes the remainder of the tag signature if it matches.
+fn is_trailing_tag<R: ReadBytes>(input: &mut R, first: u16) -> Result<bool> {
+    // An ID3v1 tag starts with "TAG", an APEv2 tag with "APETAGEX".
+    let rest: &[u8] = match first {
+        0x54_41 => b"G",
+        0x41_50 => b"ETAGEX",
+        _ => return Ok(false),
+    };
+
+    for &expected in rest {
+        if try!(input.read_u8_or_eof()) != Some(expected) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// The contents of a frame header, which can be read without decoding the frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FrameInfo {
+    /// The frame or sample number in the header, which implies the blocking strategy.
+    pub block_time: BlockTime,
+    /// The sample number of the first inter-channel sample in the frame.
+    pub first_sample: u64,
+    /// The number of inter-channel samples in the frame.
+    pub block_size: u16,
+    /// The number of channels in the frame.
+    pub channels: u32,
+    /// How the channels are coded.
+    pub channel_assignment: ChannelAssignment,
+    /// The sample rate, or `None` if the streaminfo sample rate applies.
+    pub sample_rate: Option<u32>,
+    /// The bits per sample, or `None` if the streaminfo bits per sample apply.
+    pub bits_per_sample: Option<u32>,
+}
+
+impl FrameInfo {
+    /// Returns the default speaker assignment for the number of channels in the frame.
+    ///
+    /// This does not depend on the channel assignment: the stereo
+    /// decorrelation modes decode to left and right like independent stereo.
+    pub fn channel_layout(&self) -> Option<ChannelLayout> {
+        ChannelLayout::from_channels(self.channels)
+    }
+}
+
+/// How a frame was encoded, see `FrameReader::read_next_with_report_or_eof()`.
+#[derive(Clone, Debug)]
+pub struct FrameReport {
+    /// The frame header.
+    pub info: FrameInfo,
+    /// The parameters of every subframe, one per channel.
+    pub subframes: Vec<SubframeInfo>,
+    /// The length of the frame in bytes, from the sync code up to and including the CRC.
+    ///
+    /// This is 0 for a partial frame, see `FrameReader::set_salvage_partial()`.
+    pub len: u32,
+}
+
+/// The maximum length of a frame header in bytes, including sync code and CRC.
+pub const MAX_FRAME_HEADER_LEN: usize = 16;
+
+/// The largest block size that a frame header can express, in inter-channel samples.
+///
+/// The header stores the block size minus one in 16 bits, but a block size of
+/// 65536 would exceed the maximum block size field of the streaminfo block,
+/// so it is rejected.
+pub const MAX_BLOCK_SIZE: u16 = 65535;
+
+/// Returns the first sample number of a frame, see `FrameReader::with_fixed_block_size()`.
+fn first_sample_number(header: &FrameHeader, fixed_block_size: u16) -> u64 {
+    match header.block_time {
+        BlockTime::FrameNumber(fnr) if fixed_block_size > 0 => {
+            fixed_block_size as u64 * fnr as u64
+        }
+        BlockTime::FrameNumber(fnr) => header.block_size as u64 * fnr as u64,
+        BlockTime::SampleNumber(snr) => snr,
+    }
+}
+
+/// Reads a frame header, without decoding the rest of the frame.
+///
+/// Returns `None` if the input is at the end of the stream. Afterwards, the
+/// input is positioned at the first subframe; there is no way to skip over
+/// the remainder of the frame, other than decoding it, or looking for the
+/// next frame header. The fixed block size is used to convert a frame number
+/// into a sample number, see `FrameReader::with_fixed_block_size()`.
+pub fn read_frame_info<R: ReadBytes>(input: &mut R,
+                                     fixed_block_size: u16)
+                                     -> Result<Option<FrameInfo>> {
+    let header = match try!(read_frame_header_or_eof(input)) {
+        Some(h) => h,
+        None => return Ok(None),
+    };
+    Ok(Some(frame_info(&header, fixed_block_size)))
+}
+
+/// Returns the offset of the first frame sync code candidate in `bytes`.
+///
+/// A candidate is a 0xff byte followed by 0xf8 or 0xf9: the 14-bit sync code,
+/// a reserved zero bit, and the blocking strategy bit. A 0xff as the last
+/// byte is a candidate too, because the byte after it is unknown. The sync
+/// code may occur by chance in the middle of a frame, so a candidate is not
+/// necessarily a frame header; use `read_frame_info()` to validate it. This is
+/// useful to find frames in damaged data, or in a stream without header.
+pub fn find_frame_sync(bytes: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while let Some(i) = find_ff(&bytes[start..]) {
+        let pos = start + i;
+        match bytes.get(pos + 1) {
+            Some(&next) if next & 0b1111_1110 != 0b1111_1000 => start = pos + 1,
+            _ => return Some(pos),
+        }
+    }
+    None
+}
+
+/// Returns the offset of the first 0xff byte in `bytes`.
+///
+/// Most of the audio data contains no 0xff byte at all, so this is where
+/// `find_frame_sync()` spends its time. With the `memchr` feature, it uses
+/// the vectorized search of the `memchr` crate.
+#[cfg(feature = "memchr")]
+#[inline]
+fn find_ff(bytes: &[u8]) -> Option<usize> {
+    ::memchr::memchr(0xff, bytes)
+}
+
+/// Returns the offset of the first 0xff byte in `bytes`.
+#[cfg(not(feature = "memchr"))]
+#[inline]
+fn find_ff(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().position(|&b| b == 0xff)
+}
+
+#[test]
+fn verify_find_frame_sync() {
+    assert_eq!(find_frame_sync(&[]), None);
+    assert_eq!(find_frame_sync(&[0xff, 0xf8, 0x69]), Some(0));
+    assert_eq!(find_frame_sync(&[0x00, 0xff, 0xf9]), Some(1));
+    // The reserved bit must be zero, and 0xff 0xff is no sync code.
+    assert_eq!(find_frame_sync(&[0xff, 0xfa, 0xff, 0xff, 0x00]), None);
+    assert_eq!(find_frame_sync(&[0x12, 0xff]), Some(1));
+
+    // Candidates are found anywhere, also after a 0xff that is no candidate.
+    let mut bytes = vec![0u8; 100];
+    bytes[31] = 0xff;
+    bytes[32] = 0xf8;
+    assert_eq!(find_frame_sync(&bytes), Some(31));
+    bytes[31] = 0;
+    bytes[70] = 0xff;
+    assert_eq!(find_frame_sync(&bytes), None);
+    bytes[71] = 0xf9;
+    assert_eq!(find_frame_sync(&bytes), Some(70));
+}
+
+fn frame_info(header: &FrameHeader, fixed_block_size: u16) -> FrameInfo {
+    FrameInfo {
+        block_time: header.block_time,
+        first_sample: first_sample_number(header, fixed_block_size),
+        block_size: header.block_size,
+        channels: header.channels() as u32,
+        channel_assignment: header.channel_assignment,
+        sample_rate: header.sample_rate,
+        bits_per_sample: header.bits_per_sample,
+    }
 }
 
 fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameHeader>> {
+    match try!(read_frame_header_unverified_or_eof(input)) {
+        Some((_, computed_crc, presumed_crc)) if computed_crc != presumed_crc => {
+            fmt_err("frame header CRC mismatch")
+        }
+        Some((header, _, _)) => Ok(Some(header)),
+        None => Ok(None),
+    }
+}
+
+/// Reads a frame header, and returns it with the computed and the stored CRC-8.
+fn read_frame_header_unverified_or_eof<R: ReadBytes>(input: &mut R)
+                                                     -> Result<Option<(FrameHeader, u8, u8)>> {
     // The frame header includes a CRC-8 at the end. It can be computed
     // automatically while reading, by wrapping the input reader in a reader
     // that computes the CRC.
@@ -145,6 +375,13 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
     // The first 14 bits must be 11111111111110.
     let sync_code = sync_res_block & 0b1111_1111_1111_1100;
     if sync_code != 0b1111_1111_1111_1000 {
+        // Some files have an ID3v1 or APEv2 tag appended after the last frame,
+        // even though the format does not allow this. If the stream continues
+        // with such a tag, treat it as the end of the stream, rather than
+        // failing to decode a file that is otherwise fine.
+        if try!(is_trailing_tag(&mut crc_input, sync_res_block)) {
+            return Ok(None);
+        }
         return fmt_err("frame sync code missing");
     }
 
@@ -154,6 +391,7 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
     // the spec that are not implemented as `Error::Unsupported`, and this is
     // a format error.
     if sync_res_block & 0b0000_0000_0000_0010 != 0 {
+        trace!(Reserved("frame header bit after sync code"));
         return fmt_err("invalid frame header, encountered reserved value");
     }
 
@@ -174,7 +412,10 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
     // header instead'.
     match bs_sr >> 4 {
         // The value 0000 is reserved.
-        0b0000 => return fmt_err("invalid frame header, encountered reserved value"),
+        0b0000 => {
+            trace!(Reserved("frame header block size"));
+            return fmt_err("invalid frame header, encountered reserved value");
+        }
         0b0001 => block_size = 192,
         n if 0b0010 <= n && n <= 0b0101 => block_size = 576 * (1 << (n - 2) as usize),
         0b0110 => read_8bit_bs = true,
@@ -221,7 +462,10 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         0b1001 => ChannelAssignment::RightSideStereo,
         0b1010 => ChannelAssignment::MidSideStereo,
         // Values 1011 through 1111 are reserved and thus invalid.
-        _ => return fmt_err("invalid frame header, encountered reserved value"),
+        _ => {
+            trace!(Reserved("frame header channel assignment"));
+            return fmt_err("invalid frame header, encountered reserved value");
+        }
     };
 
     // The next three bits indicate bits per sample.
@@ -232,12 +476,17 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         0b100 => Some(16),
         0b101 => Some(20),
         0b110 => Some(24),
-        // Values 011 and 111 are reserved. Other values are impossible.
-        _ => return fmt_err("invalid frame header, encountered reserved value"),
+        0b111 => Some(32),
+        // Value 011 is reserved. Other values are impossible.
+        _ => {
+            trace!(Reserved("frame header sample size"));
+            return fmt_err("invalid frame header, encountered reserved value");
+        }
     };
 
     // The final bit has a mandatory value of 0, it is a reserved bit.
     if chan_bps_res & 0b0000_0001 != 0 {
+        trace!(Reserved("frame header bit after sample size"));
         return fmt_err("invalid frame header, encountered reserved value");
     }
 
@@ -292,10 +541,6 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
     let computed_crc = crc_input.crc();
     let presumed_crc = try!(crc_input.read_u8());
 
-    if computed_crc != presumed_crc {
-        return fmt_err("frame header CRC mismatch");
-    }
-
     let frame_header = FrameHeader {
         block_time: block_time,
         block_size: block_size,
@@ -303,7 +548,178 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         channel_assignment: channel_assignment,
         bits_per_sample: bits_per_sample,
     };
-    Ok(Some(frame_header))
+    Ok(Some((frame_header, computed_crc, presumed_crc)))
+}
+
+/// Decodes the subframes of all channels into `buffer`, and undoes channel decorrelation.
+///
+/// The buffer holds `block_size` samples for every channel, the channels
+/// stored consecutively. The number of wasted bits of every subframe is stored
+/// in `wasted_bits`. If decoding fails, `decoded` is set to the number of
+/// inter-channel samples at the start of the block that were decoded in every
+/// channel, and those samples are moved to the start of the buffer, the
+/// channels stored consecutively with `decoded` samples each. Returns the
+/// number of subframes of every kind, indexed by `SubframeKind`.
+fn decode_subframes<R: ReadBytes>(input: &mut Bitstream<R>,
+                                  channel_assignment: ChannelAssignment,
+                                  bps: u32,
+                                  buffer: &mut [i32],
+                                  decoded: &mut usize,
+                                  wasted_bits: &mut [u8; 8],
+                                  mut infos: Option<&mut Vec<SubframeInfo>>)
+                                  -> Result<[u32; 4]> {
+    let n_channels = match channel_assignment {
+        ChannelAssignment::Independent(n) => n as usize,
+        _ => 2,
+    };
+    let bs = buffer.len() / n_channels;
+
+    // With 32 bits per sample, the side channel takes 33 bits, so it does
+    // not fit in the buffer. It is decoded separately then.
+    let mut wide_side = None;
+    let mut kinds = [0; 4];
+
+    for ch in 0..n_channels {
+        // The side channel has one extra bit per sample. For left-side and
+        // mid-side the side channel comes second, for right-side it is first.
+        let ch_bps = match (channel_assignment, ch) {
+            (ChannelAssignment::LeftSideStereo, 1) => bps + 1,
+            (ChannelAssignment::RightSideStereo, 0) => bps + 1,
+            (ChannelAssignment::MidSideStereo, 1) => bps + 1,
+            _ => bps,
+        };
+
+        if ch_bps > 32 {
+            let mut side = vec![0; bs];
+            let result = subframe::decode_wide(input,
+                                               ch_bps,
+                                               &mut side,
+                                               infos.as_mut().map(|v| &mut **v));
+            match result {
+                Ok((wasted, kind)) => {
+                    wasted_bits[ch] = wasted as u8;
+                    kinds[kind as usize] += 1;
+                }
+                Err(err) => {
+                    *decoded = 0;
+                    return Err(err)
+                }
+            }
+            wide_side = Some(side);
+            continue
+        }
+
+        let mut n = 0;
+        let result = subframe::decode_with_info(input,
+                                                ch_bps,
+                                                &mut buffer[ch * bs..(ch + 1) * bs],
+                                                &mut n,
+                                                infos.as_mut().map(|v| &mut **v));
+        match result {
+            Ok((wasted, kind)) => {
+                wasted_bits[ch] = wasted as u8;
+                kinds[kind as usize] += 1;
+            }
+            Err(err) => {
+                // Channels that come after the failed one have not been decoded
+                // at all, so only if the last channel failed, there is a part of
+                // the block that is complete. Move it together.
+                *decoded = if ch + 1 == n_channels && wide_side.is_none() { n } else { 0 };
+                let n = *decoded;
+                for c in 1..n_channels {
+                    for i in 0..n {
+                        buffer[c * n + i] = buffer[c * bs + i];
+                    }
+                }
+                decorrelate(channel_assignment, &mut buffer[..n * n_channels]);
+                return Err(err)
+            }
+        }
+    }
+
+    match wide_side {
+        Some(side) => decorrelate_wide(channel_assignment, &side, buffer),
+        None => decorrelate(channel_assignment, buffer),
+    }
+    Ok(kinds)
+}
+
+#[test]
+fn verify_decode_subframes_partial() {
+    use input::BufferedReader;
+
+    // Two verbatim 8-bit subframes of 4 samples, where the second one is cut
+    // off after two samples.
+    let data = io::Cursor::new(vec![0x02, 1, 2, 3, 4, 0x02, 10, 11]);
+    let mut bits = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = vec![0; 8];
+    let mut decoded = 0;
+    let mut wasted_bits = [0; 8];
+    let result = decode_subframes(&mut bits, ChannelAssignment::Independent(2), 8,
+                                  &mut buffer, &mut decoded, &mut wasted_bits, None);
+    match result {
+        Err(Error::IoError(ref err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+        _ => panic!("expected unexpected EOF"),
+    }
+    assert_eq!(decoded, 2);
+    assert_eq!(&buffer[..4], &[1, 2, 10, 11]);
+}
+
+/// Converts the side channel, if there is one, into a left or right channel.
+fn decorrelate(channel_assignment: ChannelAssignment, buffer: &mut [i32]) {
+    match channel_assignment {
+        ChannelAssignment::Independent(..) => {}
+        ChannelAssignment::LeftSideStereo => decode_left_side(buffer),
+        ChannelAssignment::RightSideStereo => decode_right_side(buffer),
+        ChannelAssignment::MidSideStereo => decode_mid_side(buffer),
+    }
+}
+
+/// Converts a 33-bit side channel into a left or right channel in `buffer`.
+///
+/// The buffer holds the other channel first, and then room for the channel
+/// that is computed from the side channel, in that order for left-side and
+/// mid-side stereo. For right-side stereo, the right channel comes second.
+fn decorrelate_wide(channel_assignment: ChannelAssignment, side: &[i64], buffer: &mut [i32]) {
+    let (fst, snd) = buffer.split_at_mut(side.len());
+    match channel_assignment {
+        ChannelAssignment::Independent(..) => {}
+        ChannelAssignment::LeftSideStereo => {
+            for ((left, right), &side) in fst.iter().zip(snd.iter_mut()).zip(side) {
+                *right = (*left as i64 - side) as i32;
+            }
+        }
+        ChannelAssignment::RightSideStereo => {
+            for ((left, right), &side) in fst.iter_mut().zip(snd.iter()).zip(side) {
+                *left = (side + *right as i64) as i32;
+            }
+        }
+        ChannelAssignment::MidSideStereo => {
+            for ((fst, snd), &side) in fst.iter_mut().zip(snd.iter_mut()).zip(side) {
+                let mid = (*fst as i64) << 1 | (side & 1);
+                *fst = ((mid + side) >> 1) as i32;
+                *snd = ((mid - side) >> 1) as i32;
+            }
+        }
+    }
+}
+
+#[test]
+fn verify_decorrelate_wide() {
+    // Full-scale 32-bit samples, where the difference needs 33 bits.
+    let (left, right) = (i32::max_value() as i64, i32::min_value() as i64);
+
+    let mut buffer = vec![left as i32, 0];
+    decorrelate_wide(ChannelAssignment::LeftSideStereo, &[left - right], &mut buffer);
+    assert_eq!(buffer, vec![left as i32, right as i32]);
+
+    let mut buffer = vec![0, right as i32];
+    decorrelate_wide(ChannelAssignment::RightSideStereo, &[left - right], &mut buffer);
+    assert_eq!(buffer, vec![left as i32, right as i32]);
+
+    let mut buffer = vec![((left + right) >> 1) as i32, 0];
+    decorrelate_wide(ChannelAssignment::MidSideStereo, &[left - right], &mut buffer);
+    assert_eq!(buffer, vec![left as i32, right as i32]);
 }
 
 /// Converts a buffer with left samples and a side channel in-place to left ++ right.
@@ -390,6 +806,14 @@ fn verify_decode_mid_side() {
 }
 
 /// A block of raw audio samples.
+///
+/// A block owns its samples; it does not borrow from the `FrameReader` that
+/// produced it. To avoid an allocation for every frame, the buffer of a block
+/// can be handed back to the frame reader with `into_buffer()`, and the next
+/// frame is then decoded into it. Only the block that gave up its buffer is
+/// affected by that. To keep decoded blocks around, for instance in a cache,
+/// either do not recycle their buffers, or recycle the buffer of a `clone()`.
+#[derive(Clone, Debug)]
 pub struct Block {
     /// The sample number of the first sample in the this block.
     first_sample_number: u64,
@@ -399,15 +823,26 @@ pub struct Block {
     channels: u32,
     /// The decoded samples, the channels stored consecutively.
     buffer: Vec<i32>,
+    /// The number of wasted bits per sample of every subframe.
+    wasted_bits: [u8; 8],
+    /// How the channels were coded in the frame.
+    channel_assignment: ChannelAssignment,
 }
 
 impl Block {
-    fn new(time: u64, bs: u32, buffer: Vec<i32>) -> Block {
+    fn new(time: u64,
+           bs: u32,
+           buffer: Vec<i32>,
+           wasted_bits: [u8; 8],
+           channel_assignment: ChannelAssignment)
+           -> Block {
         Block {
             first_sample_number: time,
             block_size: bs,
             channels: buffer.len() as u32 / bs,
             buffer: buffer,
+            wasted_bits: wasted_bits,
+            channel_assignment: channel_assignment,
         }
     }
 
@@ -418,6 +853,8 @@ impl Block {
             block_size: 0,
             channels: 0,
             buffer: Vec::with_capacity(0),
+            wasted_bits: [0; 8],
+            channel_assignment: ChannelAssignment::Independent(0),
         }
     }
 
@@ -471,6 +908,61 @@ impl Block {
         &self.buffer[ch_usz * bsz..(ch_usz + 1) * bsz]
     }
 
+    /// Returns the (zero-based) `ch`-th channel as a mutable slice.
+    ///
+    /// This allows processing the samples in place, for instance to apply
+    /// gain, before the buffer is reused with `into_buffer()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ch >= channels()`.
+    #[inline(always)]
+    pub fn channel_mut(&mut self, ch: u32) -> &mut [i32] {
+        assert!(ch < self.channels);
+        let bsz = self.block_size as usize;
+        let ch_usz = ch as usize;
+        &mut self.buffer[ch_usz * bsz..(ch_usz + 1) * bsz]
+    }
+
+    /// Returns an iterator over all channels, each as a slice.
+    ///
+    /// This yields the same slices as calling `channel()` for every channel
+    /// in order.
+    pub fn channel_slices<'a>(&'a self) -> slice::Chunks<'a, i32> {
+        // A chunk size of 0 is not allowed, and an empty block has no channels
+        // anyway.
+        let bsz = cmp::max(self.block_size as usize, 1);
+        self.buffer[..self.len() as usize].chunks(bsz)
+    }
+
+    /// Returns an iterator over all channels, each as a mutable slice.
+    ///
+    /// Unlike `channel_mut()`, this gives access to several channels at once,
+    /// for instance to swap or mix them.
+    pub fn channel_slices_mut<'a>(&'a mut self) -> slice::ChunksMut<'a, i32> {
+        let bsz = cmp::max(self.block_size as usize, 1);
+        let len = self.len() as usize;
+        self.buffer[..len].chunks_mut(bsz)
+    }
+
+    /// Converts the block into one vector of samples per channel.
+    ///
+    /// The buffer of the block is reused for the first channel.
+    pub fn into_channels(self) -> Vec<Vec<i32>> {
+        let bsz = self.block_size as usize;
+        let mut channels = Vec::with_capacity(self.channels as usize);
+        if self.channels == 0 {
+            return channels
+        }
+        for ch in 1..self.channels {
+            channels.push(self.channel(ch).to_vec());
+        }
+        let mut first = self.buffer;
+        first.truncate(bsz);
+        channels.insert(0, first);
+        channels
+    }
+
     /// Returns a sample in this block.
     ///
     /// The value returned is for the zero-based `ch`-th channel of the
@@ -487,6 +979,96 @@ impl Block {
         return self.buffer[ch as usize * bsz + sample as usize];
     }
 
+    /// Returns the number of wasted bits per sample in the subframe of channel `ch`.
+    ///
+    /// The encoder signals wasted bits when the least significant bits of all
+    /// samples in a subframe are zero, for instance in a 24-bit stream that
+    /// contains 16-bit audio. For stereo blocks, the count applies to the
+    /// subframes as stored, which may be a side channel rather than the left
+    /// or right channel. See also `effective_bits_per_sample()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ch >= channels()`.
+    pub fn wasted_bits(&self, ch: u32) -> u32 {
+        assert!(ch < self.channels);
+        self.wasted_bits[ch as usize] as u32
+    }
+
+    /// Returns how the channels were coded in the frame that this block was decoded from.
+    ///
+    /// Stereo frames can be coded as left and right, but also as left/side,
+    /// right/side, or mid/side, whichever the encoder found to compress best.
+    /// The samples in the block are always decorrelated back into left and
+    /// right. For an empty block, this is `Independent(0)`.
+    pub fn channel_assignment(&self) -> ChannelAssignment {
+        self.channel_assignment
+    }
+
+    /// Returns the number of significant bits per sample in this block.
+    ///
+    /// This is `bits_per_sample`, the bit depth of the stream, minus the wasted
+    /// bits that all subframes have in common. The decoded samples are zero in
+    /// all of the less significant bits. Note that an encoder is not required
+    /// to detect wasted bits, so the audio may carry fewer significant bits
+    /// than this.
+    pub fn effective_bits_per_sample(&self, bits_per_sample: u32) -> u32 {
+        let wasted = self.wasted_bits[..self.channels as usize].iter().cloned().min();
+        bits_per_sample.saturating_sub(wasted.unwrap_or(0) as u32)
+    }
+
+    /// Copies the samples into `out`, with the channels interleaved.
+    ///
+    /// The first `len()` elements of `out` are overwritten, the rest is left
+    /// untouched. See `interleaved()` for an iterator instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than `len()`.
+    pub fn copy_interleaved(&self, out: &mut [i32]) {
+        assert!(out.len() >= self.len() as usize,
+                "output buffer must hold at least len() samples");
+        let n_channels = self.channels as usize;
+        let len = self.len() as usize;
+        for (ch, samples) in self.channel_slices().enumerate() {
+            for (frame, &s) in out[..len].chunks_mut(n_channels).zip(samples) {
+                frame[ch] = s;
+            }
+        }
+    }
+
+    /// Returns an iterator over the samples, with the channels interleaved.
+    pub fn interleaved<'a>(&'a self) -> InterleavedSamples<'a> {
+        InterleavedSamples {
+            block: self,
+            index: 0,
+        }
+    }
+
+    /// Converts the samples to interleaved floating point, in the range [-1.0, 1.0).
+    ///
+    /// `out` is cleared, and then filled with `len()` samples, the channels
+    /// interleaved. The samples are scaled by the bit depth of the stream, see
+    /// `SamplesF32::new()`. Pass the same vector for every block to avoid an
+    /// allocation every time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_sample` is not between 1 and 32.
+    pub fn interleaved_f32_into(&self, bits_per_sample: u32, out: &mut Vec<f32>) {
+        assert!(bits_per_sample >= 1 && bits_per_sample <= 32,
+                "bits_per_sample must be between 1 and 32");
+        let scale = 1.0 / (1u64 << (bits_per_sample - 1)) as f32;
+        let bsz = self.block_size as usize;
+        out.clear();
+        out.reserve(self.len() as usize);
+        for i in 0..bsz {
+            for ch in 0..self.channels as usize {
+                out.push(self.buffer[ch * bsz + i] as f32 * scale);
+            }
+        }
+    }
+
     /// Returns the underlying buffer that stores the samples in this block.
     ///
     /// This allows the buffer to be reused to decode the next frame. The
@@ -526,6 +1108,8 @@ fn verify_block_sample() {
         block_size: 5,
         channels: 3,
         buffer: vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47],
+        wasted_bits: [0; 8],
+        channel_assignment: ChannelAssignment::Independent(3),
     };
 
     assert_eq!(block.sample(0, 2), 5);
@@ -533,6 +1117,40 @@ fn verify_block_sample() {
     assert_eq!(block.sample(2, 4), 47);
 }
 
+#[test]
+fn verify_block_channels() {
+    let mut block = Block {
+        first_sample_number: 0,
+        block_size: 3,
+        channels: 2,
+        buffer: vec![2, 3, 5, 7, 11, 13, 17],
+        wasted_bits: [0; 8],
+        channel_assignment: ChannelAssignment::Independent(2),
+    };
+
+    {
+        let slices: Vec<&[i32]> = block.channel_slices().collect();
+        assert_eq!(slices, [&[2, 3, 5][..], &[7, 11, 13][..]]);
+    }
+
+    for s in block.channel_mut(1) {
+        *s *= 2;
+    }
+    {
+        let mut channels = block.channel_slices_mut();
+        let left = channels.next().unwrap();
+        let right = channels.next().unwrap();
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            mem::swap(l, r);
+        }
+        assert!(channels.next().is_none());
+    }
+    assert_eq!(block.into_channels(), [vec![14, 22, 26], vec![2, 3, 5]]);
+
+    assert_eq!(Block::empty().channel_slices().count(), 0);
+    assert!(Block::empty().into_channels().is_empty());
+}
+
 /// An iterator over the stereo sample pairs in a block.
 ///
 /// This iterator is produced by `Block::stereo_samples()`.
@@ -570,6 +1188,60 @@ impl<'a> Iterator for StereoSamples<'a> {
     }
 }
 
+/// An iterator over the samples in a block, with the channels interleaved.
+///
+/// This iterator is produced by `Block::interleaved()`.
+pub struct InterleavedSamples<'a> {
+    block: &'a Block,
+    /// The index of the next sample, in interleaved order.
+    index: u32,
+}
+
+impl<'a> Iterator for InterleavedSamples<'a> {
+    type Item = i32;
+
+    #[inline]
+    fn next(&mut self) -> Option<i32> {
+        if self.index == self.block.len() {
+            None
+        } else {
+            let channels = self.block.channels;
+            let sample = self.block.sample(self.index % channels, self.index / channels);
+            self.index += 1;
+            Some(sample)
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.block.len() - self.index) as usize;
+        (n, Some(n))
+    }
+}
+
+impl<'a> ExactSizeIterator for InterleavedSamples<'a> {}
+
+#[test]
+fn verify_block_interleaved() {
+    let block = Block {
+        first_sample_number: 0,
+        block_size: 3,
+        channels: 2,
+        buffer: vec![2, 3, 5, 7, 11, 13, 17],
+        wasted_bits: [0; 8],
+        channel_assignment: ChannelAssignment::Independent(2),
+    };
+
+    let mut out = [0; 7];
+    block.copy_interleaved(&mut out);
+    assert_eq!(out, [2, 7, 3, 11, 5, 13, 0]);
+
+    let samples: Vec<i32> = block.interleaved().collect();
+    assert_eq!(samples, [2, 7, 3, 11, 5, 13]);
+    assert_eq!(block.interleaved().len(), 6);
+    assert_eq!(Block::empty().interleaved().next(), None);
+}
+
 #[test]
 fn verify_block_stereo_samples_iterator() {
     let block = Block {
@@ -577,6 +1249,8 @@ fn verify_block_stereo_samples_iterator() {
         block_size: 3,
         channels: 2,
         buffer: vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47],
+        wasted_bits: [0; 8],
+        channel_assignment: ChannelAssignment::Independent(2),
     };
 
     let mut iter = block.stereo_samples();
@@ -593,6 +1267,42 @@ fn verify_block_stereo_samples_iterator() {
 /// no searching for a sync code is performed at the moment.
 pub struct FrameReader<R: ReadBytes> {
     input: R,
+    /// The block size of a fixed-blocksize stream, or 0 if unknown.
+    fixed_block_size: u16,
+    /// Whether a CRC-16 mismatch is an error.
+    verify_crc: bool,
+    /// Whether a CRC-8 mismatch in the frame header is an error.
+    verify_header_crc: bool,
+    /// The checksum that did not match, if that made the last frame fail.
+    crc_mismatch: Option<CrcMismatch>,
+    /// Whether to return the decoded part of a frame that was cut off.
+    salvage_partial: bool,
+    /// The largest block size to accept, in inter-channel samples.
+    max_block_size: u16,
+    /// The number of channels that every frame must have, or 0 for any.
+    channels: u32,
+    /// Set when reading failed while iterating, to end the iteration.
+    has_failed: bool,
+    /// Counters of what was read, when the `metrics` feature is enabled.
+    metrics: Metrics,
+}
+
+/// What `FrameReader::decode_next()` decoded, apart from the samples.
+struct DecodedFrame {
+    info: FrameInfo,
+    /// The number of inter-channel samples decoded.
+    duration: u32,
+    wasted_bits: [u8; 8],
+    /// The number of bytes read, or 0 for a partial frame.
+    len: u32,
+}
+
+/// The buffer to decode a frame into.
+enum DecodeBuffer<'a> {
+    /// A vector that is grown to fit the block.
+    Growable(&'a mut Vec<i32>),
+    /// A slice provided by the caller, which must be large enough.
+    Fixed(&'a mut [i32]),
 }
 
 /// Either a `Block` or an `Error`.
@@ -625,11 +1335,92 @@ fn ensure_buffer_len(mut buffer: Vec<i32>, new_len: usize) -> Vec<i32> {
 impl<R: ReadBytes> FrameReader<R> {
     /// Creates a new frame reader that will yield at least one element.
     pub fn new(input: R) -> FrameReader<R> {
+        FrameReader::with_fixed_block_size(input, 0)
+    }
+
+    /// Creates a new frame reader for a stream with the given fixed block size.
+    ///
+    /// In a fixed-blocksize stream, frame headers contain a frame number
+    /// rather than a sample number, and the first sample number of a frame is
+    /// the frame number times the block size. Because the last frame may be
+    /// shorter, the block size must be taken from the streaminfo block, where
+    /// the minimum and maximum block size are equal for such streams. Pass 0
+    /// if the block size is not known, to use the block size of the frame.
+    pub fn with_fixed_block_size(input: R, block_size: u16) -> FrameReader<R> {
         FrameReader {
             input: input,
+            fixed_block_size: block_size,
+            verify_crc: true,
+            verify_header_crc: true,
+            crc_mismatch: None,
+            salvage_partial: false,
+            max_block_size: MAX_BLOCK_SIZE,
+            channels: 0,
+            has_failed: false,
+            metrics: Metrics::new(),
         }
     }
 
+    /// Sets whether a frame with a CRC-16 that does not match is an error.
+    ///
+    /// This is on by default. When turned off, such frames are returned as
+    /// decoded, which can help to recover audio from a damaged stream. The
+    /// CRC-16 is then not computed at all, which saves some time.
+    pub fn set_verify_crc(&mut self, verify_crc: bool) {
+        self.verify_crc = verify_crc;
+    }
+
+    /// Sets whether a frame header with a CRC-8 that does not match is an error.
+    ///
+    /// This is on by default. When turned off, a damaged header is decoded
+    /// nonetheless, but note that a damaged header is likely to make the rest
+    /// of the frame fail to decode, or decode into garbage.
+    pub fn set_verify_header_crc(&mut self, verify_header_crc: bool) {
+        self.verify_header_crc = verify_header_crc;
+    }
+
+    /// Returns the checksum mismatch that made the last frame fail to decode.
+    ///
+    /// When reading a frame fails with a CRC mismatch error, this returns the
+    /// details of the mismatch. After reading a frame that did not fail on a
+    /// checksum, it returns `None`.
+    pub fn crc_mismatch(&self) -> Option<CrcMismatch> {
+        self.crc_mismatch
+    }
+
+    /// Sets whether to return the decoded part of a frame that was cut off.
+    ///
+    /// This is off by default, and then a stream that ends in the middle of a
+    /// frame is an error. When turned on, the samples up to the point where
+    /// the stream ended are returned as a shorter block instead, if any
+    /// samples could be decoded in every channel. The CRC-16 of such a block
+    /// cannot be verified. The stream ending is still reported as an error if
+    /// not even one sample could be decoded.
+    pub fn set_salvage_partial(&mut self, salvage_partial: bool) {
+        self.salvage_partial = salvage_partial;
+    }
+
+    /// Sets the largest block size to accept, in inter-channel samples.
+    ///
+    /// A frame with a larger block size is an error, which is detected before
+    /// the decode buffer is grown. By default the limit is `MAX_BLOCK_SIZE`,
+    /// the largest block size that a frame header can express. Pass the
+    /// maximum block size from the streaminfo to bound the memory used for
+    /// decoding by what the stream declares.
+    pub fn set_max_block_size(&mut self, max_block_size: u16) {
+        self.max_block_size = max_block_size;
+    }
+
+    /// Sets the number of channels that every frame must have.
+    ///
+    /// A frame with a different number of channels is an error. By default
+    /// this is 0, and any number of channels is accepted. Pass the number of
+    /// channels from the streaminfo, to guarantee that every block has that
+    /// many channels, so code that processes blocks can rely on it.
+    pub fn set_channels(&mut self, channels: u32) {
+        self.channels = channels;
+    }
+
     /// Decodes the next frame or returns an error if the data was invalid.
     ///
     /// The buffer is moved into the returned block, so that the same buffer may
@@ -638,80 +1429,204 @@ impl<R: ReadBytes> FrameReader<R> {
     /// buffer is not large enough to hold all samples, a larger buffer is
     /// allocated automatically.
     ///
+    /// Returns `None` if the stream ends cleanly before the next frame. If the
+    /// stream ends in the middle of a frame, that is an `Error::IoError` of
+    /// kind `UnexpectedEof` instead.
+    ///
     /// TODO: I should really be consistent with 'read' and 'decode'.
-    pub fn read_next_or_eof(&mut self, mut buffer: Vec<i32>) -> FrameResult {
+    pub fn read_next_or_eof(&mut self, buffer: Vec<i32>) -> FrameResult {
+        Ok(try!(self.read_next_with_info_or_eof(buffer)).map(|(_, block)| block))
+    }
+
+    /// Decodes the next frame, and returns its header along with the block.
+    ///
+    /// This is like `read_next_or_eof()`, but it also returns what the frame
+    /// header contains, for tools that inspect the stream. To find the byte
+    /// offset at which the frame starts, ask the input for its position before
+    /// reading the frame, for example `get_ref().position()` for the reader
+    /// returned by `FlacReader::blocks()`.
+    pub fn read_next_with_info_or_eof(&mut self,
+                                      mut buffer: Vec<i32>)
+                                      -> Result<Option<(FrameInfo, Block)>> {
+        match try!(self.decode_next(DecodeBuffer::Growable(&mut buffer), None)) {
+            Some(frame) => {
+                // A salvaged partial block is stored at the start of the buffer.
+                buffer.truncate(frame.duration as usize * frame.info.channels as usize);
+                let block = Block::new(frame.info.first_sample, frame.duration, buffer,
+                                       frame.wasted_bits, frame.info.channel_assignment);
+                Ok(Some((frame.info, block)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the next frame, and returns how it was encoded along with the block.
+    ///
+    /// This is like `read_next_with_info_or_eof()`, but it also reports the
+    /// prediction method, predictor order, Rice parameters, LPC coefficients,
+    /// and residual of every subframe, and the size of the frame in bytes.
+    /// This is for tools that analyze streams or compare encoders. Copying
+    /// the residual makes decoding slower.
+    pub fn read_next_with_report_or_eof(&mut self,
+                                        mut buffer: Vec<i32>)
+                                        -> Result<Option<(FrameReport, Block)>> {
+        let mut subframes = Vec::new();
+        match try!(self.decode_next(DecodeBuffer::Growable(&mut buffer), Some(&mut subframes))) {
+            Some(frame) => {
+                buffer.truncate(frame.duration as usize * frame.info.channels as usize);
+                let block = Block::new(frame.info.first_sample, frame.duration, buffer,
+                                       frame.wasted_bits, frame.info.channel_assignment);
+                let report = FrameReport {
+                    info: frame.info,
+                    subframes: subframes,
+                    len: frame.len,
+                };
+                Ok(Some((report, block)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the next frame into a buffer provided by the caller, without allocating.
+    ///
+    /// This is for environments where heap allocation is unavailable or
+    /// undesirable. Size the buffer for the largest block, the maximum block
+    /// size times the number of channels, for instance from compile-time
+    /// constants, and limit frames to that with `set_max_block_size()` and
+    /// `set_channels()`. A frame that does not fit in the buffer is an
+    /// `Error::BufferTooSmall`, which holds the length that the buffer needs.
+    /// The only allocation left is for the side channel of stereo streams with
+    /// 32 bits per sample, which does not fit in 32 bits.
+    ///
+    /// Returns the frame header and the number of inter-channel samples `n`
+    /// that were decoded, which is the block size of the frame unless a
+    /// partial frame was salvaged. Channel `ch` is stored in
+    /// `buffer[ch * n..(ch + 1) * n]`. Returns `None` at the end of the stream.
+    pub fn read_next_into(&mut self, buffer: &mut [i32]) -> Result<Option<(FrameInfo, u32)>> {
+        match try!(self.decode_next(DecodeBuffer::Fixed(buffer), None)) {
+            Some(frame) => Ok(Some((frame.info, frame.duration))),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the next frame into `target`, and appends its subframe parameters to `infos`.
+    ///
+    /// Returns `None` at the end of the stream.
+    #[inline]
+    fn decode_next(&mut self,
+                   target: DecodeBuffer,
+                   infos: Option<&mut Vec<SubframeInfo>>)
+                   -> Result<Option<DecodedFrame>> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("frame").entered();
+        trace!(EnterFrame);
+        let result = self.decode_next_frame(target, infos);
+        trace!(ExitFrame(match result {
+            Ok(Some(ref frame)) => Some(frame.info),
+            _ => None,
+        }));
+        result
+    }
+
+    /// Implements `decode_next()`, apart from tracing.
+    fn decode_next_frame(&mut self,
+                         target: DecodeBuffer,
+                         infos: Option<&mut Vec<SubframeInfo>>)
+                         -> Result<Option<DecodedFrame>> {
         // The frame includes a CRC-16 at the end. It can be computed
         // automatically while reading, by wrapping the input reader in a reader
         // that computes the CRC. If the stream ended before the the frame
         // header (so not in the middle of the frame header), return `None`,
         // indicating EOF.
-        let mut crc_input = Crc16Reader::new(&mut self.input);
-        let header = match try!(read_frame_header_or_eof(&mut crc_input)) {
+        self.crc_mismatch = None;
+        let mut crc_input = if self.verify_crc {
+            Crc16Reader::new(&mut self.input)
+        } else {
+            Crc16Reader::without_crc(&mut self.input)
+        };
+        let header = match try!(read_frame_header_unverified_or_eof(&mut crc_input)) {
             None => return Ok(None),
-            Some(h) => h,
+            Some((header, computed_crc, presumed_crc)) => {
+                if self.verify_header_crc && computed_crc != presumed_crc {
+                    metrics::record_crc_failure(&mut self.metrics, crc_input.bytes_read());
+                    self.crc_mismatch = Some(CrcMismatch {
+                        block_time: header.block_time,
+                        checksum: Checksum::HeaderCrc8,
+                        expected: presumed_crc as u16,
+                        computed: computed_crc as u16,
+                    });
+                    return fmt_err("frame header CRC mismatch");
+                }
+                header
+            }
         };
 
+        // The block size is checked before allocating, so a damaged or crafted
+        // header cannot make us allocate more than the stream declares.
+        if header.block_size > self.max_block_size {
+            return fmt_err("block size exceeds maximum block size in streaminfo");
+        }
+        if self.channels != 0 && header.channels() as u32 != self.channels {
+            return fmt_err("number of channels differs from streaminfo");
+        }
+
         // We must allocate enough space for all channels in the block to be
         // decoded.
         let total_samples = header.channels() as usize * header.block_size as usize;
-        buffer = ensure_buffer_len(buffer, total_samples);
+        let buffer = match target {
+            DecodeBuffer::Growable(vec) => {
+                let owned = mem::replace(vec, Vec::new());
+                *vec = ensure_buffer_len(owned, total_samples);
+                &mut vec[..]
+            }
+            DecodeBuffer::Fixed(slice) => {
+                if slice.len() < total_samples {
+                    return Err(Error::BufferTooSmall { required: total_samples });
+                }
+                &mut slice[..total_samples]
+            }
+        };
 
         let bps = match header.bits_per_sample {
             Some(x) => x,
             // TODO: if the bps is missing from the header, we must get it from
             // the streaminfo block.
-            None => return Err(Error::Unsupported("header without bits per sample info")),
+            None => {
+                let feature = "header without bits per sample info";
+                return Err(Error::Unsupported { feature: feature })
+            }
         };
 
-        // The number of bits per sample must not exceed 32, for we decode into
-        // an i32. TODO: Turn this into an error instead of panic? Or is it
-        // enforced elsewhere?
-        debug_assert!(bps as usize <= 32);
-
         // In the next part of the stream, nothing is byte-aligned any more,
         // we need a bitstream. Then we can decode subframes from the bitstream.
-        {
+        let mut wasted_bits = [0; 8];
+        let kinds = {
             let mut bitstream = Bitstream::new(&mut crc_input);
-            let bs = header.block_size as usize;
-
-            match header.channel_assignment {
-                ChannelAssignment::Independent(n_ch) => {
-                    for ch in 0..n_ch as usize {
-                        try!(subframe::decode(&mut bitstream,
-                                              bps,
-                                              &mut buffer[ch * bs..(ch + 1) * bs]));
-                    }
+            let mut decoded = 0;
+
+            let kinds = match decode_subframes(&mut bitstream,
+                                               header.channel_assignment,
+                                               bps,
+                                               buffer,
+                                               &mut decoded,
+                                               &mut wasted_bits,
+                                               infos) {
+                Ok(kinds) => kinds,
+                Err(Error::IoError(ref err))
+                    if self.salvage_partial && decoded > 0 &&
+                       err.kind() == io::ErrorKind::UnexpectedEof => {
+                    // The stream ended in the middle of the frame, but the
+                    // samples before that could be decoded. The CRC cannot be
+                    // verified, as the footer is missing.
+                    return Ok(Some(DecodedFrame {
+                        info: frame_info(&header, self.fixed_block_size),
+                        duration: decoded as u32,
+                        wasted_bits: wasted_bits,
+                        len: 0,
+                    }))
                 }
-                ChannelAssignment::LeftSideStereo => {
-                    // The side channel has one extra bit per sample.
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream,
-                                          bps + 1,
-                                          &mut buffer[bs..bs * 2]));
-
-                    // Then decode the side channel into the right channel.
-                    decode_left_side(&mut buffer[..bs * 2]);
-                }
-                ChannelAssignment::RightSideStereo => {
-                    // The side channel has one extra bit per sample.
-                    try!(subframe::decode(&mut bitstream, bps + 1, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[bs..bs * 2]));
-
-                    // Then decode the side channel into the left channel.
-                    decode_right_side(&mut buffer[..bs * 2]);
-                }
-                ChannelAssignment::MidSideStereo => {
-                    // Decode mid as the first channel, then side with one
-                    // extra bitp per sample.
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream,
-                                          bps + 1,
-                                          &mut buffer[bs..bs * 2]));
-
-                    // Then decode mid-side channel into left-right.
-                    decode_mid_side(&mut buffer[..bs * 2]);
-                }
-            }
+                Err(err) => return Err(err),
+            };
 
             // When the bitstream goes out of scope, we can use the `input`
             // reader again, which will be byte-aligned. The specification
@@ -719,26 +1634,47 @@ impl<R: ReadBytes> FrameReader<R> {
             // enforce this here.
             // TODO: It could be enforced by having a read_to_byte_aligned
             // method on the bit reader; it'd be a simple comparison.
-        }
+            kinds
+        };
 
         // The frame footer is a 16-bit CRC.
         let computed_crc = crc_input.crc();
         let presumed_crc = try!(crc_input.read_be_u16());
 
-        if computed_crc != presumed_crc {
+        if self.verify_crc && computed_crc != presumed_crc {
+            metrics::record_crc_failure(&mut self.metrics, crc_input.bytes_read());
+            self.crc_mismatch = Some(CrcMismatch {
+                block_time: header.block_time,
+                checksum: Checksum::FrameCrc16,
+                expected: presumed_crc,
+                computed: computed_crc,
+            });
             return fmt_err("frame CRC mismatch");
         }
 
+        metrics::record_frame(&mut self.metrics, crc_input.bytes_read(), &kinds);
+
         // TODO: constant block size should be verified if a frame number is
         // encountered.
-        let time = match header.block_time {
-            BlockTime::FrameNumber(fnr) => header.block_size as u64 * fnr as u64,
-            BlockTime::SampleNumber(snr) => snr,
-        };
+        Ok(Some(DecodedFrame {
+            info: frame_info(&header, self.fixed_block_size),
+            duration: header.block_size as u32,
+            wasted_bits: wasted_bits,
+            len: crc_input.bytes_read(),
+        }))
+    }
 
-        let block = Block::new(time, header.block_size as u32, buffer);
+    /// Returns the counters of everything this reader decoded.
+    ///
+    /// Frames that ended in an error other than a CRC mismatch are not counted.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
 
-        Ok(Some(block))
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.input
     }
 
     /// Destroy the frame reader, returning the wrapped reader.
@@ -747,5 +1683,48 @@ impl<R: ReadBytes> FrameReader<R> {
     }
 }
 
-// TODO: implement Iterator<Item = FrameResult> for FrameReader, with an
-// accurate size hint.
+/// Iterating a frame reader yields the decoded blocks, until the end of the stream.
+///
+/// Every block is decoded into a newly allocated buffer. To reuse a buffer
+/// for all blocks instead, call `read_next_or_eof()` directly. After an
+/// error, the iteration ends.
+impl<R: ReadBytes> Iterator for FrameReader<R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Result<Block>> {
+        if self.has_failed {
+            return None
+        }
+        match self.read_next_or_eof(Vec::new()) {
+            Ok(Some(block)) => Some(Ok(block)),
+            Ok(None) => None,
+            Err(error) => {
+                self.has_failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Decodes a single complete frame from a byte slice.
+///
+/// This is for containers such as Ogg or Matroska, where a demuxer delivers
+/// every frame as a separate packet, so there is no stream to read from. The
+/// streaminfo of the stream, usually delivered in a codec header packet,
+/// determines the first sample number of the block in a fixed-blocksize
+/// stream, and bounds the block size and number of channels, like
+/// `FlacReader::blocks()` does. Like `FrameReader::read_next_or_eof()`, the
+/// buffer is moved into the returned block, so it can be reused.
+///
+/// Data after the end of the frame is ignored. A slice that ends before the
+/// end of the frame is an `Error::IoError` of kind `UnexpectedEof`.
+pub fn decode_frame(frame: &[u8], streaminfo: &StreamInfo, buffer: Vec<i32>) -> Result<Block> {
+    let mut frame_reader = FrameReader::with_fixed_block_size(io::Cursor::new(frame),
+                                                              streaminfo.fixed_block_size());
+    frame_reader.set_max_block_size(streaminfo.max_block_size);
+    frame_reader.set_channels(streaminfo.channels);
+    match try!(frame_reader.read_next_or_eof(buffer)) {
+        Some(block) => Ok(block),
+        None => fmt_err("frame is empty"),
+    }
+}
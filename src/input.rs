@@ -14,6 +14,9 @@
 
 use std::cmp;
 use std::io;
+use std::slice;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
 
 /// Similar to `std::io::BufRead`, but more performant.
 ///
@@ -28,13 +31,44 @@ pub struct BufferedReader<R: io::Read> {
     /// The buffer that holds data read from the inner reader.
     buf: Box<[u8]>,
 
+    /// The start of the buffered bytes.
+    ///
+    /// This points into `buf`, or, for a reader constructed with `from_slice()`,
+    /// into the slice that the inner cursor borrows.
+    data: *const u8,
+
     /// The index of the first byte in the buffer which has not been consumed.
     pos: u32,
 
     /// The number of bytes of the buffer which have meaningful content.
     num_valid: u32,
+
+    /// The offset in the stream of the first byte in the buffer.
+    ///
+    /// Offsets are relative to the position of the inner reader when it was
+    /// wrapped.
+    buf_offset: u64,
+
+    /// Seeks the inner reader relative to its current position, if it can seek.
+    ///
+    /// When set, `skip()` seeks over data that is not buffered, rather than
+    /// reading it.
+    seek_relative: Option<fn(&mut R, i64) -> io::Result<()>>,
+
+    /// Takes up to the given number of bytes from the inner reader, if it
+    /// is backed by memory that outlives it.
+    ///
+    /// When set, the buffer is refilled by pointing `data` at those bytes,
+    /// rather than by copying them into `buf`.
+    borrow: Option<fn(&mut R, usize) -> &[u8]>,
 }
 
+// The buffered bytes are either owned by the reader, or borrowed by the inner
+// reader for at least as long as it lives, so sharing or sending the reader is
+// as safe as sharing or sending the inner reader.
+unsafe impl<R: io::Read + Send> Send for BufferedReader<R> {}
+unsafe impl<R: io::Read + Sync> Sync for BufferedReader<R> {}
+
 impl<R: io::Read> BufferedReader<R> {
 
     /// Wrap the reader in a new buffered reader.
@@ -42,12 +76,102 @@ impl<R: io::Read> BufferedReader<R> {
         let buf = vec![0; 2048].into_boxed_slice();
         BufferedReader {
             inner: inner,
+            data: buf.as_ptr(),
             buf: buf,
             pos: 0,
             num_valid: 0,
+            buf_offset: 0,
+            seek_relative: None,
+            borrow: None,
         }
     }
 
+    /// Returns the offset in the stream of the next byte to be read.
+    ///
+    /// The offset is relative to the position of the inner reader when it was
+    /// wrapped, so it is the number of bytes consumed so far, if there were no
+    /// seeks.
+    pub fn position(&self) -> u64 {
+        self.buf_offset + self.pos as u64
+    }
+
+    /// Returns the buffered bytes, with at least `n` bytes unless the stream ends sooner.
+    ///
+    /// The bytes are not consumed; use `skip()` for that. `n` can be at most
+    /// the size of the buffer, 2048 bytes.
+    pub fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        assert!(n <= self.buf.len());
+        if ((self.num_valid - self.pos) as usize) < n {
+            // Move the remaining bytes to the front, to make room for more.
+            // They may be borrowed, rather than in the buffer, so from here
+            // on the buffer holds them until the next refill.
+            let num_left = (self.num_valid - self.pos) as usize;
+            unsafe {
+                ::std::ptr::copy(self.data.offset(self.pos as isize),
+                                 self.buf.as_mut_ptr(),
+                                 num_left);
+            }
+            self.data = self.buf.as_ptr();
+            self.buf_offset += self.pos as u64;
+            self.pos = 0;
+            self.num_valid = num_left as u32;
+
+            while (self.num_valid as usize) < n {
+                let num_read = try!(self.inner.read(&mut self.buf[self.num_valid as usize..]));
+                if num_read == 0 {
+                    break
+                }
+                self.num_valid += num_read as u32;
+            }
+        }
+        Ok(self.buffer())
+    }
+
+    /// Replaces the contents of the buffer with the next bytes of the inner reader.
+    #[inline]
+    fn refill(&mut self) -> io::Result<()> {
+        self.buf_offset += self.num_valid as u64;
+        self.pos = 0;
+        match self.borrow {
+            Some(borrow) => {
+                // Take at most 1 GiB at a time, so the length fits in a u32.
+                let bytes = borrow(&mut self.inner, 1 << 30);
+                self.data = bytes.as_ptr();
+                self.num_valid = bytes.len() as u32;
+            }
+            None => {
+                self.data = self.buf.as_ptr();
+                self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all bytes in the buffer, consumed or not.
+    #[inline(always)]
+    fn valid_bytes(&self) -> &[u8] {
+        // The first `num_valid` bytes at `data` are initialized, and they live
+        // at least as long as `self`, see the `data` field.
+        unsafe { slice::from_raw_parts(self.data, self.num_valid as usize) }
+    }
+
+    /// Returns the bytes that were read from the inner reader, but not consumed.
+    pub fn buffer(&self) -> &[u8] {
+        &self.valid_bytes()[self.pos as usize..]
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped reader.
+    ///
+    /// Reading from it directly, or seeking it, bypasses the buffer.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
     /// Destroys the buffered reader, returning the wrapped reader.
     ///
     /// Anything in the buffer will be lost.
@@ -56,6 +180,76 @@ impl<R: io::Read> BufferedReader<R> {
     }
 }
 
+/// Seeks `inner` by `delta` bytes, relative to its current position.
+fn seek_relative<R: io::Seek>(inner: &mut R, delta: i64) -> io::Result<()> {
+    try!(inner.seek(io::SeekFrom::Current(delta)));
+    Ok(())
+}
+
+/// Takes up to `n` bytes from the cursor, without copying them.
+fn borrow_from_cursor<'a, 'b>(cursor: &'b mut io::Cursor<&'a [u8]>, n: usize) -> &'b [u8] {
+    let data: &'a [u8] = cursor.get_ref();
+    let start = cmp::min(cursor.position(), data.len() as u64) as usize;
+    let end = start + cmp::min(n, data.len() - start);
+    cursor.set_position(end as u64);
+    &data[start..end]
+}
+
+impl<'a> BufferedReader<io::Cursor<&'a [u8]>> {
+    /// Wrap a slice in a new buffered reader, that reads from the slice directly.
+    ///
+    /// Unlike a reader constructed with `new_seekable()`, the bytes are not
+    /// copied into an intermediate buffer, except for the few that `peek()`
+    /// needs across a refill. The reader seeks like one constructed with
+    /// `new_seekable()`.
+    pub fn from_slice(data: &'a [u8]) -> BufferedReader<io::Cursor<&'a [u8]>> {
+        let mut reader = BufferedReader::new_seekable(io::Cursor::new(data));
+        reader.borrow = Some(borrow_from_cursor);
+        reader
+    }
+}
+
+impl<R: io::Read + io::Seek> BufferedReader<R> {
+    /// Wrap the reader in a new buffered reader, that seeks to skip data.
+    ///
+    /// When more data must be skipped than the buffer holds, the inner reader
+    /// is seeked, so the skipped data need not be read at all.
+    pub fn new_seekable(inner: R) -> BufferedReader<R> {
+        let mut reader = BufferedReader::new(inner);
+        reader.seek_relative = Some(seek_relative::<R>);
+        reader
+    }
+
+    /// Moves to the given offset in the stream, discarding the buffer.
+    ///
+    /// The offset is relative to the position of the inner reader when it was
+    /// wrapped, like the offset returned by `position()`.
+    pub fn seek(&mut self, offset: u64) -> io::Result<()> {
+        // The inner reader is positioned after the end of the buffer.
+        let inner_offset = self.buf_offset + self.num_valid as u64;
+        let delta = offset as i64 - inner_offset as i64;
+        try!(self.inner.seek(io::SeekFrom::Current(delta)));
+        self.buf_offset = offset;
+        self.pos = 0;
+        self.num_valid = 0;
+        Ok(())
+    }
+
+    /// Returns the offset of the end of the stream, relative like `position()`.
+    pub fn stream_len(&mut self) -> io::Result<u64> {
+        let inner_offset = self.buf_offset + self.num_valid as u64;
+        let inner_pos = try!(self.inner.seek(io::SeekFrom::Current(0)));
+        let end_pos = try!(self.inner.seek(io::SeekFrom::End(0)));
+        try!(self.inner.seek(io::SeekFrom::Start(inner_pos)));
+        // After a seek beyond the end, the inner reader is past the end of
+        // the stream, so `end_pos` can be less than `inner_pos`.
+        match (inner_offset + end_pos).checked_sub(inner_pos) {
+            Some(len) => Ok(len),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "stream ends before the position where it was wrapped")),
+        }
+    }
+}
 
 /// Provides convenience methods to make input less cumbersome.
 pub trait ReadBytes {
@@ -123,8 +317,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
     fn read_u8(&mut self) -> io::Result<u8> {
         if self.pos == self.num_valid {
             // The buffer was depleted, replenish it first.
-            self.pos = 0;
-            self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+            try!(self.refill());
 
             if self.num_valid == 0 {
                 return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
@@ -135,7 +328,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
         // At this point there is at least one more byte in the buffer, we
         // checked that above. However, when using regular indexing, the
         // compiler still inserts a bounds check here. It is safe to avoid it.
-        let byte = unsafe { *self.buf.get_unchecked(self.pos as usize) };
+        let byte = unsafe { *self.data.offset(self.pos as isize) };
         self.pos += 1;
         Ok(byte)
     }
@@ -143,8 +336,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
     fn read_u8_or_eof(&mut self) -> io::Result<Option<u8>> {
         if self.pos == self.num_valid {
             // The buffer was depleted, try to replenish it first.
-            self.pos = 0;
-            self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+            try!(self.refill());
 
             if self.num_valid == 0 {
                 return Ok(None);
@@ -161,14 +353,13 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
             let from = buffer.len() - bytes_left;
             let count = cmp::min(bytes_left, (self.num_valid - self.pos) as usize);
             buffer[from..from + count].copy_from_slice(
-                &self.buf[self.pos as usize..self.pos as usize + count]);
+                &self.valid_bytes()[self.pos as usize..self.pos as usize + count]);
             bytes_left -= count;
             self.pos += count as u32;
 
             if bytes_left > 0 {
                 // Replenish the buffer if there is more to be read.
-                self.pos = 0;
-                self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+                try!(self.refill());
                 if self.num_valid == 0 {
                     return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
                                               "Expected more bytes."))
@@ -180,6 +371,20 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
     }
 
     fn skip(&mut self, mut amount: u32) -> io::Result<()> {
+        if let Some(seek_relative) = self.seek_relative {
+            let num_left = self.num_valid - self.pos;
+            if amount > num_left {
+                // Discard the buffer, and seek over the rest. Seeking past the
+                // end is not an error, that only surfaces at the next read.
+                let delta = amount - num_left;
+                try!(seek_relative(&mut self.inner, delta as i64));
+                self.buf_offset += self.num_valid as u64 + delta as u64;
+                self.pos = 0;
+                self.num_valid = 0;
+                return Ok(())
+            }
+        }
+
         while amount > 0 {
             let num_left = self.num_valid - self.pos;
             let read_now = cmp::min(amount, num_left);
@@ -188,8 +393,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
 
             if amount > 0 {
                 // If there is more to skip, refill the buffer first.
-                self.pos = 0;
-                self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+                try!(self.refill());
 
                 if self.num_valid == 0 {
                     return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
@@ -280,6 +484,91 @@ fn verify_read_into_buffered_reader() {
     assert_eq!(&buf2[..], &[7u8, 11, 13, 17, 19]);
 }
 
+#[test]
+fn verify_position_and_seek_buffered_reader() {
+    let data: Vec<u8> = (0..5000u32).map(|x| (x % 251) as u8).collect();
+    let mut cursor = io::Cursor::new(data);
+    cursor.set_position(100);
+
+    // Offsets are relative to where the inner reader was when wrapped.
+    let mut reader = BufferedReader::new(cursor);
+    assert_eq!(reader.position(), 0);
+    reader.skip(3000).unwrap();
+    assert_eq!(reader.position(), 3000);
+    assert_eq!(reader.read_u8().unwrap(), (3100 % 251) as u8);
+    assert_eq!(reader.stream_len().unwrap(), 4900);
+    assert_eq!(reader.position(), 3001);
+
+    reader.seek(10).unwrap();
+    assert_eq!(reader.position(), 10);
+    assert_eq!(reader.read_u8().unwrap(), 110);
+    reader.seek(4000).unwrap();
+    assert_eq!(reader.read_u8().unwrap(), (4100 % 251) as u8);
+    assert_eq!(reader.position(), 4001);
+}
+
+#[test]
+fn verify_skip_seekable_buffered_reader() {
+    let data: Vec<u8> = (0..10_000u32).map(|x| (x % 251) as u8).collect();
+    let mut reader = BufferedReader::new_seekable(io::Cursor::new(data));
+
+    // A skip within the buffer does not seek, a longer one seeks the inner reader.
+    assert_eq!(reader.read_u8().unwrap(), 0);
+    reader.skip(10).unwrap();
+    assert_eq!(reader.read_u8().unwrap(), 11);
+    assert_eq!(reader.get_ref().position(), 2048);
+    reader.skip(5000).unwrap();
+    assert_eq!(reader.position(), 5012);
+    assert_eq!(reader.get_ref().position(), 5012);
+    assert_eq!(reader.read_u8().unwrap(), (5012 % 251) as u8);
+    assert_eq!(reader.position(), 5013);
+}
+
+#[test]
+fn verify_peek_buffered_reader() {
+    let data: Vec<u8> = (0..3000u32).map(|x| (x % 251) as u8).collect();
+    let mut reader = BufferedReader::new(io::Cursor::new(data));
+    reader.skip(2040).unwrap();
+
+    // Peeking across the end of the buffer must not lose or consume bytes.
+    assert_eq!(&reader.peek(16).unwrap()[..3], &[(2040 % 251) as u8, (2041 % 251) as u8,
+                                                 (2042 % 251) as u8]);
+    assert!(reader.peek(16).unwrap().len() >= 16);
+    assert_eq!(reader.position(), 2040);
+    assert_eq!(reader.read_u8().unwrap(), (2040 % 251) as u8);
+    reader.skip(950).unwrap();
+    assert_eq!(reader.peek(16).unwrap(), &[(2991 % 251) as u8, (2992 % 251) as u8,
+                                           (2993 % 251) as u8, (2994 % 251) as u8,
+                                           (2995 % 251) as u8, (2996 % 251) as u8,
+                                           (2997 % 251) as u8, (2998 % 251) as u8,
+                                           (2999 % 251) as u8]);
+    assert_eq!(reader.position(), 2991);
+}
+
+#[test]
+fn verify_from_slice_buffered_reader() {
+    let data: Vec<u8> = (0..5000u32).map(|x| (x % 251) as u8).collect();
+    let mut reader = BufferedReader::from_slice(&data);
+
+    // The bytes are read from the slice itself, not from a copy.
+    assert_eq!(reader.read_u8().unwrap(), 0);
+    assert_eq!(reader.buffer().as_ptr(), data[1..].as_ptr());
+    let mut buf = [0u8; 3];
+    reader.read_into(&mut buf).unwrap();
+    assert_eq!(&buf[..], &[1u8, 2, 3]);
+    reader.skip(3000).unwrap();
+    assert_eq!(reader.position(), 3004);
+    assert_eq!(reader.stream_len().unwrap(), 5000);
+
+    reader.seek(4990).unwrap();
+    assert_eq!(reader.peek(16).unwrap(), &data[4990..]);
+    assert_eq!(reader.read_u8().unwrap(), (4990 % 251) as u8);
+    reader.skip(9).unwrap();
+    assert_eq!(reader.read_u8_or_eof().unwrap(), None);
+    reader.seek(10).unwrap();
+    assert_eq!(reader.read_u8().unwrap(), 10);
+}
+
 #[test]
 fn verify_read_into_cursor() {
     let mut cursor = io::Cursor::new(vec![2u8, 3, 5, 7, 11, 13, 17, 19, 23]);
@@ -381,32 +670,21 @@ fn verify_read_le_u32_cursor() {
     assert!(reader.read_le_u32().is_err());
 }
 
-/// Left shift that does not panic when shifting by the integer width.
-#[inline(always)]
-fn shift_left(x: u8, shift: u32) -> u8 {
-    debug_assert!(shift <= 8);
-
-    // We cannot shift a u8 by 8 or more, because Rust panics when shifting by
-    // the integer width. But we can definitely shift a u32.
-    ((x as u32) << shift) as u8
-}
-
-/// Right shift that does not panic when shifting by the integer width.
-#[inline(always)]
-fn shift_right(x: u8, shift: u32) -> u8 {
-    debug_assert!(shift <= 8);
-
-    // We cannot shift a u8 by 8 or more, because Rust panics when shifting by
-    // the integer width. But we can definitely shift a u32.
-    ((x as u32) >> shift) as u8
-}
-
 /// Wraps a `Reader` to facilitate reading that is not byte-aligned.
+///
+/// Bits are buffered in a 64-bit word, and all reads are shifts and masks on
+/// that word. The word is refilled a byte at a time, and only with the bytes
+/// that a read needs: the bytes are checksummed as they are read from the
+/// underlying reader, and what follows the bitstream, such as the frame
+/// footer, must still be there afterwards. Consequently, fewer than 8 bits
+/// are buffered in between reads, and those are the padding bits when the
+/// bitstream ends.
 pub struct Bitstream<R: ReadBytes> {
     /// The source where bits are read from.
     reader: R,
-    /// Data read from the reader, but not yet fully consumed.
-    data: u8,
+    /// Bits read from the reader but not yet consumed, starting at the most
+    /// significant bit. The bits after those are zero.
+    data: u64,
     /// The number of bits of `data` that have not been consumed.
     bits_left: u32,
 }
@@ -421,83 +699,86 @@ impl<R: ReadBytes> Bitstream<R> {
         }
     }
 
-    /// Generates a bitmask with 1s in the `bits` most significant bits.
+    /// Reads bytes from the reader until at least `bits` bits are buffered.
     #[inline(always)]
-    fn mask_u8(bits: u32) -> u8 {
-        debug_assert!(bits <= 8);
+    fn refill(&mut self, bits: u32) -> io::Result<()> {
+        // At most 7 bits are left in between reads, and reads take at most 32
+        // bits, so the byte always fits in the word.
+        debug_assert!(bits <= 32);
+        while self.bits_left < bits {
+            let byte = try!(self.reader.read_u8()) as u64;
+            self.data = self.data | byte << (56 - self.bits_left);
+            self.bits_left = self.bits_left + 8;
+        }
+        Ok(())
+    }
 
-        shift_left(0xff, 8 - bits)
+    /// Consumes `bits` bits, which must be buffered, and returns them.
+    #[inline(always)]
+    fn take(&mut self, bits: u32) -> u32 {
+        debug_assert!(0 < bits && bits <= self.bits_left);
+        let result = (self.data >> (64 - bits)) as u32;
+        // Shifting by the width of the word is not allowed, but then all bits
+        // were consumed, and the word becomes zero.
+        self.data = self.data.checked_shl(bits).unwrap_or(0);
+        self.bits_left = self.bits_left - bits;
+        result
+    }
+
+    /// Reads at most 32 bits.
+    #[inline(always)]
+    fn read_bits(&mut self, bits: u32) -> io::Result<u32> {
+        if bits == 0 {
+            return Ok(0)
+        }
+        try!(self.refill(bits));
+        Ok(self.take(bits))
     }
 
     /// Reads a single bit.
-    ///
-    /// Reading a single bit can be done more efficiently than reading
-    /// more than one bit, because a bit never straddles a byte boundary.
     #[inline(always)]
     pub fn read_bit(&mut self) -> io::Result<bool> {
-
-        // If no bits are left, we will need to read the next byte.
-        let result = if self.bits_left == 0 {
-            let fresh_byte = try!(self.reader.read_u8());
-
-            // What remains later are the 7 least significant bits.
-            self.data = fresh_byte << 1;
-            self.bits_left = 7;
-
-            // What we report is the most significant bit of the fresh byte.
-            fresh_byte & 0b1000_0000
-        } else {
-            // Consume the most significant bit of the buffer byte.
-            let bit = self.data & 0b1000_0000;
-            self.data = self.data << 1;
-            self.bits_left = self.bits_left - 1;
-            bit
-        };
-
-        Ok(result != 0)
+        try!(self.refill(1));
+        Ok(self.take(1) != 0)
     }
 
     /// Reads bits until a 1 is read, and returns the number of zeros read.
     ///
-    /// Because the reader buffers a byte internally, reading unary can be done
-    /// more efficiently than by just reading bit by bit.
+    /// The zeros in the buffered bits are counted at once. Only if they are
+    /// all zero, the next byte is read.
     #[inline(always)]
     pub fn read_unary(&mut self) -> io::Result<u32> {
-        // Start initially with the number of zeros that are in the buffer byte
-        // already (counting from the most significant bit).
-        let mut n = self.data.leading_zeros();
-
-        // If the number of zeros plus the one following it was not more than
-        // the bytes left, then there is no need to look further.
-        if n < self.bits_left {
-            // Note: this shift never shifts by more than 7 places, because
-            // bits_left is always at most 7 in between read calls, and the
-            // least significant bit of the buffer byte is 0 in that case. So
-            // we count either 8 zeros, or less than 7. In the former case we
-            // would not have taken this branch, in the latter the shift below
-            // is safe.
-            self.data = self.data << (n + 1);
-            self.bits_left = self.bits_left - (n + 1);
-        } else {
-            // We inspected more bits than available, so our count is incorrect,
-            // and we need to look at the next byte.
-            n = self.bits_left;
-
-            // Continue reading bytes until we encounter a one.
-            loop {
-                let fresh_byte = try!(self.reader.read_u8());
-                let zeros = fresh_byte.leading_zeros();
-                n = n + zeros;
-                if zeros < 8 {
-                    // We consumed the zeros, plus the one following it.
-                    self.bits_left = 8 - (zeros + 1);
-                    self.data = shift_left(fresh_byte, zeros + 1);
-                    break;
-                }
+        let mut n = 0;
+        loop {
+            // The bits after the buffered ones are zero, so the count can
+            // exceed the number of buffered bits, but then no one was found.
+            let zeros = self.data.leading_zeros();
+            if zeros < self.bits_left {
+                // Consume the zeros, plus the one following them.
+                self.take(zeros + 1);
+                return Ok(n + zeros)
             }
+
+            // A valid stream never has this many zeros, but an invalid one
+            // might, and then the count must not overflow.
+            n = n.saturating_add(self.bits_left);
+            self.data = 0;
+            self.bits_left = 0;
+            try!(self.refill(8));
         }
+    }
 
-        Ok(n)
+    /// Reads a Rice code: a unary quotient, followed by a remainder of `param` bits.
+    ///
+    /// Returns the quotient shifted left by `param`, combined with the
+    /// remainder. Bits of the quotient that do not fit are discarded, which
+    /// only happens for an invalid stream.
+    #[inline(always)]
+    pub fn read_rice(&mut self, param: u32) -> io::Result<u32> {
+        debug_assert!(param < 32);
+        let q = try!(self.read_unary());
+        let r = try!(self.read_bits(param));
+        Ok(q << param | r)
     }
 
     /// Reads at most eight bits.
@@ -506,89 +787,16 @@ impl<R: ReadBytes> Bitstream<R> {
         // Of course we can read no more than 8 bits, but we do not want the
         // performance overhead of the assertion, so only do it in debug mode.
         debug_assert!(bits <= 8);
-
-        // If not enough bits left, we will need to read the next byte.
-        let result = if self.bits_left < bits {
-            // Most significant bits are shifted to the right position already.
-            let msb = self.data;
-
-            // Read a single byte.
-            self.data = try!(self.reader.read_u8());
-
-            // From the next byte, we take the additional bits that we need.
-            // Those start at the most significant bit, so we need to shift so
-            // that it does not overlap with what we have already.
-            let lsb = (self.data & Bitstream::<R>::mask_u8(bits - self.bits_left))
-                >> self.bits_left;
-
-            // Shift out the bits that we have consumed.
-            self.data = shift_left(self.data, bits - self.bits_left);
-            self.bits_left = 8 - (bits - self.bits_left);
-
-            msb | lsb
-        } else {
-            let result = self.data & Bitstream::<R>::mask_u8(bits);
-
-            // Shift out the bits that we have consumed.
-            self.data = self.data << bits;
-            self.bits_left = self.bits_left - bits;
-
-            result
-        };
-
-        // If there are more than 8 bits left, we read too far.
-        debug_assert!(self.bits_left < 8);
-
-        // The least significant bits should be zero.
-        debug_assert_eq!(self.data & !Bitstream::<R>::mask_u8(self.bits_left), 0u8);
-
-        // The resulting data is padded with zeros in the least significant
-        // bits, but we want to pad in the most significant bits, so shift.
-        Ok(shift_right(result, 8 - bits))
+        let result = try!(self.read_bits(bits));
+        Ok(result as u8)
     }
 
     /// Read n bits, where 8 < n <= 16.
     #[inline(always)]
     pub fn read_gt_u8_leq_u16(&mut self, bits: u32) -> io::Result<u32> {
         debug_assert!((8 < bits) && (bits <= 16));
-
-        // The most significant bits of the current byte are valid. Shift them
-        // by 2 so they become the most significant bits of the 10 bit number.
-        let mask_msb = 0xffffffff << (bits - self.bits_left);
-        let msb = ((self.data as u32) << (bits - 8)) & mask_msb;
-
-        // Continue reading the next bits, because no matter how many bits were
-        // still left, there were less than 10.
-        let bits_to_read = bits - self.bits_left;
-        let fresh_byte = try!(self.reader.read_u8()) as u32;
-        let lsb = if bits_to_read >= 8 {
-            fresh_byte << (bits_to_read - 8)
-        } else {
-            fresh_byte >> (8 - bits_to_read)
-        };
-        let combined = msb | lsb;
-
-        let result = if bits_to_read <= 8 {
-            // We have all bits already, update the internal state. If no
-            // bits are left we might shift by 8 which is invalid, but in that
-            // case the value is not used, so a masked shift is appropriate.
-            self.bits_left = 8 - bits_to_read;
-            self.data = fresh_byte.wrapping_shl(8 - self.bits_left) as u8;
-            combined
-        } else {
-            // We need to read one more byte to get the final bits.
-            let fresher_byte = try!(self.reader.read_u8()) as u32;
-            let lsb = fresher_byte >> (16 - bits_to_read);
-
-            // Update the reader state. The wrapping shift is appropriate for
-            // the same reason as above.
-            self.bits_left = 16 - bits_to_read;
-            self.data = fresher_byte.wrapping_shl(8 - self.bits_left) as u8;
-
-            combined | lsb
-        };
-
-        Ok(result)
+        try!(self.refill(bits));
+        Ok(self.take(bits))
     }
 
     /// Reads at most 16 bits.
@@ -596,19 +804,8 @@ impl<R: ReadBytes> Bitstream<R> {
     pub fn read_leq_u16(&mut self, bits: u32) -> io::Result<u16> {
         // As with read_leq_u8, this only makes sense if we read <= 16 bits.
         debug_assert!(bits <= 16);
-
-        // Note: the following is not the most efficient implementation
-        // possible, but it avoids duplicating the complexity of `read_leq_u8`.
-
-        if bits <= 8 {
-            let result = try!(self.read_leq_u8(bits));
-            Ok(result as u16)
-        } else {
-            // First read the 8 most significant bits, then read what is left.
-            let msb = try!(self.read_leq_u8(8)) as u16;
-            let lsb = try!(self.read_leq_u8(bits - 8)) as u16;
-            Ok((msb << (bits - 8)) | lsb)
-        }
+        let result = try!(self.read_bits(bits));
+        Ok(result as u16)
     }
 
     /// Reads at most 32 bits.
@@ -616,19 +813,7 @@ impl<R: ReadBytes> Bitstream<R> {
     pub fn read_leq_u32(&mut self, bits: u32) -> io::Result<u32> {
         // As with read_leq_u8, this only makes sense if we read <= 32 bits.
         debug_assert!(bits <= 32);
-
-        // Note: the following is not the most efficient implementation
-        // possible, but it avoids duplicating the complexity of `read_leq_u8`.
-
-        if bits <= 16 {
-            let result = try!(self.read_leq_u16(bits));
-            Ok(result as u32)
-        } else {
-            // First read the 16 most significant bits, then read what is left.
-            let msb = try!(self.read_leq_u16(16)) as u32;
-            let lsb = try!(self.read_leq_u16(bits - 16)) as u32;
-            Ok((msb << (bits - 16)) | lsb)
-        }
+        self.read_bits(bits)
     }
 }
 
@@ -747,6 +932,32 @@ fn verify_read_leq_u32() {
     assert_eq!(bits.read_leq_u32(14).unwrap(), 0b01_0010_0101_0101);
 }
 
+#[test]
+fn verify_read_rice() {
+    let data = io::Cursor::new(vec![0b1011_1000, 0b1001_1110, 0b0000_0001, 0b1111_1111, 0b1110_0000]);
+    let mut bits = Bitstream::new(BufferedReader::new(data));
+
+    assert_eq!(bits.read_rice(0).unwrap(), 0);
+    assert_eq!(bits.read_rice(2).unwrap(), 0b111);
+    assert_eq!(bits.read_rice(1).unwrap(), 0b110);
+    // A remainder that spans three bytes.
+    assert_eq!(bits.read_rice(20).unwrap(), 0b1_1110_0000_0001_1111_1111);
+    assert_eq!(bits.read_leq_u8(5).unwrap(), 0b1_1100);
+}
+
+#[test]
+fn verify_bitstream_reads_only_needed_bytes() {
+    let mut cursor = io::Cursor::new(vec![0b0000_0000, 0b0001_0110, 0b1000_0001, 0xff, 0xff]);
+    {
+        let mut bits = Bitstream::new(&mut cursor);
+        assert_eq!(bits.read_unary().unwrap(), 11);
+        assert_eq!(bits.read_leq_u8(3).unwrap(), 0b011);
+        assert_eq!(bits.read_leq_u32(9).unwrap(), 0b0_1000_0001);
+    }
+    // The bitstream ended at a byte boundary, so the rest is still there.
+    assert_eq!(cursor.position(), 3);
+}
+
 #[test]
 fn verify_read_mixed() {
     // These test data are warm-up samples from an actual stream.
@@ -0,0 +1,186 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `io` module replaces `std::io` when the `std` feature is disabled.
+//!
+//! This module only exists without the `std` feature. It provides the small
+//! part of `std::io` that the decoder needs, with the same names, so that
+//! Claxon can be used with `#![no_std]` and `alloc`. To decode a stream,
+//! implement `Read` (and `Seek`, for seeking) for the input, or wrap a byte
+//! slice in a `Cursor`.
+
+use core::cmp;
+use core::error;
+use core::fmt;
+use core::result;
+
+/// The kind of an IO error, a subset of `std::io::ErrorKind`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The input ended before all the requested data was read.
+    UnexpectedEof,
+    /// A parameter was incorrect, for example a seek before the start.
+    InvalidInput,
+    /// Any other error, for errors that readers report themselves.
+    Other,
+}
+
+/// An IO error, like `std::io::Error`, but with a static message.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    message: &'static str,
+}
+
+/// A result with an IO error.
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// Creates an error of the given kind.
+    pub fn new(kind: ErrorKind, message: &'static str) -> Error {
+        Error {
+            kind: kind,
+            message: message,
+        }
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        formatter.write_str(self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+/// A source of bytes, like `std::io::Read`.
+pub trait Read {
+    /// Reads bytes into `buf`, and returns how many were read.
+    ///
+    /// Reading 0 bytes into a nonempty buffer signals the end of the input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+/// The position to seek to, like `std::io::SeekFrom`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// An offset from the start of the input.
+    Start(u64),
+    /// An offset from the end of the input.
+    End(i64),
+    /// An offset from the current position.
+    Current(i64),
+}
+
+/// An input that can seek, like `std::io::Seek`.
+pub trait Seek {
+    /// Seeks to the given position, and returns the new offset from the start.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+}
+
+impl<'a, R: Read + ?Sized> Read for &'a mut R {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<'a, S: Seek + ?Sized> Seek for &'a mut S {
+    #[inline]
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
+impl<'a> Read for &'a [u8] {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = cmp::min(buf.len(), self.len());
+        let (head, tail) = self.split_at(n);
+        buf[..n].copy_from_slice(head);
+        *self = tail;
+        Ok(n)
+    }
+}
+
+/// A reader over bytes in memory, with a position, like `std::io::Cursor`.
+#[derive(Clone, Debug, Default)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// Creates a cursor at the start of `inner`.
+    pub fn new(inner: T) -> Cursor<T> {
+        Cursor {
+            inner: inner,
+            pos: 0,
+        }
+    }
+
+    /// Returns the current position.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the current position, which may lie beyond the end.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Returns a reference to the underlying bytes.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the underlying bytes.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let data = self.inner.as_ref();
+        let start = cmp::min(self.pos, data.len() as u64) as usize;
+        let n = try!((&data[start..]).read(buf));
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset)
+            }
+            SeekFrom::End(offset) => (self.inner.as_ref().len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        let new_pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match new_pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(Error::new(ErrorKind::InvalidInput,
+                                   "invalid seek to a negative or overflowing position")),
+        }
+    }
+}
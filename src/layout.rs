@@ -0,0 +1,163 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `layout` module describes which speaker every channel belongs to.
+//!
+//! The FLAC format defines a default speaker assignment for every number of
+//! channels, from mono up to 7.1 surround. The channels of a decoded block
+//! are in that order. Note that the stereo decorrelation modes of a frame
+//! (see `frame::ChannelAssignment`) only affect how the channels are coded:
+//! after decoding, channel 0 is always left and channel 1 right.
+//!
+//! A stream can deviate from the default assignment with a
+//! `WAVEFORMATEXTENSIBLE_CHANNEL_MASK` tag, which `FlacReader::channel_layout()`
+//! takes into account.
+
+/// A speaker position, in the terms of the WAVEFORMATEXTENSIBLE channel mask.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Speaker {
+    /// Front left.
+    FrontLeft,
+    /// Front right.
+    FrontRight,
+    /// Front center.
+    FrontCenter,
+    /// Low-frequency effects, the subwoofer.
+    LowFrequency,
+    /// Back left, also called rear left or left surround.
+    BackLeft,
+    /// Back right, also called rear right or right surround.
+    BackRight,
+    /// Back center.
+    BackCenter,
+    /// Side left.
+    SideLeft,
+    /// Side right.
+    SideRight,
+}
+
+impl Speaker {
+    /// Returns the bit of this speaker in a WAVEFORMATEXTENSIBLE channel mask.
+    pub fn mask(self) -> u32 {
+        match self {
+            Speaker::FrontLeft => 0x001,
+            Speaker::FrontRight => 0x002,
+            Speaker::FrontCenter => 0x004,
+            Speaker::LowFrequency => 0x008,
+            Speaker::BackLeft => 0x010,
+            Speaker::BackRight => 0x020,
+            Speaker::BackCenter => 0x100,
+            Speaker::SideLeft => 0x200,
+            Speaker::SideRight => 0x400,
+        }
+    }
+}
+
+/// The speaker assignments that FLAC defines, one for every number of channels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelLayout {
+    /// One channel: front center.
+    Mono,
+    /// Two channels: left, right.
+    Stereo,
+    /// Three channels: left, right, center.
+    Surround30,
+    /// Four channels: front left, front right, back left, back right.
+    Quad,
+    /// Five channels: front left, front right, center, back left, back right.
+    Surround50,
+    /// Six channels: front left, front right, center, LFE, back left, back right.
+    Surround51,
+    /// Seven channels: front left, front right, center, LFE, back center,
+    /// side left, side right.
+    Surround61,
+    /// Eight channels: front left, front right, center, LFE, back left,
+    /// back right, side left, side right.
+    Surround71,
+}
+
+/// All layouts, in order of the number of channels.
+const LAYOUTS: [ChannelLayout; 8] = [
+    ChannelLayout::Mono,
+    ChannelLayout::Stereo,
+    ChannelLayout::Surround30,
+    ChannelLayout::Quad,
+    ChannelLayout::Surround50,
+    ChannelLayout::Surround51,
+    ChannelLayout::Surround61,
+    ChannelLayout::Surround71,
+];
+
+impl ChannelLayout {
+    /// Returns the default layout for the number of channels.
+    ///
+    /// Returns `None` if the number is not between 1 and 8 inclusive.
+    pub fn from_channels(channels: u32) -> Option<ChannelLayout> {
+        // For 0 channels, the index wraps around, and is out of bounds too.
+        LAYOUTS.get(channels.wrapping_sub(1) as usize).cloned()
+    }
+
+    /// Returns the layout with exactly the speakers in a WAVEFORMATEXTENSIBLE channel mask.
+    ///
+    /// Returns `None` if the mask does not match any of the layouts.
+    pub fn from_channel_mask(mask: u32) -> Option<ChannelLayout> {
+        LAYOUTS.iter().cloned().find(|layout| layout.channel_mask() == mask)
+    }
+
+    /// Returns the number of channels in the layout.
+    pub fn channels(self) -> u32 {
+        self.speakers().len() as u32
+    }
+
+    /// Returns the speaker of every channel, in channel order.
+    pub fn speakers(self) -> &'static [Speaker] {
+        use self::Speaker::*;
+        match self {
+            ChannelLayout::Mono => &[FrontCenter],
+            ChannelLayout::Stereo => &[FrontLeft, FrontRight],
+            ChannelLayout::Surround30 => &[FrontLeft, FrontRight, FrontCenter],
+            ChannelLayout::Quad => &[FrontLeft, FrontRight, BackLeft, BackRight],
+            ChannelLayout::Surround50 => {
+                &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight]
+            }
+            ChannelLayout::Surround51 => {
+                &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight]
+            }
+            ChannelLayout::Surround61 => {
+                &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackCenter, SideLeft,
+                  SideRight]
+            }
+            ChannelLayout::Surround71 => {
+                &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight,
+                  SideLeft, SideRight]
+            }
+        }
+    }
+
+    /// Returns the WAVEFORMATEXTENSIBLE channel mask of the layout.
+    ///
+    /// This is the value to put in the `dwChannelMask` field when writing the
+    /// decoded audio to a WAV file.
+    pub fn channel_mask(self) -> u32 {
+        self.speakers().iter().fold(0, |mask, speaker| mask | speaker.mask())
+    }
+}
+
+#[test]
+fn verify_layouts_match_channel_counts_and_masks() {
+    for n in 1..9 {
+        let layout = ChannelLayout::from_channels(n).unwrap();
+        assert_eq!(layout.channels(), n);
+        assert_eq!(ChannelLayout::from_channel_mask(layout.channel_mask()), Some(layout));
+    }
+    assert_eq!(ChannelLayout::from_channels(0), None);
+    assert_eq!(ChannelLayout::from_channels(9), None);
+    assert_eq!(ChannelLayout::Surround51.channel_mask(), 0x3f);
+    assert_eq!(ChannelLayout::Surround71.channel_mask(), 0x63f);
+    assert_eq!(ChannelLayout::from_channel_mask(0x3), Some(ChannelLayout::Stereo));
+    assert_eq!(ChannelLayout::from_channel_mask(0x60f), None);
+}
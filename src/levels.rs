@@ -0,0 +1,224 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `levels` module measures the peak and RMS level of every channel.
+//!
+//! `LevelMeter` accumulates levels block by block, so a stream can be
+//! measured without holding all of its samples in memory; `measure()` does
+//! this for an entire stream. All levels are relative to full scale, so 1.0
+//! is the largest magnitude that the bit depth can represent.
+
+use std::f64;
+use std::io;
+
+use error::Result;
+use frame::Block;
+use {FlacReader, FlacReaderState};
+
+/// The number of samples that an interpolated sample depends on.
+const TAPS: usize = 16;
+
+/// The oversampling factor for the true peak estimate.
+const OVERSAMPLING: usize = 4;
+
+/// The levels of one channel, relative to full scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLevels {
+    /// The largest magnitude of any sample.
+    pub peak: f64,
+    /// An estimate of the largest magnitude of the continuous signal.
+    ///
+    /// The signal between samples can exceed the sample peak, and then it
+    /// clips after conversion to analog, or after lossy encoding. The
+    /// estimate is based on 4x oversampling, like the true peak of ITU-R
+    /// BS.1770, but the interpolation filter is not the one of the standard,
+    /// so the result may differ slightly from that of a standard meter.
+    pub true_peak: f64,
+    /// The root mean square of the samples.
+    pub rms: f64,
+}
+
+/// The state of one channel in a `LevelMeter`.
+#[derive(Clone)]
+struct ChannelState {
+    peak: f64,
+    true_peak: f64,
+    sum_squares: f64,
+    samples: u64,
+    /// The most recent samples, a ring buffer that starts at `pos`.
+    history: [f64; TAPS],
+    pos: usize,
+}
+
+/// Accumulates the levels of the blocks that it is fed.
+#[derive(Clone)]
+pub struct LevelMeter {
+    channels: Vec<ChannelState>,
+    /// The factor that scales a sample to full scale.
+    scale: f64,
+    /// The interpolation filter for every fractional position between samples.
+    filters: [[f64; TAPS]; OVERSAMPLING - 1],
+}
+
+/// Returns the Lanczos kernel of `TAPS / 2` lobes at `x`.
+fn lanczos(x: f64) -> f64 {
+    let a = (TAPS / 2) as f64;
+    if x == 0.0 {
+        1.0
+    } else if x.abs() >= a {
+        0.0
+    } else {
+        let px = f64::consts::PI * x;
+        a * px.sin() * (px / a).sin() / (px * px)
+    }
+}
+
+impl LevelMeter {
+    /// Creates a meter for a stream with the given number of channels and bit depth.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_sample` is not between 1 and 32.
+    pub fn new(channels: u32, bits_per_sample: u32) -> LevelMeter {
+        assert!(bits_per_sample >= 1 && bits_per_sample <= 32,
+                "bits_per_sample must be between 1 and 32");
+        // Interpolate between the two middle samples of the history.
+        let mut filters = [[0.0; TAPS]; OVERSAMPLING - 1];
+        for (phase, filter) in filters.iter_mut().enumerate() {
+            let t = (TAPS / 2 - 1) as f64 + (phase + 1) as f64 / OVERSAMPLING as f64;
+            for (k, coefficient) in filter.iter_mut().enumerate() {
+                *coefficient = lanczos(t - k as f64);
+            }
+        }
+        let channel = ChannelState {
+            peak: 0.0,
+            true_peak: 0.0,
+            sum_squares: 0.0,
+            samples: 0,
+            history: [0.0; TAPS],
+            pos: 0,
+        };
+        LevelMeter {
+            channels: vec![channel; channels as usize],
+            scale: 1.0 / (1u64 << (bits_per_sample - 1)) as f64,
+            filters: filters,
+        }
+    }
+
+    /// Adds the samples of a block to the measurement.
+    ///
+    /// The blocks must be fed in stream order for the true peak estimate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block has more channels than the meter.
+    pub fn update(&mut self, block: &Block) {
+        for (ch, samples) in block.channel_slices().enumerate() {
+            self.update_channel(ch as u32, samples);
+        }
+    }
+
+    /// Adds samples of a single channel to the measurement.
+    ///
+    /// The samples of every channel must be fed in stream order for the true
+    /// peak estimate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ch` is not less than the number of channels of the meter.
+    pub fn update_channel(&mut self, ch: u32, samples: &[i32]) {
+        assert!((ch as usize) < self.channels.len());
+        for &sample in samples {
+            let x = sample as f64 * self.scale;
+            self.push(ch as usize, x);
+        }
+        self.channels[ch as usize].samples += samples.len() as u64;
+    }
+
+    fn push(&mut self, ch: usize, x: f64) {
+        let filters = &self.filters;
+        let state = &mut self.channels[ch];
+        state.peak = state.peak.max(x.abs());
+        state.true_peak = state.true_peak.max(x.abs());
+        state.sum_squares += x * x;
+        state.history[state.pos] = x;
+        state.pos = (state.pos + 1) % TAPS;
+        for filter in filters {
+            let mut y = 0.0;
+            for (k, &c) in filter.iter().enumerate() {
+                y += c * state.history[(state.pos + k) % TAPS];
+            }
+            state.true_peak = state.true_peak.max(y.abs());
+        }
+    }
+
+    /// Returns the levels of every channel, of the blocks fed so far.
+    pub fn levels(&self) -> Vec<ChannelLevels> {
+        // The signal after the last samples is not interpolated yet, feed
+        // silence to a copy to flush the filter.
+        let mut flushed = self.clone();
+        for ch in 0..flushed.channels.len() {
+            for _ in 0..TAPS / 2 {
+                flushed.push(ch, 0.0);
+            }
+        }
+        flushed.channels.iter().map(|state| {
+            let mean_square = if state.samples == 0 {
+                0.0
+            } else {
+                state.sum_squares / state.samples as f64
+            };
+            ChannelLevels {
+                peak: state.peak,
+                true_peak: state.true_peak,
+                rms: mean_square.sqrt(),
+            }
+        }).collect()
+    }
+}
+
+/// Decodes the stream from the current position, and returns the levels of every channel.
+///
+/// A single buffer is reused for all blocks.
+///
+/// # Panics
+///
+/// Panics if the reader was constructed with `metadata_only` set.
+pub fn measure<R: io::Read>(reader: &mut FlacReader<R>) -> Result<Vec<ChannelLevels>> {
+    if let FlacReaderState::MetadataOnly(..) = reader.input {
+        panic!("FlacReaderOptions::metadata_only must be false \
+               to be able to measure levels")
+    }
+    let streaminfo = reader.streaminfo();
+    let mut meter = LevelMeter::new(streaminfo.channels, streaminfo.bits_per_sample);
+    let mut buffer = Vec::new();
+    while let Some(block) = try!(reader.read_next_block(buffer)) {
+        meter.update(&block);
+        buffer = block.into_buffer();
+    }
+    Ok(meter.levels())
+}
+
+#[test]
+fn verify_level_meter_measures_full_scale_square_wave() {
+    // A square wave at full scale, with a period of 4 samples.
+    let samples: Vec<i32> = (0..64).map(|i| if i % 4 < 2 { 127 } else { -128 }).collect();
+    let mut meter = LevelMeter::new(1, 8);
+    meter.update_channel(0, &samples);
+    let levels = meter.levels();
+    assert_eq!(levels.len(), 1);
+    assert_eq!(levels[0].peak, 1.0);
+    assert!(levels[0].rms > 0.99 && levels[0].rms <= 1.0);
+    // Between the samples, the reconstructed signal overshoots.
+    assert!(levels[0].true_peak > 1.2);
+
+    // Silence has no level at all.
+    let mut meter = LevelMeter::new(2, 16);
+    meter.update_channel(0, &[0; 8]);
+    let levels = meter.levels();
+    assert_eq!(levels[1], ChannelLevels { peak: 0.0, true_peak: 0.0, rms: 0.0 });
+}
@@ -65,34 +65,197 @@
 //!
 //! For more examples, see the [examples](https://github.com/ruuda/claxon/tree/master/examples)
 //! directory in the crate.
+//!
+//! Without std
+//! ===========
+//!
+//! With the default `std` feature disabled, Claxon builds with `#![no_std]`,
+//! and only needs `alloc`. The input is then read through the traits in the
+//! `io` module instead of `std::io`. The modules that need threads, files, or
+//! floating point math from `std` are not available in that case.
 
 #![warn(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "serde")]
+extern crate base64;
+#[cfg(feature = "memchr")]
+extern crate memchr;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(test)]
+extern crate sha2;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
+/// Without the `std` feature, the parts of `std` that the decoder uses come
+/// from `core` and `alloc`, and `io` is replaced by Claxon's own `io` module.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::*;
+    pub use alloc::{collections, rc, string};
+    pub use io;
+}
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
 use std::fs;
+#[cfg(feature = "std")]
 use std::io;
 use std::mem;
+use std::ops::Range;
+#[cfg(feature = "std")]
 use std::path;
+use std::rc::Rc;
+use std::slice;
+use std::time::Duration;
+use std::u64;
+use std::usize;
 use error::fmt_err;
-use frame::FrameReader;
+use frame::{CrcMismatch, FrameReader, FrameResult};
 use input::{BufferedReader, ReadBytes};
-use metadata::{MetadataBlock, MetadataBlockReader, StreamInfo, VorbisComment};
+use layout::ChannelLayout;
+use metadata::{CueSheet, MetadataBlock, MetadataLimits, SeekPoint, SeekTable, StreamInfo,
+               VorbisComment};
+
+/// Emits a `trace::Event`, if the `trace` or `tracing` feature is enabled.
+///
+/// The argument is an event variant, for example `trace!(EnterFrame)`.
+#[cfg(any(feature = "trace", feature = "tracing"))]
+macro_rules! trace {
+    ($($event: tt)*) => { ::trace::emit(::trace::Event::$($event)*) }
+}
+
+/// Without these features, the event is not even constructed.
+#[cfg(not(any(feature = "trace", feature = "tracing")))]
+macro_rules! trace {
+    ($($event: tt)*) => { () }
+}
 
+#[cfg(feature = "std")]
+pub mod background;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod checksum;
+#[cfg(feature = "std")]
+mod compare;
 mod crc;
+#[cfg(feature = "dasp")]
+pub mod dasp;
+#[cfg(feature = "std")]
+pub mod dither;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod frame;
 pub mod input;
+#[cfg(not(feature = "std"))]
+pub mod io;
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod levels;
+#[cfg(feature = "libflac")]
+pub mod libflac;
+mod md5;
 pub mod metadata;
+mod metrics;
+pub mod ogg;
+pub mod push;
+#[cfg(feature = "std")]
+pub mod replaygain;
+#[cfg(feature = "rodio")]
+pub mod rodio;
+mod seek;
+mod simd;
+#[cfg(feature = "serde")]
+mod serialize;
 pub mod subframe;
+pub mod subset;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(any(feature = "trace", feature = "tracing"))]
+pub mod trace;
 
+#[cfg(feature = "std")]
+pub use compare::{Comparison, compare};
 pub use error::{Error, Result};
 pub use frame::Block;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
 
 /// A FLAC decoder that can decode the stream from the underlying reader.
 ///
+/// A buffer for decoding is allocated when the reader is constructed, sized
+/// for the maximum block size in the streaminfo. The first iterator or
+/// `DecodeScratch` that decodes a block takes this buffer, and reuses it for
+/// every block. So as long as it is kept, decoding with `samples()`,
+/// `FlacSamples::read_samples_into()` or `decode_block_interleaved_into()`
+/// does not allocate, with a few exceptions: 32-bit streams in side-channel
+/// stereo, damaged streams in `resync` mode, and streams with more frames
+/// than the seek index reserves space for.
+///
 /// TODO: Add an example.
 pub struct FlacReader<R: io::Read> {
     streaminfo: StreamInfo,
-    vorbis_comment: Option<VorbisComment>,
+    /// All metadata blocks that were read, in stream order, streaminfo first.
+    metadata_blocks: Vec<MetadataBlock>,
+    /// The offset of the first frame, relative to the start of the input.
+    frames_offset: u64,
+    /// The number of samples that `samples()` should skip after a seek.
+    seek_skip: u32,
+    /// The frames that were seen so far, ordered by sample number.
+    seek_index: Vec<SeekPoint>,
+    /// A buffer for the first block that an iterator decodes, allocated up front.
+    buffer: Vec<i32>,
+    /// Whether to verify the CRC-16 of frames, see `FlacReaderOptions::verify_crc`.
+    verify_crc: bool,
+    /// Whether to verify the CRC-8 of frame headers, see `FlacReaderOptions::verify_header_crc`.
+    verify_header_crc: bool,
+    /// The offset of the frame at which decoding failed most recently, if it did.
+    error_offset: Option<u64>,
+    /// The index of the next frame, unless frames were seeked over or decoded elsewhere.
+    frame_index: Option<u64>,
+    /// The checksum mismatch that made decoding fail most recently, if any.
+    crc_mismatch: Option<CrcMismatch>,
+    /// Whether to continue after damaged frames, see `FlacReaderOptions::resync`.
+    resync: bool,
+    /// Whether to decode frames that were cut off, see `FlacReaderOptions::salvage_partial_frame`.
+    salvage_partial_frame: bool,
+    /// Whether to hash the decoded audio, see `FlacReaderOptions::compute_md5`.
+    compute_md5: bool,
+    /// The hash of the audio decoded so far, if it covers all audio up to there.
+    md5: Option<md5::Md5>,
+    /// The first sample after the audio that was hashed.
+    md5_next_sample: u64,
+    /// The result of comparing the hash against the streaminfo, once it is known.
+    md5_matches: Option<bool>,
+    /// Whether the sample rate was taken from the first frame, see `FlacReaderOptions::derive_sample_rate`.
+    sample_rate_is_derived: bool,
+    /// Collects deviations from the specification, see `FlacReaderOptions::check_subset`.
+    subset_checker: Option<subset::Checker>,
+    /// The ranges of samples that were lost in damaged frames, in stream order.
+    lost_samples: Vec<Range<u64>>,
+    /// The length in bytes and the duration of the frame decoded most recently.
+    last_frame: Option<(u64, u64)>,
+    /// The total length in bytes and duration of the frames decoded so far.
+    decoded_frames: (u64, u64),
+    /// Counters of what was decoded, when the `metrics` feature is enabled.
+    metrics: metrics::Metrics,
     input: FlacReaderState<BufferedReader<R>>,
 }
 
@@ -103,7 +266,7 @@ enum FlacReaderState<T> {
     MetadataOnly(T),
 }
 
-/// Controls what metadata `FlacReader` reads when constructed.
+/// Controls what metadata `FlacReader` reads when constructed, and how it decodes.
 ///
 /// The FLAC format contains a number of metadata blocks before the start of
 /// audio data. Reading these is wasteful if the data is never used. The
@@ -114,11 +277,20 @@ enum FlacReaderState<T> {
 /// A few use cases:
 ///
 /// * To read only the streaminfo, as quickly as possible, set `metadata_only`
-///   to true and `read_vorbis_comment` to false. The resulting reader cannot be
-///   used to read audio data.
+///   to true and `read_vorbis_comment` and `read_pictures` to false. The
+///   resulting reader cannot be used to read audio data.
 /// * To read only the streaminfo and tags, set `metadata_only` and
-///   `read_vorbis_comment` both to true. The resulting reader cannot be used to
+///   `read_vorbis_comment` both to true, and `read_pictures` to false, or use
+///   `FlacReaderOptions::tags_only()`. The resulting reader cannot be used to
 ///   read audio data.
+/// * To decode as much as possible of a damaged stream, set `verify_crc` to
+///   false.
+/// * To skip over damaged frames, and continue decoding after them, set
+///   `resync` to true.
+/// * To decode the start of a frame in a truncated stream, set
+///   `salvage_partial_frame` to true.
+/// * To read files with an ID3v2 tag in front of the stream, set `skip_id3v2`
+///   to true.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct FlacReaderOptions {
     /// When true, return a reader as soon as all desired metadata has been read.
@@ -139,18 +311,170 @@ pub struct FlacReaderOptions {
     ///
     /// Defaults to true.
     pub read_vorbis_comment: bool,
+
+    /// When true, read all metadata blocks to collect the pictures in them.
+    ///
+    /// When false, picture blocks are not retained, and
+    /// `FlacReader::pictures()` returns no pictures. Because a stream can
+    /// contain any number of pictures, with `metadata_only` set this causes
+    /// all metadata blocks to be read. Pictures can be large, so set this to
+    /// false when album art is not needed.
+    ///
+    /// Defaults to true.
+    pub read_pictures: bool,
+
+    /// Limits on the metadata to read, to protect against denial of service.
+    ///
+    /// Defaults to `MetadataLimits::default()`.
+    pub metadata_limits: MetadataLimits,
+
+    /// When true, a frame whose CRC-16 does not match its contents is an error.
+    ///
+    /// When false, such frames are returned as decoded. This can be useful to
+    /// recover audio from a damaged stream, but bit errors then result in
+    /// audible glitches rather than errors. The CRC-16 is then not computed,
+    /// which makes decoding slightly faster. Seeking verifies the CRC-16
+    /// regardless, to find the frames reliably. When a frame fails to decode
+    /// because of a mismatch, `FlacReader::crc_mismatch()` has the details.
+    ///
+    /// Defaults to true.
+    pub verify_crc: bool,
+
+    /// When true, a frame header whose CRC-8 does not match is an error.
+    ///
+    /// When false, such headers are used as decoded. The header describes how
+    /// to decode the rest of the frame, so a damaged header usually causes an
+    /// error later on anyway. Seeking, skipping, and `resync` verify the CRC-8
+    /// regardless, as it is needed to find the frames.
+    ///
+    /// Defaults to true.
+    pub verify_header_crc: bool,
+
+    /// When true, decoding continues at the next frame after a damaged frame.
+    ///
+    /// When false, a frame that cannot be decoded is an error. When true, the
+    /// reader instead scans forward for the next valid frame header, and
+    /// continues decoding there. The samples that were lost are reported by
+    /// `FlacReader::lost_samples()`. Only format errors are recovered from, IO
+    /// errors and a stream that is truncated in the middle of a frame are
+    /// still reported. Combine with `verify_crc` to decide what counts as
+    /// damaged: with CRC verification, frames with bit errors are dropped
+    /// entirely.
+    ///
+    /// Defaults to false.
+    pub resync: bool,
+
+    /// When true, the decodable part of a frame that was cut off is returned.
+    ///
+    /// When false, a stream that ends in the middle of a frame is an error,
+    /// and the samples of that frame are lost. This is common for interrupted
+    /// downloads. When true, the samples that could be decoded up to the point
+    /// where the stream ended are returned as a shorter block. The CRC-16 of
+    /// such a block cannot be verified. If the streaminfo specifies the number
+    /// of samples, `samples()` still reports an error after the salvaged
+    /// samples, to signal that the stream was truncated.
+    ///
+    /// Defaults to false.
+    pub salvage_partial_frame: bool,
+
+    /// When true, compute the MD5 of the audio as it is decoded.
+    ///
+    /// The streaminfo block contains an MD5 signature of the unencoded audio.
+    /// With this option, the signature is checked in the same pass that
+    /// decodes the audio, and `FlacReader::md5_matches()` reports the result
+    /// when the end of the stream is reached. Hashing is not free, so only
+    /// enable this when the result is needed.
+    ///
+    /// Defaults to false.
+    pub compute_md5: bool,
+
+    /// When true, collect deviations from the specification and its subset.
+    ///
+    /// The metadata is checked when the reader is constructed, and every frame
+    /// header is checked as the frame is decoded. Deviations do not cause
+    /// errors, they are collected, and `FlacReader::violations()` returns
+    /// them. This is intended for tools that need to reject non-conforming
+    /// streams. See the `subset` module for details.
+    ///
+    /// Defaults to false.
+    pub check_subset: bool,
+
+    /// When true, skip ID3v2 tags before the start of the FLAC stream.
+    ///
+    /// The format does not allow anything before the `fLaC` marker, but some
+    /// taggers prepend an ID3v2 tag nonetheless. The reference decoder skips
+    /// such tags. When false, a stream that starts with an ID3v2 tag is an
+    /// error.
+    ///
+    /// Defaults to false.
+    pub skip_id3v2: bool,
+
+    /// The number of bytes of unrecognized data to skip before the `fLaC` marker.
+    ///
+    /// If the stream does not start with the marker (after any ID3v2 tags, if
+    /// `skip_id3v2` is set), look for it in at most this many bytes of
+    /// leading data, rather than failing immediately. This can recover
+    /// streams with leading garbage, but a large limit makes it slower to
+    /// reject input that is not FLAC.
+    ///
+    /// Defaults to 0.
+    pub header_search_limit: u32,
+
+    /// When true, an unknown sample rate in the streaminfo is taken from the first frame.
+    ///
+    /// Some encoders write a sample rate of 0 to the streaminfo block, and
+    /// store the sample rate only in the frame headers. When false, such a
+    /// stream is an error. When true, the sample rate is taken from the header
+    /// of the first frame instead, and `FlacReader::sample_rate_is_derived()`
+    /// returns true. The metadata callback still receives the streaminfo block
+    /// as stored. This requires the audio, so with `metadata_only` set, the
+    /// stream is still an error.
+    ///
+    /// The number of channels and bits per sample are always stored in the
+    /// streaminfo. An unknown total number of samples is already supported,
+    /// see `StreamInfo::samples`.
+    ///
+    /// Defaults to false.
+    pub derive_sample_rate: bool,
 }
 
 impl Default for FlacReaderOptions {
     fn default() -> FlacReaderOptions {
         FlacReaderOptions {
             read_vorbis_comment: true,
+            read_pictures: true,
             metadata_only: false,
+            metadata_limits: MetadataLimits::default(),
+            verify_crc: true,
+            verify_header_crc: true,
+            resync: false,
+            salvage_partial_frame: false,
+            compute_md5: false,
+            check_subset: false,
+            skip_id3v2: false,
+            header_search_limit: 0,
+            derive_sample_rate: false,
         }
     }
 }
 
 impl FlacReaderOptions {
+    /// Returns options to read only the streaminfo and the Vorbis comment block.
+    ///
+    /// This is the fastest way to read tags, for example to scan a music
+    /// library. Reading stops at the Vorbis comment block, so any metadata
+    /// blocks after it, and the audio data, are not read, and no buffers for
+    /// decoding are allocated. The resulting reader cannot be used to read
+    /// audio data.
+    pub fn tags_only() -> FlacReaderOptions {
+        FlacReaderOptions {
+            metadata_only: true,
+            read_vorbis_comment: true,
+            read_pictures: false,
+            .. FlacReaderOptions::default()
+        }
+    }
+
     /// Return whether any metadata blocks need to be read.
     fn has_desired_blocks(&self) -> bool {
         // If we do not want only metadata, we want everything. Hence there are
@@ -159,45 +483,183 @@ impl FlacReaderOptions {
             return true
         }
 
-        // Should be the or of all read_* fields, of which vorbis_comment is the
-        // only one at the moment.
-        self.read_vorbis_comment
+        // The or of all read_* fields.
+        self.read_vorbis_comment || self.read_pictures
     }
 }
 
 /// An iterator that yields samples read from a `FlacReader`.
-pub struct FlacSamples<R: ReadBytes> {
-    frame_reader: FrameReader<R>,
+pub struct FlacSamples<'r, R: 'r + io::Read> {
+    reader: &'r mut FlacReader<R>,
+    state: SamplesState,
+}
+
+/// An iterator that yields samples, and owns the `FlacReader` it reads from.
+///
+/// Unlike `FlacSamples`, this iterator does not borrow the reader, so it can
+/// be boxed, or returned from a function. It is returned by
+/// `FlacReader::into_samples()`.
+pub struct FlacIntoSamples<R: io::Read> {
+    reader: FlacReader<R>,
+    state: SamplesState,
+}
+
+/// An adapter that converts samples to floating point, in the range [-1.0, 1.0).
+///
+/// It is returned by `FlacReader::samples_f32()`, but it can wrap any iterator
+/// of samples, such as `FlacIntoSamples`. See `Block::interleaved_f32_into()`
+/// to convert a block at once.
+pub struct SamplesF32<I> {
+    samples: I,
+    scale: f32,
+}
+
+/// An iterator that yields the samples of one channel read from a `FlacReader`.
+///
+/// The iterators for all channels are returned together by
+/// `FlacReader::channels()`, and they share the reader.
+pub struct ChannelSamples<'r, R: 'r + io::Read> {
+    shared: Rc<RefCell<ChannelsState<'r, R>>>,
+    channel: usize,
+}
+
+/// The state that the `ChannelSamples` iterators of a reader share.
+struct ChannelsState<'r, R: 'r + io::Read> {
+    reader: &'r mut FlacReader<R>,
+
+    /// Per channel, the decoded samples that its iterator has not yielded yet.
+    queues: Vec<VecDeque<i32>>,
+
+    /// The number of inter-channel samples to skip in the next block, after a seek.
+    skip: u32,
+
+    /// The buffer to decode the next block into.
+    buffer: Vec<i32>,
+
+    /// Set at the end of the stream, or after an error.
+    is_done: bool,
+}
+
+/// The position of a samples iterator, shared by `FlacSamples` and `FlacIntoSamples`.
+struct SamplesState {
     block: Block,
     sample: u32,
     channel: u32,
 
+    /// The number of inter-channel samples to skip in the next block, after a seek.
+    skip: u32,
+
+    /// The position of the last sample of the previous block, if there was one.
+    last_position: Option<u64>,
+
+    /// The position of the first sample to yield, if it could be determined.
+    start: Option<u64>,
+
     /// If reading ever failed, this flag is set, so that the iterator knows not
     /// to return any new values.
     has_failed: bool,
 }
 
-// TODO: Add a `FlacIntoSamples`.
+/// Reusable decode buffer for `FlacReader::decode_block_interleaved_into()`.
+///
+/// Decoding a frame requires a buffer that holds all channels of the block.
+/// A `DecodeScratch` owns such a buffer, so that it can be reused for every
+/// block. Create it once, before decoding starts, preferably with
+/// `DecodeScratch::for_streaminfo()` so the buffer is large enough for every
+/// block of the stream from the start. Then pass it to every call of
+/// `decode_block_interleaved_into()`. Once the buffer is large enough, no more
+/// allocations happen. If decoding fails, the buffer is lost, and it will be
+/// reallocated by the next decode.
+pub struct DecodeScratch {
+    buffer: Vec<i32>,
+}
+
+impl DecodeScratch {
+    /// Creates an empty scratch buffer, which will grow as needed.
+    pub fn new() -> DecodeScratch {
+        DecodeScratch {
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Creates a scratch buffer large enough for every block in the stream.
+    pub fn for_streaminfo(streaminfo: &StreamInfo) -> DecodeScratch {
+        DecodeScratch {
+            buffer: Vec::with_capacity(streaminfo.max_block_len()),
+        }
+    }
+}
+
+impl Default for DecodeScratch {
+    fn default() -> DecodeScratch {
+        DecodeScratch::new()
+    }
+}
 
-fn read_stream_header<R: ReadBytes>(input: &mut R) -> Result<()> {
+fn read_stream_header<R: ReadBytes>(input: &mut R, options: &FlacReaderOptions) -> Result<()> {
     // A FLAC stream starts with a 32-bit header 'fLaC' (big endian).
     const FLAC_HEADER: u32 = 0x66_4c_61_43;
 
     // Some files start with ID3 tag data. The reference decoder supports this
-    // for convenience. Claxon does not, but we can at least generate a helpful
-    // error message if a file starts like this.
+    // for convenience. Claxon does too if `skip_id3v2` is set, and otherwise
+    // we can at least generate a helpful error message if a file starts like
+    // this.
     const ID3_HEADER: u32 = 0x49_44_33_00;
 
-    let header = try!(input.read_be_u32());
-    if header != FLAC_HEADER {
-        if (header & 0xff_ff_ff_00) == ID3_HEADER {
-            fmt_err("stream starts with ID3 header rather than FLAC header")
-        } else {
-            fmt_err("invalid stream header")
+    // FLAC in an Ogg container starts with an Ogg page, 'OggS'. Such streams
+    // can be read with `ogg::OggFlacReader`.
+    const OGG_HEADER: u32 = 0x4f_67_67_53;
+
+    let mut header = try!(input.read_be_u32());
+    let mut searched = 0;
+    loop {
+        if header == FLAC_HEADER {
+            return Ok(())
         }
-    } else {
-        Ok(())
+        if (header & 0xff_ff_ff_00) == ID3_HEADER && options.skip_id3v2 {
+            try!(skip_id3v2_tag(input, header as u8));
+            header = try!(input.read_be_u32());
+            continue
+        }
+        if searched >= options.header_search_limit {
+            if (header & 0xff_ff_ff_00) == ID3_HEADER {
+                return fmt_err("stream starts with ID3 header rather than FLAC header")
+            } else if header == OGG_HEADER {
+                return fmt_err("stream is Ogg FLAC rather than native FLAC")
+            } else {
+                return fmt_err("invalid stream header")
+            }
+        }
+        header = (header << 8) | try!(input.read_u8()) as u32;
+        searched += 1;
+    }
+}
+
+/// Skips an ID3v2 tag, of which the first four bytes have been read.
+fn skip_id3v2_tag<R: ReadBytes>(input: &mut R, major_version: u8) -> Result<()> {
+    // The rest of the ten-byte header consists of the minor version, flags,
+    // and the size of the tag excluding the header, as a 28-bit integer
+    // stored in the low seven bits of four bytes.
+    let minor_version = try!(input.read_u8());
+    let flags = try!(input.read_u8());
+    let mut size = 0;
+    for _ in 0..4 {
+        let byte = try!(input.read_u8());
+        if byte >= 0x80 {
+            return fmt_err("invalid ID3v2 tag size")
+        }
+        size = (size << 7) | byte as u32;
+    }
+    if major_version == 0xff || minor_version == 0xff {
+        return fmt_err("invalid ID3v2 tag version")
     }
+
+    // If the footer flag is set, a copy of the header follows the tag.
+    if flags & 0x10 != 0 {
+        size += 10;
+    }
+    try!(input.skip(size));
+    Ok(())
 }
 
 impl<R: io::Read> FlacReader<R> {
@@ -224,47 +686,123 @@ impl<R: io::Read> FlacReader<R> {
     /// small damaged or malicous file could cause gigabytes of memory
     /// to be allocated. `Error::Unsupported` is returned in that case.
     pub fn new_ext(reader: R, options: FlacReaderOptions) -> Result<FlacReader<R>> {
-        let mut buf_reader = BufferedReader::new(reader);
+        FlacReader::new_impl(BufferedReader::new(reader), options, |_block| {})
+    }
+
+    /// Create a reader that reports every metadata block to a callback.
+    ///
+    /// The callback is invoked for every metadata block as soon as it has been
+    /// parsed, in the order in which the blocks occur in the stream, starting
+    /// with the streaminfo block. All callbacks happen before this constructor
+    /// returns, and hence before any audio is decoded.
+    ///
+    /// This allows reacting to metadata blocks that the `FlacReader` does not
+    /// retain, or which are not worth keeping in memory, without reading the
+    /// metadata a second time.
+    pub fn new_with_metadata_callback<F>(reader: R, callback: F) -> Result<FlacReader<R>>
+        where F: FnMut(&MetadataBlock) {
+        FlacReader::new_impl(BufferedReader::new(reader), FlacReaderOptions::default(), callback)
+    }
+
+    fn new_impl<F>(mut buf_reader: BufferedReader<R>,
+                   options: FlacReaderOptions,
+                   mut callback: F)
+                   -> Result<FlacReader<R>>
+        where F: FnMut(&MetadataBlock) {
         let mut opts_current = options;
 
         // A flac stream first of all starts with a stream header.
-        try!(read_stream_header(&mut buf_reader));
+        try!(read_stream_header(&mut buf_reader, &options));
 
         // Start a new scope, because the input reader must be available again
         // for the frame reader next.
-        let (streaminfo, vorbis_comment) = {
+        let (mut streaminfo, mut metadata_blocks) = {
             // Next are one or more metadata blocks. The flac specification
-            // dictates that the streaminfo block is the first block. The metadata
-            // block reader will yield at least one element, so the unwrap is safe.
-            let mut metadata_iter = MetadataBlockReader::new(&mut buf_reader);
-            let streaminfo_block = try!(metadata_iter.next().unwrap());
+            // dictates that the streaminfo block is the first block.
+            let limits = &options.metadata_limits;
+            let header = try!(metadata::read_metadata_block_header(&mut buf_reader));
+            let streaminfo_block = if options.derive_sample_rate && header.block_type == 0 {
+                let streaminfo = try!(metadata::read_streaminfo_block_lenient(&mut buf_reader,
+                                                                              header.length));
+                MetadataBlock::StreamInfo(streaminfo)
+            } else {
+                try!(metadata::read_metadata_block_ext(&mut buf_reader,
+                                                       header.block_type,
+                                                       header.length,
+                                                       limits))
+            };
+            callback(&streaminfo_block);
             let streaminfo = match streaminfo_block {
                 MetadataBlock::StreamInfo(info) => info,
                 _ => return fmt_err("streaminfo block missing"),
             };
 
-            let mut vorbis_comment = None;
+            let mut metadata_blocks = vec![streaminfo_block];
+            let mut has_seektable = false;
+            let mut has_cuesheet = false;
+            let mut has_vorbis_comment = false;
 
             // There might be more metadata blocks, read and store them.
-            for block_result in metadata_iter {
-                match try!(block_result) {
-                    MetadataBlock::VorbisComment(vc) => {
+            let mut is_last = header.is_last;
+            while !is_last {
+                let header = try!(metadata::read_metadata_block_header(&mut buf_reader));
+                is_last = header.is_last;
+
+                // Blocks that would be discarded are not parsed, but skipped
+                // like padding. If the input can seek, they are not even read.
+                let is_discarded = match header.block_type {
+                    4 => !options.read_vorbis_comment,
+                    6 => !options.read_pictures,
+                    _ => false,
+                };
+                if is_discarded {
+                    try!(buf_reader.skip(header.length));
+                    continue
+                }
+
+                let block = try!(metadata::read_metadata_block_ext(&mut buf_reader,
+                                                                   header.block_type,
+                                                                   header.length,
+                                                                   limits));
+                callback(&block);
+                let keep = match block {
+                    MetadataBlock::VorbisComment(..) => {
                         // The Vorbis comment block need not be present, but
                         // when it is, it must be unique.
-                        if vorbis_comment.is_some() {
+                        if has_vorbis_comment {
                             return fmt_err("encountered second Vorbis comment block")
-                        } else {
-                            vorbis_comment = Some(vc);
                         }
+                        has_vorbis_comment = true;
 
                         // We have one, no new one is desired.
                         opts_current.read_vorbis_comment = false;
+                        true
+                    }
+                    MetadataBlock::SeekTable(..) => {
+                        // Like the Vorbis comment block, the seek table is
+                        // optional, but there must be at most one.
+                        if has_seektable {
+                            return fmt_err("encountered second seek table block")
+                        }
+                        has_seektable = true;
+                        true
+                    }
+                    MetadataBlock::CueSheet(..) => {
+                        // There is at most one CUE sheet too.
+                        if has_cuesheet {
+                            return fmt_err("encountered second CUE sheet block")
+                        }
+                        has_cuesheet = true;
+                        true
                     }
                     MetadataBlock::StreamInfo(..) => {
                         return fmt_err("encountered second streaminfo block")
                     }
-                    // Other blocks are currently not handled.
-                    _block => {}
+                    _ => true,
+                };
+
+                if keep {
+                    metadata_blocks.push(block);
                 }
 
                 // Early-out reading metadata once all desired blocks have been
@@ -274,15 +812,77 @@ impl<R: io::Read> FlacReader<R> {
                 }
             }
 
-            // TODO: Rather than discarding afterwards, never parse it in the
-            // first place; treat it like padding in the MetadataBlockReader.
-            if !options.read_vorbis_comment {
-                vorbis_comment = None;
-            }
-
-            (streaminfo, vorbis_comment)
+            (streaminfo, metadata_blocks)
         };
 
+        // If all metadata blocks were read, this is where the first frame starts.
+        let frames_offset = buf_reader.position();
+
+        // If the sample rate is unknown, take it from the first frame header.
+        let sample_rate_is_derived = streaminfo.sample_rate == 0;
+        if sample_rate_is_derived {
+            trace!(DerivedStreamInfo("sample_rate"));
+            let info = if options.metadata_only {
+                None
+            } else {
+                try!(seek::peek_frame_info(&mut buf_reader, streaminfo.fixed_block_size()))
+            };
+            streaminfo.sample_rate = match info.and_then(|info| info.sample_rate) {
+                Some(sr) if sr > 0 => sr,
+                _ => return fmt_err("invalid sample rate"),
+            };
+            metadata_blocks[0] = MetadataBlock::StreamInfo(streaminfo);
+        }
+
+        Ok(FlacReader::from_parts(buf_reader, options, streaminfo, metadata_blocks, frames_offset,
+                                  sample_rate_is_derived))
+    }
+
+    /// Create a reader for bare frames, with a streaminfo supplied by the caller.
+    ///
+    /// Live streams may deliver only frames, without the stream header and
+    /// metadata, when the streaminfo is known out of band. Decoding starts at
+    /// the first valid frame header, so the input may start in the middle of a
+    /// frame. The maximum block size and the number of channels in the
+    /// streaminfo are enforced as usual; if the maximum block size is not
+    /// known, use `frame::MAX_BLOCK_SIZE`. The streaminfo is the only metadata
+    /// block of the reader. It is validated like a streaminfo block read from
+    /// a stream, so an invalid one results in a `FormatError`.
+    pub fn new_headerless(reader: R,
+                          streaminfo: StreamInfo,
+                          options: FlacReaderOptions)
+                          -> Result<FlacReader<R>> {
+        if streaminfo.max_block_size < 16 {
+            return fmt_err("invalid block size, must be at least 16");
+        }
+        if streaminfo.sample_rate == 0 || streaminfo.sample_rate > 655350 {
+            return fmt_err("invalid sample rate");
+        }
+        if streaminfo.channels < 1 || streaminfo.channels > 8 {
+            return fmt_err("invalid number of channels, must be 1 to 8");
+        }
+        if streaminfo.bits_per_sample < 4 || streaminfo.bits_per_sample > 32 {
+            return fmt_err("invalid bits per sample, must be 4 to 32");
+        }
+
+        let mut buf_reader = BufferedReader::new(reader);
+        if !options.metadata_only {
+            try!(seek::resync(&mut buf_reader, streaminfo.fixed_block_size(), 0, None));
+        }
+        let frames_offset = buf_reader.position();
+        let metadata_blocks = vec![MetadataBlock::StreamInfo(streaminfo)];
+        Ok(FlacReader::from_parts(buf_reader, options, streaminfo, metadata_blocks, frames_offset,
+                                  false))
+    }
+
+    /// Creates a reader positioned at the first frame, after the metadata has been read.
+    fn from_parts(buf_reader: BufferedReader<R>,
+                  options: FlacReaderOptions,
+                  streaminfo: StreamInfo,
+                  metadata_blocks: Vec<MetadataBlock>,
+                  frames_offset: u64,
+                  sample_rate_is_derived: bool)
+                  -> FlacReader<R> {
         // Even if we might have read all metadata blocks, only set the state to
         // "full" if `metadata_only` was false: this results in more predictable
         // behavior.
@@ -292,14 +892,54 @@ impl<R: io::Read> FlacReader<R> {
             FlacReaderState::Full(buf_reader)
         };
 
+        let subset_checker = if options.check_subset {
+            Some(subset::Checker::new(&streaminfo, &metadata_blocks))
+        } else {
+            None
+        };
+
+        // Allocate up front, so that decoding does not need to. The seek index
+        // gets one entry per frame, which is exact for a fixed block size, but
+        // the number of frames is capped in case the streaminfo is bogus.
+        let (buffer, seek_index) = if options.metadata_only {
+            (Vec::new(), Vec::new())
+        } else {
+            let n_frames = match streaminfo.samples {
+                Some(n) if streaminfo.max_block_size > 0 => {
+                    cmp::min(n / streaminfo.max_block_size as u64 + 1, 1 << 16) as usize
+                }
+                _ => 0,
+            };
+            (Vec::with_capacity(streaminfo.max_block_len()), Vec::with_capacity(n_frames))
+        };
+
         // The flac reader will contain the reader that will read frames.
-        let flac_reader = FlacReader {
+        FlacReader {
             streaminfo: streaminfo,
-            vorbis_comment: vorbis_comment,
+            metadata_blocks: metadata_blocks,
+            frames_offset: frames_offset,
+            seek_skip: 0,
+            seek_index: seek_index,
+            buffer: buffer,
+            verify_crc: options.verify_crc,
+            verify_header_crc: options.verify_header_crc,
+            error_offset: None,
+            frame_index: Some(0),
+            crc_mismatch: None,
+            resync: options.resync,
+            salvage_partial_frame: options.salvage_partial_frame,
+            compute_md5: options.compute_md5,
+            md5: None,
+            md5_next_sample: 0,
+            md5_matches: None,
+            sample_rate_is_derived: sample_rate_is_derived,
+            lost_samples: Vec::new(),
+            last_frame: None,
+            decoded_frames: (0, 0),
+            metrics: metrics::Metrics::new(),
+            subset_checker: subset_checker,
             input: state,
-        };
-
-        Ok(flac_reader)
+        }
     }
 
     /// Returns the streaminfo metadata.
@@ -309,13 +949,100 @@ impl<R: io::Read> FlacReader<R> {
         self.streaminfo
     }
 
+    /// Returns all metadata blocks that were read, in stream order.
+    ///
+    /// The first block is always the streaminfo block. Padding blocks are
+    /// included. Blocks that were not desired according to the
+    /// `FlacReaderOptions`, or that were not read because of an early-out with
+    /// `metadata_only`, are not. See the other accessors, such as
+    /// `seektable()` and `pictures()`, for typed access per block kind.
+    pub fn metadata<'a>(&'a self) -> slice::Iter<'a, MetadataBlock> {
+        self.metadata_blocks.iter()
+    }
+
+    /// Returns the seek table, if present.
+    ///
+    /// The seek table is optional. When `metadata_only` is set, the seek table
+    /// is only available if it was read before all desired blocks were found.
+    pub fn seektable(&self) -> Option<&SeekTable> {
+        find_seektable(&self.metadata_blocks)
+    }
+
+    /// Returns whether the stream has a seek table.
+    ///
+    /// This is cheaper than inspecting `seektable()` when all you need is to
+    /// decide between seeking with the table and scanning the stream.
+    pub fn has_seektable(&self) -> bool {
+        self.seektable().is_some()
+    }
+
+    /// Returns the number of seek points in the seek table, or 0 if absent.
+    ///
+    /// Placeholder points are included in the count.
+    pub fn num_seek_points(&self) -> usize {
+        self.seektable().map_or(0, |st| st.len())
+    }
+
+    /// Returns the application ID and payload of all application blocks read.
+    ///
+    /// Application blocks hold data for third-party applications, identified
+    /// by a registered 32-bit ID. They are returned in stream order.
+    pub fn application_blocks<'a>(&'a self) -> metadata::ApplicationBlocks<'a> {
+        metadata::ApplicationBlocks::new(&self.metadata_blocks[..])
+    }
+
+    /// Returns the data of a RIFF chunk preserved in an application block.
+    ///
+    /// When a WAV file is converted to FLAC with `flac --keep-foreign-metadata`,
+    /// its non-audio chunks are stored in application blocks with ID
+    /// `metadata::APPLICATION_ID_RIFF`. This returns the data of the first
+    /// such chunk with the given chunk ID, for instance the Broadcast WAV
+    /// `metadata::RIFF_CHUNK_BEXT` chunk, or `metadata::RIFF_CHUNK_IXML`.
+    pub fn riff_chunk(&self, chunk_id: &[u8; 4]) -> Option<&[u8]> {
+        self.application_blocks()
+            .filter(|&(id, _)| id == metadata::APPLICATION_ID_RIFF)
+            .filter_map(|(_, payload)| metadata::riff_chunk(payload))
+            .find(|&(id, _)| id == &chunk_id[..])
+            .map(|(_, data)| data)
+    }
+
+    /// Returns the CUE sheet, if the stream contains one.
+    ///
+    /// The CUE sheet holds the track boundaries when an entire album is
+    /// stored as a single stream.
+    pub fn cuesheet(&self) -> Option<&CueSheet> {
+        self.metadata().filter_map(|block| match *block {
+            MetadataBlock::CueSheet(ref cuesheet) => Some(cuesheet),
+            _ => None,
+        }).next()
+    }
+
+    /// Returns the pictures embedded in the stream, such as album art.
+    ///
+    /// Pictures are returned in the order in which they occur in the stream.
+    /// Use `Picture::picture_type` to find e.g. the front cover. If
+    /// `FlacReaderOptions::read_pictures` was false, no pictures are returned.
+    pub fn pictures<'a>(&'a self) -> metadata::Pictures<'a> {
+        metadata::Pictures::new(&self.metadata_blocks[..])
+    }
+
+    /// Returns the Vorbis comment block, if present.
+    ///
+    /// See `tags()` and `get_tag()` for friendlier access to the tags.
+    pub fn vorbis_comment(&self) -> Option<&VorbisComment> {
+        self.metadata().filter_map(|block| match *block {
+            MetadataBlock::VorbisComment(ref vorbis_comment) => Some(vorbis_comment),
+            _ => None,
+        }).next()
+    }
+
     /// Returns the vendor string of the Vorbis comment block, if present.
     ///
     /// This string usually contains the name and version of the program that
     /// encoded the FLAC stream, such as `reference libFLAC 1.3.2 20170101`
     /// or `Lavf57.25.100`.
     pub fn vendor(&self) -> Option<&str> {
-        self.vorbis_comment.as_ref().map(|vc| &vc.vendor[..])
+        self.vorbis_comment().map(|vc| &vc.vendor[..])
     }
 
     /// Returns name-value pairs of Vorbis comments, such as `("ARTIST", "Queen")`.
@@ -329,7 +1056,7 @@ impl<R: io::Read> FlacReader<R> {
     ///
     /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
     pub fn tags<'a>(&'a self) -> metadata::Tags<'a> {
-        match self.vorbis_comment.as_ref() {
+        match self.vorbis_comment() {
             Some(vc) => metadata::Tags::new(&vc.comments[..]),
             None => metadata::Tags::new(&[]),
         }
@@ -348,86 +1075,978 @@ impl<R: io::Read> FlacReader<R> {
     /// See also `tags()` for access to the raw tags.
     /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
     pub fn get_tag<'a>(&'a self, tag_name: &'a str) -> metadata::GetTag<'a> {
-        match self.vorbis_comment.as_ref() {
+        match self.vorbis_comment() {
             Some(vc) => metadata::GetTag::new(&vc.comments[..], tag_name),
             None => metadata::GetTag::new(&[], tag_name),
         }
     }
 
+    /// Returns the tempo in beats per minute, from the `BPM` tag.
+    ///
+    /// DJ software stores the tempo as a Vorbis comment, either as an integer
+    /// such as `128`, or as a decimal number such as `127.98`. Returns `None`
+    /// if the tag is absent, or if its value is not a positive number.
+    pub fn bpm(&self) -> Option<f64> {
+        self.get_tag("BPM")
+            .filter_map(|value| value.trim().parse::<f64>().ok())
+            .find(|&bpm| bpm > 0.0 && bpm.is_finite())
+    }
+
+    /// Returns the musical key, from the `INITIALKEY` or `KEY` tag.
+    ///
+    /// The value is returned as-is; different programs use different notations,
+    /// such as `Am`, `A minor`, or Camelot notation like `8A`. The `INITIALKEY`
+    /// tag is preferred when both are present.
+    pub fn musical_key(&self) -> Option<&str> {
+        self.get_tag("INITIALKEY")
+            .chain(self.get_tag("KEY"))
+            .map(|value| value.trim())
+            .find(|value| !value.is_empty())
+    }
+
+    #[cfg(feature = "std")]
+    /// Returns the ReplayGain of the track, from the `REPLAYGAIN_TRACK_*` tags.
+    ///
+    /// Returns `None` if there is no valid `REPLAYGAIN_TRACK_GAIN` tag. The
+    /// peak is optional. Use `replaygain::ApplyGain` to apply the gain.
+    pub fn track_gain(&self) -> Option<replaygain::ReplayGain> {
+        self.replaygain("REPLAYGAIN_TRACK_GAIN", "REPLAYGAIN_TRACK_PEAK")
+    }
+
+    #[cfg(feature = "std")]
+    /// Returns the ReplayGain of the album, from the `REPLAYGAIN_ALBUM_*` tags.
+    ///
+    /// Like `track_gain()`, but for playing the album at a consistent loudness.
+    pub fn album_gain(&self) -> Option<replaygain::ReplayGain> {
+        self.replaygain("REPLAYGAIN_ALBUM_GAIN", "REPLAYGAIN_ALBUM_PEAK")
+    }
+
+    #[cfg(feature = "std")]
+    fn replaygain(&self, gain_tag: &str, peak_tag: &str) -> Option<replaygain::ReplayGain> {
+        self.get_tag(gain_tag).filter_map(replaygain::parse_gain).next().map(|gain| {
+            replaygain::ReplayGain {
+                gain: gain,
+                peak: self.get_tag(peak_tag).filter_map(replaygain::parse_peak).next(),
+            }
+        })
+    }
+
+    /// Returns the first `TITLE` tag, if present.
+    ///
+    /// Use `get_tag("TITLE")` to get all of them.
+    pub fn title(&self) -> Option<&str> {
+        self.get_tag("TITLE").next()
+    }
+
+    /// Returns the first `ARTIST` tag, if present.
+    ///
+    /// A collaboration track may have multiple `ARTIST` tags. Use
+    /// `get_tag("ARTIST")` to get all of them.
+    pub fn artist(&self) -> Option<&str> {
+        self.get_tag("ARTIST").next()
+    }
+
+    /// Returns the first `ALBUM` tag, if present.
+    pub fn album(&self) -> Option<&str> {
+        self.get_tag("ALBUM").next()
+    }
+
+    /// Returns the track number, from the `TRACKNUMBER` tag.
+    ///
+    /// Some taggers store the total number of tracks in the same tag, as in
+    /// `3/12`; only the track number is returned then. Returns `None` if the
+    /// tag is absent or not a number.
+    pub fn track_number(&self) -> Option<u32> {
+        self.get_tag("TRACKNUMBER").filter_map(parse_number_of).next()
+    }
+
+    /// Returns the disc number, from the `DISCNUMBER` tag.
+    ///
+    /// Like the track number, the total may be included, as in `1/2`.
+    pub fn disc_number(&self) -> Option<u32> {
+        self.get_tag("DISCNUMBER").filter_map(parse_number_of).next()
+    }
+
+    /// Returns which speaker every channel belongs to.
+    ///
+    /// This is the default layout for the number of channels, unless the
+    /// stream has a `WAVEFORMATEXTENSIBLE_CHANNEL_MASK` tag. Returns `None`
+    /// if the tag holds a mask that does not match one of the layouts with
+    /// this number of channels; the speakers are unknown then.
+    pub fn channel_layout(&self) -> Option<ChannelLayout> {
+        let channels = self.streaminfo.channels;
+        let tag = "WAVEFORMATEXTENSIBLE_CHANNEL_MASK";
+        match self.get_tag(tag).filter_map(parse_channel_mask).next() {
+            Some(mask) => match ChannelLayout::from_channel_mask(mask) {
+                Some(layout) if layout.channels() == channels => Some(layout),
+                _ => None,
+            },
+            None => self.streaminfo.channel_layout(),
+        }
+    }
+
+    /// Returns the frames seen so far, as seek points ordered by sample number.
+    ///
+    /// Frames are recorded as they are decoded by `samples()` and
+    /// `decode_block_interleaved_into()`, or passed over by seeking and
+    /// skipping, but not when decoded through `blocks()`. The index makes
+    /// later seeks to an earlier position cheap, even if the stream has no
+    /// seek table. Offsets are relative to the first frame, like in a seek
+    /// table, so the index can be persisted as one.
+    pub fn seek_index(&self) -> &[SeekPoint] {
+        &self.seek_index
+    }
+
     /// Returns an iterator that decodes a single frame on every iteration.
-    /// TODO: It is not an iterator.
     ///
     /// This is a low-level primitive that gives you control over when decoding
     /// happens. The representation of the decoded audio is somewhat specific to
     /// the FLAC format. For a higher-level interface, see `samples()`.
+    ///
+    /// After a seek, the first block is the one that contains the target
+    /// sample; it may start before the target.
     pub fn blocks<'r>(&'r mut self) -> FrameReader<&'r mut BufferedReader<R>> {
+        let fixed_block_size = self.streaminfo.fixed_block_size();
+        self.seek_skip = 0;
+        // Frames decoded through the frame reader are not counted.
+        self.frame_index = None;
         match self.input {
-            FlacReaderState::Full(ref mut inp) => FrameReader::new(inp),
+            FlacReaderState::Full(ref mut inp) => {
+                let mut frame_reader = FrameReader::with_fixed_block_size(inp, fixed_block_size);
+                frame_reader.set_verify_crc(self.verify_crc);
+                frame_reader.set_verify_header_crc(self.verify_header_crc);
+                frame_reader.set_salvage_partial(self.salvage_partial_frame);
+                frame_reader.set_max_block_size(self.streaminfo.max_block_size);
+                frame_reader.set_channels(self.streaminfo.channels);
+                frame_reader
+            }
             FlacReaderState::MetadataOnly(..) =>
                 panic!("FlacReaderOptions::metadata_only must be false \
                        to be able to use FlacReader::blocks()"),
         }
     }
 
-    /// Returns an iterator over all samples.
+    /// Skips the next `n` inter-channel samples, without decoding them.
     ///
-    /// The channel data is is interleaved. The iterator is streaming. That is,
-    /// if you call this method once, read a few samples, and call this method
-    /// again, the second iterator will not start again from the beginning of
-    /// the file. It will continue somewhere after where the first iterator
-    /// stopped, and it might skip some samples. (This is because FLAC divides
-    /// a stream into blocks, which have to be decoded entirely. If you drop the
-    /// iterator, you lose the unread samples in that block.)
+    /// This works on any input, including pipes that cannot seek. Frames are
+    /// skipped by parsing only their headers, and scanning for the header of
+    /// the next frame. The frame that contains the target sample is decoded
+    /// by the next `samples()` iterator, which starts at the target sample.
+    /// Like after a seek, `blocks()` starts at the frame that contains the
+    /// target instead.
     ///
-    /// This is a user-friendly interface that trades performance for ease of
-    /// use. If performance is an issue, consider using `blocks()` instead.
+    /// Samples are counted from the start of the next frame, or from the
+    /// target of a preceding seek or skip. Returns the number of samples
+    /// skipped, which is less than `n` only if the stream ended.
     ///
-    /// This is a high-level interface to the decoder. The cost of retrieving
-    /// the next sample can vary significantly, as sometimes a new block has to
-    /// be decoded. Additionally, there is a cost to every iteration returning a
-    /// `Result`. When a block has been decoded, iterating the samples in that
-    /// block can never fail, but a match on every sample is required
-    /// nonetheless. For more control over when decoding happens, and less error
-    /// handling overhead, use `blocks()`.
-    pub fn samples<'r>(&'r mut self) -> FlacSamples<&'r mut BufferedReader<R>> {
-        match self.input {
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn skip_samples(&mut self, n: u64) -> Result<u64> {
+        let fixed_block_size = self.streaminfo.fixed_block_size();
+        let pending = self.seek_skip as u64;
+        let (skipped, skip) = match self.input {
             FlacReaderState::Full(ref mut inp) => {
-                FlacSamples {
-                    frame_reader: frame::FrameReader::new(inp),
-                    block: Block::empty(),
-                    sample: 0,
-                    channel: 0,
-                    has_failed: false,
-                }
+                try!(seek::skip_samples(inp, fixed_block_size, &mut self.seek_index,
+                                        self.frames_offset, n.saturating_add(pending)))
             }
             FlacReaderState::MetadataOnly(..) => {
                 panic!("FlacReaderOptions::metadata_only must be false \
-                       to be able to use FlacReader::samples()")
+                       to be able to use FlacReader::skip_samples()")
             }
-        }
+        };
+        self.seek_skip = skip;
+        self.frame_index = None;
+        Ok(skipped.saturating_sub(pending))
     }
 
-    /// Destroys the FLAC reader and returns the underlying reader.
+    /// Scans the rest of the stream, and returns a seek table with a point every `interval` samples.
     ///
-    /// Because the reader employs buffering internally, anything in the buffer
-    /// will be lost.
-    pub fn into_inner(self) -> R {
-        match self.input {
-            FlacReaderState::Full(inp) => inp.into_inner(),
-            FlacReaderState::MetadataOnly(inp) => inp.into_inner(),
+    /// Only frame headers are parsed, like in `skip_samples()`, so this is
+    /// much faster than decoding. For every multiple of `interval`, the table
+    /// contains a point for the frame that contains that sample. For a point
+    /// every ten seconds, pass ten times the sample rate. The table can be
+    /// used to add a seek table to a stream that lacks one.
+    ///
+    /// The table is built from the seek index, so frames before the current
+    /// position are only included if they were decoded with `samples()` or
+    /// `decode_block_interleaved_into()`, or skipped over. Call this before
+    /// decoding any audio to be sure. Afterwards, the reader is at the end of
+    /// the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero, or if the reader was constructed with
+    /// `metadata_only` set.
+    pub fn generate_seektable(&mut self, interval: u64) -> Result<SeekTable> {
+        assert!(interval > 0, "seek point interval must be positive");
+        try!(self.skip_samples(u64::MAX));
+
+        let mut seekpoints = Vec::new();
+        let mut target = 0;
+        for point in &self.seek_index {
+            let end = point.sample + point.samples as u64;
+            if end > target {
+                seekpoints.push(*point);
+                // The next target is the first multiple of the interval after this frame.
+                target = (end + interval - 1) / interval * interval;
+            }
         }
+        Ok(SeekTable::from_seekpoints(seekpoints))
     }
-}
 
-impl FlacReader<fs::File> {
-    /// Attempts to create a reader that reads from the specified file.
+    /// Returns the number of the inter-channel sample that decoding continues at.
     ///
-    /// This is a convenience constructor that opens a `File`, and constructs a
-    /// `FlacReader` from it. There is no need to wrap the file in a
+    /// This is the first sample that a new `samples()` iterator yields, taking
+    /// into account a preceding seek or skip. Samples that an earlier
+    /// `samples()` iterator did not yield before it was dropped, are not
+    /// counted; see `FlacSamples::position()` to track the position while
+    /// iterating. The header of the next frame is parsed to determine the
+    /// position, the frame is not decoded.
+    ///
+    /// At the end of the stream, this returns the total number of samples if
+    /// the streaminfo specifies it, or `None` otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn tell(&mut self) -> Result<Option<u64>> {
+        let fixed_block_size = self.streaminfo.fixed_block_size();
+        let info = match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                try!(seek::peek_frame_info(inp, fixed_block_size))
+            }
+            FlacReaderState::MetadataOnly(..) => {
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::tell()")
+            }
+        };
+        match info {
+            Some(info) => Ok(Some(info.first_sample + self.seek_skip as u64)),
+            None => Ok(self.streaminfo.samples),
+        }
+    }
+
+    /// Returns the time since the start of the stream that decoding continues at.
+    ///
+    /// This is `tell()` converted into a duration with the sample rate from
+    /// the streaminfo.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn tell_time(&mut self) -> Result<Option<Duration>> {
+        let sample_rate = self.streaminfo.sample_rate;
+        Ok(try!(self.tell()).map(|sample| sample_to_duration(sample, sample_rate)))
+    }
+
+    /// Returns the number of bytes of the input consumed so far.
+    ///
+    /// The offset includes the metadata blocks. When no frame has been
+    /// partially decoded, it is the offset of the next frame in the input.
+    pub fn byte_position(&self) -> u64 {
+        match self.input {
+            FlacReaderState::Full(ref inp) => inp.position(),
+            FlacReaderState::MetadataOnly(ref inp) => inp.position(),
+        }
+    }
+
+    /// Returns an iterator over all samples.
+    ///
+    /// The channel data is is interleaved. The iterator is streaming. That is,
+    /// if you call this method once, read a few samples, and call this method
+    /// again, the second iterator will not start again from the beginning of
+    /// the file. It will continue somewhere after where the first iterator
+    /// stopped, and it might skip some samples. (This is because FLAC divides
+    /// a stream into blocks, which have to be decoded entirely. If you drop the
+    /// iterator, you lose the unread samples in that block.)
+    ///
+    /// This is a user-friendly interface that trades performance for ease of
+    /// use. If performance is an issue, consider using `blocks()` instead.
+    ///
+    /// This is a high-level interface to the decoder. The cost of retrieving
+    /// the next sample can vary significantly, as sometimes a new block has to
+    /// be decoded. Additionally, there is a cost to every iteration returning a
+    /// `Result`. When a block has been decoded, iterating the samples in that
+    /// block can never fail, but a match on every sample is required
+    /// nonetheless. For more control over when decoding happens, and less error
+    /// handling overhead, use `blocks()`.
+    pub fn samples<'r>(&'r mut self) -> FlacSamples<'r, R> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::samples()")
+        }
+        // An error in the header of the next frame is reported when it is
+        // decoded, for now it only means that the size hint is unknown.
+        let start = self.tell().unwrap_or(None);
+        let skip = mem::replace(&mut self.seek_skip, 0);
+        FlacSamples {
+            reader: self,
+            state: SamplesState::new(skip, start),
+        }
+    }
+
+    /// Returns an iterator over all samples, as floating point numbers.
+    ///
+    /// This behaves like `samples()`, but the samples are scaled by the bit
+    /// depth of the stream, such that full scale is [-1.0, 1.0). See
+    /// `SamplesF32` for details.
+    pub fn samples_f32<'r>(&'r mut self) -> SamplesF32<FlacSamples<'r, R>> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        SamplesF32::new(self.samples(), bits_per_sample)
+    }
+
+    #[cfg(feature = "std")]
+    /// Returns an iterator over all samples, reduced to `target_bits` with dither.
+    ///
+    /// This behaves like `samples()`, but if the stream has more than
+    /// `target_bits` bits per sample, the samples are reduced with TPDF dither,
+    /// and optionally noise shaping. See `dither::Dither` for details.
+    pub fn samples_dithered<'r>(&'r mut self,
+                                target_bits: u32,
+                                noise_shaping: bool)
+                                -> dither::Dither<FlacSamples<'r, R>> {
+        let channels = self.streaminfo.channels;
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        dither::Dither::new(self.samples(), channels, bits_per_sample, target_bits, noise_shaping)
+    }
+
+    /// Returns an iterator over all samples, that takes ownership of the reader.
+    ///
+    /// This behaves like `samples()`, but the iterator owns the reader, so it
+    /// can outlive the scope in which the reader was constructed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn into_samples(mut self) -> FlacIntoSamples<R> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::into_samples()")
+        }
+        let start = self.tell().unwrap_or(None);
+        let skip = mem::replace(&mut self.seek_skip, 0);
+        FlacIntoSamples {
+            reader: self,
+            state: SamplesState::new(skip, start),
+        }
+    }
+
+    /// Returns one iterator per channel, over the samples of that channel.
+    ///
+    /// The iterators are independent, they can be zipped, or one channel can
+    /// be consumed entirely before the next. Every block is decoded once, and
+    /// the samples of the channels that were not consumed yet are queued, so
+    /// when one iterator runs ahead of the others, the queues of the others
+    /// grow. Like `samples()`, iteration continues after a seek. An error is
+    /// yielded by the iterator that triggered decoding, the other iterators
+    /// end after their queued samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn channels<'r>(&'r mut self) -> Vec<ChannelSamples<'r, R>> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::channels()")
+        }
+        let channels = self.streaminfo.channels as usize;
+        let skip = mem::replace(&mut self.seek_skip, 0);
+        let shared = Rc::new(RefCell::new(ChannelsState {
+            reader: self,
+            queues: vec![VecDeque::new(); channels],
+            skip: skip,
+            buffer: Vec::new(),
+            is_done: false,
+        }));
+        (0..channels)
+            .map(|ch| ChannelSamples { shared: shared.clone(), channel: ch })
+            .collect()
+    }
+
+    /// Decodes the remaining audio into a vector of interleaved samples.
+    ///
+    /// This is a shorthand for reading all of `samples()`. The vector is
+    /// allocated up front for the number of samples in the streaminfo, up to
+    /// a limit, so that an invalid streaminfo cannot exhaust memory. Returns
+    /// the streaminfo along with the samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn decode_all(mut self) -> Result<(StreamInfo, Vec<i32>)> {
+        let streaminfo = self.streaminfo;
+        let expected = match streaminfo.samples {
+            Some(n) => n.saturating_mul(streaminfo.channels as u64),
+            None => 0,
+        };
+        let mut buffer = Vec::with_capacity(cmp::min(expected, 1 << 24) as usize);
+        {
+            let mut samples = self.samples();
+            loop {
+                // Decode directly into the vector, in chunks of about a block.
+                let len = buffer.len();
+                buffer.resize(len + cmp::max(streaminfo.max_block_len(), 1), 0);
+                let n = try!(samples.read_samples_into(&mut buffer[len..]));
+                buffer.truncate(len + n);
+                if n == 0 {
+                    break
+                }
+            }
+        }
+        Ok((streaminfo, buffer))
+    }
+
+    /// Decodes the next block, and records it in the seek index.
+    ///
+    /// In resync mode, damaged frames are skipped, and recorded as lost. Like
+    /// `blocks()`, this discards the skip after a seek, so callers that must
+    /// start at the target sample take `seek_skip` first.
+    fn read_next_block(&mut self, mut buffer: Vec<i32>) -> FrameResult {
+        // A new iterator has no buffer yet, it gets the one allocated up front.
+        if buffer.capacity() == 0 {
+            buffer = mem::replace(&mut self.buffer, Vec::new());
+        }
+        loop {
+            // In chained streams, the header of the next stream ends this one.
+            let is_next_stream = match self.input {
+                FlacReaderState::Full(ref mut inp) => try!(inp.peek(4)).starts_with(b"fLaC"),
+                FlacReaderState::MetadataOnly(..) => unreachable!(),
+            };
+            if is_next_stream {
+                self.finish_md5();
+                return Ok(None)
+            }
+
+            let offset = self.byte_position() - self.frames_offset;
+            let frame_index = self.frame_index;
+            let (result, crc_mismatch, frame_metrics) = {
+                let mut frames = self.blocks();
+                let result = frames.read_next_with_info_or_eof(buffer);
+                (result, frames.crc_mismatch(), metrics::frame_metrics(&frames))
+            };
+            metrics::merge(&mut self.metrics, &frame_metrics);
+            let result = result.map_err(|err| {
+                error::locate_frame(err, self.frames_offset + offset, frame_index)
+            });
+            if result.is_err() {
+                self.error_offset = Some(self.frames_offset + offset);
+                self.crc_mismatch = crc_mismatch;
+            }
+            match result {
+                Ok(Some((info, block))) => {
+                    if let Some(ref mut checker) = self.subset_checker {
+                        checker.check_frame(&self.streaminfo, &info);
+                    }
+                    seek::record_frame(&mut self.seek_index, block.time(), offset,
+                                       block.duration() as u16);
+                    let len = self.byte_position() - self.frames_offset - offset;
+                    let duration = block.duration() as u64;
+                    self.last_frame = Some((len, duration));
+                    self.decoded_frames.0 += len;
+                    self.decoded_frames.1 += duration;
+                    self.frame_index = frame_index.map(|i| i + 1);
+                    if self.compute_md5 {
+                        self.update_md5(&block);
+                    }
+                    return Ok(Some(block))
+                }
+                Ok(None) => {
+                    self.finish_md5();
+                    return Ok(None)
+                }
+                Err(Error::FormatError { .. }) if self.resync => {
+                    try!(self.skip_damaged_frame(offset));
+                }
+                Err(err) => return Err(err),
+            }
+            // The buffer was consumed by the failed decode.
+            buffer = Vec::new();
+        }
+    }
+
+    /// Adds a decoded block to the running MD5 hash.
+    ///
+    /// The hash is only valid if it covers all audio from the start of the
+    /// stream, so decoding the first block (re)starts it, and a block that
+    /// does not follow the previous one, for instance after a seek, ends it.
+    fn update_md5(&mut self, block: &Block) {
+        if block.time() == 0 {
+            self.md5 = Some(md5::Md5::new());
+            self.md5_matches = None;
+        } else if block.time() != self.md5_next_sample {
+            self.md5 = None;
+        }
+        if let Some(ref mut hasher) = self.md5 {
+            hasher.update_block(block, self.streaminfo.bits_per_sample);
+        }
+        self.md5_next_sample = block.time() + block.duration() as u64;
+    }
+
+    /// Compares the running MD5 hash against the streaminfo, at the end of the stream.
+    fn finish_md5(&mut self) {
+        if let Some(hasher) = self.md5.take() {
+            // An encoder may leave the signature unset, then there is nothing
+            // to compare against.
+            if self.streaminfo.md5sum != [0; 16] {
+                self.md5_matches = Some(hasher.finalize() == self.streaminfo.md5sum);
+            }
+        }
+    }
+
+    /// Returns whether the decoded audio matches the MD5 signature in the streaminfo.
+    ///
+    /// This requires `FlacReaderOptions::compute_md5`. Once the end of the
+    /// stream has been reached, after decoding all audio from the start in
+    /// order, this returns whether the MD5 of the decoded audio matches the
+    /// signature. It returns `None` if that is not known: before the end of
+    /// the stream, when the audio was not decoded in one pass from the start
+    /// (because of a seek or skip), or if the encoder left the signature
+    /// unset. To verify the signature without decoding the audio for other
+    /// purposes, `verify_md5()` is simpler.
+    pub fn md5_matches(&self) -> Option<bool> {
+        self.md5_matches
+    }
+
+    /// Advances past a damaged frame at `offset`, and records the lost samples.
+    ///
+    /// The offset is relative to the first frame. The lost samples start
+    /// after the last frame before the damaged one that was seen, and they end
+    /// at the next valid frame.
+    fn skip_damaged_frame(&mut self, offset: u64) -> Result<()> {
+        let start = self.seek_index.iter()
+                                   .rev()
+                                   .find(|p| p.offset < offset)
+                                   .map_or(0, |p| p.sample + p.samples as u64);
+        let fixed_block_size = self.streaminfo.fixed_block_size();
+        let total = self.streaminfo.samples;
+        let next = match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                try!(seek::resync(inp, fixed_block_size, start, total))
+            }
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+        let end = match next {
+            Some(info) => info.first_sample,
+            None => total.unwrap_or(start),
+        };
+        trace!(Resync { offset: offset, lost: start..end });
+        if end > start {
+            self.lost_samples.push(start..end);
+        }
+        Ok(())
+    }
+
+    /// Returns the ranges of samples that were lost in damaged frames.
+    ///
+    /// This is only non-empty when `FlacReaderOptions::resync` is set. Every
+    /// time decoding skips over damaged data, the range of inter-channel
+    /// samples that could not be decoded is appended. When the damage extends
+    /// to the end of the stream, the range ends at the number of samples in
+    /// the streaminfo, if that is known.
+    pub fn lost_samples(&self) -> &[Range<u64>] {
+        &self.lost_samples
+    }
+
+    /// Returns the bitrate of the frame decoded most recently, in bits per second.
+    ///
+    /// This is the length of the frame, including its header, divided by its
+    /// duration. Like the seek index, it covers frames decoded by `samples()`
+    /// and `decode_block_interleaved_into()`, but not by `blocks()`. Returns
+    /// `None` if no frame has been decoded yet, or if the sample rate is unknown.
+    pub fn frame_bitrate(&self) -> Option<u32> {
+        self.last_frame.and_then(|(len, duration)| {
+            bitrate(len, duration, self.streaminfo.sample_rate)
+        })
+    }
+
+    /// Returns the counters of everything decoded so far.
+    ///
+    /// This counts the frames decoded by `samples()` and the other decoding
+    /// methods of the reader. The `FrameReader` returned by `blocks()` counts
+    /// its frames separately, see `FrameReader::metrics()`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns the average bitrate of the frames decoded so far, in bits per second.
+    ///
+    /// This is the total length of the decoded frames divided by their total
+    /// duration, see `frame_bitrate()`. Metadata, and frames that were skipped
+    /// or passed over by seeking, are not included.
+    pub fn average_bitrate(&self) -> Option<u32> {
+        let (len, duration) = self.decoded_frames;
+        bitrate(len, duration, self.streaminfo.sample_rate)
+    }
+
+    /// Returns the byte offset of the frame that failed to decode most recently.
+    ///
+    /// When `samples()`, `into_samples()` or `decode_block_interleaved_into()`
+    /// return an error, this is the offset in the input at which the frame
+    /// that could not be decoded starts, to locate the damage. Whether the
+    /// stream was truncated, damaged, or uses a feature that Claxon does not
+    /// support, is told by the variant of the `Error`. The seek index tells
+    /// which frames were decoded before. Returns `None` if no frame has failed
+    /// to decode.
+    pub fn error_offset(&self) -> Option<u64> {
+        self.error_offset
+    }
+
+    /// Returns whether the sample rate in the streaminfo was taken from the first frame.
+    ///
+    /// This is the case when `FlacReaderOptions::derive_sample_rate` is set,
+    /// and the streaminfo block does not specify the sample rate.
+    pub fn sample_rate_is_derived(&self) -> bool {
+        self.sample_rate_is_derived
+    }
+
+    /// Returns the deviations from the specification and its subset found so far.
+    ///
+    /// This requires `FlacReaderOptions::check_subset`, without it, the
+    /// result is always empty. Deviations in the metadata are found when the
+    /// reader is constructed, deviations in frames are found as the frames are
+    /// decoded, so to check the entire stream, decode all of it.
+    pub fn violations(&self) -> &[subset::Violation] {
+        match self.subset_checker {
+            Some(ref checker) => checker.violations(),
+            None => &[],
+        }
+    }
+
+    /// Returns the details of the checksum mismatch that made decoding fail most recently.
+    ///
+    /// When a frame fails to decode because its CRC-8 or CRC-16 does not
+    /// match, this returns which checksum it was, the stored and the computed
+    /// value, and the position of the frame from its header. It is updated
+    /// along with `error_offset()`, and it is `None` if the most recent
+    /// failure was not due to a checksum.
+    pub fn crc_mismatch(&self) -> Option<CrcMismatch> {
+        self.crc_mismatch
+    }
+
+    /// Decodes the next block, and writes its samples interleaved into `out`.
+    ///
+    /// This is an allocation-free alternative to `samples()`: the block is
+    /// decoded into the buffer owned by `scratch`, and then interleaved into
+    /// the caller-provided `out`. Returns the number of samples written to
+    /// `out` (the block duration times the number of channels), or `None` at
+    /// the end of the stream.
+    ///
+    /// If `out` is smaller than the maximum block size times the number of
+    /// channels, as specified in the streaminfo, `Error::BufferTooSmall` is
+    /// returned, before anything is decoded. A frame that does not fit in
+    /// `out` is an error, as it exceeds the maximum block size that the
+    /// streaminfo declares.
+    ///
+    /// Like with `blocks()`, after a seek the first block is the one that
+    /// contains the target sample; it may start before the target.
+    pub fn decode_block_interleaved_into(&mut self,
+                                         scratch: &mut DecodeScratch,
+                                         out: &mut [i32])
+                                         -> Result<Option<usize>> {
+        let required = self.streaminfo.max_block_len();
+        if out.len() < required {
+            return Err(Error::BufferTooSmall { required: required })
+        }
+
+        let buffer = mem::replace(&mut scratch.buffer, Vec::new());
+        let block = match try!(self.read_next_block(buffer)) {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        let len = block.len() as usize;
+        if len > out.len() {
+            scratch.buffer = block.into_buffer();
+            return fmt_err("block is larger than the maximum block size in the streaminfo");
+        }
+
+        block.copy_interleaved(out);
+        scratch.buffer = block.into_buffer();
+        Ok(Some(len))
+    }
+
+    /// Decodes the remaining audio, and verifies it against the MD5 signature.
+    ///
+    /// The streaminfo block contains an MD5 signature of the unencoded audio.
+    /// This method decodes all frames and compares the MD5 of the decoded
+    /// samples against that signature. It returns `Ok(true)` if they match.
+    /// The signature covers the entire stream, so this must be called before
+    /// any audio has been read. An encoder may leave the signature unset (all
+    /// zeros), in which case `Ok(false)` is returned.
+    ///
+    /// The signature is computed over the interleaved samples as
+    /// little-endian integers, regardless of the endianness of the host.
+    pub fn verify_md5(&mut self) -> Result<bool> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let mut hasher = md5::Md5::new();
+        let mut buffer = Vec::new();
+        {
+            let mut blocks = self.blocks();
+            while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+                hasher.update_block(&block, bits_per_sample);
+                buffer = block.into_buffer();
+            }
+        }
+        Ok(hasher.finalize() == self.streaminfo.md5sum)
+    }
+
+    /// Decodes the start of the stream in the form that audio fingerprinting needs.
+    ///
+    /// Fingerprinting libraries such as Chromaprint (used by AcoustID) need
+    /// roughly the first two minutes of audio as 16-bit mono samples at the
+    /// native sample rate. This method decodes at most `max_seconds` of audio
+    /// from the current position, and returns it in that form.
+    ///
+    /// Channels are downmixed by taking the average of all channels for every
+    /// inter-channel sample (rounding towards zero). Samples are then scaled
+    /// to 16 bits by shifting, dropping the least significant bits of streams
+    /// with more than 16 bits per sample.
+    pub fn fingerprint_samples(&mut self, max_seconds: f64) -> Result<Vec<i16>> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let max_samples = if max_seconds > 0.0 {
+            (max_seconds * self.streaminfo.sample_rate as f64) as u64
+        } else {
+            0
+        };
+
+        // Only allocate up front what the stream claims to contain, the time
+        // limit might be excessive.
+        let capacity = cmp::min(max_samples, self.streaminfo.samples.unwrap_or(0));
+        let mut result = Vec::with_capacity(capacity as usize);
+        let mut buffer = Vec::new();
+        let mut blocks = self.blocks();
+
+        while (result.len() as u64) < max_samples {
+            let block = match try!(blocks.read_next_or_eof(buffer)) {
+                Some(block) => block,
+                None => break,
+            };
+
+            let n_channels = block.channels() as i64;
+            let samples_left = max_samples - result.len() as u64;
+            let n = cmp::min(block.duration() as u64, samples_left) as u32;
+
+            for i in 0..n {
+                let mut sum = 0i64;
+                for ch in 0..block.channels() {
+                    sum += block.sample(ch, i) as i64;
+                }
+                let mean = sum / n_channels;
+                let sample = if bits_per_sample >= 16 {
+                    mean >> (bits_per_sample - 16)
+                } else {
+                    mean << (16 - bits_per_sample)
+                };
+                result.push(sample as i16);
+            }
+
+            buffer = block.into_buffer();
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the bytes that were read from the underlying reader, but not decoded yet.
+    ///
+    /// After decoding the last frame, this is the start of any data that
+    /// follows the stream, such as appended tags. The rest of that data can be
+    /// read from the underlying reader, after taking the buffered bytes.
+    pub fn buffer(&self) -> &[u8] {
+        match self.input {
+            FlacReaderState::Full(ref inp) => inp.buffer(),
+            FlacReaderState::MetadataOnly(ref inp) => inp.buffer(),
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        match self.input {
+            FlacReaderState::Full(ref inp) => inp.get_ref(),
+            FlacReaderState::MetadataOnly(ref inp) => inp.get_ref(),
+        }
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// The reader employs buffering internally, so the underlying reader is
+    /// usually ahead of what has been decoded. Reading from it or seeking it
+    /// while decoding is still in progress corrupts the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => inp.get_mut(),
+            FlacReaderState::MetadataOnly(ref mut inp) => inp.get_mut(),
+        }
+    }
+
+    /// Returns a reader for the stream that follows this one, if there is one.
+    ///
+    /// Internet radio, or concatenating files, produces chained streams: one
+    /// complete FLAC stream after another, each with its own header and
+    /// metadata. The `samples()` iterator ends where the next stream starts.
+    /// This decodes and discards what remains of the current stream, and then
+    /// reads the header and metadata of the next stream with the given
+    /// options. Returns `None` if the input ends instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReaderOptions::metadata_only` option was set.
+    pub fn next_stream(mut self, options: FlacReaderOptions) -> Result<Option<FlacReader<R>>> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::next_stream()")
+        }
+
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(self.read_next_block(buffer)) {
+            buffer = block.into_buffer();
+        }
+
+        match self.input {
+            FlacReaderState::Full(mut inp) => {
+                let is_next_stream = try!(inp.peek(4)).starts_with(b"fLaC");
+                if is_next_stream {
+                    FlacReader::new_impl(inp, options, |_block| {}).map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+    }
+
+    /// Destroys the FLAC reader and returns the underlying reader.
+    ///
+    /// Because the reader employs buffering internally, anything in the buffer
+    /// will be lost; use `buffer()` beforehand to retrieve it.
+    pub fn into_inner(self) -> R {
+        match self.input {
+            FlacReaderState::Full(inp) => inp.into_inner(),
+            FlacReaderState::MetadataOnly(inp) => inp.into_inner(),
+        }
+    }
+}
+
+impl<R: io::Read + io::Seek> FlacReader<R> {
+    /// Create a reader that reads the FLAC format from a seekable input, with reader options.
+    ///
+    /// This behaves like `new_ext()`, but metadata blocks that need not be
+    /// read, such as padding, or pictures when `read_pictures` is false, are
+    /// skipped by seeking over them, rather than by reading them. This makes
+    /// a difference for files with large padding blocks or cover art.
+    /// `FlacReader::open()` and `FlacReader::open_ext()` use this already.
+    pub fn new_seekable(reader: R, options: FlacReaderOptions) -> Result<FlacReader<R>> {
+        FlacReader::new_impl(BufferedReader::new_seekable(reader), options, |_block| {})
+    }
+
+    /// Seeks to the given inter-channel sample, so that decoding continues there.
+    ///
+    /// After seeking, the first item yielded by a new `samples()` iterator is
+    /// the first channel of the target sample. The lower-level `blocks()`
+    /// starts at the block that contains the target sample instead.
+    ///
+    /// The seek table is used to narrow down the search if the stream has
+    /// one; otherwise the position is found by bisection on the byte offset.
+    /// Either way, only a few frames are decoded, rather than everything up to
+    /// the target. Seeking to a sample beyond the end of the stream is an
+    /// error. If seeking fails, the position in the stream is unspecified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<()> {
+        if let Some(n) = self.streaminfo.samples {
+            if sample >= n {
+                return Err(seek::beyond_end_error())
+            }
+        }
+
+        let fixed_block_size = self.streaminfo.fixed_block_size();
+        let seektable = find_seektable(&self.metadata_blocks);
+        let skip = match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                try!(seek::seek_to_sample(inp, fixed_block_size, seektable, &mut self.seek_index,
+                                          self.frames_offset, sample))
+            }
+            FlacReaderState::MetadataOnly(..) => {
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::seek_to_sample()")
+            }
+        };
+        self.seek_skip = skip;
+        self.frame_index = None;
+        Ok(())
+    }
+
+    /// Seeks to the sample at the given time since the start of the stream.
+    ///
+    /// The time is converted into a sample number with the sample rate from
+    /// the streaminfo, rounding down, and then this behaves like
+    /// `seek_to_sample()`. The block size does not matter, so this works for
+    /// variable-blocksize streams too.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<()> {
+        let sample_rate = self.streaminfo.sample_rate as u64;
+        let sample = time.as_secs().saturating_mul(sample_rate)
+            .saturating_add(time.subsec_nanos() as u64 * sample_rate / 1_000_000_000);
+        self.seek_to_sample(sample)
+    }
+}
+
+/// Converts a number of inter-channel samples into a duration.
+fn sample_to_duration(sample: u64, sample_rate: u32) -> Duration {
+    let rate = sample_rate as u64;
+    let nanos = (sample % rate) * 1_000_000_000 / rate;
+    Duration::new(sample / rate, nanos as u32)
+}
+
+/// Returns the bitrate in bits per second of `len` bytes that hold `duration` inter-channel samples.
+fn bitrate(len: u64, duration: u64, sample_rate: u32) -> Option<u32> {
+    if duration == 0 || sample_rate == 0 {
+        return None
+    }
+    let bits_per_second = len.saturating_mul(8 * sample_rate as u64) / duration;
+    Some(cmp::min(bits_per_second, u32::max_value() as u64) as u32)
+}
+
+/// Returns the seek table among the metadata blocks, if there is one.
+fn find_seektable(blocks: &[MetadataBlock]) -> Option<&SeekTable> {
+    blocks.iter().filter_map(|block| match *block {
+        MetadataBlock::SeekTable(ref seektable) => Some(seektable),
+        _ => None,
+    }).next()
+}
+
+/// Parses a number such as `3`, or `3/12` for "3 of 12", from a tag value.
+fn parse_number_of(value: &str) -> Option<u32> {
+    let number = value.split('/').next().unwrap_or("");
+    number.trim().parse().ok()
+}
+
+/// Parses a channel mask tag value, which is usually hexadecimal, as in `0x003F`.
+fn parse_channel_mask(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if value.starts_with("0x") || value.starts_with("0X") {
+        u32::from_str_radix(&value[2..], 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+#[cfg(feature = "std")]
+impl FlacReader<fs::File> {
+    /// Attempts to create a reader that reads from the specified file.
+    ///
+    /// This is a convenience constructor that opens a `File`, and constructs a
+    /// `FlacReader` from it. There is no need to wrap the file in a
     /// `BufReader`, as the `FlacReader` employs buffering already.
     pub fn open<P: AsRef<path::Path>>(filename: P) -> Result<FlacReader<fs::File>> {
         let file = try!(fs::File::open(filename));
-        FlacReader::new(file)
+        FlacReader::new_seekable(file, FlacReaderOptions::default())
     }
 
     /// Attemps to create a reader that reads from the specified file.
@@ -439,14 +2058,98 @@ impl FlacReader<fs::File> {
                                           options: FlacReaderOptions)
                                           -> Result<FlacReader<fs::File>> {
         let file = try!(fs::File::open(filename));
-        FlacReader::new_ext(file, options)
+        FlacReader::new_seekable(file, options)
     }
 }
 
-impl<R: ReadBytes> Iterator for FlacSamples<R> {
-    type Item = Result<i32>;
+#[cfg(feature = "mmap")]
+impl FlacReader<io::Cursor<memmap2::Mmap>> {
+    /// Attempts to create a reader that decodes a memory-mapped file.
+    ///
+    /// This is only available with the `mmap` feature. The file is mapped
+    /// into memory with `memmap2`, and decoded from an `io::Cursor` over the
+    /// map, so it is read by the operating system as pages are touched,
+    /// rather than through a `read()` system call for every buffer. For tools
+    /// that process large libraries, this saves system calls. The bytes are
+    /// still copied from the map into the decoder's buffer; to avoid that
+    /// copy too, map the file yourself, and decode the map with `from_slice()`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or another
+    /// process, while the reader exists. The map would change underneath the
+    /// decoder, which is undefined behavior, or the process could receive a
+    /// bus error.
+    pub unsafe fn open_mmap<P: AsRef<path::Path>>(filename: P)
+                                                  -> Result<FlacReader<io::Cursor<memmap2::Mmap>>> {
+        let file = try!(fs::File::open(filename));
+        let map = try!(memmap2::Mmap::map(&file));
+        FlacReader::new_seekable(io::Cursor::new(map), FlacReaderOptions::default())
+    }
+}
 
-    fn next(&mut self) -> Option<Result<i32>> {
+impl<'a> FlacReader<io::Cursor<&'a [u8]>> {
+    /// Create a reader that decodes a FLAC stream held in memory, with reader options.
+    ///
+    /// This behaves like `new_seekable()` on an `io::Cursor` over the slice,
+    /// but the decoder reads from the slice directly, rather than copying it
+    /// into an intermediate buffer piece by piece. For a file that is in
+    /// memory already, or memory-mapped, this saves a copy of every byte.
+    pub fn from_slice(data: &'a [u8],
+                      options: FlacReaderOptions)
+                      -> Result<FlacReader<io::Cursor<&'a [u8]>>> {
+        FlacReader::new_impl(BufferedReader::from_slice(data), options, |_block| {})
+    }
+}
+
+impl SamplesState {
+    fn new(skip: u32, start: Option<u64>) -> SamplesState {
+        SamplesState {
+            block: Block::empty(),
+            sample: 0,
+            channel: 0,
+            skip: skip,
+            last_position: None,
+            start: start,
+            has_failed: false,
+        }
+    }
+
+    /// Returns the number of samples left, if the streaminfo specifies the total.
+    fn size_hint(&self, streaminfo: &StreamInfo) -> (usize, Option<usize>) {
+        let total = match streaminfo.samples {
+            Some(n) => n,
+            None => return (0, None),
+        };
+        let channels = streaminfo.channels as u64;
+        let remaining = if self.block.duration() > 0 {
+            let position = self.block.time() + self.sample as u64;
+            (total.saturating_sub(position) * channels).saturating_sub(self.channel as u64 + 1)
+        } else if self.has_failed || self.last_position.is_some() {
+            0
+        } else {
+            match self.start {
+                Some(start) => total.saturating_sub(start) * channels,
+                None => return (0, None),
+            }
+        };
+        if remaining > usize::MAX as u64 {
+            (usize::MAX, None)
+        } else {
+            (remaining as usize, Some(remaining as usize))
+        }
+    }
+
+    fn position(&self) -> Option<u64> {
+        if self.block.duration() > 0 {
+            Some(self.block.time() + self.sample as u64)
+        } else {
+            self.last_position
+        }
+    }
+
+    #[inline]
+    fn next<R: io::Read>(&mut self, reader: &mut FlacReader<R>) -> Option<Result<i32>> {
         // If the previous read failed, end iteration.
         if self.has_failed {
             return None;
@@ -462,23 +2165,43 @@ impl<R: ReadBytes> Iterator for FlacSamples<R> {
             self.sample += 1;
 
             // If that was the last sample in the block, decode the next block.
-            if self.sample >= self.block.duration() {
+            // Repeat if the skip after a seek covers the entire block.
+            while self.sample >= self.block.duration() {
                 self.sample = 0;
 
                 // Replace the current block with an empty one so that we may
                 // reuse the current buffer to decode again.
                 let current_block = mem::replace(&mut self.block, Block::empty());
+                if current_block.duration() > 0 {
+                    self.last_position = Some(current_block.time() +
+                                              current_block.duration() as u64 - 1);
+                }
 
-                match self.frame_reader.read_next_or_eof(current_block.into_buffer()) {
+                match reader.read_next_block(current_block.into_buffer()) {
                     Ok(Some(next_block)) => {
                         self.block = next_block;
+
+                        // After a seek, the block may start before the target.
+                        // In a damaged stream, the block that is decoded can
+                        // be shorter than the frame that was skipped into.
+                        let skip = mem::replace(&mut self.skip, 0);
+                        self.sample = cmp::min(skip, self.block.duration());
                     }
                     Ok(None) => {
-                        // The stream ended with EOF.
-                        // TODO: If a number of samples was specified in the
-                        // streaminfo metadata block, verify that we did not
-                        // read more or less samples.
-                        return None;
+                        // The stream ended with EOF. If the streaminfo
+                        // specifies the number of samples, a stream that ends
+                        // early was truncated at a frame boundary.
+                        // In resync mode, the samples at the end may have been
+                        // lost to damage instead.
+                        let lost_end = reader.lost_samples.last().map(|r| r.end);
+                        match (self.last_position, reader.streaminfo.samples) {
+                            (Some(last), Some(n)) if last + 1 < n && lost_end != Some(n) => {
+                                self.has_failed = true;
+                                return Some(fmt_err("stream ended before the number of \
+                                                     samples in the streaminfo"));
+                            }
+                            _ => return None,
+                        }
                     }
                     Err(error) => {
                         self.has_failed = true;
@@ -490,4 +2213,187 @@ impl<R: ReadBytes> Iterator for FlacSamples<R> {
 
         Some(Ok(self.block.sample(self.channel, self.sample)))
     }
+
+    fn read_into<R: io::Read>(&mut self,
+                              reader: &mut FlacReader<R>,
+                              buffer: &mut [i32])
+                              -> Result<usize> {
+        let mut n = 0;
+        while n < buffer.len() {
+            // The index of the next sample in the interleaved block.
+            let channels = self.block.channels() as usize;
+            let next = self.sample as usize * channels + self.channel as usize + 1;
+            let len = self.block.len() as usize;
+
+            if channels > 0 && next < len {
+                // Copy what is left of the current block, as far as it fits.
+                let count = cmp::min(len - next, buffer.len() - n);
+                for (i, dst) in (next..next + count).zip(&mut buffer[n..n + count]) {
+                    *dst = self.block.sample((i % channels) as u32, (i / channels) as u32);
+                }
+                let last = next + count - 1;
+                self.sample = (last / channels) as u32;
+                self.channel = (last % channels) as u32;
+                n += count;
+            } else {
+                // Take the first sample of the next block the usual way.
+                match self.next(reader) {
+                    Some(Ok(sample)) => {
+                        buffer[n] = sample;
+                        n += 1;
+                    }
+                    Some(Err(error)) => return Err(error),
+                    None => break,
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<'r, R: io::Read> FlacSamples<'r, R> {
+    /// Returns the number of the inter-channel sample that was yielded last.
+    ///
+    /// This is the position in the stream, counted in inter-channel samples
+    /// since the start, so it accounts for seeks and dropped iterators. For a
+    /// stream with multiple channels, the position changes when the first
+    /// channel of the next sample is yielded. Returns `None` if no sample has
+    /// been yielded yet.
+    pub fn position(&self) -> Option<u64> {
+        self.state.position()
+    }
+
+    /// Fills `buffer` with the next samples, and returns how many were written.
+    ///
+    /// This yields the same interleaved samples as iterating, but copies them
+    /// a block at a time, without a `Result` per sample. Fewer samples than
+    /// fit are written only at the end of the stream, and 0 after it. On
+    /// error, the contents of `buffer` are unspecified, and the iterator ends.
+    pub fn read_samples_into(&mut self, buffer: &mut [i32]) -> Result<usize> {
+        self.state.read_into(self.reader, buffer)
+    }
+}
+
+impl<'r, R: io::Read> Iterator for FlacSamples<'r, R> {
+    type Item = Result<i32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<i32>> {
+        self.state.next(self.reader)
+    }
+
+    /// Returns the number of samples left, based on the total in the streaminfo.
+    ///
+    /// The hint is exact for a valid stream. If the streaminfo does not specify
+    /// the number of samples, there is no upper bound.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state.size_hint(&self.reader.streaminfo)
+    }
+}
+
+impl<R: io::Read> FlacIntoSamples<R> {
+    /// Returns the number of the inter-channel sample that was yielded last.
+    ///
+    /// See `FlacSamples::position()`.
+    pub fn position(&self) -> Option<u64> {
+        self.state.position()
+    }
+
+    /// Fills `buffer` with the next samples, and returns how many were written.
+    ///
+    /// See `FlacSamples::read_samples_into()`.
+    pub fn read_samples_into(&mut self, buffer: &mut [i32]) -> Result<usize> {
+        self.state.read_into(&mut self.reader, buffer)
+    }
+
+    /// Returns the reader, to continue decoding after the current block.
+    pub fn into_reader(self) -> FlacReader<R> {
+        let mut reader = self.reader;
+        reader.buffer = self.state.block.into_buffer();
+        reader
+    }
+}
+
+impl<R: io::Read> Iterator for FlacIntoSamples<R> {
+    type Item = Result<i32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<i32>> {
+        self.state.next(&mut self.reader)
+    }
+
+    /// Returns the number of samples left, see `FlacSamples::size_hint()`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state.size_hint(&self.reader.streaminfo)
+    }
+}
+
+impl<'r, R: io::Read> Iterator for ChannelSamples<'r, R> {
+    type Item = Result<i32>;
+
+    fn next(&mut self) -> Option<Result<i32>> {
+        let mut guard = self.shared.borrow_mut();
+        let state = &mut *guard;
+        loop {
+            if let Some(sample) = state.queues[self.channel].pop_front() {
+                return Some(Ok(sample))
+            }
+            if state.is_done {
+                return None
+            }
+
+            let buffer = mem::replace(&mut state.buffer, Vec::new());
+            match state.reader.read_next_block(buffer) {
+                Ok(Some(block)) => {
+                    // After a seek, the block may start before the target.
+                    let skip = cmp::min(mem::replace(&mut state.skip, 0), block.duration());
+                    for (queue, samples) in state.queues.iter_mut().zip(block.channel_slices()) {
+                        queue.extend(&samples[skip as usize..]);
+                    }
+                    state.buffer = block.into_buffer();
+                }
+                Ok(None) => state.is_done = true,
+                Err(error) => {
+                    state.is_done = true;
+                    return Some(Err(error))
+                }
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<i32>>> SamplesF32<I> {
+    /// Wraps `samples` with the given bit depth, to convert them to floating point.
+    ///
+    /// A sample is divided by 2<sup>`bits_per_sample` - 1</sup>, so the most
+    /// negative sample maps to -1.0. Up to 24 bits per sample, the conversion
+    /// is exact. Beyond that, an f32 cannot represent every sample, and the
+    /// largest samples round to 1.0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_sample` is not between 1 and 32.
+    pub fn new(samples: I, bits_per_sample: u32) -> SamplesF32<I> {
+        assert!(bits_per_sample >= 1 && bits_per_sample <= 32,
+                "bits_per_sample must be between 1 and 32");
+        SamplesF32 {
+            samples: samples,
+            scale: 1.0 / (1u64 << (bits_per_sample - 1)) as f32,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<i32>>> Iterator for SamplesF32<I> {
+    type Item = Result<f32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<f32>> {
+        let scale = self.scale;
+        self.samples.next().map(|result| result.map(|sample| sample as f32 * scale))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
 }
@@ -0,0 +1,322 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `libflac` module decodes with both Claxon and libFLAC, when the
+//! `libflac` feature is enabled.
+//!
+//! The reference decoder is the specification in practice, so for any input,
+//! Claxon should produce exactly the samples that libFLAC produces, or fail
+//! where libFLAC fails. `compare_decoders()` checks this, which makes it a
+//! useful fuzz target. The feature links against the system libFLAC.
+
+use std::cmp;
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use compare::Comparison;
+use error::{Error, Result};
+use FlacReader;
+
+/// Decoded audio: the format and the interleaved samples.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decoded {
+    /// The number of channels.
+    pub channels: u32,
+    /// The number of bits per sample.
+    pub bits_per_sample: u32,
+    /// The samples of all channels, interleaved.
+    pub samples: Vec<i32>,
+}
+
+/// How the result of Claxon differs from that of libFLAC.
+#[derive(Debug)]
+pub enum Difference {
+    /// Claxon returned an error, but libFLAC decoded the stream.
+    ClaxonFailed(Error),
+    /// libFLAC reported an error, but Claxon decoded the stream.
+    LibflacFailed(Decoded),
+    /// Both decoded the stream, but to different audio.
+    Audio(Comparison),
+}
+
+/// The opaque `FLAC__StreamDecoder`.
+enum StreamDecoder {}
+
+/// The leading members of `FLAC__FrameHeader`, which starts `FLAC__Frame`.
+#[allow(dead_code)]
+#[repr(C)]
+struct FrameHeader {
+    blocksize: u32,
+    sample_rate: u32,
+    channels: u32,
+    channel_assignment: c_int,
+    bits_per_sample: u32,
+}
+
+/// `FLAC__StreamMetadata_StreamInfo`.
+#[allow(dead_code)]
+#[repr(C)]
+struct StreamInfo {
+    min_blocksize: u32,
+    max_blocksize: u32,
+    min_framesize: u32,
+    max_framesize: u32,
+    sample_rate: u32,
+    channels: u32,
+    bits_per_sample: u32,
+    total_samples: u64,
+    md5sum: [u8; 16],
+}
+
+/// `FLAC__StreamMetadata`, with only the streaminfo member of the data union.
+#[allow(dead_code)]
+#[repr(C)]
+struct StreamMetadata {
+    kind: c_int,
+    is_last: c_int,
+    length: u32,
+    stream_info: StreamInfo,
+}
+
+const METADATA_TYPE_STREAMINFO: c_int = 0;
+const INIT_STATUS_OK: c_int = 0;
+const STATE_END_OF_STREAM: c_int = 4;
+const READ_STATUS_CONTINUE: c_int = 0;
+const READ_STATUS_END_OF_STREAM: c_int = 1;
+const WRITE_STATUS_CONTINUE: c_int = 0;
+const WRITE_STATUS_ABORT: c_int = 1;
+
+type ReadCallback = extern "C" fn(*const StreamDecoder, *mut u8, *mut usize, *mut c_void) -> c_int;
+type WriteCallback = extern "C" fn(*const StreamDecoder,
+                                   *const FrameHeader,
+                                   *const *const i32,
+                                   *mut c_void)
+                                   -> c_int;
+type MetadataCallback = extern "C" fn(*const StreamDecoder, *const StreamMetadata, *mut c_void);
+type ErrorCallback = extern "C" fn(*const StreamDecoder, c_int, *mut c_void);
+
+/// The seek, tell, length and eof callbacks, which are not used.
+type UnusedCallback = extern "C" fn();
+
+#[link(name = "FLAC")]
+extern "C" {
+    fn FLAC__stream_decoder_new() -> *mut StreamDecoder;
+    fn FLAC__stream_decoder_delete(decoder: *mut StreamDecoder);
+    fn FLAC__stream_decoder_init_stream(decoder: *mut StreamDecoder,
+                                        read: Option<ReadCallback>,
+                                        seek: Option<UnusedCallback>,
+                                        tell: Option<UnusedCallback>,
+                                        length: Option<UnusedCallback>,
+                                        eof: Option<UnusedCallback>,
+                                        write: Option<WriteCallback>,
+                                        metadata: Option<MetadataCallback>,
+                                        error: Option<ErrorCallback>,
+                                        client_data: *mut c_void)
+                                        -> c_int;
+    fn FLAC__stream_decoder_process_until_end_of_stream(decoder: *mut StreamDecoder) -> c_int;
+    fn FLAC__stream_decoder_get_state(decoder: *const StreamDecoder) -> c_int;
+    fn FLAC__stream_decoder_finish(decoder: *mut StreamDecoder) -> c_int;
+}
+
+/// The client data of the libFLAC callbacks.
+struct LibflacState<'a> {
+    input: &'a [u8],
+    decoded: Option<Decoded>,
+    failed: bool,
+}
+
+extern "C" fn read_callback(_decoder: *const StreamDecoder,
+                            buffer: *mut u8,
+                            bytes: *mut usize,
+                            client_data: *mut c_void)
+                            -> c_int {
+    unsafe {
+        let state = &mut *(client_data as *mut LibflacState);
+        let n = cmp::min(*bytes, state.input.len());
+        *bytes = n;
+        if n == 0 {
+            return READ_STATUS_END_OF_STREAM
+        }
+        ptr::copy_nonoverlapping(state.input.as_ptr(), buffer, n);
+        state.input = &state.input[n..];
+        READ_STATUS_CONTINUE
+    }
+}
+
+extern "C" fn write_callback(_decoder: *const StreamDecoder,
+                             frame: *const FrameHeader,
+                             buffer: *const *const i32,
+                             client_data: *mut c_void)
+                             -> c_int {
+    unsafe {
+        let state = &mut *(client_data as *mut LibflacState);
+        let header = &*frame;
+        let decoded = match state.decoded {
+            Some(ref mut decoded) if decoded.channels == header.channels &&
+                                     decoded.bits_per_sample == header.bits_per_sample => decoded,
+            // Claxon rejects frames that disagree with the streaminfo, so
+            // libFLAC accepting them is a difference by itself.
+            _ => {
+                state.failed = true;
+                return WRITE_STATUS_ABORT
+            }
+        };
+        let channels = slice::from_raw_parts(buffer, header.channels as usize);
+        for i in 0..header.blocksize as isize {
+            for &channel in channels {
+                decoded.samples.push(*channel.offset(i));
+            }
+        }
+        WRITE_STATUS_CONTINUE
+    }
+}
+
+extern "C" fn metadata_callback(_decoder: *const StreamDecoder,
+                                metadata: *const StreamMetadata,
+                                client_data: *mut c_void) {
+    unsafe {
+        let state = &mut *(client_data as *mut LibflacState);
+        let metadata = &*metadata;
+        if metadata.kind == METADATA_TYPE_STREAMINFO {
+            state.decoded = Some(Decoded {
+                channels: metadata.stream_info.channels,
+                bits_per_sample: metadata.stream_info.bits_per_sample,
+                samples: Vec::new(),
+            });
+        }
+    }
+}
+
+extern "C" fn error_callback(_decoder: *const StreamDecoder,
+                             _status: c_int,
+                             client_data: *mut c_void) {
+    // libFLAC skips to the next frame after an error, but Claxon stops, so
+    // anything decoded after it cannot be compared.
+    let state = unsafe { &mut *(client_data as *mut LibflacState) };
+    state.failed = true;
+}
+
+/// Decodes the stream with libFLAC.
+///
+/// Returns `None` if libFLAC reports any error. libFLAC does not verify the
+/// MD5 signature here, so neither does Claxon in `decode_claxon()`.
+pub fn decode_libflac(data: &[u8]) -> Option<Decoded> {
+    let mut state = LibflacState {
+        input: data,
+        decoded: None,
+        failed: false,
+    };
+    unsafe {
+        let decoder = FLAC__stream_decoder_new();
+        if decoder.is_null() {
+            return None
+        }
+        let client_data = &mut state as *mut LibflacState as *mut c_void;
+        let status = FLAC__stream_decoder_init_stream(decoder,
+                                                      Some(read_callback),
+                                                      None,
+                                                      None,
+                                                      None,
+                                                      None,
+                                                      Some(write_callback),
+                                                      Some(metadata_callback),
+                                                      Some(error_callback),
+                                                      client_data);
+        if status == INIT_STATUS_OK {
+            let ok = FLAC__stream_decoder_process_until_end_of_stream(decoder) != 0;
+            let at_end = FLAC__stream_decoder_get_state(decoder) == STATE_END_OF_STREAM;
+            state.failed |= !ok || !at_end;
+            FLAC__stream_decoder_finish(decoder);
+        } else {
+            state.failed = true;
+        }
+        FLAC__stream_decoder_delete(decoder);
+    }
+    if state.failed { None } else { state.decoded }
+}
+
+/// Decodes the stream with Claxon.
+pub fn decode_claxon(data: &[u8]) -> Result<Decoded> {
+    let mut reader = try!(FlacReader::new(io::Cursor::new(data)));
+    let streaminfo = reader.streaminfo();
+    let mut samples = Vec::new();
+    let mut buffer = Vec::new();
+    while let Some(block) = try!(reader.read_next_block(buffer)) {
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                samples.push(block.sample(ch, i));
+            }
+        }
+        buffer = block.into_buffer();
+    }
+    Ok(Decoded {
+        channels: streaminfo.channels,
+        bits_per_sample: streaminfo.bits_per_sample,
+        samples: samples,
+    })
+}
+
+/// Compares the audio of two decoded streams, like `compare()` does for readers.
+fn compare_decoded(a: &Decoded, b: &Decoded) -> Comparison {
+    if a.channels != b.channels || a.bits_per_sample != b.bits_per_sample {
+        return Comparison::FormatDiffers
+    }
+    let channels = a.channels as usize;
+    match a.samples.iter().zip(&b.samples).position(|(x, y)| x != y) {
+        Some(i) => Comparison::SampleDiffers {
+            sample: (i / channels) as u64,
+            channel: (i % channels) as u32,
+        },
+        None if a.samples.len() != b.samples.len() => {
+            let shortest = cmp::min(a.samples.len(), b.samples.len());
+            Comparison::LengthDiffers((shortest / channels) as u64)
+        }
+        None => Comparison::Equal,
+    }
+}
+
+/// Decodes the stream with Claxon and with libFLAC, and compares the results.
+///
+/// Returns `Ok` if both decode the stream to the same audio, or if both fail.
+/// Where they fail may differ, because libFLAC detects some errors later or
+/// earlier than Claxon does.
+pub fn compare_decoders(data: &[u8]) -> ::std::result::Result<(), Difference> {
+    match (decode_claxon(data), decode_libflac(data)) {
+        (Ok(ours), Some(theirs)) => match compare_decoded(&ours, &theirs) {
+            Comparison::Equal => Ok(()),
+            difference => Err(Difference::Audio(difference)),
+        },
+        (Err(err), Some(..)) => Err(Difference::ClaxonFailed(err)),
+        (Ok(ours), None) => Err(Difference::LibflacFailed(ours)),
+        (Err(..), None) => Ok(()),
+    }
+}
+
+#[test]
+fn verify_compare_decoders_agrees_on_testsamples() {
+    use std::fs;
+    use std::io::Read;
+
+    for fname in &["testsamples/short.flac", "testsamples/wasted_bits.flac"] {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+        assert!(compare_decoders(&data).is_ok(), "{} decodes differently", fname);
+
+        // A truncated stream is an error for both decoders.
+        let truncated = &data[..data.len() - 10];
+        assert!(compare_decoders(truncated).is_ok(), "{} truncated", fname);
+    }
+
+    let a = Decoded { channels: 2, bits_per_sample: 16, samples: vec![1, 2, 3, 4, 5, 6] };
+    let mut b = a.clone();
+    b.samples[5] = 0;
+    assert_eq!(compare_decoded(&a, &b), Comparison::SampleDiffers { sample: 2, channel: 1 });
+    b.samples.truncate(4);
+    assert_eq!(compare_decoded(&a, &b), Comparison::LengthDiffers(2));
+}
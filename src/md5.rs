@@ -0,0 +1,237 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! MD5 hashing, used to verify decoded audio against the streaminfo signature.
+//!
+//! This is a straightforward implementation of RFC 1321. It is not fast, but
+//! hashing is only done when verification is explicitly requested.
+
+use frame::Block;
+
+// Per-round shift amounts, from RFC 1321.
+const SHIFTS: [u32; 64] =
+    [7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+     5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20,
+     4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+     6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21];
+
+// The integer parts of the sines of integers (in radians) times 2^32.
+const SINES: [u32; 64] =
+    [0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+     0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+     0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+     0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+     0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+     0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+     0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+     0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+     0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+     0xeb86d391];
+
+/// An incremental MD5 hasher.
+#[derive(Clone)]
+pub struct Md5 {
+    state: [u32; 4],
+    /// Bytes that did not yet fill up a complete 64-byte chunk.
+    buffer: [u8; 64],
+    /// The total number of bytes hashed so far.
+    len: u64,
+}
+
+impl Md5 {
+    /// Creates a hasher in the initial state.
+    pub fn new() -> Md5 {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: [0; 64],
+            len: 0,
+        }
+    }
+
+    /// Processes a single 64-byte chunk.
+    fn process_chunk(state: &mut [u32; 4], chunk: &[u8]) {
+        debug_assert_eq!(chunk.len(), 64);
+
+        // MD5 interprets the chunk as sixteen little-endian words. Assemble
+        // them byte by byte, so the result does not depend on the endianness
+        // of the host.
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(chunk.chunks(4)) {
+            *word = (bytes[0] as u32) | (bytes[1] as u32) << 8 |
+                    (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24;
+        }
+
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f)
+                           .wrapping_add(SINES[i])
+                           .wrapping_add(words[g])
+                           .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    /// Feeds bytes into the hasher.
+    pub fn update(&mut self, mut data: &[u8]) {
+        let buffered = (self.len % 64) as usize;
+        self.len += data.len() as u64;
+
+        // First top up a partially filled buffer, if there is one.
+        if buffered > 0 {
+            let n = if data.len() < 64 - buffered { data.len() } else { 64 - buffered };
+            self.buffer[buffered..buffered + n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+            if buffered + n < 64 {
+                return
+            }
+            let buffer = self.buffer;
+            Md5::process_chunk(&mut self.state, &buffer);
+        }
+
+        // Then process as many complete chunks as possible directly from the
+        // input, and buffer the remainder.
+        while data.len() >= 64 {
+            Md5::process_chunk(&mut self.state, &data[..64]);
+            data = &data[64..];
+        }
+        self.buffer[..data.len()].copy_from_slice(data);
+    }
+
+    /// Feeds the samples of a block into the hasher.
+    ///
+    /// The MD5 signature in the streaminfo block is computed over the
+    /// interleaved samples, each stored as a little-endian signed integer of
+    /// `(bits_per_sample + 7) / 8` bytes. This is independent of the
+    /// endianness of the host.
+    pub fn update_block(&mut self, block: &Block, bits_per_sample: u32) {
+        let bytes_per_sample = ((bits_per_sample + 7) / 8) as usize;
+        let mut buffer = [0u8; 64];
+        let mut n = 0;
+
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                let sample = block.sample(ch, i);
+                write_sample_le(sample, &mut buffer[n..n + bytes_per_sample]);
+                n += bytes_per_sample;
+
+                // There is room for at least one more sample of at most four
+                // bytes before the buffer must be flushed.
+                if n > 60 {
+                    self.update(&buffer[..n]);
+                    n = 0;
+                }
+            }
+        }
+
+        self.update(&buffer[..n]);
+    }
+
+    /// Finishes the hash computation and returns the digest.
+    pub fn finalize(mut self) -> [u8; 16] {
+        let len_bits = self.len.wrapping_mul(8);
+
+        // Pad with a single 1 bit, then zeros until 8 bytes remain in the
+        // chunk, and finally the message length in bits, little-endian.
+        let buffered = (self.len % 64) as usize;
+        let pad_len = if buffered < 56 { 56 - buffered } else { 120 - buffered };
+        let mut padding = [0u8; 72];
+        padding[0] = 0x80;
+        for i in 0..8 {
+            padding[pad_len + i] = (len_bits >> (8 * i)) as u8;
+        }
+        self.update(&padding[..pad_len + 8]);
+        debug_assert_eq!(self.len % 64, 0);
+
+        let mut digest = [0u8; 16];
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state.iter()) {
+            write_sample_le(*word as i32, bytes);
+        }
+        digest
+    }
+}
+
+/// Writes the `out.len()` least significant bytes of `sample`, little-endian.
+///
+/// The bytes are extracted with shifts rather than by reinterpreting memory,
+/// so the result is the same on big-endian and little-endian hosts.
+#[inline(always)]
+fn write_sample_le(sample: i32, out: &mut [u8]) {
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = (sample >> (8 * i)) as u8;
+    }
+}
+
+#[test]
+fn verify_write_sample_le() {
+    let mut buffer = [0u8; 4];
+
+    write_sample_le(0x123456, &mut buffer[..3]);
+    assert_eq!(&buffer[..3], &[0x56, 0x34, 0x12]);
+
+    write_sample_le(-2, &mut buffer[..2]);
+    assert_eq!(&buffer[..2], &[0xfe, 0xff]);
+
+    write_sample_le(-0x123456, &mut buffer[..3]);
+    assert_eq!(&buffer[..3], &[0xaa, 0xcb, 0xed]);
+
+    write_sample_le(-128, &mut buffer[..1]);
+    assert_eq!(&buffer[..1], &[0x80]);
+}
+
+#[test]
+fn verify_md5_test_suite() {
+    // Test vectors from RFC 1321.
+    fn md5_hex(data: &[u8]) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(data);
+        let digest = hasher.finalize();
+        let strs: Vec<String> = digest.iter().map(|x| format!("{:02x}", x)).collect();
+        strs.concat()
+    }
+
+    assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(md5_hex(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+    assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(md5_hex(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+    assert_eq!(md5_hex(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+    assert_eq!(md5_hex(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+               "d174ab98d277d9f5a5611c2c9f419d9f");
+    assert_eq!(md5_hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+               "57edf4a22be3c955ac49da2e2107b67a");
+}
+
+#[test]
+fn verify_md5_incremental_update() {
+    // Feeding data in pieces that straddle chunk boundaries must give the same
+    // result as feeding it at once.
+    let data: Vec<u8> = (0..200u32).map(|x| (x * 7) as u8).collect();
+
+    let mut at_once = Md5::new();
+    at_once.update(&data);
+
+    let mut in_pieces = Md5::new();
+    for piece in data.chunks(13) {
+        in_pieces.update(piece);
+    }
+
+    assert_eq!(at_once.finalize(), in_pieces.finalize());
+}
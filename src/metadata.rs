@@ -9,18 +9,53 @@
 
 use error::{Error, Result, fmt_err};
 use input::ReadBytes;
+use layout::ChannelLayout;
+use std::cmp;
+use std::fmt;
 use std::str;
+use std::time::Duration;
 use std::slice;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
 
-#[derive(Clone, Copy)]
-struct MetadataBlockHeader {
-    is_last: bool,
-    block_type: u8,
-    length: u32,
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The number of bytes or entries to allocate before any data has been read.
+///
+/// Lengths and counts in metadata come from the stream, and a damaged or
+/// malicious stream can declare far more data than it contains. Buffers start
+/// out with at most this capacity, and grow only as data is actually read, so
+/// memory use is bounded by the size of the input, not by what it declares.
+const INITIAL_CAPACITY: usize = 64 * 1024;
+
+/// Reads `len` bytes into a new vector, which grows as data is read.
+fn read_bytes<R: ReadBytes>(input: &mut R, len: u32) -> Result<Vec<u8>> {
+    let len = len as usize;
+    let mut bytes = Vec::with_capacity(cmp::min(len, INITIAL_CAPACITY));
+    while bytes.len() < len {
+        let start = bytes.len();
+        let end = cmp::min(len, start + INITIAL_CAPACITY);
+        bytes.resize(end, 0);
+        try!(input.read_into(&mut bytes[start..end]));
+    }
+    Ok(bytes)
+}
+
+/// The header that precedes every metadata block.
+#[derive(Clone, Copy, Debug)]
+pub struct MetadataBlockHeader {
+    /// Whether this is the last metadata block before the audio data.
+    pub is_last: bool,
+    /// The type of the block, such as 4 for a Vorbis comment block.
+    pub block_type: u8,
+    /// The length of the block in bytes, excluding the header.
+    pub length: u32,
 }
 
 /// The streaminfo metadata block, with important information about the stream.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StreamInfo {
     // TODO: "size" would better be called "duration" for clarity.
     /// The minimum block size (in inter-channel samples) used in the stream.
@@ -42,9 +77,9 @@ pub struct StreamInfo {
     pub max_frame_size: Option<u32>,
     /// The sample rate in Hz.
     pub sample_rate: u32,
-    /// The number of channels.
+    /// The number of channels, between 1 and 8 inclusive.
     pub channels: u32,
-    /// The number of bits per sample.
+    /// The number of bits per sample, between 4 and 32 inclusive.
     pub bits_per_sample: u32,
     /// The total number of inter-channel samples in the stream.
     // TODO: rename to `duration` for clarity?
@@ -53,8 +88,112 @@ pub struct StreamInfo {
     pub md5sum: [u8; 16],
 }
 
+impl StreamInfo {
+    /// Returns the maximum number of samples in a block, for all channels together.
+    ///
+    /// A frame with a block size larger than `max_block_size` is an error,
+    /// which is detected before any memory is allocated for the frame. So the
+    /// buffer used for decoding never needs to hold more than this many
+    /// samples, which bounds the memory needed to decode the stream.
+    pub fn max_block_len(&self) -> usize {
+        self.max_block_size as usize * self.channels as usize
+    }
+
+    /// Returns the block size if the stream is a fixed-blocksize stream, or 0 otherwise.
+    ///
+    /// Like the reference decoder, consider the stream fixed-blocksize when the
+    /// minimum and maximum block size in the streaminfo are equal.
+    pub fn fixed_block_size(&self) -> u16 {
+        if self.min_block_size == self.max_block_size {
+            self.max_block_size
+        } else {
+            0
+        }
+    }
+
+    /// Returns the total number of samples in the stream, for all channels together.
+    ///
+    /// This is the number of samples that `FlacReader::samples()` yields, or
+    /// `None` if the streaminfo does not record the length of the stream.
+    /// For the number of inter-channel samples, see the `samples` field.
+    pub fn num_samples(&self) -> Option<u64> {
+        self.samples.and_then(|n| n.checked_mul(self.channels as u64))
+    }
+
+    /// Returns the default speaker assignment for the number of channels.
+    ///
+    /// Returns `None` if the number of channels is not between 1 and 8. Use
+    /// `FlacReader::channel_layout()` to take a channel mask tag into account.
+    pub fn channel_layout(&self) -> Option<ChannelLayout> {
+        ChannelLayout::from_channels(self.channels)
+    }
+
+    /// Returns the duration of the stream.
+    ///
+    /// Returns `None` if the streaminfo does not record the length of the
+    /// stream, or if the sample rate is unknown (0). The duration is rounded
+    /// down to whole nanoseconds.
+    pub fn duration(&self) -> Option<Duration> {
+        let rate = self.sample_rate as u64;
+        match self.samples {
+            Some(n) if rate > 0 => {
+                let nanos = (n % rate) * 1_000_000_000 / rate;
+                Some(Duration::new(n / rate, nanos as u32))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bytes per second of the decoded audio.
+    ///
+    /// This is the data rate of the audio as uncompressed PCM, such as in a
+    /// WAV file, where every sample takes a whole number of bytes. For 16-bit
+    /// stereo at 44.1 kHz, this is 176400.
+    pub fn pcm_bytes_per_second(&self) -> u64 {
+        let bytes_per_sample = (self.bits_per_sample as u64 + 7) / 8;
+        self.sample_rate as u64 * self.channels as u64 * bytes_per_sample
+    }
+
+    /// Estimates the number of bytes per second of the encoded stream.
+    ///
+    /// `len` is the length of the encoded audio in bytes, for example the
+    /// file size minus the size of the metadata. Returns `None` if the
+    /// duration of the stream is unknown or zero.
+    pub fn average_bytes_per_second(&self, len: u64) -> Option<u64> {
+        match self.samples {
+            Some(n) if n > 0 && self.sample_rate > 0 => {
+                Some(len.saturating_mul(self.sample_rate as u64) / n)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Formats the streaminfo like `metaflac --list` does, indented by two spaces.
+///
+/// Unknown frame sizes and an unknown number of samples are printed as 0,
+/// which is how they are stored in the stream.
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "  minimum blocksize: {} samples", self.min_block_size));
+        try!(write!(f, "\n  maximum blocksize: {} samples", self.max_block_size));
+        try!(write!(f, "\n  minimum framesize: {} bytes", self.min_frame_size.unwrap_or(0)));
+        try!(write!(f, "\n  maximum framesize: {} bytes", self.max_frame_size.unwrap_or(0)));
+        try!(write!(f, "\n  sample_rate: {} Hz", self.sample_rate));
+        try!(write!(f, "\n  channels: {}", self.channels));
+        try!(write!(f, "\n  bits-per-sample: {}", self.bits_per_sample));
+        try!(write!(f, "\n  total samples: {}", self.samples.unwrap_or(0)));
+        try!(write!(f, "\n  MD5 signature: "));
+        for byte in &self.md5sum {
+            try!(write!(f, "{:02x}", byte));
+        }
+        Ok(())
+    }
+}
+
 /// A seek point in the seek table.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SeekPoint {
     /// Sample number of the first sample in the target frame, or 2<sup>64</sup> - 1 for a placeholder.
     pub sample: u64,
@@ -65,14 +204,71 @@ pub struct SeekPoint {
     pub samples: u16,
 }
 
+impl SeekPoint {
+    /// Returns whether this is a placeholder point, reserved for later use by an encoder.
+    ///
+    /// A placeholder does not point to a frame, and must be ignored for seeking.
+    pub fn is_placeholder(&self) -> bool {
+        self.sample == 0xffff_ffff_ffff_ffff
+    }
+}
+
 /// A seek table to aid seeking in the stream.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SeekTable {
     /// The seek points, sorted in ascending order by sample number.
-    #[allow(dead_code)] // TODO: Implement seeking.
     seekpoints: Vec<SeekPoint>,
 }
 
+impl SeekTable {
+    /// Constructs a seek table from seek points, which need not be sorted.
+    pub fn from_seekpoints(mut seekpoints: Vec<SeekPoint>) -> SeekTable {
+        seekpoints.sort_by_key(|p| p.sample);
+        SeekTable { seekpoints: seekpoints }
+    }
+
+    /// Returns the seek points, sorted in ascending order by sample number.
+    ///
+    /// Placeholder points, if any, are included; they come last.
+    pub fn seekpoints(&self) -> &[SeekPoint] {
+        &self.seekpoints
+    }
+
+    /// Returns the number of seek points in the table, placeholders included.
+    pub fn len(&self) -> usize {
+        self.seekpoints.len()
+    }
+
+    /// Returns whether the table contains no seek points at all.
+    pub fn is_empty(&self) -> bool {
+        self.seekpoints.is_empty()
+    }
+
+    /// Returns the seek points, sorted in ascending order by sample number.
+    pub fn into_seekpoints(self) -> Vec<SeekPoint> {
+        self.seekpoints
+    }
+}
+
+/// Formats the seek table like `metaflac --list` does, indented by two spaces.
+impl fmt::Display for SeekTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "  seek points: {}", self.seekpoints.len()));
+        for (i, point) in self.seekpoints.iter().enumerate() {
+            if point.is_placeholder() {
+                try!(write!(f, "\n    point {}: PLACEHOLDER", i));
+            } else {
+                try!(write!(f, "\n    point {}: sample_number={}, stream_offset={}, frame_samples={}",
+                            i, point.sample, point.offset, point.samples));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Vorbis comments, also known as FLAC tags (e.g. artist, title, etc.).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VorbisComment {
     /// The “vendor string”, chosen by the encoder vendor.
     ///
@@ -97,10 +293,400 @@ pub struct VorbisComment {
     /// be present on a collaboration track.
     ///
     /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
+    #[cfg_attr(feature = "serde", serde(with = "::serialize::vorbis_comments"))]
     pub comments: Vec<(String, usize)>,
 }
 
+/// Formats the Vorbis comments like `metaflac --list` does, indented by two spaces.
+impl fmt::Display for VorbisComment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "  vendor string: {}", self.vendor));
+        try!(write!(f, "\n  comments: {}", self.comments.len()));
+        for (i, comment) in self.comments.iter().enumerate() {
+            try!(write!(f, "\n    comment[{}]: {}", i, comment.0));
+        }
+        Ok(())
+    }
+}
+
+/// A CUE sheet, which describes the tracks of e.g. a CD in a single stream.
+///
+/// Album rips are often stored as a single FLAC stream with an embedded CUE
+/// sheet, which holds the track boundaries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CueSheet {
+    /// The media catalog number, such as the UPC/EAN code of a CD.
+    ///
+    /// Empty if the catalog number is not known.
+    pub catalog_number: String,
+
+    /// The number of lead-in samples, only meaningful for CD-DA.
+    pub lead_in_samples: u64,
+
+    /// Whether the CUE sheet corresponds to a Compact Disc.
+    pub is_cd: bool,
+
+    /// The tracks, including the lead-out track, which is always last.
+    pub tracks: Vec<CueSheetTrack>,
+}
+
+/// A track in a CUE sheet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CueSheetTrack {
+    /// The offset of the track in samples, relative to the start of the stream.
+    pub offset: u64,
+
+    /// The track number. The lead-out track is number 170 for CD-DA, or 255 otherwise.
+    pub number: u8,
+
+    /// The International Standard Recording Code, or empty if there is none.
+    pub isrc: String,
+
+    /// Whether the track contains audio, as opposed to data.
+    pub is_audio: bool,
+
+    /// Whether the track was recorded with pre-emphasis.
+    pub pre_emphasis: bool,
+
+    /// The index points of the track; empty for the lead-out track.
+    pub indices: Vec<CueSheetIndex>,
+}
+
+impl CueSheetTrack {
+    /// Returns whether this is the lead-out track, which marks the end of the last track.
+    pub fn is_lead_out(&self) -> bool {
+        self.number == 170 || self.number == 255
+    }
+}
+
+/// An index point in a CUE sheet track.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CueSheetIndex {
+    /// The offset of the index point in samples, relative to the track offset.
+    pub offset: u64,
+
+    /// The index point number. Index 1 marks the start of the track proper,
+    /// index 0 the start of the pregap, if present.
+    pub number: u8,
+}
+
+/// Formats the CUE sheet like `metaflac --list` does, indented by two spaces.
+impl fmt::Display for CueSheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "  media catalog number: {}", self.catalog_number));
+        try!(write!(f, "\n  lead-in: {}", self.lead_in_samples));
+        try!(write!(f, "\n  is CD: {}", self.is_cd));
+        try!(write!(f, "\n  number of tracks: {}", self.tracks.len()));
+        for (i, track) in self.tracks.iter().enumerate() {
+            try!(write!(f, "\n    track[{}]", i));
+            try!(write!(f, "\n      offset: {}", track.offset));
+            if track.is_lead_out() {
+                try!(write!(f, "\n      number: {} (LEAD-OUT)", track.number));
+                continue
+            }
+            try!(write!(f, "\n      number: {}", track.number));
+            try!(write!(f, "\n      ISRC: {}", track.isrc));
+            try!(write!(f, "\n      type: {}", if track.is_audio { "AUDIO" } else { "DATA" }));
+            try!(write!(f, "\n      pre-emphasis: {}", track.pre_emphasis));
+            try!(write!(f, "\n      number of index points: {}", track.indices.len()));
+            for (j, index) in track.indices.iter().enumerate() {
+                try!(write!(f, "\n        index[{}]", j));
+                try!(write!(f, "\n          offset: {}", index.offset));
+                try!(write!(f, "\n          number: {}", index.number));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The kind of picture in a picture block, such as the front cover.
+///
+/// These are the picture types of the ID3v2 APIC frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PictureType {
+    /// Other.
+    Other,
+    /// 32x32 pixels file icon, PNG only.
+    FileIcon,
+    /// Other file icon.
+    OtherFileIcon,
+    /// Front cover.
+    FrontCover,
+    /// Back cover.
+    BackCover,
+    /// Leaflet page.
+    LeafletPage,
+    /// Media, such as the label side of a CD.
+    Media,
+    /// Lead artist, lead performer, or soloist.
+    LeadArtist,
+    /// Artist or performer.
+    Artist,
+    /// Conductor.
+    Conductor,
+    /// Band or orchestra.
+    Band,
+    /// Composer.
+    Composer,
+    /// Lyricist or text writer.
+    Lyricist,
+    /// Recording location.
+    RecordingLocation,
+    /// During recording.
+    DuringRecording,
+    /// During performance.
+    DuringPerformance,
+    /// Movie or video screen capture.
+    ScreenCapture,
+    /// A bright colored fish.
+    BrightColoredFish,
+    /// Illustration.
+    Illustration,
+    /// Band or artist logotype.
+    BandLogotype,
+    /// Publisher or studio logotype.
+    PublisherLogotype,
+    /// A picture type that is reserved at the moment of writing.
+    Reserved(u32),
+}
+
+impl PictureType {
+    /// Converts the numeric picture type as stored in the picture block.
+    pub fn from_u32(picture_type: u32) -> PictureType {
+        match picture_type {
+            0 => PictureType::Other,
+            1 => PictureType::FileIcon,
+            2 => PictureType::OtherFileIcon,
+            3 => PictureType::FrontCover,
+            4 => PictureType::BackCover,
+            5 => PictureType::LeafletPage,
+            6 => PictureType::Media,
+            7 => PictureType::LeadArtist,
+            8 => PictureType::Artist,
+            9 => PictureType::Conductor,
+            10 => PictureType::Band,
+            11 => PictureType::Composer,
+            12 => PictureType::Lyricist,
+            13 => PictureType::RecordingLocation,
+            14 => PictureType::DuringRecording,
+            15 => PictureType::DuringPerformance,
+            16 => PictureType::ScreenCapture,
+            17 => PictureType::BrightColoredFish,
+            18 => PictureType::Illustration,
+            19 => PictureType::BandLogotype,
+            20 => PictureType::PublisherLogotype,
+            n => PictureType::Reserved(n),
+        }
+    }
+
+    /// Returns the numeric picture type as stored in the picture block.
+    pub fn to_u32(&self) -> u32 {
+        match *self {
+            PictureType::Other => 0,
+            PictureType::FileIcon => 1,
+            PictureType::OtherFileIcon => 2,
+            PictureType::FrontCover => 3,
+            PictureType::BackCover => 4,
+            PictureType::LeafletPage => 5,
+            PictureType::Media => 6,
+            PictureType::LeadArtist => 7,
+            PictureType::Artist => 8,
+            PictureType::Conductor => 9,
+            PictureType::Band => 10,
+            PictureType::Composer => 11,
+            PictureType::Lyricist => 12,
+            PictureType::RecordingLocation => 13,
+            PictureType::DuringRecording => 14,
+            PictureType::DuringPerformance => 15,
+            PictureType::ScreenCapture => 16,
+            PictureType::BrightColoredFish => 17,
+            PictureType::Illustration => 18,
+            PictureType::BandLogotype => 19,
+            PictureType::PublisherLogotype => 20,
+            PictureType::Reserved(n) => n,
+        }
+    }
+}
+
+/// Formats the picture type with the description that `metaflac --list` uses.
+impl fmt::Display for PictureType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            PictureType::Other => "Other",
+            PictureType::FileIcon => "32x32 pixels 'file icon' (PNG only)",
+            PictureType::OtherFileIcon => "Other file icon",
+            PictureType::FrontCover => "Cover (front)",
+            PictureType::BackCover => "Cover (back)",
+            PictureType::LeafletPage => "Leaflet page",
+            PictureType::Media => "Media (e.g. label side of CD)",
+            PictureType::LeadArtist => "Lead artist/lead performer/soloist",
+            PictureType::Artist => "Artist/performer",
+            PictureType::Conductor => "Conductor",
+            PictureType::Band => "Band/Orchestra",
+            PictureType::Composer => "Composer",
+            PictureType::Lyricist => "Lyricist/text writer",
+            PictureType::RecordingLocation => "Recording Location",
+            PictureType::DuringRecording => "During recording",
+            PictureType::DuringPerformance => "During performance",
+            PictureType::ScreenCapture => "Movie/video screen capture",
+            PictureType::BrightColoredFish => "A bright coloured fish",
+            PictureType::Illustration => "Illustration",
+            PictureType::BandLogotype => "Band/artist logotype",
+            PictureType::PublisherLogotype => "Publisher/Studio logotype",
+            PictureType::Reserved(_) => "Reserved",
+        };
+        f.write_str(description)
+    }
+}
+
+/// A picture embedded in the stream, such as album art.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Picture {
+    /// What the picture depicts, such as the front cover.
+    pub picture_type: PictureType,
+
+    /// The MIME type of the image data, such as `image/jpeg`.
+    ///
+    /// The MIME type may also be `-->`, in which case `data` contains a URL
+    /// that points to the image, rather than the image itself.
+    pub mime_type: String,
+
+    /// A description of the picture, often empty.
+    pub description: String,
+
+    /// The width of the picture in pixels.
+    pub width: u32,
+
+    /// The height of the picture in pixels.
+    pub height: u32,
+
+    /// The color depth of the picture in bits per pixel.
+    pub color_depth: u32,
+
+    /// For indexed-color pictures such as GIF, the number of colors used, or 0 otherwise.
+    pub num_colors: u32,
+
+    /// The image data, in the format indicated by the MIME type.
+    ///
+    /// Note that the dimensions and color depth above are as stated in the
+    /// picture block. Claxon does not decode the image to verify them.
+    ///
+    /// With the `serde` feature, the data is serialized as a base64 string.
+    #[cfg_attr(feature = "serde", serde(with = "::serialize::base64"))]
+    pub data: Vec<u8>,
+}
+
+/// Formats the picture like `metaflac --list` does, indented by two spaces.
+///
+/// Unlike `metaflac`, this prints only the length of the image data, not a
+/// hex dump of it.
+impl fmt::Display for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "  type: {} ({})", self.picture_type.to_u32(), self.picture_type));
+        try!(write!(f, "\n  MIME type: {}", self.mime_type));
+        try!(write!(f, "\n  description: {}", self.description));
+        try!(write!(f, "\n  width: {}", self.width));
+        try!(write!(f, "\n  height: {}", self.height));
+        try!(write!(f, "\n  depth: {}", self.color_depth));
+        try!(write!(f, "\n  colors: {}", self.num_colors));
+        if self.num_colors == 0 {
+            try!(f.write_str(" (unindexed)"));
+        }
+        write!(f, "\n  data length: {}", self.data.len())
+    }
+}
+
+/// Application ID of blocks that hold a chunk of a RIFF (WAV) file, `riff`.
+///
+/// When converting WAV to FLAC, `flac --keep-foreign-metadata` preserves the
+/// non-audio chunks of the WAV file verbatim, one chunk per application block
+/// with this ID. This is how Broadcast WAV metadata such as the `bext` and
+/// `iXML` chunks survives the conversion. Use `riff_chunk()` to split the
+/// payload of such a block into chunk ID and chunk data.
+pub const APPLICATION_ID_RIFF: u32 = 0x72_69_66_66;
+
+/// Application ID of blocks that hold a chunk of an AIFF file, `aiff`.
+///
+/// Like `APPLICATION_ID_RIFF`, but for files converted from AIFF. Note that
+/// AIFF chunks store their size big-endian, so `riff_chunk()` does not apply.
+pub const APPLICATION_ID_AIFF: u32 = 0x61_69_66_66;
+
+/// Chunk ID of the Broadcast WAV extension chunk, which holds broadcast metadata.
+pub const RIFF_CHUNK_BEXT: [u8; 4] = *b"bext";
+
+/// Chunk ID of the iXML chunk, which holds production metadata as XML.
+pub const RIFF_CHUNK_IXML: [u8; 4] = *b"iXML";
+
+/// Splits a RIFF chunk into its chunk ID and chunk data.
+///
+/// This is intended for the payload of application blocks with ID
+/// `APPLICATION_ID_RIFF`. A RIFF chunk consists of a 4-byte ID, a 32-bit
+/// little-endian size, and the data. If the data is shorter than the declared
+/// size, only the data that is present is returned. (This is the case for the
+/// `data` chunk, for which `flac` does not store the audio again.) Returns
+/// `None` if the payload is too short to contain a chunk header.
+pub fn riff_chunk(payload: &[u8]) -> Option<(&[u8], &[u8])> {
+    if payload.len() < 8 {
+        return None
+    }
+
+    let size = (payload[4] as u32) | (payload[5] as u32) << 8 |
+               (payload[6] as u32) << 16 | (payload[7] as u32) << 24;
+    let body = &payload[8..];
+    let len = if (size as u64) < body.len() as u64 { size as usize } else { body.len() };
+
+    Some((&payload[..4], &body[..len]))
+}
+
+#[test]
+fn verify_riff_chunk() {
+    let payload = [b'b', b'e', b'x', b't', 3, 0, 0, 0, 1, 2, 3, 0];
+    assert_eq!(riff_chunk(&payload), Some((&b"bext"[..], &[1u8, 2, 3][..])));
+
+    // A chunk that declares more data than is present.
+    let payload = [b'd', b'a', b't', b'a', 0, 1, 0, 0, 7];
+    assert_eq!(riff_chunk(&payload), Some((&b"data"[..], &[7u8][..])));
+
+    assert_eq!(riff_chunk(&payload[..7]), None);
+}
+
+/// Limits on the metadata that Claxon is willing to read.
+///
+/// Claxon allocates memory for metadata up front, based on lengths declared
+/// in the stream. These limits bound the allocations, so that a small damaged
+/// or malicious file cannot make the decoder allocate large amounts of memory.
+/// Metadata that exceeds a limit results in `Error::Unsupported`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MetadataLimits {
+    /// The maximum number of comments in a Vorbis comment block.
+    ///
+    /// Defaults to 65536, far more than any real-world file contains.
+    pub max_vorbis_comments: u32,
+
+    /// The maximum length in bytes of a single Vorbis comment, such as `ARTIST=Queen`.
+    ///
+    /// Defaults to 10 MiB, which is also the maximum size of the entire Vorbis
+    /// comment block, so by default only the block size limits the length.
+    pub max_vorbis_comment_len: u32,
+}
+
+impl Default for MetadataLimits {
+    fn default() -> MetadataLimits {
+        MetadataLimits {
+            max_vorbis_comments: 64 * 1024,
+            max_vorbis_comment_len: 10 * 1024 * 1024,
+        }
+    }
+}
+
 /// A metadata about the flac stream.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MetadataBlock {
     /// A stream info block.
     StreamInfo(StreamInfo),
@@ -114,6 +700,7 @@ pub enum MetadataBlock {
         /// The registered application ID.
         id: u32,
         /// The contents of the application block.
+        #[cfg_attr(feature = "serde", serde(with = "::serialize::base64"))]
         data: Vec<u8>,
     },
     /// A seek table block.
@@ -121,13 +708,50 @@ pub enum MetadataBlock {
     /// A Vorbis comment block, also known as FLAC tags.
     VorbisComment(VorbisComment),
     /// A CUE sheet block.
-    CueSheet, // TODO
-    /// A picture block.
-    Picture, // TODO
+    CueSheet(CueSheet),
+    /// A picture block, such as album art.
+    Picture(Picture),
     /// A block with a reserved block type, not supported by this library.
     Reserved,
 }
 
+/// Formats the block like `metaflac --list` does, indented by two spaces.
+///
+/// The block type comes first, followed by the contents of the block. The
+/// `is last` and `length` lines of `metaflac` are omitted, because they are
+/// part of the block header, except for padding, where the length is the
+/// contents. For application blocks, only the length of the data is printed.
+impl fmt::Display for MetadataBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetadataBlock::StreamInfo(ref info) => {
+                write!(f, "  type: 0 (STREAMINFO)\n{}", info)
+            }
+            MetadataBlock::Padding { length } => {
+                write!(f, "  type: 1 (PADDING)\n  length: {}", length)
+            }
+            MetadataBlock::Application { id, ref data } => {
+                try!(write!(f, "  type: 2 (APPLICATION)"));
+                try!(write!(f, "\n  application ID: {:08x}", id));
+                write!(f, "\n  data length: {}", data.len())
+            }
+            MetadataBlock::SeekTable(ref table) => {
+                write!(f, "  type: 3 (SEEKTABLE)\n{}", table)
+            }
+            MetadataBlock::VorbisComment(ref comment) => {
+                write!(f, "  type: 4 (VORBIS_COMMENT)\n{}", comment)
+            }
+            MetadataBlock::CueSheet(ref cuesheet) => {
+                write!(f, "  type: 5 (CUESHEET)\n{}", cuesheet)
+            }
+            MetadataBlock::Picture(ref picture) => {
+                write!(f, "  type: 6 (PICTURE)\n{}", picture)
+            }
+            MetadataBlock::Reserved => f.write_str("  type: UNKNOWN"),
+        }
+    }
+}
+
 /// Iterates over Vorbis comments (FLAC tags) in a FLAC stream.
 ///
 /// See `FlacReader::tags()` for more details.
@@ -155,9 +779,14 @@ impl<'a> Iterator for Tags<'a> {
             (&comment[..sep_idx], &comment[sep_idx+1..])
         })
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
 }
 
-// TODO: `Tags` could implement `ExactSizeIterator`.
+impl<'a> ExactSizeIterator for Tags<'a> {}
 
 /// Iterates over Vorbis comments looking for a specific one; returns its values as `&str`.
 ///
@@ -190,6 +819,7 @@ impl<'a> Iterator for GetTag<'a> {
     fn next(&mut self) -> Option<&'a str> {
         // This import is actually required on Rust 1.13.
         #[allow(unused_imports)]
+        #[cfg(feature = "std")]
         use std::ascii::AsciiExt;
 
         while self.index < self.vorbis_comments.len() {
@@ -205,8 +835,86 @@ impl<'a> Iterator for GetTag<'a> {
     }
 }
 
+/// Iterates over the application ID and payload of application blocks.
+///
+/// See `FlacReader::application_blocks()` for more details.
+pub struct ApplicationBlocks<'a> {
+    /// The underlying iterator, over blocks of all kinds.
+    iter: slice::Iter<'a, MetadataBlock>,
+}
+
+impl<'a> ApplicationBlocks<'a> {
+    /// Returns a new `ApplicationBlocks` iterator over the application blocks among `blocks`.
+    #[inline]
+    pub fn new(blocks: &'a [MetadataBlock]) -> ApplicationBlocks<'a> {
+        ApplicationBlocks {
+            iter: blocks.iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for ApplicationBlocks<'a> {
+    type Item = (u32, &'a [u8]);
+
+    #[inline]
+    fn next(&mut self) -> Option<(u32, &'a [u8])> {
+        for block in &mut self.iter {
+            if let MetadataBlock::Application { id, ref data } = *block {
+                return Some((id, &data[..]))
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Iterates over the pictures among metadata blocks.
+///
+/// See `FlacReader::pictures()` for more details.
+pub struct Pictures<'a> {
+    /// The underlying iterator, over blocks of all kinds.
+    iter: slice::Iter<'a, MetadataBlock>,
+}
+
+impl<'a> Pictures<'a> {
+    /// Returns a new `Pictures` iterator over the picture blocks among `blocks`.
+    #[inline]
+    pub fn new(blocks: &'a [MetadataBlock]) -> Pictures<'a> {
+        Pictures {
+            iter: blocks.iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for Pictures<'a> {
+    type Item = &'a Picture;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a Picture> {
+        for block in &mut self.iter {
+            if let MetadataBlock::Picture(ref picture) = *block {
+                return Some(picture)
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
 #[inline]
-fn read_metadata_block_header<R: ReadBytes>(input: &mut R) -> Result<MetadataBlockHeader> {
+/// Reads the header of a metadata block.
+///
+/// Together with `read_metadata_block_ext()`, this allows to read only the
+/// blocks of interest, and to skip over the others.
+pub fn read_metadata_block_header<R: ReadBytes>(input: &mut R) -> Result<MetadataBlockHeader> {
     let byte = try!(input.read_u8());
 
     // The first bit specifies whether this is the last block, the next 7 bits
@@ -257,6 +965,18 @@ pub fn read_metadata_block<R: ReadBytes>(input: &mut R,
                                          block_type: u8,
                                          length: u32)
                                          -> Result<MetadataBlock> {
+    read_metadata_block_ext(input, block_type, length, &MetadataLimits::default())
+}
+
+/// Read a single metadata block of the given type and length, with limits.
+///
+/// Like `read_metadata_block()`, but rather than the default limits, the
+/// provided limits are enforced.
+pub fn read_metadata_block_ext<R: ReadBytes>(input: &mut R,
+                                             block_type: u8,
+                                             length: u32,
+                                             limits: &MetadataLimits)
+                                             -> Result<MetadataBlock> {
     match block_type {
         0 => {
             // The streaminfo block has a fixed size of 34 bytes.
@@ -279,23 +999,20 @@ pub fn read_metadata_block<R: ReadBytes>(input: &mut R,
             })
         }
         3 => {
-            // TODO: implement seektable reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+            let seektable = try!(read_seektable_block(input, length));
+            Ok(MetadataBlock::SeekTable(seektable))
         }
         4 => {
-            let vorbis_comment = try!(read_vorbis_comment_block(input, length));
+            let vorbis_comment = try!(read_vorbis_comment_block(input, length, limits));
             Ok(MetadataBlock::VorbisComment(vorbis_comment))
         }
         5 => {
-            // TODO: implement CUE sheet reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+            let cuesheet = try!(read_cuesheet_block(input, length));
+            Ok(MetadataBlock::CueSheet(cuesheet))
         }
         6 => {
-            // TODO: implement picture reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+            let picture = try!(read_picture_block(input, length));
+            Ok(MetadataBlock::Picture(picture))
         }
         127 => {
             // This code is invalid to avoid confusion with a frame sync code.
@@ -307,13 +1024,36 @@ pub fn read_metadata_block<R: ReadBytes>(input: &mut R,
             // one way of handling it, but maybe there should be some kind of
             // 'strict' mode (configurable at compile time?) so that this can
             // be an error if desired.
+            trace!(Reserved("metadata block type"));
             try!(input.skip(length));
             Ok(MetadataBlock::Reserved)
         }
     }
 }
 
+/// Reads a streaminfo block of the given length, allowing an unknown sample rate.
+///
+/// Like `read_metadata_block()` for a streaminfo block, but a sample rate of
+/// 0 is not an error. Some encoders write 0 when the sample rate is stored
+/// only in the frame headers. The caller must then determine the sample rate
+/// in another way, for instance with `frame::read_frame_info()`.
+pub fn read_streaminfo_block_lenient<R: ReadBytes>(input: &mut R,
+                                                   length: u32)
+                                                   -> Result<StreamInfo> {
+    if length == 34 {
+        read_streaminfo_block_ext(input, true)
+    } else {
+        fmt_err("invalid streaminfo metadata block length")
+    }
+}
+
 fn read_streaminfo_block<R: ReadBytes>(input: &mut R) -> Result<StreamInfo> {
+    read_streaminfo_block_ext(input, false)
+}
+
+fn read_streaminfo_block_ext<R: ReadBytes>(input: &mut R,
+                                           allow_unknown_sample_rate: bool)
+                                           -> Result<StreamInfo> {
     let min_block_size = try!(input.read_be_u16());
     let max_block_size = try!(input.read_be_u16());
 
@@ -364,10 +1104,19 @@ fn read_streaminfo_block<R: ReadBytes>(input: &mut R) -> Result<StreamInfo> {
 
     // A sample rate of 0 is invalid, and the maximum sample rate is limited by
     // the structure of the frame headers to 655350 Hz.
-    if sample_rate == 0 || sample_rate > 655350 {
+    if (sample_rate == 0 && !allow_unknown_sample_rate) || sample_rate > 655350 {
         return fmt_err("invalid sample rate");
     }
 
+    // The number of channels and bits per sample are stored minus one, so
+    // they can never be zero. The spec does require at least 4 bits per
+    // sample though. Fewer would produce degenerate output downstream, where
+    // e.g. the number of bytes per sample is computed from the bit depth.
+    debug_assert!(n_channels > 0);
+    if bits_per_sample < 4 {
+        return fmt_err("invalid bits per sample, must be at least 4");
+    }
+
     let stream_info = StreamInfo {
         min_block_size: min_block_size,
         max_block_size: max_block_size,
@@ -394,7 +1143,50 @@ fn read_streaminfo_block<R: ReadBytes>(input: &mut R) -> Result<StreamInfo> {
     Ok(stream_info)
 }
 
-fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<VorbisComment> {
+fn read_seektable_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<SeekTable> {
+    // The number of seek points is not stored explicitly, it follows from the
+    // length of the block. Every seek point takes up 18 bytes.
+    if length % 18 != 0 {
+        return fmt_err("invalid seek table block length")
+    }
+
+    let n_points = length / 18;
+    let mut seekpoints = Vec::with_capacity(cmp::min(n_points as usize, INITIAL_CAPACITY / 24));
+
+    for _ in 0..n_points {
+        // The sample number and offset are 64-bit big-endian integers,
+        // followed by a 16-bit number of samples.
+        let sample_msb = try!(input.read_be_u32());
+        let sample_lsb = try!(input.read_be_u32());
+        let offset_msb = try!(input.read_be_u32());
+        let offset_lsb = try!(input.read_be_u32());
+        let samples = try!(input.read_be_u16());
+
+        let seekpoint = SeekPoint {
+            sample: (sample_msb as u64) << 32 | sample_lsb as u64,
+            offset: (offset_msb as u64) << 32 | offset_lsb as u64,
+            samples: samples,
+        };
+        seekpoints.push(seekpoint);
+    }
+
+    // The format requires the points to be sorted already, but not all
+    // encoders get this right. Rather than rejecting the stream, sort them, so
+    // users can rely on the order. Placeholders have the largest possible
+    // sample number, so they end up last.
+    seekpoints.sort_by_key(|p| p.sample);
+
+    let seektable = SeekTable {
+        seekpoints: seekpoints,
+    };
+
+    Ok(seektable)
+}
+
+fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R,
+                                           length: u32,
+                                           limits: &MetadataLimits)
+                                           -> Result<VorbisComment> {
     if length < 8 {
         // We expect at a minimum a 32-bit vendor string length, and a 32-bit
         // comment count.
@@ -416,7 +1208,7 @@ fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result
     // place for that anyway.
     if length > 10 * 1024 * 1024 {
         let msg = "Vorbis comment blocks larger than 10 MiB are not supported";
-        return Err(Error::Unsupported(msg))
+        return Err(Error::Unsupported { feature: msg })
     }
 
     // The Vorbis comment block starts with a length-prefixed "vendor string".
@@ -424,26 +1216,25 @@ fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result
     // 32-bit vendor string length, and comment count.
     let vendor_len = try!(input.read_le_u32());
     if vendor_len > length - 8 { return fmt_err("vendor string too long") }
-    let mut vendor_bytes = Vec::with_capacity(vendor_len as usize);
-
-    // We can safely set the lenght of the vector here; the uninitialized memory
-    // is not exposed. If `read_into` succeeds, it will have overwritten all
-    // bytes. If not, an error is returned and the memory is never exposed.
-    unsafe { vendor_bytes.set_len(vendor_len as usize); }
-    try!(input.read_into(&mut vendor_bytes));
+    let vendor_bytes = try!(read_bytes(input, vendor_len));
     let vendor = try!(String::from_utf8(vendor_bytes));
 
+    let mut bytes_left = length - 8 - vendor_len;
+
     // Next up is the number of comments. Because every comment is at least 4
     // bytes to indicate its length, there cannot be more comments than the
-    // length of the block divided by 4. This is only an upper bound to ensure
-    // that we don't allocate a big vector, to protect against DoS attacks.
+    // remaining length of the block divided by 4. This is only an upper bound
+    // to ensure that we don't allocate a big vector, to protect against DoS
+    // attacks. The configured limit is checked before allocating too.
     let comments_len = try!(input.read_le_u32());
-    if comments_len >= length / 4 {
+    if comments_len > bytes_left / 4 {
         return fmt_err("too many entries for Vorbis comment block")
     }
-    let mut comments = Vec::with_capacity(comments_len as usize);
-
-    let mut bytes_left = length - 8 - vendor_len;
+    if comments_len > limits.max_vorbis_comments {
+        let msg = "Vorbis comment blocks with more comments than the limit are not supported";
+        return Err(Error::Unsupported { feature: msg })
+    }
+    let mut comments = Vec::with_capacity(cmp::min(comments_len as usize, INITIAL_CAPACITY / 32));
 
     // For every comment, there is a length-prefixed string of the form
     // "NAME=value".
@@ -454,11 +1245,12 @@ fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result
         if comment_len > bytes_left {
             return fmt_err("Vorbis comment too long for Vorbis comment block")
         }
+        if comment_len > limits.max_vorbis_comment_len {
+            let msg = "Vorbis comments longer than the limit are not supported";
+            return Err(Error::Unsupported { feature: msg })
+        }
 
-        // For the same reason as above, setting the length is safe here.
-        let mut comment_bytes = Vec::with_capacity(comment_len as usize);
-        unsafe { comment_bytes.set_len(comment_len as usize); }
-        try!(input.read_into(&mut comment_bytes));
+        let comment_bytes = try!(read_bytes(input, comment_len));
 
         bytes_left -= comment_len;
 
@@ -513,23 +1305,201 @@ fn read_application_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<(u
     // `read_vorbis_comment_block()`.
     if length > 10 * 1024 * 1024 {
         let msg = "application blocks larger than 10 MiB are not supported";
-        return Err(Error::Unsupported(msg))
+        return Err(Error::Unsupported { feature: msg })
     }
 
     let id = try!(input.read_be_u32());
 
     // Four bytes of the block have been used for the ID, the rest is payload.
-    // Create a vector of uninitialized memory, and read the block into it. The
-    // uninitialized memory is never exposed: read_into will either fill the
-    // buffer completely, or return an err, in which case the memory is not
-    // exposed.
-    let mut data = Vec::with_capacity(length as usize - 4);
-    unsafe { data.set_len(length as usize - 4); }
-    try!(input.read_into(&mut data));
+    let data = try!(read_bytes(input, length - 4));
 
     Ok((id, data))
 }
 
+/// Reads a fixed-size, NUL-padded ASCII string, as used in the CUE sheet block.
+fn read_ascii_string<R: ReadBytes>(input: &mut R,
+                                   buffer: &mut [u8],
+                                   what: &'static str)
+                                   -> Result<String> {
+    try!(input.read_into(buffer));
+    let len = buffer.iter().position(|&x| x == 0).unwrap_or(buffer.len());
+    if buffer[..len].iter().any(|&x| x < 0x20 || x > 0x7e) {
+        return fmt_err(what)
+    }
+
+    // Printable ASCII is valid UTF-8, so this does not fail.
+    Ok(String::from_utf8(buffer[..len].to_vec()).unwrap())
+}
+
+fn read_u64<R: ReadBytes>(input: &mut R) -> Result<u64> {
+    let msb = try!(input.read_be_u32());
+    let lsb = try!(input.read_be_u32());
+    Ok((msb as u64) << 32 | lsb as u64)
+}
+
+fn read_cuesheet_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<CueSheet> {
+    // The fixed part of the block is 396 bytes, followed by 36 bytes per track
+    // and 12 bytes per index point.
+    if length < 396 {
+        return fmt_err("CUE sheet block is too short")
+    }
+
+    let mut catalog_buffer = [0u8; 128];
+    let catalog_number = try!(read_ascii_string(input, &mut catalog_buffer,
+                                                "CUE sheet catalog number contains invalid byte"));
+    let lead_in_samples = try!(read_u64(input));
+
+    // One bit for whether this is a CD, followed by 7 + 258 * 8 reserved bits.
+    let is_cd = try!(input.read_u8()) >> 7 == 1;
+    try!(input.skip(258));
+
+    let n_tracks = try!(input.read_u8());
+    let mut bytes_left = length - 396;
+
+    // With a track count of at most 255, the allocation is bounded.
+    let mut tracks = Vec::with_capacity(n_tracks as usize);
+
+    for _ in 0..n_tracks {
+        if bytes_left < 36 {
+            return fmt_err("CUE sheet block is too short for its tracks")
+        }
+        bytes_left -= 36;
+
+        let offset = try!(read_u64(input));
+        let number = try!(input.read_u8());
+        let mut isrc_buffer = [0u8; 12];
+        let isrc = try!(read_ascii_string(input, &mut isrc_buffer,
+                                          "CUE sheet track ISRC contains invalid byte"));
+
+        // One bit for the track type (0 is audio), one for pre-emphasis,
+        // followed by 6 + 13 * 8 reserved bits.
+        let flags = try!(input.read_u8());
+        try!(input.skip(13));
+
+        let n_indices = try!(input.read_u8());
+        let mut indices = Vec::with_capacity(n_indices as usize);
+
+        for _ in 0..n_indices {
+            if bytes_left < 12 {
+                return fmt_err("CUE sheet block is too short for its index points")
+            }
+            bytes_left -= 12;
+
+            let index = CueSheetIndex {
+                offset: try!(read_u64(input)),
+                number: try!(input.read_u8()),
+            };
+            try!(input.skip(3));
+            indices.push(index);
+        }
+
+        let track = CueSheetTrack {
+            offset: offset,
+            number: number,
+            isrc: isrc,
+            is_audio: flags >> 7 == 0,
+            pre_emphasis: (flags >> 6) & 1 == 1,
+            indices: indices,
+        };
+        tracks.push(track);
+    }
+
+    if bytes_left != 0 {
+        return fmt_err("CUE sheet block length does not match its contents")
+    }
+
+    // The lead-out track is required, so there is at least one track.
+    match tracks.last() {
+        Some(track) if track.is_lead_out() => {}
+        _ => return fmt_err("CUE sheet lead-out track missing"),
+    }
+
+    let cuesheet = CueSheet {
+        catalog_number: catalog_number,
+        lead_in_samples: lead_in_samples,
+        is_cd: is_cd,
+        tracks: tracks,
+    };
+
+    Ok(cuesheet)
+}
+
+/// Reads a length-prefixed string of a picture block.
+///
+/// Returns the string, and the remaining number of bytes in the block.
+fn read_picture_string<R: ReadBytes>(input: &mut R,
+                                     bytes_left: u32,
+                                     what: &'static str)
+                                     -> Result<(String, u32)> {
+    if bytes_left < 4 {
+        return fmt_err("picture block is too short")
+    }
+    let len = try!(input.read_be_u32());
+    if len > bytes_left - 4 {
+        return fmt_err(what)
+    }
+
+    // Like the Vorbis comment strings, the string cannot be longer than the
+    // block, so the allocation is bounded.
+    let bytes = try!(read_bytes(input, len));
+    let string = try!(String::from_utf8(bytes));
+
+    Ok((string, bytes_left - 4 - len))
+}
+
+fn read_picture_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<Picture> {
+    // There are eight 32-bit fields, and two strings and the data in between.
+    if length < 32 {
+        return fmt_err("picture block is too short")
+    }
+
+    // The block length is a 24-bit field, so all allocations below are bounded
+    // by 16 MiB, and by the size of the input. Unlike the Vorbis comment block,
+    // it is not unusual for a picture block to be a few megabytes, so there is
+    // no further limit.
+    let picture_type = try!(input.read_be_u32());
+    let bytes_left = length - 4;
+
+    // The MIME type is restricted to printable ASCII, which is valid UTF-8.
+    let (mime_type, bytes_left) = try!(read_picture_string(input, bytes_left,
+                                                           "picture MIME type too long"));
+    if mime_type.bytes().any(|x| x < 0x20 || x > 0x7e) {
+        return fmt_err("picture MIME type contains invalid byte")
+    }
+
+    let (description, bytes_left) = try!(read_picture_string(input, bytes_left,
+                                                             "picture description too long"));
+
+    if bytes_left < 20 {
+        return fmt_err("picture block is too short")
+    }
+    let width = try!(input.read_be_u32());
+    let height = try!(input.read_be_u32());
+    let color_depth = try!(input.read_be_u32());
+    let num_colors = try!(input.read_be_u32());
+    let data_len = try!(input.read_be_u32());
+
+    // The data must fill up the remainder of the block exactly.
+    if data_len != bytes_left - 20 {
+        return fmt_err("picture data length does not match block length")
+    }
+
+    let data = try!(read_bytes(input, data_len));
+
+    let picture = Picture {
+        picture_type: PictureType::from_u32(picture_type),
+        mime_type: mime_type,
+        description: description,
+        width: width,
+        height: height,
+        color_depth: color_depth,
+        num_colors: num_colors,
+        data: data,
+    };
+
+    Ok(picture)
+}
+
 /// Reads metadata blocks from a stream and exposes them as an iterator.
 ///
 /// It is assumed that the next byte that the reader will read, is the first
@@ -538,6 +1508,7 @@ fn read_application_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<(u
 /// data will be read thereafter, and the next value will be `None`.
 pub struct MetadataBlockReader<R: ReadBytes> {
     input: R,
+    limits: MetadataLimits,
     done: bool,
 }
 
@@ -547,8 +1518,14 @@ pub type MetadataBlockResult = Result<MetadataBlock>;
 impl<R: ReadBytes> MetadataBlockReader<R> {
     /// Creates a metadata block reader that will yield at least one element.
     pub fn new(input: R) -> MetadataBlockReader<R> {
+        MetadataBlockReader::new_ext(input, MetadataLimits::default())
+    }
+
+    /// Creates a metadata block reader that enforces the given limits.
+    pub fn new_ext(input: R, limits: MetadataLimits) -> MetadataBlockReader<R> {
         MetadataBlockReader {
             input: input,
+            limits: limits,
             done: false,
         }
     }
@@ -556,7 +1533,10 @@ impl<R: ReadBytes> MetadataBlockReader<R> {
     #[inline]
     fn read_next(&mut self) -> MetadataBlockResult {
         let header = try!(read_metadata_block_header(&mut self.input));
-        let block = try!(read_metadata_block(&mut self.input, header.block_type, header.length));
+        let block = try!(read_metadata_block_ext(&mut self.input,
+                                                 header.block_type,
+                                                 header.length,
+                                                 &self.limits));
         self.done = header.is_last;
         Ok(block)
     }
@@ -589,3 +1569,313 @@ impl<R: ReadBytes> Iterator for MetadataBlockReader<R> {
         if self.done { (0, Some(0)) } else { (1, None) }
     }
 }
+
+#[test]
+fn verify_vorbis_comment_block_with_absurd_comment_count_fails_fast() {
+    use std::io;
+
+    // A Vorbis comment block of 8 bytes: an empty vendor string, followed by
+    // a comment count of a billion, but no comments. This must be rejected
+    // before a vector for a billion comments is allocated.
+    let data = vec![0, 0, 0, 0, 0x00, 0xca, 0x9a, 0x3b];
+    let mut cursor = io::Cursor::new(data);
+    match read_metadata_block(&mut cursor, 4, 8) {
+        Err(Error::FormatError { reason, .. }) => {
+            assert_eq!(reason, "too many entries for Vorbis comment block")
+        }
+        Err(err) => panic!("expected format error, got {:?}", err),
+        Ok(..) => panic!("expected Vorbis comment block to be rejected"),
+    }
+}
+
+#[test]
+fn verify_application_block_with_absurd_length_fails_without_allocating() {
+    use std::io;
+
+    // An application block that declares almost 10 MiB of data, but the input
+    // ends after a few bytes. This must fail with an error, and the buffer must
+    // not be allocated at the declared size up front.
+    let data = vec![b't', b'e', b's', b't', 1, 2, 3];
+    let mut cursor = io::Cursor::new(data);
+    match read_metadata_block(&mut cursor, 2, 0x9f_ffff) {
+        Err(Error::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+        Err(err) => panic!("expected unexpected end of input, got {:?}", err),
+        Ok(..) => panic!("expected application block to be rejected"),
+    }
+
+    // Data larger than the initial capacity is still read in full.
+    let mut data = b"test".to_vec();
+    data.extend((0..INITIAL_CAPACITY + 10).map(|i| i as u8));
+    let len = data.len() as u32;
+    let mut cursor = io::Cursor::new(data);
+    match read_metadata_block(&mut cursor, 2, len).unwrap() {
+        MetadataBlock::Application { data, .. } => {
+            assert_eq!(data.len(), INITIAL_CAPACITY + 10);
+            assert_eq!(data[INITIAL_CAPACITY + 9], (INITIAL_CAPACITY + 9) as u8);
+        }
+        _ => panic!("expected application block"),
+    }
+}
+
+#[test]
+fn verify_vorbis_comment_block_limits_are_enforced() {
+    use std::io;
+
+    // A Vorbis comment block with vendor string "V" and comments "A=1" and
+    // "B=22".
+    let data = vec![1, 0, 0, 0, b'V', 2, 0, 0, 0,
+                    3, 0, 0, 0, b'A', b'=', b'1',
+                    4, 0, 0, 0, b'B', b'=', b'2', b'2'];
+    let length = data.len() as u32;
+
+    let limits = MetadataLimits::default();
+    let mut cursor = io::Cursor::new(&data[..]);
+    match read_metadata_block_ext(&mut cursor, 4, length, &limits).unwrap() {
+        MetadataBlock::VorbisComment(vc) => assert_eq!(vc.comments.len(), 2),
+        _ => panic!("expected Vorbis comment block"),
+    }
+
+    let limits = MetadataLimits { max_vorbis_comments: 1, .. MetadataLimits::default() };
+    let mut cursor = io::Cursor::new(&data[..]);
+    match read_metadata_block_ext(&mut cursor, 4, length, &limits) {
+        Err(Error::Unsupported { .. }) => {}
+        _ => panic!("expected comment count limit to be enforced"),
+    }
+
+    let limits = MetadataLimits { max_vorbis_comment_len: 3, .. MetadataLimits::default() };
+    let mut cursor = io::Cursor::new(&data[..]);
+    match read_metadata_block_ext(&mut cursor, 4, length, &limits) {
+        Err(Error::Unsupported { .. }) => {}
+        _ => panic!("expected comment length limit to be enforced"),
+    }
+}
+
+#[test]
+fn verify_picture_block() {
+    use std::io;
+
+    // A front cover with MIME type "image/png", description "A", 2x3 pixels,
+    // 24 bits per pixel, and three bytes of "image data".
+    let data = vec![0, 0, 0, 3,
+                    0, 0, 0, 9, b'i', b'm', b'a', b'g', b'e', b'/', b'p', b'n', b'g',
+                    0, 0, 0, 1, b'A',
+                    0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 24, 0, 0, 0, 0,
+                    0, 0, 0, 3, 1, 2, 3];
+    let length = data.len() as u32;
+
+    let mut cursor = io::Cursor::new(&data[..]);
+    let picture = match read_metadata_block(&mut cursor, 6, length) {
+        Ok(MetadataBlock::Picture(picture)) => picture,
+        _ => panic!("expected picture block to be read"),
+    };
+    assert_eq!(picture.picture_type, PictureType::FrontCover);
+    assert_eq!(picture.mime_type, "image/png");
+    assert_eq!(picture.description, "A");
+    assert_eq!((picture.width, picture.height), (2, 3));
+    assert_eq!(picture.color_depth, 24);
+    assert_eq!(picture.num_colors, 0);
+    assert_eq!(picture.data, vec![1, 2, 3]);
+
+    // If the lengths inside the block do not add up, the block is invalid.
+    let mut cursor = io::Cursor::new(&data[..]);
+    assert!(read_metadata_block(&mut cursor, 6, length + 1).is_err());
+    let mut truncated = data.clone();
+    truncated[20] = 100;
+    let mut cursor = io::Cursor::new(&truncated[..]);
+    assert!(read_metadata_block(&mut cursor, 6, length).is_err());
+}
+
+#[test]
+fn verify_cuesheet_block() {
+    use std::io;
+
+    // A CD CUE sheet with catalog number "1234567890123", and 88200 samples of
+    // lead-in, followed by two tracks.
+    let mut data = vec![0u8; 396];
+    data[..13].copy_from_slice(b"1234567890123");
+    data[128 + 5] = 0x01;
+    data[128 + 6] = 0x58;
+    data[128 + 7] = 0x88;
+    data[136] = 0x80;
+    data[395] = 2;
+
+    // Track 1 at offset 0 with ISRC, pre-emphasis, and index points 0 and 1.
+    let mut track = vec![0u8; 36];
+    track[8] = 1;
+    track[9..21].copy_from_slice(b"NLA000000001");
+    track[21] = 0x40;
+    track[35] = 2;
+    data.extend_from_slice(&track);
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x02, 0x4c, 1, 0, 0, 0]);
+
+    // The lead-out track at offset 44100, a data track without index points.
+    let mut lead_out = vec![0u8; 36];
+    lead_out[6] = 0xac;
+    lead_out[7] = 0x44;
+    lead_out[8] = 170;
+    lead_out[21] = 0x80;
+    data.extend_from_slice(&lead_out);
+
+    let length = data.len() as u32;
+    let cuesheet = match read_metadata_block(&mut io::Cursor::new(&data[..]), 5, length) {
+        Ok(MetadataBlock::CueSheet(cuesheet)) => cuesheet,
+        _ => panic!("expected CUE sheet block to be read"),
+    };
+    assert_eq!(cuesheet.catalog_number, "1234567890123");
+    assert_eq!(cuesheet.lead_in_samples, 88200);
+    assert!(cuesheet.is_cd);
+    assert_eq!(cuesheet.tracks.len(), 2);
+
+    let track = &cuesheet.tracks[0];
+    assert_eq!(track.offset, 0);
+    assert_eq!(track.number, 1);
+    assert_eq!(track.isrc, "NLA000000001");
+    assert!(track.is_audio);
+    assert!(track.pre_emphasis);
+    assert!(!track.is_lead_out());
+    assert_eq!(&track.indices[..], &[CueSheetIndex { offset: 0, number: 0 },
+                                     CueSheetIndex { offset: 588, number: 1 }]);
+
+    let lead_out = &cuesheet.tracks[1];
+    assert_eq!(lead_out.offset, 44100);
+    assert!(lead_out.is_lead_out());
+    assert!(!lead_out.is_audio);
+    assert_eq!(lead_out.isrc, "");
+    assert!(lead_out.indices.is_empty());
+
+    // A block with a length that does not match the contents is invalid.
+    assert!(read_metadata_block(&mut io::Cursor::new(&data[..]), 5, length - 36).is_err());
+
+    // So is a CUE sheet without lead-out track.
+    data[396 + 36 + 24 + 8] = 2;
+    assert!(read_metadata_block(&mut io::Cursor::new(&data[..]), 5, length).is_err());
+}
+
+#[test]
+fn verify_seektable_block() {
+    use std::io;
+
+    // Two seek points out of order, followed by a placeholder.
+    let data = vec![0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0x20, 0, 0x10, 0,
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0,
+                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut cursor = io::Cursor::new(&data[..]);
+    let seektable = match read_metadata_block(&mut cursor, 3, 54) {
+        Ok(MetadataBlock::SeekTable(seektable)) => seektable,
+        _ => panic!("expected seek table block to be read"),
+    };
+
+    let seekpoints = seektable.into_seekpoints();
+    assert_eq!(&seekpoints[..2], &[SeekPoint { sample: 0, offset: 0, samples: 4096 },
+                                   SeekPoint { sample: 4096, offset: 8192, samples: 4096 }]);
+    assert!(!seekpoints[1].is_placeholder());
+    assert!(seekpoints[2].is_placeholder());
+
+    // The length must be a multiple of the size of a seek point.
+    let mut cursor = io::Cursor::new(&data[..]);
+    assert!(read_metadata_block(&mut cursor, 3, 53).is_err());
+}
+
+#[test]
+fn verify_application_block() {
+    use std::io;
+
+    // An application block with ID "riff" and a three-byte payload.
+    let data = vec![b'r', b'i', b'f', b'f', 1, 2, 3];
+    let mut cursor = io::Cursor::new(&data[..]);
+    match read_metadata_block(&mut cursor, 2, 7) {
+        Ok(MetadataBlock::Application { id, data }) => {
+            assert_eq!(id, APPLICATION_ID_RIFF);
+            assert_eq!(data, vec![1, 2, 3]);
+        }
+        _ => panic!("expected application block to be read"),
+    }
+
+    // The block must be large enough to hold the ID.
+    let mut cursor = io::Cursor::new(&data[..]);
+    assert!(read_metadata_block(&mut cursor, 2, 3).is_err());
+}
+
+#[test]
+fn verify_display_matches_metaflac_list() {
+    use std::io;
+
+    let seektable = SeekTable::from_seekpoints(vec![
+        SeekPoint { sample: 0xffff_ffff_ffff_ffff, offset: 0, samples: 0 },
+        SeekPoint { sample: 0, offset: 0, samples: 4096 },
+    ]);
+    assert_eq!(format!("{}", MetadataBlock::SeekTable(seektable)),
+               "  type: 3 (SEEKTABLE)\n\
+               \x20 seek points: 2\n\
+               \x20   point 0: sample_number=0, stream_offset=0, frame_samples=4096\n\
+               \x20   point 1: PLACEHOLDER");
+
+    let comment = VorbisComment {
+        vendor: "reference libFLAC 1.3.2 20170101".to_string(),
+        comments: vec![("ARTIST=Queen".to_string(), 6)],
+    };
+    assert_eq!(format!("{}", comment),
+               "  vendor string: reference libFLAC 1.3.2 20170101\n\
+               \x20 comments: 1\n\
+               \x20   comment[0]: ARTIST=Queen");
+
+    let picture = Picture {
+        picture_type: PictureType::FrontCover,
+        mime_type: "image/png".to_string(),
+        description: String::new(),
+        width: 1,
+        height: 1,
+        color_depth: 24,
+        num_colors: 0,
+        data: vec![0; 42],
+    };
+    assert_eq!(format!("{}", picture),
+               "  type: 3 (Cover (front))\n\
+               \x20 MIME type: image/png\n\
+               \x20 description: \n\
+               \x20 width: 1\n\
+               \x20 height: 1\n\
+               \x20 depth: 24\n\
+               \x20 colors: 0 (unindexed)\n\
+               \x20 data length: 42");
+
+    // The streaminfo block of a real file, after the "fLaC" marker.
+    let data = include_bytes!("../testsamples/wasted_bits.flac");
+    let mut reader = MetadataBlockReader::new(io::Cursor::new(&data[4..]));
+    let streaminfo = format!("{}", reader.next().unwrap().unwrap());
+    assert!(streaminfo.starts_with("  type: 0 (STREAMINFO)\n  minimum blocksize: "));
+    assert!(streaminfo.contains("\n  sample_rate: 44100 Hz\n"));
+}
+
+#[test]
+fn verify_streaminfo_duration_helpers() {
+    let mut info = StreamInfo {
+        min_block_size: 4096,
+        max_block_size: 4096,
+        min_frame_size: None,
+        max_frame_size: None,
+        sample_rate: 44100,
+        channels: 2,
+        bits_per_sample: 16,
+        samples: Some(66150),
+        md5sum: [0; 16],
+    };
+    assert_eq!(info.num_samples(), Some(132300));
+    assert_eq!(info.duration(), Some(Duration::new(1, 500_000_000)));
+    assert_eq!(info.pcm_bytes_per_second(), 176400);
+    assert_eq!(info.average_bytes_per_second(132300), Some(88200));
+
+    info.bits_per_sample = 20;
+    assert_eq!(info.pcm_bytes_per_second(), 264600);
+
+    info.samples = None;
+    assert_eq!(info.num_samples(), None);
+    assert_eq!(info.duration(), None);
+    assert_eq!(info.average_bytes_per_second(132300), None);
+
+    info.samples = Some(66150);
+    info.sample_rate = 0;
+    assert_eq!(info.duration(), None);
+    assert_eq!(info.average_bytes_per_second(132300), None);
+}
@@ -0,0 +1,154 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `metrics` module counts what the decoder did, when the `metrics`
+//! feature is enabled.
+//!
+//! Without the feature, `Metrics` is empty and recording does nothing, so
+//! decoders that do not export metrics do not pay for counting.
+
+use frame::FrameReader;
+use input::ReadBytes;
+
+/// Counters of the frames and subframes that a decoder read.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Metrics {
+    /// The number of frames that were decoded successfully.
+    pub frames: u64,
+    /// The number of bytes read in frames that were decoded or that failed a CRC check.
+    pub bytes_read: u64,
+    /// The number of frames with a header CRC-8 or frame CRC-16 that did not match.
+    pub crc_failures: u64,
+    /// The number of constant subframes decoded.
+    pub constant_subframes: u64,
+    /// The number of verbatim subframes decoded.
+    pub verbatim_subframes: u64,
+    /// The number of subframes with a fixed predictor decoded.
+    pub fixed_subframes: u64,
+    /// The number of subframes with a linear predictor decoded.
+    pub lpc_subframes: u64,
+}
+
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics;
+
+#[cfg(not(feature = "metrics"))]
+impl Metrics {
+    #[inline(always)]
+    pub fn new() -> Metrics {
+        Metrics
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Returns metrics with all counters at zero.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Adds the counters of `other` to these.
+    ///
+    /// This sums the metrics of several readers, for instance to export
+    /// totals for a service that decodes many streams.
+    pub fn add(&mut self, other: &Metrics) {
+        self.frames += other.frames;
+        self.bytes_read += other.bytes_read;
+        self.crc_failures += other.crc_failures;
+        self.constant_subframes += other.constant_subframes;
+        self.verbatim_subframes += other.verbatim_subframes;
+        self.fixed_subframes += other.fixed_subframes;
+        self.lpc_subframes += other.lpc_subframes;
+    }
+}
+
+/// Records a decoded frame of `len` bytes, with the number of subframes of
+/// every kind, indexed by `SubframeKind`.
+#[cfg(feature = "metrics")]
+pub fn record_frame(metrics: &mut Metrics, len: u32, subframes: &[u32; 4]) {
+    metrics.frames += 1;
+    metrics.bytes_read += len as u64;
+    metrics.constant_subframes += subframes[0] as u64;
+    metrics.verbatim_subframes += subframes[1] as u64;
+    metrics.fixed_subframes += subframes[2] as u64;
+    metrics.lpc_subframes += subframes[3] as u64;
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub fn record_frame(_metrics: &mut Metrics, _len: u32, _subframes: &[u32; 4]) {}
+
+/// Records a frame of `len` bytes that failed the CRC check.
+#[cfg(feature = "metrics")]
+pub fn record_crc_failure(metrics: &mut Metrics, len: u32) {
+    metrics.crc_failures += 1;
+    metrics.bytes_read += len as u64;
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub fn record_crc_failure(_metrics: &mut Metrics, _len: u32) {}
+
+/// Adds the counters of `other` to `metrics`.
+#[cfg(feature = "metrics")]
+pub fn merge(metrics: &mut Metrics, other: &Metrics) {
+    metrics.add(other)
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub fn merge(_metrics: &mut Metrics, _other: &Metrics) {}
+
+/// Returns the counters of a frame reader.
+#[cfg(feature = "metrics")]
+pub fn frame_metrics<R: ReadBytes>(frames: &FrameReader<R>) -> Metrics {
+    *frames.metrics()
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub fn frame_metrics<R: ReadBytes>(_frames: &FrameReader<R>) -> Metrics {
+    Metrics
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn verify_metrics_count_frames_and_crc_failures() {
+    use std::fs;
+    use std::io::{self, Read};
+    use FlacReader;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/wasted_bits.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // The file has two frames, of 977 and 104 bytes, with one subframe each.
+    let mut reader = FlacReader::new(io::Cursor::new(data.clone())).unwrap();
+    for sample in reader.samples() {
+        sample.unwrap();
+    }
+    assert_eq!(*reader.metrics(), Metrics {
+        frames: 2,
+        bytes_read: 977 + 104,
+        crc_failures: 0,
+        constant_subframes: 0,
+        verbatim_subframes: 0,
+        fixed_subframes: 2,
+        lpc_subframes: 0,
+    });
+
+    // Damaging the CRC-16 of the last frame makes that frame fail.
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    let mut reader = FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    assert!(reader.samples().any(|s| s.is_err()));
+    let metrics = *reader.metrics();
+    assert_eq!((metrics.frames, metrics.crc_failures), (1, 1));
+    assert_eq!(metrics.bytes_read, 977 + 104);
+    assert_eq!(metrics.fixed_subframes, 1);
+}
@@ -0,0 +1,406 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `ogg` module reads FLAC streams in an Ogg container.
+//!
+//! An Ogg stream consists of pages, which carry the packets of one or more
+//! logical streams. In the FLAC mapping, the first packet contains the
+//! streaminfo, every following header packet contains one metadata block, and
+//! after those, every packet contains one frame. See
+//! <https://xiph.org/flac/ogg_mapping.html> for the details.
+
+use std::io;
+use std::mem;
+use std::slice;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use error::{Error, Result, fmt_err};
+use frame;
+use frame::{Block, FrameResult};
+use input::{BufferedReader, ReadBytes};
+use metadata;
+use metadata::{MetadataBlock, StreamInfo};
+
+/// Header type flag of a page that continues the last packet of the previous page.
+const PAGE_CONTINUED: u8 = 0x01;
+
+/// Header type flag of the first page of a logical stream.
+const PAGE_BEGINNING_OF_STREAM: u8 = 0x02;
+
+/// Header type flag of the last page of a logical stream.
+const PAGE_END_OF_STREAM: u8 = 0x04;
+
+// The CRC-32 of the byte `i`, for the polynomial 0x04c11db7 that Ogg uses.
+const CRC32_TABLE: [u32; 256] =
+    [0x00000000, 0x04c11db7, 0x09823b6e, 0x0d4326d9, 0x130476dc, 0x17c56b6b, 0x1a864db2,
+     0x1e475005, 0x2608edb8, 0x22c9f00f, 0x2f8ad6d6, 0x2b4bcb61, 0x350c9b64, 0x31cd86d3,
+     0x3c8ea00a, 0x384fbdbd, 0x4c11db70, 0x48d0c6c7, 0x4593e01e, 0x4152fda9, 0x5f15adac,
+     0x5bd4b01b, 0x569796c2, 0x52568b75, 0x6a1936c8, 0x6ed82b7f, 0x639b0da6, 0x675a1011,
+     0x791d4014, 0x7ddc5da3, 0x709f7b7a, 0x745e66cd, 0x9823b6e0, 0x9ce2ab57, 0x91a18d8e,
+     0x95609039, 0x8b27c03c, 0x8fe6dd8b, 0x82a5fb52, 0x8664e6e5, 0xbe2b5b58, 0xbaea46ef,
+     0xb7a96036, 0xb3687d81, 0xad2f2d84, 0xa9ee3033, 0xa4ad16ea, 0xa06c0b5d, 0xd4326d90,
+     0xd0f37027, 0xddb056fe, 0xd9714b49, 0xc7361b4c, 0xc3f706fb, 0xceb42022, 0xca753d95,
+     0xf23a8028, 0xf6fb9d9f, 0xfbb8bb46, 0xff79a6f1, 0xe13ef6f4, 0xe5ffeb43, 0xe8bccd9a,
+     0xec7dd02d, 0x34867077, 0x30476dc0, 0x3d044b19, 0x39c556ae, 0x278206ab, 0x23431b1c,
+     0x2e003dc5, 0x2ac12072, 0x128e9dcf, 0x164f8078, 0x1b0ca6a1, 0x1fcdbb16, 0x018aeb13,
+     0x054bf6a4, 0x0808d07d, 0x0cc9cdca, 0x7897ab07, 0x7c56b6b0, 0x71159069, 0x75d48dde,
+     0x6b93dddb, 0x6f52c06c, 0x6211e6b5, 0x66d0fb02, 0x5e9f46bf, 0x5a5e5b08, 0x571d7dd1,
+     0x53dc6066, 0x4d9b3063, 0x495a2dd4, 0x44190b0d, 0x40d816ba, 0xaca5c697, 0xa864db20,
+     0xa527fdf9, 0xa1e6e04e, 0xbfa1b04b, 0xbb60adfc, 0xb6238b25, 0xb2e29692, 0x8aad2b2f,
+     0x8e6c3698, 0x832f1041, 0x87ee0df6, 0x99a95df3, 0x9d684044, 0x902b669d, 0x94ea7b2a,
+     0xe0b41de7, 0xe4750050, 0xe9362689, 0xedf73b3e, 0xf3b06b3b, 0xf771768c, 0xfa325055,
+     0xfef34de2, 0xc6bcf05f, 0xc27dede8, 0xcf3ecb31, 0xcbffd686, 0xd5b88683, 0xd1799b34,
+     0xdc3abded, 0xd8fba05a, 0x690ce0ee, 0x6dcdfd59, 0x608edb80, 0x644fc637, 0x7a089632,
+     0x7ec98b85, 0x738aad5c, 0x774bb0eb, 0x4f040d56, 0x4bc510e1, 0x46863638, 0x42472b8f,
+     0x5c007b8a, 0x58c1663d, 0x558240e4, 0x51435d53, 0x251d3b9e, 0x21dc2629, 0x2c9f00f0,
+     0x285e1d47, 0x36194d42, 0x32d850f5, 0x3f9b762c, 0x3b5a6b9b, 0x0315d626, 0x07d4cb91,
+     0x0a97ed48, 0x0e56f0ff, 0x1011a0fa, 0x14d0bd4d, 0x19939b94, 0x1d528623, 0xf12f560e,
+     0xf5ee4bb9, 0xf8ad6d60, 0xfc6c70d7, 0xe22b20d2, 0xe6ea3d65, 0xeba91bbc, 0xef68060b,
+     0xd727bbb6, 0xd3e6a601, 0xdea580d8, 0xda649d6f, 0xc423cd6a, 0xc0e2d0dd, 0xcda1f604,
+     0xc960ebb3, 0xbd3e8d7e, 0xb9ff90c9, 0xb4bcb610, 0xb07daba7, 0xae3afba2, 0xaafbe615,
+     0xa7b8c0cc, 0xa379dd7b, 0x9b3660c6, 0x9ff77d71, 0x92b45ba8, 0x9675461f, 0x8832161a,
+     0x8cf30bad, 0x81b02d74, 0x857130c3, 0x5d8a9099, 0x594b8d2e, 0x5408abf7, 0x50c9b640,
+     0x4e8ee645, 0x4a4ffbf2, 0x470cdd2b, 0x43cdc09c, 0x7b827d21, 0x7f436096, 0x7200464f,
+     0x76c15bf8, 0x68860bfd, 0x6c47164a, 0x61043093, 0x65c52d24, 0x119b4be9, 0x155a565e,
+     0x18197087, 0x1cd86d30, 0x029f3d35, 0x065e2082, 0x0b1d065b, 0x0fdc1bec, 0x3793a651,
+     0x3352bbe6, 0x3e119d3f, 0x3ad08088, 0x2497d08d, 0x2056cd3a, 0x2d15ebe3, 0x29d4f654,
+     0xc5a92679, 0xc1683bce, 0xcc2b1d17, 0xc8ea00a0, 0xd6ad50a5, 0xd26c4d12, 0xdf2f6bcb,
+     0xdbee767c, 0xe3a1cbc1, 0xe760d676, 0xea23f0af, 0xeee2ed18, 0xf0a5bd1d, 0xf464a0aa,
+     0xf9278673, 0xfde69bc4, 0x89b8fd09, 0x8d79e0be, 0x803ac667, 0x84fbdbd0, 0x9abc8bd5,
+     0x9e7d9662, 0x933eb0bb, 0x97ffad0c, 0xafb010b1, 0xab710d06, 0xa6322bdf, 0xa2f33668,
+     0xbcb4666d, 0xb8757bda, 0xb5365d03, 0xb1f740b4];
+
+/// Updates the CRC-32 of an Ogg page with `bytes`.
+///
+/// Ogg uses the polynomial 0x04c11db7, with an initial value of 0, and bits
+/// are processed most significant bit first.
+fn update_crc(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc = (crc << 8) ^ CRC32_TABLE[((crc >> 24) as u8 ^ byte) as usize];
+    }
+    crc
+}
+
+/// A reader for FLAC streams in an Ogg container.
+///
+/// If the container multiplexes several logical streams, the first FLAC
+/// stream is read, and pages of other streams are skipped. Like `FlacReader`,
+/// the header and metadata are read immediately, and frames are decoded on
+/// demand.
+pub struct OggFlacReader<R: io::Read> {
+    input: BufferedReader<R>,
+    /// The serial number of the logical stream that is being read.
+    serial: u32,
+    /// The body of the current page.
+    page: Vec<u8>,
+    /// The lacing values of the current page, which are the segment lengths.
+    lacing: Vec<u8>,
+    /// The index of the next segment in `lacing`.
+    segment: usize,
+    /// The offset of the next segment in `page`.
+    page_pos: usize,
+    /// Whether the current page is the last page of the logical stream.
+    is_last_page: bool,
+    /// The packet that is being assembled.
+    packet: Vec<u8>,
+    streaminfo: StreamInfo,
+    metadata_blocks: Vec<MetadataBlock>,
+}
+
+impl<R: io::Read> OggFlacReader<R> {
+    /// Creates a reader that reads FLAC in an Ogg container.
+    ///
+    /// The header packet and the metadata packets are read immediately.
+    pub fn new(input: R) -> Result<OggFlacReader<R>> {
+        let mut reader = OggFlacReader {
+            input: BufferedReader::new(input),
+            serial: 0,
+            page: Vec::new(),
+            lacing: Vec::new(),
+            segment: 0,
+            page_pos: 0,
+            is_last_page: false,
+            packet: Vec::new(),
+            streaminfo: StreamInfo {
+                min_block_size: 0,
+                max_block_size: 0,
+                min_frame_size: None,
+                max_frame_size: None,
+                sample_rate: 0,
+                channels: 0,
+                bits_per_sample: 0,
+                samples: None,
+                md5sum: [0; 16],
+            },
+            metadata_blocks: Vec::new(),
+        };
+
+        if !try!(reader.read_page(true)) {
+            return fmt_err("Ogg stream does not contain FLAC")
+        }
+        if !try!(reader.read_packet()) {
+            return fmt_err("invalid Ogg FLAC header packet")
+        }
+        let mut is_last = try!(reader.read_header_packet());
+
+        // Every header packet contains one metadata block. The header packet
+        // states how many there are, but 0 means unknown, so rely on the last
+        // block flag instead.
+        while !is_last {
+            if !try!(reader.read_packet()) {
+                return fmt_err("Ogg stream ends before the last metadata block")
+            }
+            let mut cursor = io::Cursor::new(&reader.packet[..]);
+            let header = try!(metadata::read_metadata_block_header(&mut cursor));
+            let block = try!(metadata::read_metadata_block(&mut cursor,
+                                                           header.block_type,
+                                                           header.length));
+            if let MetadataBlock::StreamInfo(..) = block {
+                return fmt_err("Ogg FLAC stream contains more than one streaminfo block")
+            }
+            reader.metadata_blocks.push(block);
+            is_last = header.is_last;
+        }
+
+        Ok(reader)
+    }
+
+    /// Parses the first packet, and returns whether the streaminfo is the last metadata block.
+    fn read_header_packet(&mut self) -> Result<bool> {
+        let mut cursor = io::Cursor::new(&self.packet[..]);
+        let mut magic = [0; 5];
+        try!(cursor.read_into(&mut magic));
+        if &magic != b"\x7fFLAC" {
+            return fmt_err("Ogg stream does not contain FLAC")
+        }
+        let major_version = try!(cursor.read_u8());
+        let _minor_version = try!(cursor.read_u8());
+        if major_version != 1 {
+            let feature = "Ogg FLAC mapping version is not supported";
+            return Err(Error::Unsupported { feature: feature })
+        }
+        let _header_packets = try!(cursor.read_be_u16());
+        if try!(cursor.read_be_u32()) != 0x66_4c_61_43 {
+            return fmt_err("invalid stream header")
+        }
+
+        let header = try!(metadata::read_metadata_block_header(&mut cursor));
+        let block = try!(metadata::read_metadata_block(&mut cursor,
+                                                       header.block_type,
+                                                       header.length));
+        match block {
+            MetadataBlock::StreamInfo(streaminfo) => {
+                self.streaminfo = streaminfo;
+                self.metadata_blocks.push(block);
+                Ok(header.is_last)
+            }
+            _ => fmt_err("streaminfo block missing"),
+        }
+    }
+
+    /// Reads the next page of the logical stream, and returns false at the end of the input.
+    ///
+    /// If `is_first` is true, look for the first page of a FLAC stream instead,
+    /// and select its logical stream. The first pages of all logical streams
+    /// precede any other pages, and the first page of a FLAC stream contains
+    /// only the header packet.
+    fn read_page(&mut self, is_first: bool) -> Result<bool> {
+        loop {
+            let mut header = [0; 27];
+            match try!(self.input.read_u8_or_eof()) {
+                Some(byte) => header[0] = byte,
+                None => return Ok(false),
+            }
+            try!(self.input.read_into(&mut header[1..]));
+            if &header[..4] != b"OggS" {
+                return fmt_err("invalid Ogg page header")
+            }
+            if header[4] != 0 {
+                return Err(Error::Unsupported { feature: "Ogg page version is not supported" })
+            }
+            let header_type = header[5];
+            let serial = (header[14] as u32) | (header[15] as u32) << 8 |
+                         (header[16] as u32) << 16 | (header[17] as u32) << 24;
+            let presumed_crc = (header[22] as u32) | (header[23] as u32) << 8 |
+                               (header[24] as u32) << 16 | (header[25] as u32) << 24;
+
+            self.lacing.resize(header[26] as usize, 0);
+            try!(self.input.read_into(&mut self.lacing));
+            let len = self.lacing.iter().map(|&x| x as usize).sum();
+            self.page.resize(len, 0);
+            try!(self.input.read_into(&mut self.page));
+
+            // The checksum is computed with the checksum field set to zero.
+            header[22..26].copy_from_slice(&[0; 4]);
+            let crc = update_crc(update_crc(update_crc(0, &header), &self.lacing), &self.page);
+            if crc != presumed_crc {
+                return fmt_err("Ogg page CRC mismatch")
+            }
+
+            if is_first {
+                if header_type & PAGE_BEGINNING_OF_STREAM == 0 {
+                    return fmt_err("Ogg stream does not contain FLAC")
+                }
+                if !self.page.starts_with(b"\x7fFLAC") {
+                    continue
+                }
+                self.serial = serial;
+            } else if serial != self.serial {
+                continue
+            }
+
+            let is_continued = header_type & PAGE_CONTINUED != 0;
+            if is_continued != !self.packet.is_empty() {
+                return fmt_err("Ogg page does not continue the packet of the previous page")
+            }
+            self.segment = 0;
+            self.page_pos = 0;
+            self.is_last_page = header_type & PAGE_END_OF_STREAM != 0;
+            return Ok(true)
+        }
+    }
+
+    /// Reads the next packet of the logical stream, and returns false at the end.
+    fn read_packet(&mut self) -> Result<bool> {
+        self.packet.clear();
+        loop {
+            if self.segment == self.lacing.len() {
+                let has_page = !self.is_last_page && try!(self.read_page(false));
+                if !has_page {
+                    if self.packet.is_empty() {
+                        return Ok(false)
+                    } else {
+                        return fmt_err("Ogg stream ends in the middle of a packet")
+                    }
+                }
+                continue
+            }
+
+            // A packet ends with the first segment shorter than 255 bytes.
+            let len = self.lacing[self.segment] as usize;
+            self.packet.extend_from_slice(&self.page[self.page_pos..self.page_pos + len]);
+            self.segment += 1;
+            self.page_pos += len;
+            if len < 255 {
+                return Ok(true)
+            }
+        }
+    }
+
+    /// Returns the streaminfo metadata.
+    pub fn streaminfo(&self) -> StreamInfo {
+        self.streaminfo
+    }
+
+    /// Returns all metadata blocks, in stream order.
+    ///
+    /// The first block is always the streaminfo block.
+    pub fn metadata<'a>(&'a self) -> slice::Iter<'a, MetadataBlock> {
+        self.metadata_blocks.iter()
+    }
+
+    /// Returns name-value pairs of Vorbis comments, such as `("ARTIST", "Queen")`.
+    ///
+    /// See `FlacReader::tags()` for details.
+    pub fn tags<'a>(&'a self) -> metadata::Tags<'a> {
+        for block in self.metadata() {
+            if let MetadataBlock::VorbisComment(ref vc) = *block {
+                return metadata::Tags::new(&vc.comments[..])
+            }
+        }
+        metadata::Tags::new(&[])
+    }
+
+    /// Decodes the next frame, or returns `None` at the end of the stream.
+    ///
+    /// Like `FrameReader::read_next_or_eof()`, the buffer is moved into the
+    /// returned block, so it can be reused. Empty packets are skipped.
+    pub fn read_next_or_eof(&mut self, buffer: Vec<i32>) -> FrameResult {
+        loop {
+            if !try!(self.read_packet()) {
+                return Ok(None)
+            }
+            if !self.packet.is_empty() {
+                break
+            }
+        }
+        let block = try!(frame::decode_frame(&self.packet, &self.streaminfo, buffer));
+        Ok(Some(block))
+    }
+
+    /// Returns an iterator over all samples, interleaved.
+    ///
+    /// Every block is decoded into the same buffer. Unlike
+    /// `FlacReader::samples()`, the iteration ends after an error.
+    pub fn samples<'r>(&'r mut self) -> OggFlacSamples<'r, R> {
+        OggFlacSamples {
+            reader: self,
+            block: Block::empty(),
+            sample: 0,
+            channel: 0,
+            has_failed: false,
+        }
+    }
+
+    /// Destroys the reader and returns the underlying reader.
+    ///
+    /// Because the reader employs buffering, the underlying reader may have
+    /// been advanced beyond the last page that was read.
+    pub fn into_inner(self) -> R {
+        self.input.into_inner()
+    }
+}
+
+/// An iterator that yields samples read from an `OggFlacReader`.
+pub struct OggFlacSamples<'r, R: 'r + io::Read> {
+    reader: &'r mut OggFlacReader<R>,
+    block: Block,
+    sample: u32,
+    channel: u32,
+    has_failed: bool,
+}
+
+impl<'r, R: io::Read> Iterator for OggFlacSamples<'r, R> {
+    type Item = Result<i32>;
+
+    fn next(&mut self) -> Option<Result<i32>> {
+        if self.has_failed {
+            return None
+        }
+
+        self.channel += 1;
+        if self.channel >= self.block.channels() {
+            self.channel = 0;
+            self.sample += 1;
+
+            // Decode blocks until one with samples, or the end of the stream.
+            while self.sample >= self.block.duration() {
+                let buffer = mem::replace(&mut self.block, Block::empty()).into_buffer();
+                match self.reader.read_next_or_eof(buffer) {
+                    Ok(Some(block)) => self.block = block,
+                    Ok(None) => return None,
+                    Err(error) => {
+                        self.has_failed = true;
+                        return Some(Err(error))
+                    }
+                }
+                self.sample = 0;
+            }
+        }
+
+        Some(Ok(self.block.sample(self.channel, self.sample)))
+    }
+}
+
+#[test]
+fn verify_crc32() {
+    // The check value of CRC-32/POSIX, before its final inversion.
+    assert_eq!(update_crc(0, b"123456789"), 0x89a1_897f);
+    assert_eq!(update_crc(update_crc(0, b"1234"), b"56789"), 0x89a1_897f);
+    assert_eq!(CRC32_TABLE[1], 0x04c1_1db7);
+}
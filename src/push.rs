@@ -0,0 +1,266 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `push` module provides a decoder that is fed bytes, rather than reading them.
+//!
+//! `FlacReader` pulls data from an `io::Read`, which blocks until data is
+//! available. `PushDecoder` instead buffers the bytes that are pushed into it,
+//! and decodes as far as the buffered data allows whenever it is polled. This
+//! makes it usable with async runtimes, in WASM, or with any other transport
+//! that delivers data in chunks.
+
+use std::io;
+use std::mem;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use error::{Error, Result, fmt_err};
+use frame::{Block, FrameReader, MAX_FRAME_HEADER_LEN};
+use metadata;
+use metadata::{MetadataBlock, StreamInfo};
+use {FlacReaderOptions, read_stream_header};
+
+/// The outcome of polling a `PushDecoder`.
+#[derive(Debug)]
+pub enum Event {
+    /// The streaminfo block, which is always the first event.
+    StreamInfo(StreamInfo),
+    /// A metadata block other than the streaminfo block.
+    MetadataBlock(MetadataBlock),
+    /// A decoded block of audio.
+    Block(Block),
+    /// More data must be pushed before decoding can continue.
+    NeedMoreData,
+    /// The end of the stream was reached.
+    End,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Header,
+    Metadata,
+    Frames,
+    End,
+}
+
+/// A decoder that is fed bytes with `push()`, and polled for events with `poll()`.
+///
+/// Every time a frame or metadata block is incomplete, decoding it is attempted
+/// again on the next poll after more data was pushed. Push data in chunks of
+/// at least a few kilobytes to avoid decoding the same frame many times.
+pub struct PushDecoder {
+    /// The bytes that were pushed and not yet consumed, starting at `pos`.
+    data: Vec<u8>,
+    pos: usize,
+    /// Whether `finish()` was called, so no more data will follow.
+    is_finished: bool,
+    state: State,
+    streaminfo: Option<StreamInfo>,
+    /// A buffer to decode the next block into.
+    buffer: Vec<i32>,
+    options: FlacReaderOptions,
+}
+
+impl PushDecoder {
+    /// Creates a decoder that expects a stream starting with the `fLaC` header.
+    pub fn new() -> PushDecoder {
+        PushDecoder::new_ext(FlacReaderOptions::default())
+    }
+
+    /// Creates a decoder with reader options.
+    ///
+    /// The options that control how the stream header is found, the metadata
+    /// limits, and the CRC checks are used like by `FlacReader::new_ext()`.
+    /// Every metadata block is decoded and returned, and the other options do
+    /// not apply to a push decoder.
+    ///
+    /// Claxon rejects metadata blocks that exceed the limits, to protect
+    /// against denial of service attacks where a small damaged or malicious
+    /// stream could cause gigabytes of memory to be allocated.
+    pub fn new_ext(options: FlacReaderOptions) -> PushDecoder {
+        PushDecoder {
+            data: Vec::new(),
+            pos: 0,
+            is_finished: false,
+            state: State::Header,
+            streaminfo: None,
+            buffer: Vec::new(),
+            options: options,
+        }
+    }
+
+    /// Appends data to the input.
+    pub fn push(&mut self, data: &[u8]) {
+        // Drop the consumed bytes first, if that halves the buffer at least.
+        if self.pos > self.data.len() / 2 {
+            self.data.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.data.extend_from_slice(data);
+    }
+
+    /// Marks the end of the input.
+    ///
+    /// After this, polling returns the remaining events and then `Event::End`,
+    /// or an error if the stream was cut off.
+    pub fn finish(&mut self) {
+        self.is_finished = true;
+    }
+
+    /// Returns the streaminfo, once it has been decoded.
+    pub fn streaminfo(&self) -> Option<StreamInfo> {
+        self.streaminfo
+    }
+
+    /// Hands back the buffer of a block, so it can be reused to decode the next block.
+    pub fn recycle(&mut self, buffer: Vec<i32>) {
+        self.buffer = buffer;
+    }
+
+    /// Decodes the next event from the buffered data.
+    ///
+    /// After an error, the decoder cannot continue, and polling it again
+    /// returns `Event::End`.
+    pub fn poll(&mut self) -> Result<Event> {
+        let result = match self.state {
+            State::Header => self.poll_header(),
+            State::Metadata => self.poll_metadata(),
+            State::Frames => self.poll_frame(),
+            State::End => return Ok(Event::End),
+        };
+        match result {
+            // Running out of data is only an error once there is no more.
+            Err(Error::IoError(ref err))
+                if err.kind() == io::ErrorKind::UnexpectedEof && !self.is_finished => {
+                Ok(Event::NeedMoreData)
+            }
+            Err(err) => {
+                self.state = State::End;
+                Err(err)
+            }
+            Ok(event) => Ok(event),
+        }
+    }
+
+    /// Decodes all blocks that the buffered data allows, as planar floating point samples.
+    ///
+    /// This suits Web Audio, which takes one buffer of `f32` per channel. The
+    /// samples of channel `ch` are appended to `out[ch]`, scaled by the bit
+    /// depth such that full scale is [-1.0, 1.0), and `out` is resized to the
+    /// number of channels. Metadata blocks are skipped, use `poll()` to
+    /// receive them. Returns the number of inter-channel samples appended,
+    /// which is 0 if more data is needed, or at the end of the stream.
+    pub fn decode_planar_f32(&mut self, out: &mut Vec<Vec<f32>>) -> Result<usize> {
+        let mut n = 0;
+        loop {
+            match try!(self.poll()) {
+                Event::StreamInfo(..) | Event::MetadataBlock(..) => continue,
+                Event::NeedMoreData | Event::End => return Ok(n),
+                Event::Block(block) => {
+                    let streaminfo = self.streaminfo.expect("streaminfo is decoded before frames");
+                    // The streaminfo block was validated when it was read, so
+                    // the bit depth is between 4 and 32, and the shift is fine.
+                    let scale = 1.0 / (1u64 << (streaminfo.bits_per_sample - 1)) as f32;
+                    out.resize(block.channels() as usize, Vec::new());
+                    for (ch, samples) in out.iter_mut().enumerate() {
+                        samples.extend(block.channel(ch as u32).iter().map(|&s| s as f32 * scale));
+                    }
+                    n += block.duration() as usize;
+                    self.recycle(block.into_buffer());
+                }
+            }
+        }
+    }
+
+    fn poll_header(&mut self) -> Result<Event> {
+        let (streaminfo, is_last, len) = {
+            let mut cursor = io::Cursor::new(&self.data[self.pos..]);
+            try!(read_stream_header(&mut cursor, &self.options));
+            let header = try!(metadata::read_metadata_block_header(&mut cursor));
+            let block = try!(metadata::read_metadata_block_ext(&mut cursor,
+                                                               header.block_type,
+                                                               header.length,
+                                                               &self.options.metadata_limits));
+            match block {
+                MetadataBlock::StreamInfo(streaminfo) => {
+                    (streaminfo, header.is_last, cursor.position() as usize)
+                }
+                _ => return fmt_err("streaminfo block missing"),
+            }
+        };
+        self.pos += len;
+        self.streaminfo = Some(streaminfo);
+        self.state = if is_last { State::Frames } else { State::Metadata };
+        Ok(Event::StreamInfo(streaminfo))
+    }
+
+    fn poll_metadata(&mut self) -> Result<Event> {
+        let (block, is_last, len) = {
+            let mut cursor = io::Cursor::new(&self.data[self.pos..]);
+            let header = try!(metadata::read_metadata_block_header(&mut cursor));
+
+            // Wait for the entire block, rather than parsing a partial block
+            // after every push.
+            if self.data.len() - self.pos < 4 + header.length as usize && !self.is_finished {
+                return Ok(Event::NeedMoreData)
+            }
+            let block = try!(metadata::read_metadata_block_ext(&mut cursor,
+                                                               header.block_type,
+                                                               header.length,
+                                                               &self.options.metadata_limits));
+            (block, header.is_last, cursor.position() as usize)
+        };
+        if let MetadataBlock::StreamInfo(..) = block {
+            return fmt_err("encountered second streaminfo block")
+        }
+        self.pos += len;
+        if is_last {
+            self.state = State::Frames;
+        }
+        Ok(Event::MetadataBlock(block))
+    }
+
+    fn poll_frame(&mut self) -> Result<Event> {
+        // A frame is at least as long as a frame header, and so is the
+        // signature of a tag after the last frame.
+        let available = self.data.len() - self.pos;
+        if available == 0 && self.is_finished {
+            self.state = State::End;
+            return Ok(Event::End)
+        }
+        if available < MAX_FRAME_HEADER_LEN && !self.is_finished {
+            return Ok(Event::NeedMoreData)
+        }
+
+        let streaminfo = self.streaminfo.expect("streaminfo is decoded before frames");
+        let buffer = mem::replace(&mut self.buffer, Vec::new());
+        let mut frame_reader =
+            FrameReader::with_fixed_block_size(io::Cursor::new(&self.data[self.pos..]),
+                                               streaminfo.fixed_block_size());
+        frame_reader.set_max_block_size(streaminfo.max_block_size);
+        frame_reader.set_channels(streaminfo.channels);
+        frame_reader.set_verify_crc(self.options.verify_crc);
+        frame_reader.set_verify_header_crc(self.options.verify_header_crc);
+        match try!(frame_reader.read_next_or_eof(buffer)) {
+            Some(block) => {
+                self.pos += frame_reader.into_inner().position() as usize;
+                Ok(Event::Block(block))
+            }
+            // A tag after the last frame ends the stream.
+            None => {
+                self.state = State::End;
+                Ok(Event::End)
+            }
+        }
+    }
+}
+
+impl Default for PushDecoder {
+    fn default() -> PushDecoder {
+        PushDecoder::new()
+    }
+}
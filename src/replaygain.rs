@@ -0,0 +1,131 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `replaygain` module deals with loudness normalization through ReplayGain tags.
+//!
+//! Taggers store the ReplayGain of a track and of its album as Vorbis
+//! comments, such as `REPLAYGAIN_TRACK_GAIN=-7.89 dB`. Use
+//! `FlacReader::track_gain()` or `FlacReader::album_gain()` to read them, and
+//! wrap the samples in `ApplyGain` to play them at the normalized loudness.
+
+use error::Result;
+
+/// A ReplayGain adjustment, read from the tags.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReplayGain {
+    /// The gain to apply, in decibels. Usually negative for modern recordings.
+    pub gain: f64,
+
+    /// The peak sample amplitude, where 1.0 is full scale, if known.
+    pub peak: Option<f64>,
+}
+
+impl ReplayGain {
+    /// Returns the factor to multiply samples by to apply the gain.
+    ///
+    /// If the peak is known, the factor is reduced if necessary so that the
+    /// peak does not clip.
+    pub fn scale_factor(&self) -> f64 {
+        let scale = 10.0_f64.powf(self.gain / 20.0);
+        match self.peak {
+            Some(peak) if peak * scale > 1.0 => 1.0 / peak,
+            _ => scale,
+        }
+    }
+}
+
+/// Parses a gain tag value such as `-7.89 dB` into decibels.
+pub fn parse_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = if value.ends_with("dB") || value.ends_with("db") {
+        &value[..value.len() - 2]
+    } else {
+        value
+    };
+    number.trim().parse::<f64>().ok().and_then(|x| if x.is_finite() { Some(x) } else { None })
+}
+
+/// Parses a peak tag value such as `0.988739`.
+pub fn parse_peak(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().and_then(|x| if x.is_finite() && x > 0.0 { Some(x) } else { None })
+}
+
+/// An adapter that applies a ReplayGain adjustment to samples.
+///
+/// The samples are scaled by `ReplayGain::scale_factor()`, rounded, and
+/// clamped to the range of the bit depth, so even without a known peak, the
+/// result does not overflow.
+pub struct ApplyGain<I> {
+    samples: I,
+    scale: f64,
+    min: f64,
+    max: f64,
+}
+
+impl<I: Iterator<Item = Result<i32>>> ApplyGain<I> {
+    /// Wraps `samples` with the given bit depth, to apply `gain` to them.
+    ///
+    /// `samples` is typically `FlacReader::samples()`, and `bits_per_sample`
+    /// the bit depth from the streaminfo.
+    pub fn new(samples: I, gain: ReplayGain, bits_per_sample: u32) -> ApplyGain<I> {
+        let max = (1_i64 << (bits_per_sample - 1)) - 1;
+        ApplyGain {
+            samples: samples,
+            scale: gain.scale_factor(),
+            min: -(max as f64) - 1.0,
+            max: max as f64,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<i32>>> Iterator for ApplyGain<I> {
+    type Item = Result<i32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<i32>> {
+        self.samples.next().map(|result| result.map(|sample| {
+            let scaled = (sample as f64 * self.scale).round();
+            scaled.max(self.min).min(self.max) as i32
+        }))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
+}
+
+#[test]
+fn verify_parse_gain_and_peak() {
+    assert_eq!(parse_gain("-7.89 dB"), Some(-7.89));
+    assert_eq!(parse_gain("+2.50 dB"), Some(2.5));
+    assert_eq!(parse_gain("1.5"), Some(1.5));
+    assert_eq!(parse_gain("loud"), None);
+    assert_eq!(parse_peak("0.988739"), Some(0.988739));
+    assert_eq!(parse_peak("-1"), None);
+}
+
+#[test]
+fn verify_apply_gain_prevents_clipping() {
+    let samples = [1000, -32768, 32766];
+
+    // A gain of -6.02 dB halves the amplitude.
+    let gain = ReplayGain { gain: 20.0 * 0.5_f64.log10(), peak: None };
+    let out: Vec<i32> = ApplyGain::new(samples.iter().map(|&s| Ok(s)), gain, 16)
+        .map(|s| s.unwrap()).collect();
+    assert_eq!(out, vec![500, -16384, 16383]);
+
+    // A positive gain is limited by the peak.
+    let gain = ReplayGain { gain: 20.0, peak: Some(0.5) };
+    assert_eq!(gain.scale_factor(), 2.0);
+
+    // Without a known peak, the samples are clamped instead.
+    let gain = ReplayGain { gain: 20.0 * 2.0_f64.log10(), peak: None };
+    let out: Vec<i32> = ApplyGain::new(samples.iter().map(|&s| Ok(s)), gain, 16)
+        .map(|s| s.unwrap()).collect();
+    assert_eq!(out, vec![2000, -32768, 32767]);
+}
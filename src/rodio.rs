@@ -0,0 +1,170 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `rodio` module plays decoded audio through `rodio`.
+//!
+//! This module is only available with the `rodio` feature. `FlacSource`
+//! implements `rodio::Source`, so a stream can be played, mixed, or
+//! transformed like any other rodio source.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate claxon;
+//! # fn main() {
+//! let reader = claxon::FlacReader::open("music.flac").unwrap();
+//! let source = claxon::rodio::FlacSource::new(reader).unwrap();
+//! // Append the source to a rodio player here.
+//! # let _ = source;
+//! # }
+//! ```
+
+extern crate rodio;
+
+use std::io;
+use std::time::Duration;
+
+use self::rodio::{ChannelCount, Sample, SampleRate, Source};
+use error::{Error, Result, fmt_err};
+use {FlacIntoSamples, FlacReader};
+
+/// A `rodio::Source` that decodes a FLAC stream.
+///
+/// Samples are converted to floating point, such that full scale is
+/// [-1.0, 1.0), as rodio expects. A `Source` cannot report errors, so when a
+/// frame fails to decode, the source ends early, and the error is available
+/// through `error()`.
+pub struct FlacSource<R: io::Read> {
+    samples: FlacIntoSamples<R>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    total_duration: Option<Duration>,
+    scale: Sample,
+    error: Option<Error>,
+}
+
+impl<R: io::Read> FlacSource<R> {
+    /// Creates a source that decodes the stream from the current position of the reader.
+    ///
+    /// Returns an error if the sample rate of the stream is unknown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader was constructed with `metadata_only` set.
+    pub fn new(reader: FlacReader<R>) -> Result<FlacSource<R>> {
+        let streaminfo = reader.streaminfo();
+        let sample_rate = match SampleRate::new(streaminfo.sample_rate) {
+            Some(rate) => rate,
+            None => return fmt_err("sample rate is unknown"),
+        };
+        // The number of channels is stored as 3 bits, plus one.
+        let channels = ChannelCount::new(streaminfo.channels as u16).unwrap();
+        Ok(FlacSource {
+            channels: channels,
+            sample_rate: sample_rate,
+            total_duration: streaminfo.duration(),
+            scale: 1.0 / (1u64 << (streaminfo.bits_per_sample - 1)) as Sample,
+            error: None,
+            samples: reader.into_samples(),
+        })
+    }
+
+    /// Returns the error that ended the source early, if any.
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Returns the reader, to continue decoding after the current block.
+    pub fn into_reader(self) -> FlacReader<R> {
+        self.samples.into_reader()
+    }
+}
+
+impl<R: io::Read> Iterator for FlacSource<R> {
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.error.is_some() {
+            return None
+        }
+        match self.samples.next() {
+            Some(Ok(sample)) => Some(sample as Sample * self.scale),
+            Some(Err(err)) => {
+                self.error = Some(err);
+                None
+            }
+            None => None,
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.error {
+            Some(..) => (0, Some(0)),
+            None => self.samples.size_hint(),
+        }
+    }
+}
+
+impl<R: io::Read> Source for FlacSource<R> {
+    fn current_span_len(&self) -> Option<usize> {
+        // The number of channels and the sample rate are fixed for the entire
+        // stream, so the span lasts until the end of the stream.
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+#[test]
+fn verify_flac_source_yields_scaled_samples() {
+    let mut reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let streaminfo = reader.streaminfo();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let source = FlacSource::new(reader).unwrap();
+    assert_eq!(source.channels().get() as u32, streaminfo.channels);
+    assert_eq!(source.sample_rate().get(), streaminfo.sample_rate);
+    assert_eq!(source.total_duration(), streaminfo.duration());
+    assert_eq!(source.size_hint(), (samples.len(), Some(samples.len())));
+
+    let scale = (1u32 << (streaminfo.bits_per_sample - 1)) as Sample;
+    let decoded: Vec<Sample> = source.collect();
+    assert_eq!(decoded.len(), samples.len());
+    for (&x, &sample) in decoded.iter().zip(samples.iter()) {
+        assert_eq!(x * scale, sample as Sample);
+    }
+}
+
+#[test]
+fn verify_flac_source_ends_at_error() {
+    use std::fs;
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/wasted_bits.flac").unwrap().read_to_end(&mut data).unwrap();
+    // Damage the CRC-16 of the last frame.
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    let reader = FlacReader::new(io::Cursor::new(data)).unwrap();
+    let mut source = FlacSource::new(reader).unwrap();
+    assert!(source.by_ref().count() > 0);
+    assert!(source.error().is_some());
+    assert_eq!(source.next(), None);
+}
@@ -0,0 +1,344 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `seek` module implements seeking to a sample in a seekable stream.
+//!
+//! To find the frame that contains a given sample, the seek table narrows down
+//! the range of bytes to search, if the stream has one. Within that range, the
+//! search bisects on byte offsets, scanning for the next frame at every step,
+//! until the range is small enough to decode frames sequentially.
+//!
+//! Frames that are decoded or skipped are recorded in an in-memory seek index,
+//! which narrows down later seeks in the same way as the seek table does.
+//!
+//! For input that is not seekable, skipping forward is possible without
+//! decoding, by scanning for the header of the next frame.
+
+use std::cmp;
+use std::io;
+use std::mem;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use error::{Error, Result, fmt_err};
+use frame;
+use frame::FrameReader;
+use input::{BufferedReader, ReadBytes};
+use metadata::{SeekPoint, SeekTable};
+
+/// Below this size in bytes, a range is decoded sequentially rather than bisected.
+const BISECTION_THRESHOLD: u64 = 4096;
+
+/// Returns the error for a seek past the last sample of the stream.
+pub fn beyond_end_error() -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "seek target beyond end of stream"))
+}
+
+/// Records a frame in the in-memory seek index, which is ordered by sample.
+///
+/// The offset is relative to the first frame, as in a seek table.
+pub fn record_frame(index: &mut Vec<SeekPoint>, sample: u64, offset: u64, samples: u16) {
+    // Frames are usually decoded in order, so check the end first.
+    let is_new = match index.last() {
+        Some(last) => last.sample < sample,
+        None => true,
+    };
+    let point = SeekPoint {
+        sample: sample,
+        offset: offset,
+        samples: samples,
+    };
+    if is_new {
+        index.push(point);
+    } else if let Err(i) = index.binary_search_by_key(&sample, |p| p.sample) {
+        index.insert(i, point);
+    }
+}
+
+/// Decodes the frame at the current position, and returns its first sample and duration.
+///
+/// The decode buffer is put back into `buffer` so it can be reused.
+fn read_frame<R: ReadBytes>(input: R,
+                            fixed_block_size: u16,
+                            buffer: &mut Vec<i32>)
+                            -> Result<Option<(u64, u32)>> {
+    let mut frame_reader = FrameReader::with_fixed_block_size(input, fixed_block_size);
+    let buf = mem::replace(buffer, Vec::new());
+    match try!(frame_reader.read_next_or_eof(buf)) {
+        Some(block) => {
+            let result = (block.time(), block.duration());
+            *buffer = block.into_buffer();
+            Ok(Some(result))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Finds the first valid frame that starts in the range `from..to`.
+///
+/// Returns the offset of the frame and its first sample. A sync code can
+/// occur by chance in the middle of a frame, so a candidate frame is only
+/// accepted if it decodes successfully, including the CRC checks.
+fn find_frame<R: io::Read + io::Seek>(input: &mut BufferedReader<R>,
+                                      fixed_block_size: u16,
+                                      from: u64,
+                                      to: u64,
+                                      buffer: &mut Vec<i32>)
+                                      -> Result<Option<(u64, u64)>> {
+    let mut offset = from;
+    loop {
+        try!(input.seek(offset));
+
+        // Look for the 14-bit sync code, followed by a reserved zero bit.
+        loop {
+            if input.position() >= to {
+                return Ok(None)
+            }
+            let position = input.position();
+            let step = {
+                let bytes = try!(input.peek(2));
+                if bytes.len() < 2 {
+                    return Ok(None)
+                }
+                match frame::find_frame_sync(bytes) {
+                    // Only a trailing 0xff is not followed by a byte, move to
+                    // it so that the next byte can be peeked.
+                    Some(i) if i + 1 == bytes.len() => i,
+                    Some(i) => {
+                        offset = position + i as u64;
+                        break
+                    }
+                    None => bytes.len(),
+                }
+            };
+            try!(input.skip(step as u32));
+        }
+        if offset >= to {
+            return Ok(None)
+        }
+
+        try!(input.seek(offset));
+        match read_frame(&mut *input, fixed_block_size, buffer) {
+            Ok(Some((time, _))) => return Ok(Some((offset, time))),
+            Ok(None) => return Ok(None),
+            // A real IO error must be reported. Running into the end of the
+            // stream only means that the candidate was not a frame.
+            Err(Error::IoError(err)) => {
+                if err.kind() != io::ErrorKind::UnexpectedEof {
+                    return Err(Error::IoError(err))
+                }
+            }
+            Err(..) => {}
+        }
+
+        // The candidate was not a frame, continue looking after its start.
+        offset += 1;
+    }
+}
+
+/// Narrows down the range `lo..hi` that contains the frame with `sample` using seek points.
+///
+/// The points could be stale if the stream was edited, so only trust a point
+/// if there is a frame with the right sample number at its offset.
+fn narrow_range<R: io::Read + io::Seek>(input: &mut BufferedReader<R>,
+                                        fixed_block_size: u16,
+                                        points: &[SeekPoint],
+                                        frames_offset: u64,
+                                        sample: u64,
+                                        range: &mut (u64, u64),
+                                        buffer: &mut Vec<i32>)
+                                        -> Result<()> {
+    let points: Vec<_> = points.iter().filter(|p| !p.is_placeholder()).collect();
+    let n_before = points.iter().take_while(|p| p.sample <= sample).count();
+    if n_before > 0 {
+        let point = points[n_before - 1];
+        let offset = frames_offset + point.offset;
+        if offset > range.0 && offset < range.1 {
+            try!(input.seek(offset));
+            match read_frame(&mut *input, fixed_block_size, buffer) {
+                Ok(Some((time, _))) if time == point.sample => range.0 = offset,
+                _ => {}
+            }
+        }
+    }
+    if let Some(point) = points.get(n_before) {
+        range.1 = cmp::min(range.1, frames_offset + point.offset);
+    }
+    Ok(())
+}
+
+/// Positions `input` at the start of the frame that contains `sample`.
+///
+/// `frames_offset` is the offset of the first frame. Frames that are decoded
+/// along the way are recorded in `index`. Returns the number of inter-channel
+/// samples in the frame that precede the target sample.
+pub fn seek_to_sample<R: io::Read + io::Seek>(input: &mut BufferedReader<R>,
+                                              fixed_block_size: u16,
+                                              seektable: Option<&SeekTable>,
+                                              index: &mut Vec<SeekPoint>,
+                                              frames_offset: u64,
+                                              sample: u64)
+                                              -> Result<u32> {
+    let mut buffer = Vec::new();
+    let mut range = (frames_offset, try!(input.stream_len()));
+
+    // Narrow down the range with the seek table, if there is one, and with
+    // the frames that were seen before.
+    if let Some(seektable) = seektable {
+        try!(narrow_range(input, fixed_block_size, seektable.seekpoints(), frames_offset,
+                          sample, &mut range, &mut buffer));
+    }
+    try!(narrow_range(input, fixed_block_size, index, frames_offset, sample, &mut range,
+                      &mut buffer));
+    let (mut lo, mut hi) = range;
+
+    // Bisect on the byte offset, until the range is small.
+    while hi > lo && hi - lo > BISECTION_THRESHOLD {
+        let mid = lo + (hi - lo) / 2;
+        match try!(find_frame(input, fixed_block_size, mid, hi, &mut buffer)) {
+            Some((offset, time)) if time <= sample => lo = offset,
+            _ => hi = mid,
+        }
+    }
+
+    // Then decode frames sequentially, until the one that contains the target.
+    try!(input.seek(lo));
+    loop {
+        let offset = input.position();
+        let (time, duration) = match try!(read_frame(&mut *input, fixed_block_size, &mut buffer)) {
+            Some(frame) => frame,
+            None => return Err(beyond_end_error()),
+        };
+        record_frame(index, time, offset - frames_offset, duration as u16);
+        if sample < time {
+            return fmt_err("frame sample numbers are not increasing")
+        }
+        if sample < time + duration as u64 {
+            try!(input.seek(offset));
+            return Ok((sample - time) as u32)
+        }
+    }
+}
+
+/// Parses the header of the frame at the current position, without consuming it.
+///
+/// Returns `None` at the end of the stream.
+pub fn peek_frame_info<R: io::Read>(input: &mut BufferedReader<R>,
+                                    fixed_block_size: u16)
+                                    -> Result<Option<frame::FrameInfo>> {
+    let bytes = try!(input.peek(frame::MAX_FRAME_HEADER_LEN));
+    if bytes.is_empty() {
+        return Ok(None)
+    }
+    frame::read_frame_info(&mut io::Cursor::new(bytes), fixed_block_size)
+}
+
+/// Advances to the next valid frame header for which `accept` returns true, or to the end.
+///
+/// Returns the header of the frame, or `None` at the end of the stream.
+fn scan_to_frame<R, F>(input: &mut BufferedReader<R>,
+                       fixed_block_size: u16,
+                       mut accept: F)
+                       -> Result<Option<frame::FrameInfo>>
+    where R: io::Read,
+          F: FnMut(&frame::FrameInfo) -> bool
+{
+    loop {
+        let step = {
+            let bytes = try!(input.peek(frame::MAX_FRAME_HEADER_LEN));
+            if bytes.is_empty() {
+                return Ok(None)
+            }
+            match frame::find_frame_sync(bytes) {
+                None => bytes.len(),
+                Some(0) => match frame::read_frame_info(&mut io::Cursor::new(bytes),
+                                                        fixed_block_size) {
+                    Ok(Some(info)) => {
+                        if accept(&info) {
+                            return Ok(Some(info))
+                        }
+                        1
+                    }
+                    _ => 1,
+                },
+                // Move to the candidate, so that the entire header can be peeked.
+                Some(i) => i,
+            }
+        };
+        try!(input.skip(step as u32));
+    }
+}
+
+/// Advances to the header of the frame that starts at `first_sample`, or to the end.
+///
+/// Frames do not store their length, so the only way to find the end of a
+/// frame without decoding it, is to look for the next frame header. The sync
+/// code may occur by chance inside a frame, but a header is only accepted if
+/// its CRC-8 is valid, and if it has the expected sample number, so it is
+/// extremely unlikely to be fooled.
+fn skip_to_frame<R: io::Read>(input: &mut BufferedReader<R>,
+                              fixed_block_size: u16,
+                              first_sample: u64)
+                              -> Result<()> {
+    try!(scan_to_frame(input, fixed_block_size, |info| info.first_sample == first_sample));
+    Ok(())
+}
+
+/// Advances to the first frame header after damaged data, or to the end.
+///
+/// A header is accepted if its CRC-8 is valid, and if its first sample lies
+/// in the range `min_sample..max_sample`. The end is exclusive, and if it is
+/// `None`, there is no upper bound. Returns the header of the frame, or `None`
+/// if the stream ended first.
+pub fn resync<R: io::Read>(input: &mut BufferedReader<R>,
+                           fixed_block_size: u16,
+                           min_sample: u64,
+                           max_sample: Option<u64>)
+                           -> Result<Option<frame::FrameInfo>> {
+    scan_to_frame(input, fixed_block_size, |info| {
+        let below_max = match max_sample {
+            Some(max) => info.first_sample < max,
+            None => true,
+        };
+        info.first_sample >= min_sample && below_max
+    })
+}
+
+/// Skips `samples` inter-channel samples, starting at the frame at the current position.
+///
+/// Only frame headers are parsed, frames are not decoded. Skipped frames are
+/// recorded in `index`. Returns the number of samples skipped, which is less
+/// than `samples` only if the stream ended, and the number of samples to skip
+/// in the frame at the new position.
+pub fn skip_samples<R: io::Read>(input: &mut BufferedReader<R>,
+                                 fixed_block_size: u16,
+                                 index: &mut Vec<SeekPoint>,
+                                 frames_offset: u64,
+                                 samples: u64)
+                                 -> Result<(u64, u32)> {
+    let mut remaining = samples;
+    loop {
+        // Parse the header of the current frame without consuming it, so that
+        // when the target is in this frame, it can still be decoded.
+        let info = match try!(peek_frame_info(input, fixed_block_size)) {
+            Some(info) => info,
+            None => return Ok((samples - remaining, 0)),
+        };
+
+        if remaining < info.block_size as u64 {
+            return Ok((samples, remaining as u32))
+        }
+        remaining -= info.block_size as u64;
+        let offset = input.position() - frames_offset;
+        record_frame(index, info.first_sample, offset, info.block_size);
+
+        // Step over the sync code, then look for the next frame.
+        try!(input.skip(2));
+        let next_sample = info.first_sample + info.block_size as u64;
+        try!(skip_to_frame(input, fixed_block_size, next_sample));
+    }
+}
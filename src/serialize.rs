@@ -0,0 +1,150 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `serialize` module holds the Serde implementations that cannot be derived.
+//!
+//! Binary data, such as picture data, is represented as a base64 string, so
+//! it stays compact in text formats like JSON. Vorbis comments are
+//! represented as their `NAME=value` strings, and the position of the `=` is
+//! recomputed when deserializing, so a deserialized comment is always valid.
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use metadata::{SeekPoint, SeekTable};
+
+/// Serializes bytes as a base64 string, for `#[serde(with)]`.
+///
+/// The string is padded, as in RFC 4648, and padding is required when deserializing.
+pub mod base64 {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let string = try!(String::deserialize(deserializer));
+        STANDARD.decode(&string).map_err(|_| de::Error::custom("invalid base64 data"))
+    }
+}
+
+/// Serializes Vorbis comments as their `NAME=value` strings, for `#[serde(with)]`.
+pub mod vorbis_comments {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{SerializeSeq, Serializer};
+
+    pub fn serialize<S: Serializer>(comments: &[(String, usize)],
+                                    serializer: S)
+                                    -> Result<S::Ok, S::Error> {
+        let mut seq = try!(serializer.serialize_seq(Some(comments.len())));
+        for comment in comments {
+            try!(seq.serialize_element(&comment.0));
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<(String, usize)>, D::Error>
+        where D: Deserializer<'de>
+    {
+        let strings = try!(Vec::<String>::deserialize(deserializer));
+        let mut comments = Vec::with_capacity(strings.len());
+        for comment in strings {
+            let sep_index = match comment.find('=') {
+                Some(i) => i,
+                None => return Err(de::Error::custom("Vorbis comment does not contain '='")),
+            };
+            comments.push((comment, sep_index));
+        }
+        Ok(comments)
+    }
+}
+
+impl Serialize for SeekTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.seekpoints().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SeekTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SeekTable, D::Error> {
+        // The seek points are sorted by the constructor.
+        Vec::<SeekPoint>::deserialize(deserializer).map(SeekTable::from_seekpoints)
+    }
+}
+
+#[test]
+fn verify_base64_round_trips() {
+    use serde_json;
+
+    let cases: &[(&[u8], &str)] = &[
+        (b"", ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (b"foob", "Zm9vYg=="),
+        (b"\xff\xfe\x00", "//4A"),
+    ];
+    for &(bytes, string) in cases {
+        let json = format!("\"{}\"", string);
+        let mut out = Vec::new();
+        base64::serialize(bytes, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(out, json.as_bytes());
+        let mut de = serde_json::Deserializer::from_str(&json);
+        assert_eq!(base64::deserialize(&mut de).unwrap(), bytes);
+    }
+    for json in &["\"Zg=\"", "\"Z===\"", "\"Zg==Zg==\"", "\"Zm9*\"", "\"Zg\""] {
+        let mut de = serde_json::Deserializer::from_str(json);
+        assert!(base64::deserialize(&mut de).is_err());
+    }
+}
+
+#[test]
+fn verify_metadata_serializes_to_json() {
+    use serde_json;
+    use metadata::{Picture, PictureType, VorbisComment};
+    use FlacReader;
+
+    let reader = FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let streaminfo = reader.streaminfo();
+    let json = serde_json::to_string(&streaminfo).unwrap();
+    assert!(json.contains("\"sample_rate\":44100"));
+
+    let comment = VorbisComment {
+        vendor: "claxon".to_string(),
+        comments: vec![("ARTIST=Queen".to_string(), 6)],
+    };
+    let json = serde_json::to_string(&comment).unwrap();
+    assert_eq!(json, r#"{"vendor":"claxon","comments":["ARTIST=Queen"]}"#);
+    assert_eq!(serde_json::from_str::<VorbisComment>(&json).unwrap(), comment);
+    assert!(serde_json::from_str::<VorbisComment>(r#"{"vendor":"","comments":["x"]}"#).is_err());
+
+    let picture = Picture {
+        picture_type: PictureType::FrontCover,
+        mime_type: "image/png".to_string(),
+        description: String::new(),
+        width: 1,
+        height: 1,
+        color_depth: 24,
+        num_colors: 0,
+        data: b"\x89PNG".to_vec(),
+    };
+    let json = serde_json::to_string(&picture).unwrap();
+    assert!(json.contains("\"data\":\"iVBORw==\""));
+    assert_eq!(serde_json::from_str::<Picture>(&json).unwrap(), picture);
+
+    // Seek points are sorted on deserialization, like in the constructor.
+    let json = r#"[{"sample":4096,"offset":977,"samples":314},
+                   {"sample":0,"offset":0,"samples":4096}]"#;
+    let table: SeekTable = serde_json::from_str(json).unwrap();
+    assert_eq!(table.seekpoints()[0].sample, 0);
+    assert_eq!(serde_json::to_string(&table).unwrap(),
+               r#"[{"sample":0,"offset":0,"samples":4096},{"sample":4096,"offset":977,"samples":314}]"#);
+}
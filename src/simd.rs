@@ -0,0 +1,286 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `simd` module contains vectorized LPC prediction, with AVX2 on x86_64 and NEON on aarch64.
+//!
+//! Every predicted sample depends on the one before it, so the prediction
+//! cannot simply be computed for several samples at once. Instead, samples are
+//! predicted in groups of four. The terms that involve samples from before the
+//! previous group do not depend on recent predictions, and they are computed
+//! with one vector lane per sample of the group. The remaining terms, at most
+//! seven per sample, are added one sample at a time.
+//!
+//! With `std`, support for the vector extension is detected at runtime.
+//! Without `std`, a kernel is only used when the crate is compiled for a CPU
+//! that has the extension.
+//!
+//! There is no kernel for SSE4.1. With two lanes per vector, it was slower
+//! than the scalar loop: LLVM turns the `_mm_mul_epi32` multiplications into
+//! general 64-bit multiplications, which SSE4.1 lacks. NEON has a widening
+//! multiply-add of 32-bit lanes, `vmlal_s32`, so it has no such problem.
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Predicts `buffer[12..]`, if the CPU supports a vectorized kernel, and returns where it stopped.
+///
+/// The predictor has order 12, see `subframe::predict_lpc()`, and
+/// `buffer[..12]` must have been predicted already. The kernel predicts whole
+/// groups of four samples, the samples from the returned index onwards are
+/// left to the caller. Returns 12 if the CPU has no supported vector
+/// extension.
+///
+/// The coefficients are quantized LPC coefficients, which are at most 15 bits.
+/// The kernels rely on this: they multiply only the low 32 bits of every
+/// coefficient.
+#[inline]
+pub fn predict_lpc_12(coefficients: &[i64; 12], qlp_shift: i16, buffer: &mut [i32]) -> usize {
+    debug_assert!(coefficients.iter().all(|&c| c as i16 as i64 == c),
+                  "LPC coefficients must fit in 16 bits");
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return unsafe { predict_lpc_12_avx2(coefficients, qlp_shift, buffer) }
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if has_neon() {
+            return unsafe { predict_lpc_12_neon(coefficients, qlp_shift, buffer) }
+        }
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let _ = (coefficients, qlp_shift, buffer);
+    12
+}
+
+/// Returns whether `predict_lpc_12()` has a kernel for this CPU.
+#[cfg(test)]
+fn has_kernel() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return has_avx2();
+    #[cfg(target_arch = "aarch64")]
+    return has_neon();
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    return false;
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+fn has_avx2() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+fn has_avx2() -> bool {
+    cfg!(target_feature = "avx2")
+}
+
+#[cfg(all(target_arch = "aarch64", feature = "std"))]
+fn has_neon() -> bool {
+    ::std::arch::is_aarch64_feature_detected!("neon")
+}
+
+#[cfg(all(target_arch = "aarch64", not(feature = "std")))]
+fn has_neon() -> bool {
+    cfg!(target_feature = "neon")
+}
+
+/// Adds the terms that involve the previous group and the group itself, and predicts the group.
+///
+/// `partial` holds the sums of the terms that involve older samples, as
+/// computed by a kernel, `p` holds the previous group, and `group` holds the
+/// residuals, which are replaced by the predicted samples. Returns those
+/// samples. The predictions are truncated to 32 bits, as in the scalar loop.
+#[inline(always)]
+fn predict_group(c: &[i64; 12],
+                 qlp_shift: i16,
+                 partial: [i64; 4],
+                 p: &[i64; 4],
+                 group: &mut [i32])
+                 -> [i64; 4] {
+    let s0 = partial[0] + c[8] * p[0] + c[9] * p[1] + c[10] * p[2] + c[11] * p[3];
+    let x0 = ((s0 >> qlp_shift) + group[0] as i64) as i32 as i64;
+    let s1 = partial[1] + c[7] * p[0] + c[8] * p[1] + c[9] * p[2] + c[10] * p[3] + c[11] * x0;
+    let x1 = ((s1 >> qlp_shift) + group[1] as i64) as i32 as i64;
+    let s2 = partial[2] + c[6] * p[0] + c[7] * p[1] + c[8] * p[2] + c[9] * p[3] + c[10] * x0 +
+             c[11] * x1;
+    let x2 = ((s2 >> qlp_shift) + group[2] as i64) as i32 as i64;
+    let s3 = partial[3] + c[5] * p[0] + c[6] * p[1] + c[7] * p[2] + c[8] * p[3] + c[9] * x0 +
+             c[10] * x1 + c[11] * x2;
+    let x3 = ((s3 >> qlp_shift) + group[3] as i64) as i32 as i64;
+    group[0] = x0 as i32;
+    group[1] = x1 as i32;
+    group[2] = x2 as i32;
+    group[3] = x3 as i32;
+    [x0, x1, x2, x3]
+}
+
+/// Implements `predict_lpc_12()` with AVX2.
+///
+/// This is unsafe because the CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn predict_lpc_12_avx2(coefficients: &[i64; 12], qlp_shift: i16, buffer: &mut [i32]) -> usize {
+    if buffer.len() < 16 {
+        return 12
+    }
+    let c = coefficients;
+
+    // Lane t holds the terms for sample i + t that involve samples
+    // i - 12 .. i - 4, the window of sample i - 12 + k is multiplied with
+    // coefficient k. Lanes where that window reaches into the previous group
+    // get a zero coefficient, those terms are added separately below.
+    let mut lane_coefficients = [_mm256_setzero_si256(); 8];
+    for (k, lc) in lane_coefficients.iter_mut().enumerate() {
+        let lane = |t: usize| if k + t < 8 { c[k] } else { 0 };
+        *lc = _mm256_set_epi64x(lane(3), lane(2), lane(1), lane(0));
+    }
+
+    // The samples before the previous group, eight of them, and the previous
+    // group itself. These are kept in registers rather than loaded from the
+    // buffer, because loading them right after they were stored is slow.
+    let mut older = _mm_loadu_si128(buffer.as_ptr() as *const __m128i);
+    let mut old = _mm_loadu_si128(buffer.as_ptr().offset(4) as *const __m128i);
+    let mut p = [buffer[8] as i64, buffer[9] as i64, buffer[10] as i64, buffer[11] as i64];
+
+    let mut i = 12;
+    while i + 4 <= buffer.len() {
+        macro_rules! term {
+            ($k: expr, $window: expr) => {
+                _mm256_mul_epi32(lane_coefficients[$k], _mm256_cvtepi32_epi64($window))
+            }
+        }
+        let sum_older = _mm256_add_epi64(
+            _mm256_add_epi64(term!(0, older), term!(1, _mm_alignr_epi8(old, older, 4))),
+            _mm256_add_epi64(term!(2, _mm_alignr_epi8(old, older, 8)),
+                             term!(3, _mm_alignr_epi8(old, older, 12))));
+        let sum_old = _mm256_add_epi64(
+            _mm256_add_epi64(term!(4, old), term!(5, _mm_srli_si128(old, 4))),
+            _mm256_add_epi64(term!(6, _mm_srli_si128(old, 8)), term!(7, _mm_srli_si128(old, 12))));
+        let sum = _mm256_add_epi64(sum_older, sum_old);
+
+        let partial = [_mm256_extract_epi64(sum, 0),
+                       _mm256_extract_epi64(sum, 1),
+                       _mm256_extract_epi64(sum, 2),
+                       _mm256_extract_epi64(sum, 3)];
+        let x = predict_group(c, qlp_shift, partial, &p, &mut buffer[i..i + 4]);
+
+        older = old;
+        old = _mm_set_epi32(p[3] as i32, p[2] as i32, p[1] as i32, p[0] as i32);
+        p = x;
+        i += 4;
+    }
+    i
+}
+
+/// Implements `predict_lpc_12()` with NEON.
+///
+/// This works like `predict_lpc_12_avx2()`, but a vector holds two 64-bit
+/// lanes, so the lanes of a group are split over a low and a high half.
+///
+/// This is unsafe because the CPU must support NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn predict_lpc_12_neon(coefficients: &[i64; 12], qlp_shift: i16, buffer: &mut [i32]) -> usize {
+    if buffer.len() < 16 {
+        return 12
+    }
+    let c = coefficients;
+
+    // The coefficients per lane, as in `predict_lpc_12_avx2()`, for samples
+    // i and i + 1 in the low half, and i + 2 and i + 3 in the high half.
+    let mut low_coefficients = [vdup_n_s32(0); 8];
+    let mut high_coefficients = [vdup_n_s32(0); 8];
+    for k in 0..8 {
+        let lane = |t: usize| if k + t < 8 { c[k] as i32 } else { 0 };
+        low_coefficients[k] = vld1_s32([lane(0), lane(1)].as_ptr());
+        high_coefficients[k] = vld1_s32([lane(2), lane(3)].as_ptr());
+    }
+
+    let mut older = vld1q_s32(buffer.as_ptr());
+    let mut old = vld1q_s32(buffer.as_ptr().offset(4));
+    let mut p = [buffer[8] as i64, buffer[9] as i64, buffer[10] as i64, buffer[11] as i64];
+
+    let mut i = 12;
+    while i + 4 <= buffer.len() {
+        // The window of sample i - 12 + k, for every coefficient k. Lanes
+        // that reach beyond `old` have a zero coefficient.
+        let windows = [older,
+                       vextq_s32(older, old, 1),
+                       vextq_s32(older, old, 2),
+                       vextq_s32(older, old, 3),
+                       old,
+                       vextq_s32(old, old, 1),
+                       vextq_s32(old, old, 2),
+                       vextq_s32(old, old, 3)];
+
+        // Two accumulators per half, to shorten the dependency chains.
+        let mut low = [vdupq_n_s64(0); 2];
+        let mut high = [vdupq_n_s64(0); 2];
+        for k in 0..8 {
+            let a = k % 2;
+            low[a] = vmlal_s32(low[a], vget_low_s32(windows[k]), low_coefficients[k]);
+            high[a] = vmlal_s32(high[a], vget_high_s32(windows[k]), high_coefficients[k]);
+        }
+        let low = vaddq_s64(low[0], low[1]);
+        let high = vaddq_s64(high[0], high[1]);
+
+        let partial = [vgetq_lane_s64(low, 0),
+                       vgetq_lane_s64(low, 1),
+                       vgetq_lane_s64(high, 0),
+                       vgetq_lane_s64(high, 1)];
+        let x = predict_group(c, qlp_shift, partial, &p, &mut buffer[i..i + 4]);
+
+        older = old;
+        old = vld1q_s32([p[0] as i32, p[1] as i32, p[2] as i32, p[3] as i32].as_ptr());
+        p = x;
+        i += 4;
+    }
+    i
+}
+
+#[test]
+fn verify_predict_lpc_12_matches_scalar_prediction() {
+    // A linear congruential generator, for arbitrary samples and coefficients.
+    let mut state = 1u32;
+    let mut next = || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        state as i32
+    };
+    for len in 12..64 {
+        for &(bits, qlp_shift) in &[(16, 9), (24, 13), (32, 15)] {
+            let mut coefficients = [0i64; 12];
+            for c in coefficients.iter_mut() {
+                *c = (next() >> 16) as i64;
+            }
+            let mut buffer = Vec::with_capacity(len);
+            for _ in 0..len {
+                buffer.push(next() >> (32 - bits));
+            }
+
+            // The predictions overflow for these coefficients, so this also
+            // checks that they are truncated to 32 bits in the same way.
+            let mut expected = buffer.clone();
+            for i in 12..len {
+                let prediction = (0..12).map(|k| coefficients[k] * expected[i - 12 + k] as i64)
+                                        .sum::<i64>() >> qlp_shift;
+                expected[i] = (prediction + expected[i] as i64) as i32;
+            }
+
+            // Where there is a kernel, it predicts all groups of four.
+            let start = predict_lpc_12(&coefficients, qlp_shift, &mut buffer);
+            if has_kernel() && len >= 16 {
+                assert_eq!(start, len - (len - 12) % 4);
+            } else {
+                assert_eq!(start, 12);
+            }
+            assert_eq!(&buffer[..start], &expected[..start]);
+        }
+    }
+}
@@ -8,9 +8,11 @@
 //! The `subframe` module deals with subframes that make up a frame of the FLAC stream.
 
 use std::cmp;
-use std::num;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 use error::{Error, Result, fmt_err};
 use input::{Bitstream, ReadBytes};
+use simd;
 
 #[derive(Clone, Copy, Debug)]
 enum SubframeType {
@@ -20,12 +22,92 @@ enum SubframeType {
     Lpc(u8),
 }
 
+/// The prediction method of a subframe.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubframeKind {
+    /// All samples have the same value.
+    Constant,
+    /// The samples are stored unencoded.
+    Verbatim,
+    /// The samples are predicted by a fixed polynomial predictor.
+    Fixed,
+    /// The samples are predicted by a linear predictor with stored coefficients.
+    Lpc,
+}
+
+/// The parameters that a subframe was encoded with.
+///
+/// This is for tools that analyze or compare encoders, see
+/// `FrameReader::read_next_with_report_or_eof()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubframeInfo {
+    /// The prediction method.
+    pub kind: SubframeKind,
+    /// The predictor order, 0 for constant and verbatim subframes.
+    pub order: u32,
+    /// The number of wasted bits per sample.
+    pub wasted_bits: u32,
+    /// The partition order of the residual, 0 if there is no residual.
+    pub partition_order: u32,
+    /// The Rice parameter of every residual partition, empty if there is no residual.
+    pub rice_parameters: Vec<u32>,
+    /// The quantized LPC coefficients in stream order, empty unless this is an LPC subframe.
+    ///
+    /// The prediction for sample `i` is the sum of `coefficients[j] * sample[i - j - 1]`,
+    /// shifted right by `qlp_shift`.
+    pub coefficients: Vec<i32>,
+    /// The right shift applied to the LPC prediction, 0 unless this is an LPC subframe.
+    pub qlp_shift: u32,
+    /// The residual of the predictor, one value per sample after the warm-up samples.
+    ///
+    /// This is the signal before the prediction is added back, and before the
+    /// samples are shifted left by the wasted bits. It is empty for constant
+    /// and verbatim subframes.
+    pub residual: Vec<i32>,
+}
+
+impl SubframeInfo {
+    fn new(header: &SubframeHeader) -> SubframeInfo {
+        let order = match header.sf_type {
+            SubframeType::Constant | SubframeType::Verbatim => 0,
+            SubframeType::Fixed(order) | SubframeType::Lpc(order) => order as u32,
+        };
+        SubframeInfo {
+            kind: header.kind(),
+            order: order,
+            wasted_bits: header.wasted_bits_per_sample,
+            partition_order: 0,
+            rice_parameters: Vec::new(),
+            coefficients: Vec::new(),
+            qlp_shift: 0,
+            residual: Vec::new(),
+        }
+    }
+}
+
+/// Returns the partition order, given a Rice parameter for every partition.
+fn partition_order(params: &[u32]) -> u32 {
+    // There are 2^order partitions, or none if there is no residual.
+    if params.is_empty() { 0 } else { params.len().trailing_zeros() }
+}
+
 #[derive(Clone, Copy)]
 struct SubframeHeader {
     sf_type: SubframeType,
     wasted_bits_per_sample: u32,
 }
 
+impl SubframeHeader {
+    fn kind(&self) -> SubframeKind {
+        match self.sf_type {
+            SubframeType::Constant => SubframeKind::Constant,
+            SubframeType::Verbatim => SubframeKind::Verbatim,
+            SubframeType::Fixed(..) => SubframeKind::Fixed,
+            SubframeType::Lpc(..) => SubframeKind::Lpc,
+        }
+    }
+}
+
 fn read_subframe_header<R: ReadBytes>(input: &mut Bitstream<R>) -> Result<SubframeHeader> {
     // The first bit must be a 0 padding bit.
     if try!(input.read_bit()) {
@@ -44,6 +126,7 @@ fn read_subframe_header<R: ReadBytes>(input: &mut Bitstream<R>) -> Result<Subfra
         // implemented.
         n if (n & 0b111_110 == 0b000_010) || (n & 0b111_100 == 0b000_100) ||
              (n & 0b110_000 == 0b010_000) => {
+            trace!(Reserved("subframe type"));
             return fmt_err("invalid subframe header, encountered reserved value");
         }
 
@@ -52,6 +135,7 @@ fn read_subframe_header<R: ReadBytes>(input: &mut Bitstream<R>) -> Result<Subfra
 
             // A fixed frame has order up to 4, other bit patterns are reserved.
             if order > 4 {
+                trace!(Reserved("fixed subframe order"));
                 return fmt_err("invalid subframe header, encountered reserved value");
             }
 
@@ -185,13 +269,53 @@ pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
                             bps: u32,
                             buffer: &mut [i32])
                             -> Result<()> {
+    let mut decoded = 0;
+    try!(decode_counted(input, bps, buffer, &mut decoded));
+    Ok(())
+}
+
+/// Decodes a subframe, and counts the samples that have been decoded.
+///
+/// This is like `decode()`, but if decoding fails, `decoded` is the number of
+/// samples at the start of the buffer that were decoded completely before the
+/// error occurred. This makes it possible to salvage the start of a subframe
+/// that was cut off.
+///
+/// Returns the number of wasted bits per sample in the subframe: the number of
+/// least significant bits that are zero in every sample, as indicated by the
+/// encoder.
+pub fn decode_counted<R: ReadBytes>(input: &mut Bitstream<R>,
+                                    bps: u32,
+                                    buffer: &mut [i32],
+                                    decoded: &mut usize)
+                                    -> Result<u32> {
+    decode_with_info(input, bps, buffer, decoded, None).map(|(wasted_bits, _)| wasted_bits)
+}
+
+/// Decodes a subframe like `decode_counted()`, and appends its parameters to `infos`.
+///
+/// If decoding fails after the subframe header, the parameters that were read
+/// before the error are appended. Returns the number of wasted bits per
+/// sample, and the prediction method, which is also known when `infos` is
+/// `None`.
+pub fn decode_with_info<R: ReadBytes>(input: &mut Bitstream<R>,
+                                      bps: u32,
+                                      buffer: &mut [i32],
+                                      decoded: &mut usize,
+                                      mut infos: Option<&mut Vec<SubframeInfo>>)
+                                      -> Result<(u32, SubframeKind)> {
+    *decoded = 0;
+
     // The sample type i32 should be wide enough to accomodate for all bits of
-    // the stream, but this can be verified at a higher level than here. Still,
-    // it is a good idea to make the assumption explicit. FLAC supports up to
-    // sample widths of 32 in theory, so with the delta between channels that
-    // requires 33 bits, but the reference decoder supports only subset FLAC of
-    // 24 bits per sample at most, so restricting ourselves to i32 is fine.
-    debug_assert!(32 >= bps);
+    // the stream. FLAC supports up to sample widths of 32 in theory, so with
+    // the delta between channels that requires 33 bits, but the reference
+    // decoder supports only subset FLAC of 24 bits per sample at most, so
+    // restricting ourselves to i32 is fine. This is checked at a higher level
+    // already, but decoding a wider sample would panic, so be sure.
+    if bps > 32 {
+        let feature = "more than 32 bits per sample is not supported";
+        return Err(Error::Unsupported { feature: feature });
+    }
 
     let header = try!(read_subframe_header(input));
 
@@ -203,20 +327,38 @@ pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
     // than the stream bps. We later shift all the samples left to correct this.
     let sf_bps = bps - header.wasted_bits_per_sample;
 
-    match header.sf_type {
-        SubframeType::Constant => try!(decode_constant(input, sf_bps, buffer)),
-        SubframeType::Verbatim => try!(decode_verbatim(input, sf_bps, buffer)),
-        SubframeType::Fixed(ord) => try!(decode_fixed(input, sf_bps, ord as u32, buffer)),
-        SubframeType::Lpc(ord) => try!(decode_lpc(input, sf_bps, ord as u32, buffer)),
+    let result = {
+        // The predictors record their parameters and residual.
+        let info = match infos {
+            Some(ref mut infos) => {
+                infos.push(SubframeInfo::new(&header));
+                infos.last_mut()
+            }
+            None => None,
+        };
+        match header.sf_type {
+            SubframeType::Constant => decode_constant(input, sf_bps, buffer, decoded),
+            SubframeType::Verbatim => decode_verbatim(input, sf_bps, buffer, decoded),
+            SubframeType::Fixed(ord) => {
+                decode_fixed(input, sf_bps, ord as u32, buffer, decoded, info)
+            }
+            SubframeType::Lpc(ord) => {
+                decode_lpc(input, sf_bps, ord as u32, buffer, decoded, info)
+            }
+        }
+    };
+    if let Some(info) = infos.and_then(|infos| infos.last_mut()) {
+        info.partition_order = partition_order(&info.rice_parameters);
     }
 
     // Finally, everything must be shifted by 'wasted bits per sample' to
     // the left. Note: it might be better performance-wise to do this on
     // the fly while decoding. That could be done if this is a bottleneck.
+    // If decoding failed, shift only the samples that were decoded.
     if header.wasted_bits_per_sample > 0 {
         debug_assert!(header.wasted_bits_per_sample <= 31,
                       "Cannot shift by more than the sample width.");
-        for s in buffer {
+        for s in &mut buffer[..*decoded] {
             // For a valid FLAC file, this shift does not overflow. For an
             // invalid file it might, and then we decode garbage, but we don't
             // crash the program in debug mode due to shift overflow.
@@ -224,7 +366,7 @@ pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
         }
     }
 
-    Ok(())
+    result.map(|()| (header.wasted_bits_per_sample, header.kind()))
 }
 
 #[derive(Copy, Clone)]
@@ -233,16 +375,24 @@ enum RicePartitionType {
     Rice2,
 }
 
+/// Decodes the residual into `buffer`, and adds the samples decoded to `decoded`.
+///
+/// If `params` is provided, the Rice parameter of every partition is appended to it.
 fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
                                  block_size: u16,
-                                 buffer: &mut [i32])
+                                 buffer: &mut [i32],
+                                 decoded: &mut usize,
+                                 mut params: Option<&mut Vec<u32>>)
                                  -> Result<()> {
     // Residual starts with two bits of coding method.
     let partition_type = match try!(input.read_leq_u8(2)) {
         0b00 => RicePartitionType::Rice,
         0b01 => RicePartitionType::Rice2,
         // 10 and 11 are reserved.
-        _ => return fmt_err("invalid residual, encountered reserved value"),
+        _ => {
+            trace!(Reserved("residual coding method"));
+            return fmt_err("invalid residual, encountered reserved value");
+        }
     };
 
     // Next are 4 bits partition order.
@@ -263,25 +413,38 @@ fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
         return fmt_err("invalid residual");
     }
 
+    // The partitions must cover the block exactly. If the block size is not a
+    // multiple of the number of partitions, the samples at the end would not
+    // be decoded at all, and the buffer would be left with garbage.
+    if n_samples as u32 * n_partitions != block_size as u32 {
+        return fmt_err("invalid residual, block size not divisible by partition count");
+    }
+
     // Finally decode the partitions themselves.
     match partition_type {
         RicePartitionType::Rice => {
-            let mut start = 0;
+            let mut rest = buffer;
             let mut len = n_samples - n_warm_up;
             for _ in 0..n_partitions {
-                let slice = &mut buffer[start..start + len as usize];
-                try!(decode_rice_partition(input, slice));
-                start = start + len as usize;
+                let (slice, tail) = { rest }.split_at_mut(len as usize);
+                let param = try!(decode_rice_partition(input, slice, decoded));
+                if let Some(ref mut params) = params {
+                    params.push(param);
+                }
+                rest = tail;
                 len = n_samples;
             }
         }
         RicePartitionType::Rice2 => {
-            let mut start = 0;
+            let mut rest = buffer;
             let mut len = n_samples - n_warm_up;
             for _ in 0..n_partitions {
-                let slice = &mut buffer[start..start + len as usize];
-                try!(decode_rice2_partition(input, slice));
-                start = start + len as usize;
+                let (slice, tail) = { rest }.split_at_mut(len as usize);
+                let param = try!(decode_rice2_partition(input, slice, decoded));
+                if let Some(ref mut params) = params {
+                    params.push(param);
+                }
+                rest = tail;
                 len = n_samples;
             }
         }
@@ -290,19 +453,39 @@ fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
     Ok(())
 }
 
+#[test]
+fn verify_decode_residual_rejects_partial_partitions() {
+    use std::io;
+    use input::BufferedReader;
+
+    // Rice coding with a partition order of 1, so two partitions, for a block
+    // of 5 samples. The partitions cannot cover the block exactly.
+    let data = io::Cursor::new(vec![0b0000_0100, 0, 0, 0]);
+    let mut bits = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 5];
+    let mut decoded = 0;
+    match decode_residual(&mut bits, 5, &mut buffer, &mut decoded, None) {
+        Err(Error::FormatError { .. }) => {}
+        _ => panic!("expected residual to be rejected"),
+    }
+}
+
+// Returns the Rice parameter of the partition.
+//
 // Performance note: all Rice partitions in real-world FLAC files are Rice
 // partitions, not Rice2 partitions. Therefore it makes sense to inline this
 // function into decode_residual.
 #[inline(always)]
 fn decode_rice_partition<R: ReadBytes>(input: &mut Bitstream<R>,
-                                       buffer: &mut [i32])
-                                       -> Result<()> {
+                                       buffer: &mut [i32],
+                                       decoded: &mut usize)
+                                       -> Result<u32> {
     // A Rice partition (not Rice2), starts with a 4-bit Rice parameter.
     let rice_param = try!(input.read_leq_u8(4)) as u32;
 
     // All ones is an escape code that indicates unencoded binary.
     if rice_param == 0b1111 {
-        return Err(Error::Unsupported("unencoded binary is not yet implemented"))
+        return Err(Error::Unsupported { feature: "unencoded binary is not yet implemented" })
     }
 
     // About the decoding below: the first part of the sample is the quotient,
@@ -314,27 +497,14 @@ fn decode_rice_partition<R: ReadBytes>(input: &mut Bitstream<R>,
     // so the quotient can potentially be very large. However, in practice
     // it is rarely greater than 5. Values as large as 75 still occur though.
     //
-    // Next up is the remainder in rice_param bits. Depending on the number of
-    // bits, at most two or three bytes need to be read, so the code below is
-    // split into two cases to allow a more efficient reading function to be
-    // used when possible. About 45% of the time, rice_param is less than 9
-    // (measured from real-world FLAC files).
-
-    if rice_param <= 8 {
-        for sample in buffer.iter_mut() {
-            let q = try!(input.read_unary());
-            let r = try!(input.read_leq_u8(rice_param)) as u32;
-            *sample = rice_to_signed((q << rice_param) | r);
-        }
-    } else {
-        for sample in buffer.iter_mut() {
-            let q = try!(input.read_unary());
-            let r = try!(input.read_gt_u8_leq_u16(rice_param));
-            *sample = rice_to_signed((q << rice_param) | r);
-        }
+    // Next up is the remainder in rice_param bits. The bitstream buffers bits
+    // in a 64-bit word, so the width of the remainder does not matter.
+    for sample in buffer.iter_mut() {
+        *sample = rice_to_signed(try!(input.read_rice(rice_param)));
+        *decoded += 1;
     }
 
-    Ok(())
+    Ok(rice_param)
 }
 
 // Performance note: a Rice2 partition is extremely uncommon, I haven’t seen a
@@ -343,39 +513,39 @@ fn decode_rice_partition<R: ReadBytes>(input: &mut Bitstream<R>,
 #[inline(never)]
 #[cold]
 fn decode_rice2_partition<R: ReadBytes>(input: &mut Bitstream<R>,
-                                        buffer: &mut [i32])
-                                        -> Result<()> {
+                                        buffer: &mut [i32],
+                                        decoded: &mut usize)
+                                        -> Result<u32> {
     // A Rice2 partition, starts with a 5-bit Rice parameter.
     let rice_param = try!(input.read_leq_u8(5)) as u32;
 
     // All ones is an escape code that indicates unencoded binary.
     if rice_param == 0b11111 {
-        return Err(Error::Unsupported("unencoded binary is not yet implemented"))
+        return Err(Error::Unsupported { feature: "unencoded binary is not yet implemented" })
     }
 
+    // The quotient is unary encoded, and the remainder takes rice_param bits.
+    // Because at this point rice_param is at most 30, it fits in a u32.
     for sample in buffer.iter_mut() {
-        // First part of the sample is the quotient, unary encoded.
-        let q = try!(input.read_unary());
-
-        // Next is the remainder, in rice_param bits. Because at this
-        // point rice_param is at most 30, we can safely read into a u32.
-        let r = try!(input.read_leq_u32(rice_param));
-        *sample = rice_to_signed((q << rice_param) | r);
+        *sample = rice_to_signed(try!(input.read_rice(rice_param)));
+        *decoded += 1;
     }
 
-    Ok(())
+    Ok(rice_param)
 }
 
 fn decode_constant<R: ReadBytes>(input: &mut Bitstream<R>,
                                  bps: u32,
-                                 buffer: &mut [i32])
+                                 buffer: &mut [i32],
+                                 decoded: &mut usize)
                                  -> Result<()> {
     let sample_u32 = try!(input.read_leq_u32(bps));
     let sample = extend_sign_u32(sample_u32, bps);
 
-    for s in buffer {
+    for s in buffer.iter_mut() {
         *s = sample;
     }
+    *decoded = buffer.len();
 
     Ok(())
 }
@@ -383,7 +553,8 @@ fn decode_constant<R: ReadBytes>(input: &mut Bitstream<R>,
 #[cold]
 fn decode_verbatim<R: ReadBytes>(input: &mut Bitstream<R>,
                                  bps: u32,
-                                 buffer: &mut [i32])
+                                 buffer: &mut [i32],
+                                 decoded: &mut usize)
                                  -> Result<()> {
 
     // This function must not be called for a sample wider than the sample type.
@@ -396,6 +567,7 @@ fn decode_verbatim<R: ReadBytes>(input: &mut Bitstream<R>,
     // A verbatim block stores samples without encoding whatsoever.
     for s in buffer {
         *s = extend_sign_u32(try!(input.read_leq_u32(bps)), bps);
+        *decoded += 1;
     }
 
     Ok(())
@@ -407,54 +579,75 @@ fn predict_fixed(order: u32, buffer: &mut [i32]) -> Result<()> {
     // 0 <= order <= 4. Still, it is good to state that assumption explicitly.
     debug_assert!(order <= 4);
 
-    // Coefficients for fitting an order n polynomial. You get these
-    // coefficients by writing down n numbers, then their differences, then the
-    // differences of the differences, etc. What results is Pascal's triangle
-    // with alternating signs.
-    let o0 = [];
-    let o1 = [1];
-    let o2 = [-1, 2];
-    let o3 = [1, -3, 3];
-    let o4 = [-1, 4, -6, 4];
-
+    // The predictors fit an order n polynomial through the previous n
+    // samples. You get the coefficients by writing down n numbers, then their
+    // differences, then the differences of the differences, etc. What results
+    // is Pascal's triangle with alternating signs.
+    //
     // Multiplying samples with at most 6 adds 3 bits. Then summing at most 5
     // of those values again adds at most 4 bits, so a sample type that is 7
     // bits wider than bps should suffice. Subset FLAC supports at most 24 bits
-    // per sample, 25 for the channel delta, so using an i32 is safe here.
-
-    let coefficients: &[i32] = match order {
-        0 => &o0,
-        1 => &o1,
-        2 => &o2,
-        3 => &o3,
-        4 => &o4,
-        _ => unreachable!(),
-    };
-
-    let window_size = order as usize + 1;
-
-    // TODO: abstract away this iterating over a window into a function?
-    for i in 0..buffer.len() - order as usize {
-        // Manually do the windowing, because .windows() returns immutable slices.
-        let window = &mut buffer[i..i + window_size];
-
-        // The #coefficients elements of the window store already decoded
-        // samples, the last element of the window is the delta. Therefore,
-        // predict based on the first #coefficients samples. From the note
-        // above we know that the multiplication will not overflow for 24-bit
-        // samples, so the wrapping mul is safe. If it wraps, the file was
-        // invalid, and we make no guarantees about the decoded result. But
-        // we explicitly do not crash.
-        let prediction = coefficients.iter()
-                                     .zip(window.iter())
-                                     .map(|(&c, &s)| num::Wrapping(c) * num::Wrapping(s))
-                                     // Rust 1.13 does not support using `sum`
-                                     // with `Wrapping`, so do a fold.
-                                     .fold(num::Wrapping(0), |a, x| a + x).0;
+    // per sample, 25 for the channel delta, so using an i32 is safe here. For
+    // wider samples the intermediate values may wrap, but unlike for LPC, no
+    // shift is involved, so wrapping arithmetic still produces the exact
+    // result, as long as the sample itself fits in an i32. If it wraps for
+    // another reason, the file was invalid, and we make no guarantees about
+    // the decoded result. But we explicitly do not crash.
+
+    // If decoding stopped before the warm-up samples were complete, there is
+    // nothing to predict.
+    let order = order as usize;
+    if buffer.len() <= order {
+        return Ok(())
+    }
 
-        // The delta is stored, so the sample is the prediction + delta.
-        let delta = window[coefficients.len()];
-        window[coefficients.len()] = prediction.wrapping_add(delta);
+    // There is a loop per order, so the coefficients are constants, and the
+    // previous samples are kept in locals rather than read back from the
+    // buffer. The buffer stores the delta, so the sample is the prediction
+    // plus the delta.
+    let (warm_up, deltas) = buffer.split_at_mut(order);
+    match order {
+        0 => {}
+        1 => {
+            let mut s1 = warm_up[0];
+            for x in deltas {
+                s1 = x.wrapping_add(s1);
+                *x = s1;
+            }
+        }
+        2 => {
+            let (mut s2, mut s1) = (warm_up[0], warm_up[1]);
+            for x in deltas {
+                let prediction = s1.wrapping_mul(2).wrapping_sub(s2);
+                s2 = s1;
+                s1 = x.wrapping_add(prediction);
+                *x = s1;
+            }
+        }
+        3 => {
+            let (mut s3, mut s2, mut s1) = (warm_up[0], warm_up[1], warm_up[2]);
+            for x in deltas {
+                let prediction = s1.wrapping_sub(s2).wrapping_mul(3).wrapping_add(s3);
+                s3 = s2;
+                s2 = s1;
+                s1 = x.wrapping_add(prediction);
+                *x = s1;
+            }
+        }
+        4 => {
+            let (mut s4, mut s3, mut s2, mut s1) = (warm_up[0], warm_up[1], warm_up[2], warm_up[3]);
+            for x in deltas {
+                let prediction = s1.wrapping_add(s3).wrapping_mul(4)
+                                   .wrapping_sub(s2.wrapping_mul(6))
+                                   .wrapping_sub(s4);
+                s4 = s3;
+                s3 = s2;
+                s2 = s1;
+                s1 = x.wrapping_add(prediction);
+                *x = s1;
+            }
+        }
+        _ => unreachable!(),
     }
 
     Ok(())
@@ -476,10 +669,37 @@ fn verify_predict_fixed() {
     assert_eq!(&buffer, &[21877, 27482, 26574]);
 }
 
+#[test]
+fn verify_predict_fixed_matches_coefficients() {
+    // Compare every order against a direct evaluation of the polynomial
+    // coefficients, also with values that wrap.
+    let coefficients: [&[i32]; 5] = [&[], &[1], &[-1, 2], &[1, -3, 3], &[-1, 4, -6, 4]];
+    let deltas: Vec<i32> = (0..40).map(|i: i32| i.wrapping_mul(0x3a5f_1b27) >> (i % 24)).collect();
+    for order in 0..5 {
+        let mut expected = deltas.clone();
+        for i in order..expected.len() {
+            let prediction = coefficients[order].iter()
+                                                .zip(&expected[i - order..i])
+                                                .fold(0i32, |a, (&c, &s)| a.wrapping_add(c.wrapping_mul(s)));
+            expected[i] = expected[i].wrapping_add(prediction);
+        }
+        let mut buffer = deltas.clone();
+        assert!(predict_fixed(order as u32, &mut buffer).is_ok());
+        assert_eq!(buffer, expected);
+
+        // Only the warm-up samples, or fewer, are left untouched.
+        let mut buffer = deltas[..order].to_vec();
+        assert!(predict_fixed(order as u32, &mut buffer).is_ok());
+        assert_eq!(&buffer[..], &deltas[..order]);
+    }
+}
+
 fn decode_fixed<R: ReadBytes>(input: &mut Bitstream<R>,
                               bps: u32,
                               order: u32,
-                              buffer: &mut [i32])
+                              buffer: &mut [i32],
+                              decoded: &mut usize,
+                              mut info: Option<&mut SubframeInfo>)
                               -> Result<()> {
     // The length of the buffer which is passed in, is the length of the block.
     // Thus, the number of warm-up samples must not exceed that length.
@@ -488,18 +708,24 @@ fn decode_fixed<R: ReadBytes>(input: &mut Bitstream<R>,
     }
 
     // There are order * bits per sample unencoded warm-up sample bits.
-    try!(decode_verbatim(input, bps, &mut buffer[..order as usize]));
+    try!(decode_verbatim(input, bps, &mut buffer[..order as usize], decoded));
 
     // Next up is the residual. We decode into the buffer directly, the
     // predictor contributions will be added in a second pass. The first
     // `order` samples have been decoded already, so continue after that.
-    try!(decode_residual(input,
-                         buffer.len() as u16,
-                         &mut buffer[order as usize..]));
+    let result = decode_residual(input,
+                                 buffer.len() as u16,
+                                 &mut buffer[order as usize..],
+                                 decoded,
+                                 info.as_mut().map(|info| &mut info.rice_parameters));
+    if let Some(info) = info {
+        info.residual = buffer[order as usize..*decoded].to_vec();
+    }
 
-    try!(predict_fixed(order, buffer));
+    // If the residual was cut off, the samples before that can be predicted.
+    try!(predict_fixed(order, &mut buffer[..*decoded]));
 
-    Ok(())
+    result
 }
 
 fn predict_lpc(raw_coefficients: &[i16],
@@ -508,20 +734,26 @@ fn predict_lpc(raw_coefficients: &[i16],
                -> Result<()> {
     debug_assert!(qlp_shift >= 0, "Right-shift by negative value is not allowed.");
     debug_assert!(qlp_shift < 64, "Cannot shift by more than integer width.");
-    // The decoded residuals are 25 bits at most (assuming subset FLAC of at
-    // most 24 bits per sample, but there is the delta encoding for channels).
-    // The coefficients are 16 bits at most, so their product is 41 bits. In
-    // practice the predictor order does not exceed 12, so adding 12 numbers of
-    // 41 bits each requires at most 53 bits. Therefore, do all intermediate
-    // computations as i64.
+    // The samples are at most 32 bits, and the coefficients are at most 15
+    // bits, so their product is at most 47 bits. The order is at most 32, so
+    // adding the products adds at most 5 bits, and the sum takes at most 52
+    // bits. Therefore, do all intermediate computations as i64. The prediction
+    // is only truncated to i32 after the shift; for a valid stream, the result
+    // fits, also for 32-bit samples.
 
     // The spec allocates 5 bits for (order - 1), so the order could be as much
-    // as 32. However, I have never observed an order larger than 12, in
-    // practice, and this function is optimized under that assumption. If there
-    // ever is a need for higher orders, it a fallback predictor could be added
-    // easily.
+    // as 32. However, subset streams have an order of at most 12, and this
+    // function is optimized under that assumption. Higher orders are handled
+    // by a slower general predictor.
     if raw_coefficients.len() > 12 {
-        return Err(Error::Unsupported("LPC order > 12 is not supported"));
+        predict_lpc_high_order(raw_coefficients, qlp_shift, buffer);
+        return Ok(())
+    }
+
+    // If decoding stopped before the warm-up samples were complete, there is
+    // nothing to predict.
+    if buffer.len() < raw_coefficients.len() {
+        return Ok(())
     }
 
     // In the code below, a predictor order of 12 is assumed. This aids
@@ -531,10 +763,8 @@ fn predict_lpc(raw_coefficients: &[i16],
     let order = raw_coefficients.len();
     let coefficients = {
         let mut buf = [0i64; 12];
-        let mut i = 12 - order;
-        for c in raw_coefficients {
-            buf[i] = *c as i64;
-            i = i + 1;
+        for (dst, &c) in buf[12 - order..].iter_mut().zip(raw_coefficients) {
+            *dst = c as i64;
         }
         buf
     };
@@ -546,31 +776,55 @@ fn predict_lpc(raw_coefficients: &[i16],
     // warm-up samples.
     let left = cmp::min(12, buffer.len()) - order;
     for i in 0..left {
-        let prediction = raw_coefficients.iter()
-                                         .zip(&buffer[i..order + i])
-                                         .map(|(&c, &s)| c as i64 * s as i64)
-                                         .sum::<i64>() >> qlp_shift;
-        let delta = buffer[order + i] as i64;
-        buffer[order + i] = (prediction + delta) as i32;
+        predict_lpc_sample(raw_coefficients, qlp_shift, &mut buffer[i..order + i + 1]);
     }
 
     if buffer.len() <= 12 { return Ok(()) }
 
     // At this point, buffer[0..12] has been predicted. For the rest of the
     // buffer we can do inner products of 12 samples. This reduces the amount of
-    // conditional code, and improves performance significantly.
-    for i in 12..buffer.len() {
+    // conditional code, and improves performance significantly. If the CPU
+    // supports it, most of the buffer is predicted with vector instructions,
+    // see the `simd` module. Splitting a window of exactly 13 samples makes
+    // both lengths known to the optimizer, so the loop body contains no bounds
+    // checks.
+    let start = simd::predict_lpc_12(&coefficients, qlp_shift, buffer);
+    for i in start..buffer.len() {
+        let (history, current) = buffer[i - 12..i + 1].split_at_mut(12);
         let prediction = coefficients.iter()
-                                     .zip(&buffer[i - 12..i])
+                                     .zip(history.iter())
                                      .map(|(&c, &s)| c * s as i64)
                                      .sum::<i64>() >> qlp_shift;
-        let delta = buffer[i] as i64;
-        buffer[i] = (prediction + delta) as i32;
+        let delta = current[0] as i64;
+        current[0] = (prediction + delta) as i32;
     }
 
     Ok(())
 }
 
+/// Predicts the last sample of `window` from the samples before it.
+///
+/// The window must be one sample longer than the predictor order.
+#[inline(always)]
+fn predict_lpc_sample(raw_coefficients: &[i16], qlp_shift: i16, window: &mut [i32]) {
+    let (history, current) = window.split_at_mut(raw_coefficients.len());
+    let prediction = raw_coefficients.iter()
+                                     .zip(history.iter())
+                                     .map(|(&c, &s)| c as i64 * s as i64)
+                                     .sum::<i64>() >> qlp_shift;
+    let delta = current[0] as i64;
+    current[0] = (prediction + delta) as i32;
+}
+
+/// Applies an LPC predictor of any order, see `predict_lpc()`.
+#[cold]
+fn predict_lpc_high_order(raw_coefficients: &[i16], qlp_shift: i16, buffer: &mut [i32]) {
+    let order = raw_coefficients.len();
+    for i in order..buffer.len() {
+        predict_lpc_sample(raw_coefficients, qlp_shift, &mut buffer[i - order..i + 1]);
+    }
+}
+
 #[test]
 fn verify_predict_lpc() {
     // The following data is from an actual FLAC stream and has been verified
@@ -589,10 +843,32 @@ fn verify_predict_lpc() {
     assert_eq!(&buffer, &[-21363, -21951, -22649, -24364, -27297, -26870, -30017, -29718]);
 }
 
+#[test]
+fn verify_predict_lpc_wide_samples_and_high_order() {
+    // Predict every sample as twice the previous one minus the one before.
+    // The products overflow 32 bits, but the result must be exact.
+    let coefficients = [-512, 1024];
+    let mut buffer = [1_000_000_000, 1_000_000_100, 0, -7];
+    assert!(predict_lpc(&coefficients, 9, &mut buffer).is_ok());
+    assert_eq!(&buffer, &[1_000_000_000, 1_000_000_100, 1_000_000_200, 1_000_000_293]);
+
+    // An order of 16, that predicts every sample as the previous one.
+    let mut coefficients = [0; 16];
+    coefficients[15] = 1 << 9;
+    let mut buffer = [0; 20];
+    for (i, x) in buffer.iter_mut().enumerate() {
+        *x = if i < 16 { i as i32 } else { 1 };
+    }
+    assert!(predict_lpc(&coefficients, 9, &mut buffer).is_ok());
+    assert_eq!(&buffer[16..], &[16, 17, 18, 19]);
+}
+
 fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
                             bps: u32,
                             order: u32,
-                            buffer: &mut [i32])
+                            buffer: &mut [i32],
+                            decoded: &mut usize,
+                            mut info: Option<&mut SubframeInfo>)
                             -> Result<()> {
     // The order minus one fits in 5 bits, so the order is at most 32.
     debug_assert!(order <= 32);
@@ -605,8 +881,77 @@ fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
     }
 
     // There are order * bits per sample unencoded warm-up sample bits.
-    try!(decode_verbatim(input, bps, &mut buffer[..order as usize]));
+    try!(decode_verbatim(input, bps, &mut buffer[..order as usize], decoded));
+
+    let (qlp_shift, coefficients) = try!(read_lpc_parameters(input, order));
+    if let Some(ref mut info) = info {
+        record_lpc_parameters(info, qlp_shift, &coefficients[..order as usize]);
+    }
 
+    // Next up is the residual. We decode it into the buffer directly, the
+    // predictor contributions will be added in a second pass. The first
+    // `order` samples have been decoded already, so continue after that.
+    let result = decode_residual(input,
+                                 buffer.len() as u16,
+                                 &mut buffer[order as usize..],
+                                 decoded,
+                                 info.as_mut().map(|info| &mut info.rice_parameters));
+    if let Some(info) = info {
+        info.residual = buffer[order as usize..*decoded].to_vec();
+    }
+
+    // If the residual was cut off, the samples before that can be predicted.
+    try!(predict_lpc(&coefficients[..order as usize], qlp_shift, &mut buffer[..*decoded]));
+
+    result
+}
+
+#[test]
+fn verify_decode_with_info_records_lpc_parameters() {
+    use std::io;
+    use input::BufferedReader;
+
+    // An LPC subframe of order 1 for four 8-bit samples: warm-up sample 5, a
+    // coefficient precision of 4 bits, a shift of 1, coefficient 2, and a
+    // residual of 1, -1, and 0, Rice coded with parameter 0.
+    let bits = concat!("01000000", "00000101", "0011", "00001", "0010",
+                       "00", "0000", "0000", "001", "01", "1");
+    let bytes: Vec<u8> = bits.as_bytes().chunks(8).map(|chunk| {
+        let byte = chunk.iter().fold(0, |b, &c| b << 1 | (c - b'0'));
+        byte << (8 - chunk.len())
+    }).collect();
+    let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(bytes)));
+
+    let mut buffer = [0; 4];
+    let mut decoded = 0;
+    let mut infos = Vec::new();
+    decode_with_info(&mut input, 8, &mut buffer, &mut decoded, Some(&mut infos)).unwrap();
+    assert_eq!(buffer, [5, 6, 5, 5]);
+    assert_eq!(infos, vec![SubframeInfo {
+        kind: SubframeKind::Lpc,
+        order: 1,
+        wasted_bits: 0,
+        partition_order: 0,
+        rice_parameters: vec![0],
+        coefficients: vec![2],
+        qlp_shift: 1,
+        residual: vec![1, -1, 0],
+    }]);
+}
+
+/// Stores the shift and the coefficients, which are in reverse order, in `info`.
+fn record_lpc_parameters(info: &mut SubframeInfo, qlp_shift: i16, coefficients: &[i16]) {
+    info.qlp_shift = qlp_shift as u32;
+    info.coefficients = coefficients.iter().rev().map(|&c| c as i32).collect();
+}
+
+/// Reads the coefficient precision, shift, and coefficients of an LPC subframe.
+///
+/// Returns the shift, and the coefficients in reverse order, because that is
+/// how they are used in prediction. Only the first `order` are meaningful.
+fn read_lpc_parameters<R: ReadBytes>(input: &mut Bitstream<R>,
+                                     order: u32)
+                                     -> Result<(i16, [i16; 32])> {
     // Next are four bits quantised linear predictor coefficient precision - 1.
     let qlp_precision = try!(input.read_leq_u8(4)) as u32 + 1;
 
@@ -628,7 +973,7 @@ fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
     if qlp_shift < 0 {
         let msg = "a negative quantized linear predictor coefficient shift is \
                    not supported, please file a bug.";
-        return Err(Error::Unsupported(msg))
+        return Err(Error::Unsupported { feature: msg })
     }
 
     // Finally, the coefficients themselves. The order is at most 32, so all
@@ -641,14 +986,155 @@ fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
         *coef = extend_sign_u16(coef_unsig, qlp_precision);
     }
 
-    // Next up is the residual. We decode it into the buffer directly, the
-    // predictor contributions will be added in a second pass. The first
-    // `order` samples have been decoded already, so continue after that.
-    try!(decode_residual(input,
-                         buffer.len() as u16,
-                         &mut buffer[order as usize..]));
+    Ok((qlp_shift, coefficients))
+}
+
+/// Reads a signed sample of `bps` bits, where `bps` may be up to 33.
+fn read_wide_sample<R: ReadBytes>(input: &mut Bitstream<R>, bps: u32) -> Result<i64> {
+    let raw = if bps > 32 {
+        let msb = try!(input.read_leq_u32(bps - 32)) as u64;
+        let lsb = try!(input.read_leq_u32(32)) as u64;
+        msb << 32 | lsb
+    } else {
+        try!(input.read_leq_u32(bps)) as u64
+    };
+    Ok(((raw << (64 - bps)) as i64) >> (64 - bps))
+}
 
-    try!(predict_lpc(&coefficients[..order as usize], qlp_shift, buffer));
+/// Decodes a subframe of more than 32 bits per sample into `buffer`.
+///
+/// Only the side channel of a stream with 32 bits per sample needs this, as it
+/// takes 33 bits. The samples of such a subframe do not fit in an i32, so it is
+/// decoded into an i64 buffer instead. This is much less common than the
+/// regular case, so there is no attempt to be fast here, and a subframe that
+/// is cut off cannot be salvaged. Returns the number of wasted bits per sample
+/// and the kind of the subframe, and appends the parameters of the subframe
+/// to `infos`, like `decode_with_info()`.
+#[cold]
+pub fn decode_wide<R: ReadBytes>(input: &mut Bitstream<R>,
+                                 bps: u32,
+                                 buffer: &mut [i64],
+                                 mut infos: Option<&mut Vec<SubframeInfo>>)
+                                 -> Result<(u32, SubframeKind)> {
+    if bps > 33 {
+        let feature = "more than 33 bits per sample is not supported";
+        return Err(Error::Unsupported { feature: feature });
+    }
 
-    Ok(())
+    let header = try!(read_subframe_header(input));
+    if header.wasted_bits_per_sample >= bps {
+        return fmt_err("subframe has no non-wasted bits");
+    }
+    let sf_bps = bps - header.wasted_bits_per_sample;
+    if let Some(ref mut infos) = infos {
+        infos.push(SubframeInfo::new(&header));
+    }
+
+    let order = match header.sf_type {
+        SubframeType::Constant => {
+            let sample = try!(read_wide_sample(input, sf_bps));
+            for s in buffer.iter_mut() {
+                *s = sample;
+            }
+            0
+        }
+        SubframeType::Verbatim => {
+            for s in buffer.iter_mut() {
+                *s = try!(read_wide_sample(input, sf_bps));
+            }
+            0
+        }
+        SubframeType::Fixed(order) | SubframeType::Lpc(order) => order as usize,
+    };
+
+    // Constant and verbatim subframes are complete at this point, for the
+    // others, read the warm-up samples.
+    if buffer.len() < order {
+        return fmt_err("invalid subframe, predictor order is larger than block size")
+    }
+    for s in &mut buffer[..order] {
+        *s = try!(read_wide_sample(input, sf_bps));
+    }
+
+    let prediction = match header.sf_type {
+        SubframeType::Fixed(..) => {
+            let coefficients: &[i16] = match order {
+                0 => &[],
+                1 => &[1],
+                2 => &[-1, 2],
+                3 => &[1, -3, 3],
+                _ => &[-1, 4, -6, 4],
+            };
+            let mut buf = [0; 32];
+            buf[..order].copy_from_slice(coefficients);
+            Some((0, buf))
+        }
+        SubframeType::Lpc(..) => {
+            let (shift, coefficients) = try!(read_lpc_parameters(input, order as u32));
+            if let Some(info) = infos.as_mut().and_then(|infos| infos.last_mut()) {
+                record_lpc_parameters(info, shift, &coefficients[..order]);
+            }
+            Some((shift, coefficients))
+        }
+        _ => None,
+    };
+
+    if let Some((shift, coefficients)) = prediction {
+        // The residual itself fits in 32 bits, only the prediction needs more.
+        let mut residual = vec![0; buffer.len() - order];
+        let mut decoded = 0;
+        let mut params = Vec::new();
+        let result = decode_residual(input, buffer.len() as u16, &mut residual, &mut decoded,
+                                     Some(&mut params));
+        try!(result);
+        for i in order..buffer.len() {
+            let sum = coefficients[..order].iter()
+                                           .zip(&buffer[i - order..i])
+                                           .map(|(&c, &s)| c as i64 * s)
+                                           .fold(0i64, |a, x| a.wrapping_add(x));
+            buffer[i] = (sum >> shift).wrapping_add(residual[i - order] as i64);
+        }
+        if let Some(info) = infos.and_then(|infos| infos.last_mut()) {
+            info.partition_order = partition_order(&params);
+            info.rice_parameters = params;
+            info.residual = residual;
+        }
+    }
+
+    if header.wasted_bits_per_sample > 0 {
+        for s in buffer.iter_mut() {
+            *s = s.wrapping_shl(header.wasted_bits_per_sample);
+        }
+    }
+
+    Ok((header.wasted_bits_per_sample, header.kind()))
+}
+
+#[test]
+fn verify_decode_wide() {
+    use std::io;
+    use input::BufferedReader;
+
+    // A verbatim subframe with two 33-bit samples, -2^32 and -1, followed by
+    // a fixed subframe of order 1 with warm-up sample -2^32, and a residual of
+    // three times -1, Rice coded with parameter 0.
+    let bits = concat!("00000010",
+                       "100000000000000000000000000000000",
+                       "111111111111111111111111111111111",
+                       "00010010",
+                       "100000000000000000000000000000000",
+                       "00", "0000", "0000", "010101");
+    let bytes: Vec<u8> = bits.as_bytes().chunks(8).map(|chunk| {
+        let byte = chunk.iter().fold(0, |b, &c| b << 1 | (c - b'0'));
+        byte << (8 - chunk.len())
+    }).collect();
+    let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(bytes)));
+
+    let mut buffer = [0i64; 2];
+    decode_wide(&mut input, 33, &mut buffer, None).unwrap();
+    assert_eq!(buffer, [-(1 << 32), -1]);
+
+    let mut buffer = [0i64; 4];
+    decode_wide(&mut input, 33, &mut buffer, None).unwrap();
+    assert_eq!(buffer, [-(1 << 32), -(1 << 32) - 1, -(1 << 32) - 2, -(1 << 32) - 3]);
 }
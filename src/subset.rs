@@ -0,0 +1,180 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `subset` module checks streams for conformance to the FLAC specification.
+//!
+//! The specification defines a *streamable subset* of the format, with
+//! restrictions that make streams easier to decode, for instance in hardware.
+//! Most encoders produce subset streams by default. Claxon decodes streams
+//! outside of the subset too, and it tolerates some deviations from the
+//! specification itself. Use `FlacReaderOptions::check_subset` to have the
+//! reader collect such deviations as it reads, and inspect them with
+//! `FlacReader::violations()`.
+//!
+//! Reserved values in frame and subframe headers are always an error, they are
+//! not reported here.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use frame::{BlockTime, FrameInfo};
+use metadata::{MetadataBlock, StreamInfo};
+
+/// A deviation from the FLAC specification or from its streamable subset.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Violation {
+    /// The first sample of the frame that deviates, or `None` for metadata.
+    pub sample: Option<u64>,
+    /// True if the specification allows this, and only the subset does not.
+    pub subset_only: bool,
+    /// A description of the deviation.
+    pub description: &'static str,
+}
+
+/// Accumulates the deviations found in the metadata and frames of a stream.
+pub struct Checker {
+    violations: Vec<Violation>,
+    /// The blocking strategy of the first frame, true for a variable block size.
+    variable_block_size: Option<bool>,
+}
+
+impl Checker {
+    /// Creates a checker, and checks the metadata blocks.
+    ///
+    /// The streaminfo block is already validated when it is read, so only
+    /// the subset restrictions on it are checked here.
+    pub fn new(streaminfo: &StreamInfo, blocks: &[MetadataBlock]) -> Checker {
+        let mut checker = Checker {
+            violations: Vec::new(),
+            variable_block_size: None,
+        };
+
+        let (mut n_streaminfo, mut n_seektable, mut n_vorbis_comment) = (0, 0, 0);
+        for block in blocks {
+            match *block {
+                MetadataBlock::StreamInfo(..) => n_streaminfo += 1,
+                MetadataBlock::SeekTable(..) => n_seektable += 1,
+                MetadataBlock::VorbisComment(..) => n_vorbis_comment += 1,
+                _ => {}
+            }
+        }
+        if n_streaminfo > 1 {
+            checker.report(None, false, "stream contains more than one streaminfo block");
+        }
+        if n_seektable > 1 {
+            checker.report(None, false, "stream contains more than one seek table");
+        }
+        if n_vorbis_comment > 1 {
+            checker.report(None, false, "stream contains more than one Vorbis comment block");
+        }
+
+        if streaminfo.max_block_size > 16384 {
+            checker.report(None, true, "maximum block size exceeds 16384");
+        } else if streaminfo.sample_rate <= 48000 && streaminfo.max_block_size > 4608 {
+            checker.report(None, true,
+                           "maximum block size exceeds 4608 at a sample rate of at most 48 kHz");
+        }
+        match streaminfo.bits_per_sample {
+            8 | 12 | 16 | 20 | 24 => {}
+            32 => checker.report(None, true, "bits per sample exceeds 24"),
+            _ => checker.report(None, true, "bits per sample cannot be stored in a frame header"),
+        }
+
+        checker
+    }
+
+    fn report(&mut self, sample: Option<u64>, subset_only: bool, description: &'static str) {
+        self.violations.push(Violation {
+            sample: sample,
+            subset_only: subset_only,
+            description: description,
+        });
+    }
+
+    /// Checks the header of a frame against the streaminfo and the preceding frames.
+    pub fn check_frame(&mut self, streaminfo: &StreamInfo, info: &FrameInfo) {
+        let sample = Some(info.first_sample);
+
+        let is_variable = match info.block_time {
+            BlockTime::FrameNumber(..) => false,
+            BlockTime::SampleNumber(..) => true,
+        };
+        match self.variable_block_size {
+            None => self.variable_block_size = Some(is_variable),
+            Some(v) if v != is_variable => {
+                self.report(sample, false, "blocking strategy changes within the stream");
+            }
+            Some(..) => {}
+        }
+
+        if info.block_size > streaminfo.max_block_size {
+            self.report(sample, false, "block size exceeds maximum block size in streaminfo");
+        }
+        if info.channels != streaminfo.channels {
+            self.report(sample, false, "number of channels differs from streaminfo");
+        }
+        match info.sample_rate {
+            None => self.report(sample, true, "sample rate is not stored in frame header"),
+            Some(sr) if sr != streaminfo.sample_rate => {
+                self.report(sample, false, "sample rate differs from streaminfo");
+            }
+            Some(..) => {}
+        }
+        match info.bits_per_sample {
+            None => self.report(sample, true, "bits per sample is not stored in frame header"),
+            Some(bps) if bps != streaminfo.bits_per_sample => {
+                self.report(sample, false, "bits per sample differs from streaminfo");
+            }
+            Some(..) => {}
+        }
+    }
+
+    /// Returns the deviations found so far, in the order they were found.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+#[test]
+fn verify_check_frame_reports_deviations() {
+    use frame::ChannelAssignment;
+
+    let streaminfo = StreamInfo {
+        min_block_size: 4096,
+        max_block_size: 4096,
+        min_frame_size: None,
+        max_frame_size: None,
+        sample_rate: 44100,
+        channels: 2,
+        bits_per_sample: 16,
+        samples: None,
+        md5sum: [0; 16],
+    };
+    let mut checker = Checker::new(&streaminfo, &[]);
+    assert_eq!(checker.violations(), &[]);
+
+    let mut info = FrameInfo {
+        block_time: BlockTime::FrameNumber(0),
+        first_sample: 0,
+        block_size: 4096,
+        channels: 2,
+        channel_assignment: ChannelAssignment::MidSideStereo,
+        sample_rate: Some(44100),
+        bits_per_sample: Some(16),
+    };
+    checker.check_frame(&streaminfo, &info);
+    assert_eq!(checker.violations(), &[]);
+
+    info.block_time = BlockTime::SampleNumber(4096);
+    info.first_sample = 4096;
+    info.sample_rate = None;
+    checker.check_frame(&streaminfo, &info);
+    let descriptions: Vec<_> = checker.violations().iter().map(|v| v.description).collect();
+    assert_eq!(descriptions, ["blocking strategy changes within the stream",
+                              "sample rate is not stored in frame header"]);
+    assert!(checker.violations().iter().all(|v| v.sample == Some(4096)));
+    assert!(checker.violations()[1].subset_only);
+}
@@ -0,0 +1,317 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `tokio` module decodes streams from a `tokio::io::AsyncRead`.
+//!
+//! This module is only available with the `tokio` feature. `AsyncFlacReader`
+//! reads from the input whenever the `PushDecoder` that it drives needs more
+//! data, so a server can decode uploads without `spawn_blocking`. Decoding
+//! itself does not yield; a frame is decoded at once when its data is there.
+//!
+//! With the `futures` feature, `AsyncFlacReader` is also a `futures::Stream`
+//! of decoded blocks, so the audio can flow through stream combinators. The
+//! stream ends after the last block, or after the first error.
+//!
+//! # Examples
+//!
+//! ```edition2018
+//! use claxon::tokio::AsyncFlacReader;
+//! use tokio::io::AsyncRead;
+//!
+//! async fn count_samples<R: AsyncRead + Unpin>(input: R) -> claxon::Result<u64> {
+//!     let mut reader = AsyncFlacReader::read_metadata(input).await?;
+//!     let mut buffer = Vec::new();
+//!     let mut n = 0;
+//!     while let Some(block) = reader.read_next_or_eof(buffer).await? {
+//!         n += block.duration() as u64;
+//!         buffer = block.into_buffer();
+//!     }
+//!     Ok(n)
+//! }
+//! ```
+
+#[cfg(feature = "futures")]
+extern crate futures_core;
+extern crate tokio;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(feature = "futures")]
+use self::futures_core::Stream;
+use self::tokio::io::{AsyncRead, ReadBuf};
+use error::{Error, Result};
+use frame::Block;
+use metadata::{MetadataBlock, StreamInfo};
+use push::{Event, PushDecoder};
+
+/// The number of bytes to read from the input at once.
+const CHUNK_LEN: usize = 16 * 1024;
+
+/// A reader that decodes a FLAC stream from an `AsyncRead`.
+///
+/// It is constructed with `AsyncFlacReader::read_metadata()`, which reads the metadata.
+pub struct AsyncFlacReader<R> {
+    input: R,
+    decoder: PushDecoder,
+    metadata_blocks: Vec<MetadataBlock>,
+    /// The first block, if it was decoded while looking for the end of the metadata.
+    pending: Option<Block>,
+    chunk: Box<[u8]>,
+}
+
+/// A future that reads the metadata of a stream, returned by `AsyncFlacReader::read_metadata()`.
+pub struct ReadMetadata<R> {
+    reader: Option<AsyncFlacReader<R>>,
+}
+
+/// A future that decodes the next block, returned by `AsyncFlacReader::read_next_or_eof()`.
+pub struct ReadNext<'r, R: 'r> {
+    reader: &'r mut AsyncFlacReader<R>,
+    buffer: Option<Vec<i32>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncFlacReader<R> {
+    /// Returns a future that reads the stream header and the metadata blocks.
+    ///
+    /// The future resolves to a reader that is positioned at the first frame.
+    pub fn read_metadata(input: R) -> ReadMetadata<R> {
+        ReadMetadata {
+            reader: Some(AsyncFlacReader {
+                input: input,
+                decoder: PushDecoder::new(),
+                metadata_blocks: Vec::new(),
+                pending: None,
+                chunk: vec![0; CHUNK_LEN].into_boxed_slice(),
+            }),
+        }
+    }
+
+    /// Returns the streaminfo metadata.
+    pub fn streaminfo(&self) -> StreamInfo {
+        self.decoder.streaminfo().expect("streaminfo is read before the reader is constructed")
+    }
+
+    /// Returns the metadata blocks that follow the streaminfo block.
+    pub fn metadata(&self) -> &[MetadataBlock] {
+        &self.metadata_blocks
+    }
+
+    /// Returns a future that decodes the next block, or resolves to `None` at the end.
+    ///
+    /// The buffer is reused to decode the block into, like in
+    /// `FrameReader::read_next_or_eof()`.
+    pub fn read_next_or_eof<'r>(&'r mut self, buffer: Vec<i32>) -> ReadNext<'r, R> {
+        ReadNext {
+            reader: self,
+            buffer: Some(buffer),
+        }
+    }
+
+    /// Attempts to decode the next block, and registers for a wakeup if more input is needed.
+    ///
+    /// This is the poll-based counterpart of `read_next_or_eof()`, for
+    /// implementing futures and streams on top of the reader.
+    pub fn poll_read_next(&mut self, cx: &mut Context) -> Poll<Result<Option<Block>>> {
+        if let Some(block) = self.pending.take() {
+            return Poll::Ready(Ok(Some(block)))
+        }
+        loop {
+            match self.poll_event(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Ready(Ok(Event::Block(block))) => return Poll::Ready(Ok(Some(block))),
+                Poll::Ready(Ok(Event::End)) => return Poll::Ready(Ok(None)),
+                // Metadata was consumed by the constructor.
+                Poll::Ready(Ok(..)) => continue,
+            }
+        }
+    }
+
+    /// Hands back the buffer of a block, so it can be reused to decode the next block.
+    pub fn recycle(&mut self, buffer: Vec<i32>) {
+        self.decoder.recycle(buffer);
+    }
+
+    /// Returns the underlying reader.
+    ///
+    /// The decoder reads ahead, so the reader may be positioned beyond the
+    /// last decoded frame.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Polls the decoder, and reads more input until it produces an event.
+    fn poll_event(&mut self, cx: &mut Context) -> Poll<Result<Event>> {
+        loop {
+            match self.decoder.poll() {
+                Ok(Event::NeedMoreData) => {}
+                other => return Poll::Ready(other),
+            }
+            let mut buf = ReadBuf::new(&mut self.chunk);
+            match Pin::new(&mut self.input).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::from(err))),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => self.decoder.finish(),
+                Poll::Ready(Ok(())) => self.decoder.push(buf.filled()),
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Future for ReadMetadata<R> {
+    type Output = Result<AsyncFlacReader<R>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        {
+            let reader = self.reader.as_mut().expect("ReadMetadata polled after completion");
+            loop {
+                // The metadata ends where the first frame or the end of the
+                // stream is, so the first block may be decoded already.
+                match reader.poll_event(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Ready(Ok(Event::MetadataBlock(block))) => {
+                        reader.metadata_blocks.push(block)
+                    }
+                    Poll::Ready(Ok(Event::Block(block))) => {
+                        reader.pending = Some(block);
+                        break
+                    }
+                    Poll::Ready(Ok(Event::End)) => break,
+                    Poll::Ready(Ok(..)) => continue,
+                }
+            }
+        }
+        Poll::Ready(Ok(self.reader.take().unwrap()))
+    }
+}
+
+impl<'r, R: AsyncRead + Unpin> Future for ReadNext<'r, R> {
+    type Output = Result<Option<Block>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(buffer) = self.buffer.take() {
+            self.reader.recycle(buffer);
+        }
+        self.reader.poll_read_next(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<R: AsyncRead + Unpin> Stream for AsyncFlacReader<R> {
+    type Item = Result<Block>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Result<Block>>> {
+        // After an error, the decoder is at the end, so the stream ends.
+        match self.get_mut().poll_read_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(Some(block))) => Poll::Ready(Some(Ok(block))),
+            Poll::Ready(Ok(None)) => Poll::Ready(None),
+            Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+/// An input that delivers a few bytes at a time, and is not ready every other poll.
+#[cfg(test)]
+struct Trickle<'a> {
+    data: &'a [u8],
+    is_ready: bool,
+}
+
+#[cfg(test)]
+impl<'a> AsyncRead for Trickle<'a> {
+    fn poll_read(mut self: Pin<&mut Self>,
+                 cx: &mut Context,
+                 buf: &mut ReadBuf)
+                 -> Poll<::std::io::Result<()>> {
+        self.is_ready = !self.is_ready;
+        if !self.is_ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending
+        }
+        let n = ::std::cmp::min(self.data.len(), ::std::cmp::min(buf.remaining(), 1000));
+        buf.put_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Polls the future until it completes.
+#[cfg(test)]
+fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+    let mut cx = Context::from_waker(::std::task::Waker::noop());
+    loop {
+        if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+            return output
+        }
+    }
+}
+
+/// Decodes the entire stream, and returns the samples interleaved.
+#[cfg(test)]
+fn decode_all<R: AsyncRead + Unpin>(input: R) -> Vec<i32> {
+    let mut reader = block_on(AsyncFlacReader::read_metadata(input)).unwrap();
+    let mut samples = Vec::new();
+    let mut buffer = Vec::new();
+    while let Some(block) = block_on(reader.read_next_or_eof(buffer)).unwrap() {
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                samples.push(block.sample(ch, i));
+            }
+        }
+        buffer = block.into_buffer();
+    }
+    samples
+}
+
+#[test]
+fn verify_async_reader_decodes_like_flac_reader() {
+    use FlacReader;
+
+    let data = include_bytes!("../testsamples/wasted_bits.flac");
+    let mut reader = FlacReader::new(&data[..]).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let async_reader = block_on(AsyncFlacReader::read_metadata(&data[..])).unwrap();
+    assert_eq!(async_reader.streaminfo().samples, reader.streaminfo().samples);
+    assert_eq!(decode_all(&data[..]), expected);
+    assert_eq!(decode_all(Trickle { data: &data[..], is_ready: false }), expected);
+}
+
+#[test]
+fn verify_async_reader_reports_truncation() {
+    let data = include_bytes!("../testsamples/wasted_bits.flac");
+    assert!(block_on(AsyncFlacReader::read_metadata(&data[..20])).err().unwrap().is_truncated());
+
+    let mut reader = block_on(AsyncFlacReader::read_metadata(&data[..data.len() - 10])).unwrap();
+    assert!(block_on(reader.read_next_or_eof(Vec::new())).unwrap().is_some());
+    assert!(block_on(reader.read_next_or_eof(Vec::new())).unwrap_err().is_truncated());
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn verify_async_reader_streams_blocks() {
+    let data = include_bytes!("../testsamples/wasted_bits.flac");
+    let input = Trickle { data: &data[..data.len() - 10], is_ready: false };
+    let mut reader = block_on(AsyncFlacReader::read_metadata(input)).unwrap();
+    let mut cx = Context::from_waker(::std::task::Waker::noop());
+    let mut items = Vec::new();
+    loop {
+        match Pin::new(&mut reader).poll_next(&mut cx) {
+            Poll::Pending => continue,
+            Poll::Ready(Some(item)) => items.push(item),
+            Poll::Ready(None) => break,
+        }
+    }
+    // The first frame is complete, the second one is cut off.
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].as_ref().unwrap().duration(), 4096);
+    assert!(items[1].as_ref().unwrap_err().is_truncated());
+}
@@ -0,0 +1,133 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2018 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `trace` module reports what the decoder encounters, when the `trace`
+//! or `tracing` feature is enabled.
+//!
+//! To diagnose a problem with a particular file, install a handler with
+//! `set_handler()`, and decode the file. The handler receives an event when
+//! decoding of every frame starts and ends, and events for unusual conditions
+//! in between, so it can tell in which frame they occur. Handlers are per
+//! thread: only decoding on the thread that installed the handler reports to
+//! it. Without either feature, no events are constructed at all.
+//!
+//! With the `tracing` feature, every event is also logged at debug level
+//! through the `tracing` crate, inside a `frame` span for every frame, so
+//! an application that already collects `tracing` output needs no handler.
+
+use std::cell::RefCell;
+use std::ops::Range;
+
+use frame::FrameInfo;
+
+/// Something that the decoder encountered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A frame is about to be read.
+    ///
+    /// Every `EnterFrame` is followed by an `ExitFrame`, and all events in
+    /// between concern that frame.
+    EnterFrame,
+    /// Reading a frame finished.
+    ///
+    /// Holds the header of the frame if the frame was decoded, or `None` at
+    /// the end of the stream or if decoding failed.
+    ExitFrame(Option<FrameInfo>),
+    /// A value that is reserved by the specification was encountered.
+    ///
+    /// In frames this is followed by a format error, but reserved metadata
+    /// block types are skipped.
+    Reserved(&'static str),
+    /// A streaminfo field was not set, and was derived from elsewhere.
+    DerivedStreamInfo(&'static str),
+    /// A damaged frame was skipped, in resync mode.
+    Resync {
+        /// The offset of the damaged frame, relative to the first frame.
+        offset: u64,
+        /// The inter-channel samples that were lost, possibly empty.
+        lost: Range<u64>,
+    },
+}
+
+type Handler = Box<Fn(&Event)>;
+
+thread_local!(static HANDLER: RefCell<Option<Handler>> = RefCell::new(None));
+
+/// Installs the handler for events emitted on the current thread.
+///
+/// This replaces the previous handler, if any. The handler must not call
+/// `set_handler()` or `clear_handler()` itself.
+pub fn set_handler<F: Fn(&Event) + 'static>(handler: F) {
+    HANDLER.with(|h| *h.borrow_mut() = Some(Box::new(handler)));
+}
+
+/// Removes the handler of the current thread.
+pub fn clear_handler() {
+    HANDLER.with(|h| *h.borrow_mut() = None);
+}
+
+/// Passes the event to the handler of the current thread, if there is one.
+pub fn emit(event: Event) {
+    forward(&event);
+    HANDLER.with(|h| {
+        if let Some(ref handler) = *h.borrow() {
+            handler(&event);
+        }
+    });
+}
+
+/// Logs the event at debug level through `tracing`.
+#[cfg(feature = "tracing")]
+fn forward(event: &Event) {
+    ::tracing::debug!(event = ?event, "claxon");
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+fn forward(_event: &Event) {}
+
+#[test]
+fn verify_trace_reports_frames_and_derived_sample_rate() {
+    use std::fs;
+    use std::io::{self, Read};
+    use std::rc::Rc;
+    use {FlacReader, FlacReaderOptions};
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/wasted_bits.flac").unwrap().read_to_end(&mut data).unwrap();
+    // Clear the 20-bit sample rate in the streaminfo, which follows the
+    // stream header, the block header, and 10 bytes of block and frame sizes.
+    data[18] = 0;
+    data[19] = 0;
+    data[20] &= 0x0f;
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = events.clone();
+    set_handler(move |event| sink.borrow_mut().push(event.clone()));
+
+    let opts = FlacReaderOptions {
+        derive_sample_rate: true,
+        ..FlacReaderOptions::default()
+    };
+    let mut reader = FlacReader::new_ext(io::Cursor::new(data), opts).unwrap();
+    assert_eq!(reader.streaminfo().sample_rate, 44100);
+    for sample in reader.samples() {
+        sample.unwrap();
+    }
+    clear_handler();
+
+    // The derived sample rate, two frames, and the end of the stream.
+    let events = events.borrow();
+    assert_eq!(events.len(), 7);
+    assert_eq!(events[0], Event::DerivedStreamInfo("sample_rate"));
+    assert_eq!(events[1], Event::EnterFrame);
+    match events[2] {
+        Event::ExitFrame(Some(info)) => assert_eq!(info.block_size, 4096),
+        ref other => panic!("unexpected event {:?}", other),
+    }
+    assert_eq!(&events[5..], &[Event::EnterFrame, Event::ExitFrame(None)]);
+}
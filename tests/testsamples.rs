@@ -7,11 +7,17 @@
 
 extern crate claxon;
 extern crate hound;
+extern crate ogg;
 extern crate walkdir;
 
+use std::cmp;
 use std::fs;
 use std::io;
+use std::io::Read;
 use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use claxon::frame::ChannelAssignment;
 
 fn run_metaflac_streaminfo<P: AsRef<Path>>(fname: P) -> String {
     use std::process::Command;
@@ -322,6 +328,203 @@ fn test_flac_reader_get_tag_returns_all_matches() {
     assert_eq!(foo.next(), None);
 }
 
+#[test]
+fn test_flac_reader_seektable_is_read() {
+    // This file contains a seek table with a single seek point, for the
+    // first and only frame, which holds 4 samples.
+    let flac_reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert!(flac_reader.has_seektable());
+    assert_eq!(flac_reader.num_seek_points(), 1);
+
+    let seekpoints = flac_reader.seektable().unwrap().seekpoints();
+    assert_eq!(seekpoints[0].sample, 0);
+    assert_eq!(seekpoints[0].offset, 0);
+    assert_eq!(seekpoints[0].samples, 4);
+}
+
+#[test]
+fn test_flac_reader_without_seektable() {
+    // This file contains only a streaminfo block.
+    let flac_reader = claxon::FlacReader::open("testsamples/pop.flac").unwrap();
+    assert!(!flac_reader.has_seektable());
+    assert_eq!(flac_reader.num_seek_points(), 0);
+    assert!(flac_reader.seektable().is_none());
+}
+
+/// Builds a FLAC stream with the audio of short.flac, and the given metadata block.
+fn flac_with_metadata_block(block_type: u8, block: &[u8]) -> Vec<u8> {
+    let mut original = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut original).unwrap();
+
+    // Keep the header and streaminfo block, then add the new block as last
+    // metadata block, followed by the frames. In short.flac, the frames start
+    // at byte 108.
+    let len = block.len();
+    let mut data = original[..4 + 4 + 34].to_vec();
+    data.extend_from_slice(&[0x80 | block_type, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+    data.extend_from_slice(block);
+    data.extend_from_slice(&original[108..]);
+    data
+}
+
+/// Builds a FLAC stream with the audio of short.flac, and the given Vorbis comments.
+fn flac_with_tags(comments: &[&str]) -> Vec<u8> {
+    let mut block = Vec::new();
+    let vendor = b"claxon test";
+    block.extend_from_slice(&[vendor.len() as u8, 0, 0, 0]);
+    block.extend_from_slice(vendor);
+    block.extend_from_slice(&[comments.len() as u8, 0, 0, 0]);
+    for comment in comments {
+        block.extend_from_slice(&[comment.len() as u8, 0, 0, 0]);
+        block.extend_from_slice(comment.as_bytes());
+    }
+
+    flac_with_metadata_block(4, &block)
+}
+
+#[test]
+fn test_flac_reader_bpm_and_musical_key() {
+    let data = flac_with_tags(&["BPM=128", "INITIALKEY=Am"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.bpm(), Some(128.0));
+    assert_eq!(reader.musical_key(), Some("Am"));
+
+    let data = flac_with_tags(&["bpm=127.98", "KEY=8A"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.bpm(), Some(127.98));
+    assert_eq!(reader.musical_key(), Some("8A"));
+
+    let data = flac_with_tags(&["BPM=fast"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.bpm(), None);
+    assert_eq!(reader.musical_key(), None);
+}
+
+#[test]
+fn test_flac_reader_common_tag_helpers() {
+    let data = flac_with_tags(&["title=Bohemian Rhapsody", "Artist=Queen", "ARTIST=Freddie",
+                                "ALBUM=A Night at the Opera", "TRACKNUMBER=11/12",
+                                "DISCNUMBER= 1 "]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.title(), Some("Bohemian Rhapsody"));
+    assert_eq!(reader.artist(), Some("Queen"));
+    assert_eq!(reader.get_tag("artist").collect::<Vec<_>>(), ["Queen", "Freddie"]);
+    assert_eq!(reader.album(), Some("A Night at the Opera"));
+    assert_eq!(reader.track_number(), Some(11));
+    assert_eq!(reader.disc_number(), Some(1));
+
+    let data = flac_with_tags(&["TRACKNUMBER=B2"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.title(), None);
+    assert_eq!(reader.track_number(), None);
+    assert_eq!(reader.disc_number(), None);
+}
+
+#[test]
+fn test_flac_reader_channel_layout() {
+    use claxon::layout::{ChannelLayout, Speaker};
+
+    // short.flac is mono.
+    let data = flac_with_tags(&[]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.channel_layout(), Some(ChannelLayout::Mono));
+    assert_eq!(reader.streaminfo().channel_layout(), Some(ChannelLayout::Mono));
+    assert_eq!(ChannelLayout::Mono.speakers(), &[Speaker::FrontCenter]);
+
+    // A channel mask that matches the number of channels is taken into account.
+    let data = flac_with_tags(&["WAVEFORMATEXTENSIBLE_CHANNEL_MASK=0x0004"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.channel_layout(), Some(ChannelLayout::Mono));
+
+    // A mask for a different number of channels, or with speakers that none
+    // of the layouts has, makes the layout unknown.
+    for mask in &["0x0003", "0x0001"] {
+        let tag = format!("WAVEFORMATEXTENSIBLE_CHANNEL_MASK={}", mask);
+        let data = flac_with_tags(&[&tag]);
+        let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+        assert_eq!(reader.channel_layout(), None);
+    }
+}
+
+#[test]
+fn test_flac_reader_replaygain() {
+    let data = flac_with_tags(&["REPLAYGAIN_TRACK_GAIN=-6.02 dB", "REPLAYGAIN_TRACK_PEAK=0.5",
+                                "replaygain_album_gain=+3.00 dB"]);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let track = reader.track_gain().unwrap();
+    assert_eq!(track.gain, -6.02);
+    assert_eq!(track.peak, Some(0.5));
+    let album = reader.album_gain().unwrap();
+    assert_eq!(album.gain, 3.0);
+    assert_eq!(album.peak, None);
+
+    let bps = reader.streaminfo().bits_per_sample;
+    let original: Vec<i32> = claxon::FlacReader::open("testsamples/short.flac").unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    let adjusted: Vec<i32> = claxon::replaygain::ApplyGain::new(reader.samples(), track, bps)
+        .map(|s| s.unwrap()).collect();
+    assert_eq!(adjusted.len(), original.len());
+    for (&a, &o) in adjusted.iter().zip(original.iter()) {
+        assert!((a as f64 - o as f64 * track.scale_factor()).abs() <= 0.5);
+    }
+
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert!(reader.track_gain().is_none());
+}
+
+#[test]
+fn test_flac_reader_tags_are_split_into_name_and_value() {
+    let data = flac_with_tags(&["ARTIST=Queen", "TITLE=Under Pressure", "COMMENT=a=b"]);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.vendor(), Some("claxon test"));
+
+    let tags = reader.tags();
+    assert_eq!(tags.len(), 3);
+    let tags: Vec<(&str, &str)> = tags.collect();
+    assert_eq!(&tags[..], &[("ARTIST", "Queen"),
+                            ("TITLE", "Under Pressure"),
+                            ("COMMENT", "a=b")]);
+
+    // Without a Vorbis comment block, there are no tags.
+    let reader = claxon::FlacReader::open("testsamples/pop.flac").unwrap();
+    assert_eq!(reader.vendor(), None);
+    assert_eq!(reader.tags().len(), 0);
+}
+
+#[test]
+fn test_flac_reader_pictures() {
+    // A back cover, MIME type "image/gif", empty description, 1x1 pixels, 8
+    // bits per pixel with 4 colors, and 300 bytes of "image data".
+    let mut block = vec![0, 0, 0, 4, 0, 0, 0, 9];
+    block.extend_from_slice(b"image/gif");
+    block.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 4]);
+    block.extend_from_slice(&[0, 0, 0x01, 0x2c]);
+    block.extend((0..300).map(|x| x as u8));
+
+    let data = flac_with_metadata_block(6, &block);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    {
+        let pictures: Vec<_> = reader.pictures().collect();
+        assert_eq!(pictures.len(), 1);
+        assert_eq!(pictures[0].picture_type, claxon::metadata::PictureType::BackCover);
+        assert_eq!(pictures[0].mime_type, "image/gif");
+        assert_eq!(pictures[0].description, "");
+        assert_eq!(pictures[0].num_colors, 4);
+        assert_eq!(pictures[0].data.len(), 300);
+        assert_eq!(pictures[0].data[299], 43);
+    }
+
+    // The audio after the picture block must still decode.
+    assert_eq!(reader.samples().count(), 4);
+
+    let opts = claxon::FlacReaderOptions {
+        read_pictures: false,
+        .. claxon::FlacReaderOptions::default()
+    };
+    let reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    assert_eq!(reader.pictures().count(), 0);
+}
+
 #[test]
 fn verify_decoded_stream_p0() {
     compare_decoded_stream("testsamples/p0.flac");
@@ -364,6 +567,991 @@ fn verify_decoded_stream_wasted_bits() {
     compare_decoded_stream("testsamples/wasted_bits.flac");
 }
 
+#[test]
+fn verify_md5_pop() {
+    let mut reader = claxon::FlacReader::open("testsamples/pop.flac").unwrap();
+    assert!(reader.verify_md5().unwrap());
+}
+
+#[test]
+fn verify_md5_short() {
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert!(reader.verify_md5().unwrap());
+}
+
+#[test]
+fn verify_md5_wasted_bits() {
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    assert!(reader.verify_md5().unwrap());
+}
+
+#[test]
+fn md5_matches_after_decoding_in_one_pass() {
+    let opts = claxon::FlacReaderOptions {
+        compute_md5: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let file = fs::File::open("testsamples/wasted_bits.flac").unwrap();
+    let mut reader = claxon::FlacReader::new_ext(file, opts).unwrap();
+    assert_eq!(reader.samples().take(100).count(), 100);
+    assert_eq!(reader.md5_matches(), None);
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 4410 - 4096);
+    assert_eq!(reader.md5_matches(), Some(true));
+
+    // After a skip, the hash does not cover the entire stream.
+    let file = fs::File::open("testsamples/wasted_bits.flac").unwrap();
+    let mut reader = claxon::FlacReader::new_ext(file, opts).unwrap();
+    assert_eq!(reader.skip_samples(4096).unwrap(), 4096);
+    assert_eq!(reader.samples().count(), 4410 - 4096);
+    assert_eq!(reader.md5_matches(), None);
+}
+
+#[test]
+fn check_subset_reports_violations() {
+    let opts = claxon::FlacReaderOptions {
+        check_subset: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let file = fs::File::open("testsamples/short.flac").unwrap();
+    let mut reader = claxon::FlacReader::new_ext(file, opts).unwrap();
+    assert_eq!(reader.samples().count(), 4);
+    assert_eq!(reader.violations(), &[]);
+
+    // A block size of 8192 at 44.1 kHz is allowed, but not in the subset.
+    let data = encode_verbatim(&test_signal(10_000), 8192, None);
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(data), opts).unwrap();
+    assert_eq!(reader.samples().count(), 10_000);
+    let violations = reader.violations();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].sample, None);
+    assert!(violations[0].subset_only);
+}
+
+#[test]
+fn fingerprint_samples_respects_time_limit() {
+    // This file contains 4410 samples at 44.1 kHz, so 0.1 seconds of audio.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let samples = reader.fingerprint_samples(0.05).unwrap();
+    assert_eq!(samples.len(), 2205);
+
+    // When the limit exceeds the duration, the entire stream is returned.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let samples = reader.fingerprint_samples(120.0).unwrap();
+    assert_eq!(samples.len(), 4410);
+}
+
+#[test]
+fn fingerprint_samples_of_mono_16_bit_stream_are_unchanged() {
+    let mut reader = claxon::FlacReader::open("testsamples/pop.flac").unwrap();
+    let fingerprint_samples = reader.fingerprint_samples(120.0).unwrap();
+
+    let mut reader = claxon::FlacReader::open("testsamples/pop.flac").unwrap();
+    let samples: Vec<i16> = reader.samples().map(|s| s.unwrap() as i16).collect();
+
+    assert_eq!(fingerprint_samples, samples);
+}
+
+#[test]
+fn cloned_block_survives_subsequent_decodes() {
+    // This file contains two frames, of 4096 and 314 samples.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let mut blocks = reader.blocks();
+    let first = blocks.read_next_or_eof(Vec::new()).unwrap().unwrap();
+    let cached = first.clone();
+
+    // Decode the next block into the buffer of the first one.
+    let second = blocks.read_next_or_eof(first.into_buffer()).unwrap().unwrap();
+    assert_eq!(second.time(), 4096);
+    assert_eq!(second.duration(), 314);
+
+    // The clone must still hold the samples of the first block.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let reference = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(cached.time(), 0);
+    assert_eq!(cached.duration(), reference.duration());
+    assert_eq!(cached.channel(0), reference.channel(0));
+}
+
+#[test]
+fn riff_chunk_is_read_from_application_block() {
+    // Insert an application block that holds a Broadcast WAV "bext" chunk,
+    // like `flac --keep-foreign-metadata` would write it, right after the
+    // streaminfo block of this file.
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut data).unwrap();
+    let chunk = b"bext\x05\x00\x00\x00Ruud!\x00";
+    let mut block = vec![0x02, 0, 0, 4 + chunk.len() as u8];
+    block.extend_from_slice(b"riff");
+    block.extend_from_slice(chunk);
+    let tail = data.split_off(4 + 4 + 34);
+    data.extend_from_slice(&block);
+    data.extend_from_slice(&tail);
+
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let blocks: Vec<_> = reader.application_blocks().collect();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].0, claxon::metadata::APPLICATION_ID_RIFF);
+    assert_eq!(reader.riff_chunk(&claxon::metadata::RIFF_CHUNK_BEXT), Some(&b"Ruud!"[..]));
+    assert_eq!(reader.riff_chunk(&claxon::metadata::RIFF_CHUNK_IXML), None);
+}
+
+#[test]
+fn metadata_callback_reports_every_block_once_in_order() {
+    use claxon::metadata::MetadataBlock;
+
+    let mut kinds = Vec::new();
+    let file = fs::File::open("testsamples/short.flac").unwrap();
+    let reader = claxon::FlacReader::new_with_metadata_callback(file, |block| {
+        kinds.push(match *block {
+            MetadataBlock::StreamInfo(..) => "streaminfo",
+            MetadataBlock::SeekTable(..) => "seektable",
+            MetadataBlock::VorbisComment(..) => "vorbis_comment",
+            _ => "other",
+        });
+    });
+
+    assert!(reader.is_ok());
+    assert_eq!(kinds, ["streaminfo", "seektable", "vorbis_comment"]);
+}
+
+#[test]
+fn metadata_yields_every_block_read_in_order() {
+    use claxon::metadata::MetadataBlock;
+
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let kinds: Vec<&str> = reader.metadata().map(|block| match *block {
+        MetadataBlock::StreamInfo(..) => "streaminfo",
+        MetadataBlock::SeekTable(..) => "seektable",
+        MetadataBlock::VorbisComment(..) => "vorbis_comment",
+        _ => "other",
+    }).collect();
+    assert_eq!(kinds, ["streaminfo", "seektable", "vorbis_comment"]);
+    assert_eq!(reader.vorbis_comment().unwrap().comments.len(), 0);
+
+    // Blocks that were not desired are not retained.
+    let opts = claxon::FlacReaderOptions {
+        read_vorbis_comment: false,
+        .. claxon::FlacReaderOptions::default()
+    };
+    let reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
+    assert_eq!(reader.metadata().count(), 2);
+    assert!(reader.vorbis_comment().is_none());
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Encodes mono 16-bit 44.1 kHz audio as a fixed-blocksize FLAC stream.
+///
+/// Every frame holds a single verbatim subframe, so the stream is large, but
+/// the encoding is simple. If `seek_every` is set, a seek table is included
+/// with a seek point for every `seek_every` frames, and a placeholder.
+fn encode_verbatim(samples: &[i16], block_size: u16, seek_every: Option<usize>) -> Vec<u8> {
+    let n = samples.len() as u64;
+    let mut data = b"fLaC".to_vec();
+
+    let is_last = if seek_every.is_some() { 0 } else { 0x80 };
+    data.extend_from_slice(&[is_last, 0, 0, 34]);
+    data.extend_from_slice(&[(block_size >> 8) as u8, block_size as u8,
+                             (block_size >> 8) as u8, block_size as u8]);
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    // 44100 Hz, 1 channel, 16 bits per sample, and the number of samples.
+    data.extend_from_slice(&[0x0a, 0xc4, 0x40, 0xf0 | (n >> 32) as u8,
+                             (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+    data.extend_from_slice(&[0; 16]);
+
+    let mut frames = Vec::new();
+    let mut seekpoints = Vec::new();
+    for (i, block) in samples.chunks(block_size as usize).enumerate() {
+        if let Some(k) = seek_every {
+            if i % k == 0 {
+                seekpoints.push((i as u64 * block_size as u64, frames.len() as u64, block.len()));
+            }
+        }
+
+        // Block size stored in 16 bits, 44.1 kHz, mono, 16 bits per sample.
+        let mut frame = vec![0xff, 0xf8, 0x79, 0x08];
+        match i {
+            0...0x7f => frame.push(i as u8),
+            0x80...0x7ff => frame.extend_from_slice(&[0xc0 | (i >> 6) as u8, 0x80 | (i & 0x3f) as u8]),
+            _ => frame.extend_from_slice(&[0xe0 | (i >> 12) as u8,
+                                           0x80 | ((i >> 6) & 0x3f) as u8,
+                                           0x80 | (i & 0x3f) as u8]),
+        }
+        let bs = block.len() - 1;
+        frame.extend_from_slice(&[(bs >> 8) as u8, bs as u8]);
+        let crc = crc8(&frame);
+        frame.push(crc);
+
+        // A verbatim subframe without wasted bits.
+        frame.push(0x02);
+        for &x in block {
+            frame.extend_from_slice(&[(x >> 8) as u8, x as u8]);
+        }
+        let crc = crc16(&frame);
+        frame.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+        frames.extend_from_slice(&frame);
+    }
+
+    if seek_every.is_some() {
+        let len = (seekpoints.len() + 1) * 18;
+        data.extend_from_slice(&[0x83, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+        for &(sample, offset, samples) in &seekpoints {
+            for k in 0..8 {
+                data.push((sample >> (56 - 8 * k)) as u8);
+            }
+            for k in 0..8 {
+                data.push((offset >> (56 - 8 * k)) as u8);
+            }
+            data.extend_from_slice(&[(samples >> 8) as u8, samples as u8]);
+        }
+        data.extend_from_slice(&[0xff; 8]);
+        data.extend_from_slice(&[0; 10]);
+    }
+
+    data.extend_from_slice(&frames);
+    data
+}
+
+/// Returns `n` samples of a deterministic test signal.
+fn test_signal(n: usize) -> Vec<i16> {
+    (0..n).map(|i| ((i * 7919) % 65536) as u16 as i16).collect()
+}
+
+#[test]
+fn verify_encode_verbatim_round_trips() {
+    let signal = test_signal(1000);
+    let data = encode_verbatim(&signal, 64, Some(4));
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(1000));
+    assert_eq!(reader.num_seek_points(), 5);
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+}
+
+fn check_seek(data: Vec<u8>, signal: &[i16]) {
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    check_seek_reader(&mut reader, signal);
+}
+
+fn check_seek_reader<R: io::Read + io::Seek>(reader: &mut claxon::FlacReader<R>, signal: &[i16]) {
+    // Seek around in no particular order, including the first sample, the
+    // boundaries of frames, and the last sample, in the last, short frame.
+    let n = signal.len() as u64;
+    for &target in &[5000, 0, 63, 64, 65, 12345, 1, n - 1, 20000 - 64, 777] {
+        reader.seek_to_sample(target).unwrap();
+        let decoded: Vec<i32> = reader.samples().take(100).map(|s| s.unwrap()).collect();
+        let end = std::cmp::min(n, target + 100) as usize;
+        let expected: Vec<i32> = signal[target as usize..end].iter().map(|&x| x as i32).collect();
+        assert_eq!(decoded, expected, "seek to {}", target);
+    }
+
+    match reader.seek_to_sample(n) {
+        Err(claxon::Error::IoError(ref err)) if err.kind() == io::ErrorKind::InvalidInput => {}
+        _ => panic!("expected seeking beyond the end to fail"),
+    }
+}
+
+#[test]
+fn seek_to_sample_without_seektable() {
+    // 313 frames of 64 samples, and a short last frame, about 40 KiB.
+    let signal = test_signal(20_003);
+    check_seek(encode_verbatim(&signal, 64, None), &signal);
+}
+
+#[test]
+fn seek_to_sample_with_seektable() {
+    let signal = test_signal(20_003);
+    check_seek(encode_verbatim(&signal, 64, Some(50)), &signal);
+}
+
+#[test]
+fn from_slice_decodes_and_seeks_like_new() {
+    let signal = test_signal(20_003);
+    let data = encode_verbatim(&signal, 64, Some(50));
+    let options = claxon::FlacReaderOptions::default();
+    let mut reader = claxon::FlacReader::from_slice(&data, options).unwrap();
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+    check_seek_reader(&mut reader, &signal);
+
+    for fname in &["testsamples/pop.flac", "testsamples/wasted_bits.flac"] {
+        let data = fs::read(fname).unwrap();
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let mut reader = claxon::FlacReader::from_slice(&data, options).unwrap();
+        let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+    }
+}
+
+#[test]
+fn seek_to_sample_in_real_file() {
+    // This file contains two frames, of 4096 and 314 samples, and a seek table.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    for &target in &[4409, 4096, 4095, 0] {
+        reader.seek_to_sample(target).unwrap();
+        let rest: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(&rest[..], &all[target as usize..]);
+    }
+}
+
+#[test]
+fn seek_to_sample_in_damaged_file_does_not_panic() {
+    // A mutated short.flac, found by fuzzing. Its metadata blocks are skipped
+    // by seeking, which leaves the input past its end, and determining the
+    // length of the stream then underflowed.
+    let path = "testsamples/fuzz/bc1ddb68ec8dc8295d1bc98e57d2c6c30588fc59.flac";
+    let file = fs::File::open(path).unwrap();
+    let options = claxon::FlacReaderOptions::default();
+    let mut reader = claxon::FlacReader::new_seekable(file, options).unwrap();
+    let _ = reader.seek_to_sample(4820);
+}
+
+#[test]
+fn seek_to_time_converts_with_sample_rate() {
+    // At 44.1 kHz, 100 ms is 4410 samples, and 250 µs is 11.025 samples.
+    let signal = test_signal(20_003);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(encode_verbatim(&signal, 64, None))).unwrap();
+    reader.seek_to_time(Duration::from_millis(100)).unwrap();
+    assert_eq!(reader.samples().next().unwrap().unwrap(), signal[4410] as i32);
+    reader.seek_to_time(Duration::new(0, 250_000)).unwrap();
+    assert_eq!(reader.samples().next().unwrap().unwrap(), signal[11] as i32);
+    assert!(reader.seek_to_time(Duration::from_secs(1)).is_err());
+    assert!(reader.seek_to_time(Duration::from_secs(u64::max_value())).is_err());
+}
+
+/// A reader that does not implement `Seek`, like a pipe.
+struct Pipe<R: io::Read>(R);
+
+impl<R: io::Read> io::Read for Pipe<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[test]
+fn skip_samples_without_seek() {
+    let signal = test_signal(20_003);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(Pipe(io::Cursor::new(data))).unwrap();
+
+    assert_eq!(reader.skip_samples(1000).unwrap(), 1000);
+    assert_eq!(reader.samples().next().unwrap().unwrap(), signal[1000] as i32);
+
+    // Skips add up, and counting starts after the block that `samples()` read.
+    assert_eq!(reader.skip_samples(10).unwrap(), 10);
+    assert_eq!(reader.skip_samples(64).unwrap(), 64);
+    assert_eq!(reader.samples().next().unwrap().unwrap(), signal[1024 + 74] as i32);
+
+    assert_eq!(reader.skip_samples(20_000).unwrap(), 20_003 - 1152);
+    assert!(reader.samples().next().is_none());
+}
+
+#[test]
+fn skip_samples_in_real_file() {
+    // This file contains two frames, of 4096 and 314 samples.
+    let all: Vec<i32> = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    for &n in &[0, 1, 4095, 4096, 4409] {
+        let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+        assert_eq!(reader.skip_samples(n).unwrap(), n);
+        let rest: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(&rest[..], &all[n as usize..]);
+    }
+}
+
+#[test]
+fn skip_samples_into_salvaged_frame_does_not_panic() {
+    // A mutated short.flac, found by fuzzing. The frame that is skipped into
+    // is cut off, and the salvaged part is shorter than the skip.
+    let path = "testsamples/fuzz/12ce4627e63bebbeaa6df9e95bda260750e8787b.flac";
+    let file = fs::File::open(path).unwrap();
+    let options = claxon::FlacReaderOptions {
+        salvage_partial_frame: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(file, options).unwrap();
+    let _ = reader.skip_samples(300);
+    for sample in reader.samples() {
+        if sample.is_err() {
+            break
+        }
+    }
+}
+
+#[test]
+fn tell_reports_the_next_sample() {
+    let signal = test_signal(20_003);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.tell().unwrap(), Some(0));
+
+    reader.skip_samples(1000).unwrap();
+    assert_eq!(reader.tell().unwrap(), Some(1000));
+    {
+        let mut samples = reader.samples();
+        assert_eq!(samples.position(), None);
+        samples.next();
+        samples.next();
+        assert_eq!(samples.position(), Some(1001));
+    }
+
+    // The rest of the block was dropped along with the iterator.
+    assert_eq!(reader.tell().unwrap(), Some(1024));
+    assert_eq!(reader.tell_time().unwrap(), Some(Duration::new(0, 23_219_954)));
+
+    reader.seek_to_sample(20_000).unwrap();
+    assert_eq!(reader.tell().unwrap(), Some(20_000));
+    {
+        let mut samples = reader.samples();
+        assert_eq!(samples.by_ref().count(), 3);
+        assert_eq!(samples.position(), Some(20_002));
+    }
+    assert_eq!(reader.tell().unwrap(), Some(20_003));
+}
+
+#[test]
+fn seek_index_records_decoded_frames() {
+    let signal = test_signal(20_003);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert!(reader.seek_index().is_empty());
+
+    // Skipping records the frames it passes over, decoding records the rest.
+    reader.skip_samples(640).unwrap();
+    assert_eq!(reader.seek_index().len(), 10);
+    assert_eq!(reader.samples().count(), 20_003 - 640);
+
+    let index = reader.seek_index().to_vec();
+    assert_eq!(index.len(), (20_003 + 63) / 64);
+    assert_eq!(index[0].offset, 0);
+    for (i, point) in index.iter().enumerate() {
+        assert_eq!(point.sample, i as u64 * 64);
+        if i > 0 {
+            assert!(point.offset > index[i - 1].offset);
+        }
+    }
+    assert_eq!(index[0].samples, 64);
+    assert_eq!(index.last().unwrap().samples, 20_003 % 64);
+
+    // Seeking back uses the index, and seeking does not change it.
+    for &target in &[19_999, 0, 5000, 64, 63] {
+        reader.seek_to_sample(target).unwrap();
+        assert_eq!(reader.samples().next().unwrap().unwrap(), signal[target as usize] as i32);
+    }
+    assert_eq!(reader.seek_index(), &index[..]);
+}
+
+#[test]
+fn generate_seektable_points_at_frames_containing_multiples() {
+    let signal = test_signal(20_003);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let seektable = reader.generate_seektable(1000).unwrap();
+
+    let samples: Vec<u64> = seektable.seekpoints().iter().map(|p| p.sample).collect();
+    let expected: Vec<u64> = (0..21).map(|k| k * 1000 / 64 * 64).collect();
+    assert_eq!(samples, expected);
+
+    assert!(reader.samples().next().is_none());
+
+    // With a point for every frame, the table matches the one of the encoder.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let generated = reader.generate_seektable(64).unwrap();
+    let with_table = encode_verbatim(&signal, 64, Some(1));
+    let reader = claxon::FlacReader::new(io::Cursor::new(with_table)).unwrap();
+    let encoded: Vec<_> = reader.seektable().unwrap().seekpoints().iter()
+        .filter(|p| !p.is_placeholder()).cloned().collect();
+    assert_eq!(generated.seekpoints(), &encoded[..]);
+}
+
+#[test]
+fn blocks_expose_frame_headers_and_offsets() {
+    use claxon::frame::{BlockTime, ChannelAssignment};
+
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::new();
+
+    for i in 0..4 {
+        offsets.push(blocks.get_ref().position());
+        let (info, block) = blocks.read_next_with_info_or_eof(buffer).unwrap().unwrap();
+        assert_eq!(info.block_time, BlockTime::FrameNumber(i));
+        assert_eq!(info.first_sample, i as u64 * 64);
+        assert_eq!(info.first_sample, block.time());
+        assert_eq!(info.block_size as u32, block.duration());
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.channel_assignment, ChannelAssignment::Independent(1));
+        assert_eq!(info.sample_rate, Some(44_100));
+        assert_eq!(info.bits_per_sample, Some(16));
+        buffer = block.into_buffer();
+    }
+    assert!(blocks.read_next_with_info_or_eof(buffer).unwrap().is_none());
+
+    // Verbatim frames are the header, the subframe header, and the samples.
+    assert_eq!(offsets[2] - offsets[1], offsets[1] - offsets[0]);
+    assert!(offsets[1] - offsets[0] > 64 * 2);
+}
+
+#[test]
+fn blocks_can_be_iterated() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let durations: Vec<u32> = reader.blocks().map(|b| b.unwrap().duration()).collect();
+    assert_eq!(durations, vec![64, 64, 64, 8]);
+
+    // Corrupt the last sample of the second frame, so its CRC does not match.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let end_of_second = {
+        let mut blocks = reader.blocks();
+        blocks.next();
+        blocks.next();
+        blocks.get_ref().position() as usize
+    };
+    let mut corrupted = data.clone();
+    corrupted[end_of_second - 3] ^= 1;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(corrupted)).unwrap();
+    let mut blocks = reader.blocks();
+    assert!(blocks.next().unwrap().is_ok());
+    assert!(blocks.next().unwrap().is_err());
+    assert!(blocks.next().is_none());
+}
+
+fn open_samples(fname: &str) -> Box<Iterator<Item = claxon::Result<i32>>> {
+    Box::new(claxon::FlacReader::open(fname).unwrap().into_samples())
+}
+
+#[test]
+fn into_samples_owns_the_reader() {
+    let fname = "testsamples/wasted_bits.flac";
+    let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    let samples: Vec<i32> = open_samples(fname).map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Seeking before, and continuing with the reader afterwards, works too.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    reader.seek_to_sample(10).unwrap();
+    let mut samples = reader.into_samples();
+    assert_eq!(samples.next().unwrap().unwrap(), expected[10]);
+    assert_eq!(samples.position(), Some(10));
+    let mut reader = samples.into_reader();
+    assert_eq!(reader.tell().unwrap(), Some(4096));
+}
+
+#[test]
+fn samples_size_hint_counts_down() {
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let total = 4410;
+    {
+        let mut samples = reader.samples();
+        assert_eq!(samples.size_hint(), (total, Some(total)));
+        samples.next();
+        assert_eq!(samples.size_hint(), (total - 1, Some(total - 1)));
+        for _ in 0..4096 {
+            samples.next();
+        }
+        assert_eq!(samples.size_hint(), (total - 4097, Some(total - 4097)));
+    }
+
+    // After a seek, counting starts at the target, so collect is exact.
+    reader.seek_to_sample(4000).unwrap();
+    let mut samples = reader.into_samples();
+    assert_eq!(samples.size_hint(), (410, Some(410)));
+    assert_eq!(samples.by_ref().count(), 410);
+    assert_eq!(samples.size_hint(), (0, Some(0)));
+}
+
+#[test]
+fn trailing_data_can_be_recovered_after_decoding() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+    let stream_len = data.len() as u64;
+    let trailer: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    data.extend_from_slice(&trailer);
+
+    // Decode until the last frame, then take what follows it.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.blocks().take(4).count(), 4);
+    assert_eq!(reader.byte_position(), stream_len);
+    assert!(reader.get_ref().position() > stream_len);
+
+    let mut rest = reader.buffer().to_vec();
+    reader.get_mut().read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, trailer);
+}
+
+#[test]
+fn verify_crc_can_be_disabled() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+
+    // Flip the last bit of the last sample, just before the frame CRC-16.
+    let n = data.len();
+    data[n - 3] ^= 1;
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    assert!(reader.samples().collect::<claxon::Result<Vec<i32>>>().is_err());
+
+    let options = claxon::FlacReaderOptions {
+        verify_crc: false,
+        .. claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), options).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples.len(), 200);
+    assert_eq!(samples[199], signal[199] as i32 ^ 1);
+}
+
+#[test]
+fn truncated_stream_is_distinguished_from_clean_end() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let frames_start = reader.byte_position() as usize;
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 200);
+    assert!(reader.samples().next().is_none());
+
+    // Cut off the last frame. The frames end cleanly, but the streaminfo
+    // promises more samples.
+    let last_frame = frames_start + reader.seek_index()[3].offset as usize;
+    let truncated = &data[..last_frame];
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(truncated)).unwrap();
+    let mut samples = reader.samples();
+    assert_eq!(samples.by_ref().take(192).filter(|s| s.is_ok()).count(), 192);
+    match samples.next() {
+        Some(Err(claxon::Error::FormatError { .. })) => {}
+        other => panic!("expected format error, got {:?}", other),
+    }
+    assert!(samples.next().is_none());
+
+    // Ending in the middle of a frame is an IO error.
+    let truncated = &data[..last_frame + 10];
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(truncated)).unwrap();
+    match reader.samples().skip(192).next() {
+        Some(Err(claxon::Error::IoError(ref err))) => {
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        other => panic!("expected IO error, got {:?}", other),
+    }
+}
+
+#[test]
+fn salvage_partial_frame_returns_samples_before_the_end() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let frames_start = reader.byte_position() as usize;
+    assert_eq!(reader.samples().count(), 200);
+    let last_frame = frames_start + reader.seek_index()[3].offset as usize;
+
+    // Cut off the last frame after its 8-byte header, the subframe header,
+    // and five and a half 16-bit samples.
+    let truncated = &data[..last_frame + 9 + 11];
+    let opts = claxon::FlacReaderOptions {
+        salvage_partial_frame: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(truncated), opts).unwrap();
+    let mut samples = reader.samples();
+    let decoded: Vec<i32> = samples.by_ref().take(197).map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal[..197].iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+
+    // The streaminfo promises more samples, so the truncation is reported.
+    match samples.next() {
+        Some(Err(claxon::Error::FormatError { .. })) => {}
+        other => panic!("expected format error, got {:?}", other),
+    }
+}
+
+#[test]
+fn error_offset_locates_the_damaged_frame() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let frames_start = reader.byte_position();
+    assert_eq!(reader.samples().count(), 200);
+    assert_eq!(reader.error_offset(), None);
+    let second_frame = frames_start + reader.seek_index()[1].offset;
+    let third_frame = frames_start + reader.seek_index()[2].offset;
+
+    // Damage a sample in the second frame, so its CRC-16 no longer matches.
+    data[third_frame as usize - 4] ^= 0x10;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    match reader.samples().nth(64) {
+        Some(Err(claxon::Error::FormatError { byte_offset, frame_index, .. })) => {
+            assert_eq!(byte_offset, Some(second_frame));
+            assert_eq!(frame_index, Some(1));
+        }
+        other => panic!("expected format error, got {:?}", other),
+    }
+    assert_eq!(reader.error_offset(), Some(second_frame));
+}
+
+#[test]
+fn crc_mismatch_reports_details() {
+    use claxon::frame::{BlockTime, Checksum};
+
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let frames_start = reader.byte_position();
+    assert_eq!(reader.samples().count(), 200);
+    assert_eq!(reader.crc_mismatch(), None);
+    let second_frame = (frames_start + reader.seek_index()[1].offset) as usize;
+    let third_frame = (frames_start + reader.seek_index()[2].offset) as usize;
+
+    // Damage a sample in the second frame.
+    let mut damaged = data.clone();
+    damaged[third_frame - 4] ^= 0x10;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&damaged[..])).unwrap();
+    assert!(reader.samples().nth(64).unwrap().is_err());
+    let mismatch = reader.crc_mismatch().unwrap();
+    assert_eq!(mismatch.block_time, BlockTime::FrameNumber(1));
+    assert_eq!(mismatch.checksum, Checksum::FrameCrc16);
+    assert!(mismatch.expected != mismatch.computed);
+
+    // Damage the CRC-8 of the header of the second frame. It is the eighth
+    // byte of the header.
+    let mut damaged = data.clone();
+    damaged[second_frame + 7] ^= 0x01;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&damaged[..])).unwrap();
+    assert!(reader.samples().nth(64).unwrap().is_err());
+    let mismatch = reader.crc_mismatch().unwrap();
+    assert_eq!(mismatch.block_time, BlockTime::FrameNumber(1));
+    assert_eq!(mismatch.checksum, Checksum::HeaderCrc8);
+    assert_eq!(mismatch.expected ^ mismatch.computed, 0x01);
+
+    // Without verification, the frame decodes fine.
+    let opts = claxon::FlacReaderOptions {
+        verify_crc: false,
+        verify_header_crc: false,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&damaged[..]), opts).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 200);
+    assert_eq!(reader.crc_mismatch(), None);
+}
+
+#[test]
+fn resync_skips_damaged_frames() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let frames_start = reader.byte_position();
+    assert_eq!(reader.samples().count(), 200);
+    let third_frame = frames_start + reader.seek_index()[2].offset;
+    let last_frame = frames_start + reader.seek_index()[3].offset;
+
+    // Damage a sample in the second frame, and in the last frame.
+    data[third_frame as usize - 4] ^= 0x10;
+    let len = data.len();
+    data[len - 4] ^= 0x10;
+
+    let opts = claxon::FlacReaderOptions {
+        resync: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal[..64].iter().chain(&signal[128..192]).map(|&x| x as i32).collect();
+    assert_eq!(samples, expected);
+    assert_eq!(reader.lost_samples(), &[64..128, 192..200]);
+    assert_eq!(reader.error_offset(), Some(last_frame));
+}
+
+#[test]
+fn byte_position_starts_after_metadata() {
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert_eq!(reader.byte_position(), 108);
+    assert_eq!(reader.samples().count(), 4);
+    assert_eq!(reader.byte_position(), fs::metadata("testsamples/short.flac").unwrap().len());
+}
+
+/// Reads short.flac and lets `patch` modify the 34-byte streaminfo block.
+fn open_with_patched_streaminfo<F>(patch: F) -> claxon::Result<claxon::FlacReader<io::Cursor<Vec<u8>>>>
+    where F: FnOnce(&mut [u8]) {
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut data).unwrap();
+    patch(&mut data[8..8 + 34]);
+    claxon::FlacReader::new(io::Cursor::new(data))
+}
+
+#[test]
+fn verify_zero_bits_per_sample_field_is_rejected() {
+    // The bits per sample are stored minus one, in the least significant bit
+    // of byte 12 and the most significant nibble of byte 13. A zero field
+    // means 1 bit per sample, which is below the minimum of 4.
+    let result = open_with_patched_streaminfo(|streaminfo| {
+        streaminfo[12] &= 0b1111_1110;
+        streaminfo[13] &= 0b0000_1111;
+    });
+    match result {
+        Ok(..) => panic!("This file should fail to load"),
+        Err(claxon::Error::FormatError { reason, .. }) => {
+            assert_eq!(reason, "invalid bits per sample, must be at least 4")
+        }
+        Err(err) => panic!("expected format error, got {:?}", err),
+    }
+}
+
+#[test]
+fn verify_zero_channels_field_means_mono() {
+    // The number of channels is stored minus one, so a zero field is the
+    // smallest possible value, one channel, and a stream can never claim to
+    // have zero channels.
+    let reader = open_with_patched_streaminfo(|streaminfo| {
+        streaminfo[12] &= 0b1111_0001;
+    }).unwrap();
+    assert_eq!(reader.streaminfo().channels, 1);
+}
+
+#[test]
+fn decode_block_interleaved_into_matches_samples() {
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let streaminfo = reader.streaminfo();
+    let mut scratch = claxon::DecodeScratch::for_streaminfo(&streaminfo);
+    let mut out = vec![0; streaminfo.max_block_size as usize * streaminfo.channels as usize];
+    let mut decoded = Vec::new();
+
+    // A buffer that is too small is rejected before anything is decoded.
+    let required = out.len();
+    match reader.decode_block_interleaved_into(&mut scratch, &mut out[1..]) {
+        Err(claxon::Error::BufferTooSmall { required: r }) => assert_eq!(r, required),
+        _ => panic!("expected a buffer that is too small to be rejected"),
+    }
+
+    while let Some(n) = reader.decode_block_interleaved_into(&mut scratch, &mut out).unwrap() {
+        decoded.extend_from_slice(&out[..n]);
+    }
+
+    assert_eq!(decoded, expected);
+}
+
+fn decode_with_trailer(trailer: &[u8]) -> claxon::Result<Vec<i32>> {
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut data).unwrap();
+    data.extend_from_slice(trailer);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    reader.samples().collect()
+}
+
+#[test]
+fn unknown_sample_rate_is_derived_from_first_frame() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+
+    // The sample rate is 20 bits, starting 10 bytes into the streaminfo.
+    data[18] = 0;
+    data[19] = 0;
+    data[20] &= 0x0f;
+    assert!(claxon::FlacReader::new(io::Cursor::new(&data[..])).is_err());
+
+    let opts = claxon::FlacReaderOptions {
+        derive_sample_rate: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    assert_eq!(reader.streaminfo().sample_rate, 44100);
+    assert!(reader.sample_rate_is_derived());
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 200);
+
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert!(!reader.sample_rate_is_derived());
+}
+
+#[test]
+fn leading_id3v2_tag_and_garbage_can_be_skipped() {
+    let signal = test_signal(200);
+    let flac = encode_verbatim(&signal, 64, None);
+
+    // An ID3v2.4 tag with 128 bytes of content, which is 0x80 as a 28-bit
+    // integer in the low seven bits of four bytes.
+    let mut data = b"ID3\x04\x00\x00\x00\x00\x01\x00".to_vec();
+    data.extend_from_slice(&[0; 128]);
+    data.extend_from_slice(&flac);
+
+    match claxon::FlacReader::new(io::Cursor::new(&data[..])) {
+        Err(claxon::Error::FormatError { reason, .. }) => assert!(reason.contains("ID3")),
+        _ => panic!("expected format error"),
+    }
+    let opts = claxon::FlacReaderOptions {
+        skip_id3v2: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 200);
+
+    // Garbage is only skipped up to the limit.
+    let mut data = b"junk!".to_vec();
+    data.extend_from_slice(&flac);
+    let opts = claxon::FlacReaderOptions {
+        header_search_limit: 4,
+        ..claxon::FlacReaderOptions::default()
+    };
+    assert!(claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).is_err());
+    let opts = claxon::FlacReaderOptions {
+        header_search_limit: 5,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 200);
+}
+
+#[test]
+fn decoding_stops_at_trailing_id3v1_tag() {
+    let mut tag = b"TAGNever Gonna Give You Up".to_vec();
+    tag.resize(128, 0);
+    let samples = decode_with_trailer(&tag).unwrap();
+    assert_eq!(samples, decode_with_trailer(&[]).unwrap());
+    assert_eq!(samples.len(), 4);
+}
+
+#[test]
+fn decoding_stops_at_trailing_apev2_tag() {
+    let mut tag = b"APETAGEX\xd0\x07\x00\x00".to_vec();
+    tag.resize(32, 0);
+    let samples = decode_with_trailer(&tag).unwrap();
+    assert_eq!(samples.len(), 4);
+}
+
+#[test]
+fn decoding_fails_on_trailing_garbage() {
+    assert!(decode_with_trailer(b"TAX").is_err());
+    assert!(decode_with_trailer(b"garbage").is_err());
+}
+
 #[test]
 fn verify_limits_on_vendor_string() {
     // This file claims to have a vendor string which would not fit in the
@@ -371,9 +1559,10 @@ fn verify_limits_on_vendor_string() {
     let file = fs::File::open("testsamples/large_vendor_string.flac").unwrap();
     match claxon::FlacReader::new(file) {
         Ok(..) => panic!("This file should fail to load"),
-        Err(err) => {
-            assert_eq!(err, claxon::Error::FormatError("vendor string too long"))
+        Err(claxon::Error::FormatError { reason, .. }) => {
+            assert_eq!(reason, "vendor string too long")
         }
+        Err(err) => panic!("expected format error, got {:?}", err),
     }
 }
 
@@ -383,7 +1572,7 @@ fn verify_limits_on_vorbis_comment_block() {
     // make the decoder go OOM.
     match claxon::FlacReader::open("testsamples/large_vorbis_comment_block.flac") {
         Ok(..) => panic!("This file should fail to load"),
-        Err(claxon::Error::Unsupported(..)) => { }
+        Err(claxon::Error::Unsupported { .. }) => { }
         Err(..) => panic!("Expected 'Unsupported' error."),
     }
 }
@@ -393,6 +1582,7 @@ fn metadata_only_still_reads_vorbis_comment_block() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        .. claxon::FlacReaderOptions::default()
     };
     let reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     assert_eq!(reader.vendor(), Some("reference libFLAC 1.3.2 20170101"));
@@ -403,17 +1593,103 @@ fn no_read_vorbis_comment_block_does_not_contain_vendor_string() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: false,
+        .. claxon::FlacReaderOptions::default()
     };
     let reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     assert_eq!(reader.vendor(), None);
 }
 
+#[test]
+fn tags_only_stops_after_vorbis_comment_block() {
+    let mut original = Vec::new();
+    fs::File::open("testsamples/short.flac").unwrap().read_to_end(&mut original).unwrap();
+
+    // Put an invalid picture block after the Vorbis comment block of short.flac,
+    // which is the last of its metadata blocks, and cut off the audio data.
+    // The Vorbis comment block is the last 44 bytes before the audio data.
+    let mut data = original[..108].to_vec();
+    assert_eq!(data[64], 0x84);
+    data[64] = 0x04;
+    data.extend_from_slice(&[0x86, 0, 0, 3, 1, 2, 3]);
+    assert!(claxon::FlacReader::new(io::Cursor::new(&data[..])).is_err());
+
+    let opts = claxon::FlacReaderOptions::tags_only();
+    let reader = claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts).unwrap();
+    assert_eq!(reader.vendor(), Some("reference libFLAC 1.3.2 20170101"));
+    assert_eq!(reader.metadata().count(), 3);
+    assert_eq!(reader.pictures().count(), 0);
+}
+
+/// A reader that counts the bytes read from it.
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: u64,
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: io::Seek> io::Seek for CountingReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn new_seekable_skips_large_blocks_without_reading_them() {
+    let padding = flac_with_metadata_block(1, &vec![0; 1 << 20]);
+    let mut picture = vec![0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0];
+    picture.extend_from_slice(&[0; 16]);
+    picture.extend_from_slice(&[0, 0x10, 0, 0]);
+    picture.extend_from_slice(&vec![0xff; 1 << 20]);
+    let picture = flac_with_metadata_block(6, &picture);
+
+    let no_pictures = claxon::FlacReaderOptions {
+        read_pictures: false,
+        .. claxon::FlacReaderOptions::default()
+    };
+    for &(ref data, options) in &[(&padding, claxon::FlacReaderOptions::default()),
+                                  (&picture, no_pictures)] {
+        let input = CountingReader { inner: io::Cursor::new(&data[..]), bytes_read: 0 };
+        let mut reader = claxon::FlacReader::new_seekable(input, options).unwrap();
+        assert_eq!(reader.samples().count(), 4);
+        assert!(reader.into_inner().bytes_read < 10_000);
+
+        // Without seeking, the block must be read to get past it.
+        let input = CountingReader { inner: io::Cursor::new(&data[..]), bytes_read: 0 };
+        let mut reader = claxon::FlacReader::new_ext(input, options).unwrap();
+        assert_eq!(reader.samples().count(), 4);
+        assert!(reader.into_inner().bytes_read > 1 << 20);
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn open_mmap_decodes_like_open() {
+    let fname = "testsamples/wasted_bits.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    // The test samples are not modified while the test runs.
+    let mut reader = unsafe { claxon::FlacReader::open_mmap(fname) }.unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+    reader.seek_to_sample(1000).unwrap();
+    assert_eq!(reader.samples().next().unwrap().unwrap(), expected[1000]);
+}
+
 #[test]
 #[should_panic]
 fn samples_panics_when_metadata_only_is_set() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        .. claxon::FlacReaderOptions::default()
     };
     let mut reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     let _samples = reader.samples();
@@ -425,6 +1701,7 @@ fn blocks_panics_when_metadata_only_is_set() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        .. claxon::FlacReaderOptions::default()
     };
     let mut reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     let _blocks = reader.blocks();
@@ -482,3 +1759,854 @@ fn regression_test_fuzz_samples() {
         }
     }
 }
+
+#[test]
+fn block_size_beyond_streaminfo_maximum_is_rejected() {
+    let signal = test_signal(200);
+    let mut data = encode_verbatim(&signal, 64, None);
+
+    // Declare block sizes of 16 to 32 in the streaminfo, while the frames
+    // have a block size of 64.
+    data[8..12].copy_from_slice(&[0, 16, 0, 32]);
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    assert_eq!(reader.streaminfo().max_block_len(), 32);
+    let buffer = Vec::with_capacity(reader.streaminfo().max_block_len());
+    match reader.blocks().read_next_or_eof(buffer) {
+        Err(claxon::Error::FormatError { reason, .. }) => {
+            assert_eq!(reason, "block size exceeds maximum block size in streaminfo")
+        }
+        Err(err) => panic!("expected format error, got {:?}", err),
+        Ok(..) => panic!("expected frame with a too large block size to be rejected"),
+    }
+}
+
+#[test]
+fn decoding_damaged_stream_does_not_panic() {
+    let mut original = Vec::new();
+    fs::File::open("testsamples/wasted_bits.flac").unwrap().read_to_end(&mut original).unwrap();
+
+    let lenient = claxon::FlacReaderOptions {
+        resync: true,
+        salvage_partial_frame: true,
+        verify_header_crc: false,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let options = [claxon::FlacReaderOptions::default(), lenient];
+
+    // Damage every byte in turn. Decoding may fail, but it must not panic.
+    for i in 0..original.len() {
+        let mut data = original.clone();
+        data[i] ^= 0x5a;
+        for &opts in &options {
+            let mut reader = match claxon::FlacReader::new_ext(io::Cursor::new(&data[..]), opts) {
+                Ok(r) => r,
+                Err(..) => continue,
+            };
+            for sample in reader.samples() {
+                if sample.is_err() {
+                    break
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn decode_32_bit_left_side_stereo() {
+    // A stream with one frame of 16 samples, 32 bits per sample, in left-side
+    // stereo. The samples are near full scale, so the side channel needs all
+    // of its 33 bits.
+    let left: Vec<i64> = (0..16).map(|i| 0x7fff_ffff - i * 1_000_003).collect();
+    let right: Vec<i64> = (0..16).map(|i| -0x8000_0000 + i * i * 7).collect();
+
+    let mut data = b"fLaC".to_vec();
+    data.extend_from_slice(&[0x80, 0, 0, 34, 0, 16, 0, 16, 0, 0, 0, 0, 0, 0]);
+    // 44100 Hz, 2 channels, 32 bits per sample, 16 samples.
+    data.extend_from_slice(&[0x0a, 0xc4, 0x43, 0xf0, 0, 0, 0, 16]);
+    data.extend_from_slice(&[0; 16]);
+
+    // Block size stored in 8 bits, 44.1 kHz, left-side stereo, 32 bits per
+    // sample, frame 0, block size 16.
+    let mut frame = vec![0xff, 0xf8, 0x69, 0x8e, 0x00, 15];
+    let crc = crc8(&frame);
+    frame.push(crc);
+
+    // Two verbatim subframes, of 32 and 33 bits per sample.
+    let mut bits = String::from("00000010");
+    for &x in &left {
+        bits.push_str(&format!("{:032b}", x as u32));
+    }
+    bits.push_str("00000010");
+    for (&l, &r) in left.iter().zip(&right) {
+        bits.push_str(&format!("{:033b}", (l - r) as u64 & 0x1_ffff_ffff));
+    }
+    for chunk in bits.as_bytes().chunks(8) {
+        let byte = chunk.iter().fold(0u8, |b, &c| b << 1 | (c - b'0'));
+        frame.push(byte << (8 - chunk.len()));
+    }
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+    data.extend_from_slice(&frame);
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data.clone())).unwrap();
+    assert_eq!(reader.streaminfo().bits_per_sample, 32);
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = left.iter().zip(&right)
+                                 .flat_map(|(&l, &r)| vec![l as i32, r as i32])
+                                 .collect();
+    assert_eq!(decoded, expected);
+
+    // The block reports the coding, even though it holds left and right.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(block.channel_assignment(), ChannelAssignment::LeftSideStereo);
+}
+
+#[test]
+fn blocks_report_wasted_bits() {
+    // This file is 16 bits per sample, but only the 8 most significant bits
+    // are used.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    assert_eq!(reader.streaminfo().bits_per_sample, 16);
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+    while let Some(block) = blocks.read_next_or_eof(buffer).unwrap() {
+        assert_eq!(block.wasted_bits(0), 8);
+        assert_eq!(block.channel_assignment(), ChannelAssignment::Independent(1));
+        assert_eq!(block.effective_bits_per_sample(16), 8);
+        buffer = block.into_buffer();
+    }
+
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let bps = reader.streaminfo().bits_per_sample;
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(block.effective_bits_per_sample(bps), bps);
+}
+
+#[test]
+fn samples_f32_are_scaled_by_bit_depth() {
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let bps = reader.streaminfo().bits_per_sample;
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let floats: Vec<f32> = reader.samples_f32().map(|s| s.unwrap()).collect();
+    assert_eq!(floats.len(), samples.len());
+    for (&f, &s) in floats.iter().zip(&samples) {
+        assert_eq!(f, s as f32 / (1 << (bps - 1)) as f32);
+        assert!(f >= -1.0);
+        assert!(f < 1.0);
+    }
+
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    let mut out = vec![2.0; 3];
+    block.interleaved_f32_into(bps, &mut out);
+    assert_eq!(out, floats);
+
+    // The extremes of a 20-bit stream.
+    let extremes = [-(1 << 19), (1 << 19) - 1].iter().map(|&s| Ok(s));
+    let floats: Vec<f32> = claxon::SamplesF32::new(extremes, 20).map(|s| s.unwrap()).collect();
+    assert_eq!(floats, [-1.0, 1.0 - 1.0 / (1 << 19) as f32]);
+}
+
+#[test]
+#[should_panic]
+fn samples_f32_panics_for_zero_bits_per_sample() {
+    let _samples = claxon::SamplesF32::new(Vec::<claxon::Result<i32>>::new().into_iter(), 0);
+}
+
+#[test]
+fn samples_dithered_reduce_bit_depth() {
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    assert_eq!(reader.streaminfo().bits_per_sample, 16);
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    for &shaping in &[false, true] {
+        let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+        let reduced: Vec<i32> = reader.samples_dithered(8, shaping).map(|s| s.unwrap()).collect();
+        assert_eq!(reduced.len(), samples.len());
+        for (&r, &s) in reduced.iter().zip(&samples) {
+            assert!(r >= -128 && r <= 127);
+            assert!((r - (s >> 8)).abs() <= 2);
+        }
+    }
+
+    // Reducing to the bit depth of the stream leaves the samples untouched.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    let same: Vec<i32> = reader.samples_dithered(16, true).map(|s| s.unwrap()).collect();
+    assert_eq!(same, samples);
+}
+
+#[test]
+fn decode_frame_decodes_packets() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let streaminfo = reader.streaminfo();
+    let mut offsets = Vec::new();
+    {
+        let mut blocks = reader.blocks();
+        offsets.push(blocks.get_ref().position() as usize);
+        while blocks.next().is_some() {
+            offsets.push(blocks.get_ref().position() as usize);
+        }
+    }
+    assert_eq!(offsets.len(), 5);
+
+    // Decode the frames as separate packets, out of order, as after a seek.
+    let mut buffer = Vec::new();
+    for &i in &[2, 0, 3, 1] {
+        let packet = &data[offsets[i]..offsets[i + 1]];
+        let block = claxon::frame::decode_frame(packet, &streaminfo, buffer).unwrap();
+        assert_eq!(block.time(), i as u64 * 64);
+        let expected: Vec<i32> = signal[i * 64..].iter().take(64).map(|&x| x as i32).collect();
+        assert_eq!(block.channel(0), &expected[..]);
+        buffer = block.into_buffer();
+    }
+
+    // A truncated packet and an empty packet are errors.
+    let truncated = &data[offsets[0]..offsets[1] - 1];
+    assert!(claxon::frame::decode_frame(truncated, &streaminfo, Vec::new()).is_err());
+    assert!(claxon::frame::decode_frame(&[], &streaminfo, Vec::new()).is_err());
+}
+
+/// Wraps a native FLAC stream in Ogg, with a foreign logical stream multiplexed in.
+fn encode_ogg_flac(data: &[u8]) -> Vec<u8> {
+    use ogg::PacketWriteEndInfo::{EndPage, EndStream, NormalPacket};
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let mut offsets = Vec::new();
+    {
+        let mut blocks = reader.blocks();
+        offsets.push(blocks.get_ref().position() as usize);
+        while blocks.next().is_some() {
+            offsets.push(blocks.get_ref().position() as usize);
+        }
+    }
+
+    let mut writer = ogg::PacketWriter::new(Vec::new());
+    writer.write_packet(b"foreign"[..].to_vec().into_boxed_slice(), 7, EndPage, 0).unwrap();
+
+    // The header packet contains the streaminfo block, with its header.
+    let mut header = vec![0x7f, b'F', b'L', b'A', b'C', 1, 0, 0, 1];
+    header.extend_from_slice(&data[..42]);
+    writer.write_packet(header.into_boxed_slice(), 1, EndPage, 0).unwrap();
+    writer.write_packet(data[42..offsets[0]].to_vec().into_boxed_slice(), 1, EndPage, 0).unwrap();
+
+    let n = offsets.len() - 1;
+    for i in 0..n {
+        let frame = data[offsets[i]..offsets[i + 1]].to_vec().into_boxed_slice();
+        let end = if i + 1 == n { EndStream } else { NormalPacket };
+        writer.write_packet(frame, 1, end, i as u64).unwrap();
+        if i == 0 {
+            writer.write_packet(vec![1; 300].into_boxed_slice(), 7, EndStream, 0).unwrap();
+        }
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn ogg_flac_reader_decodes_multiplexed_stream() {
+    // Frames of 40000 samples are larger than an Ogg page, so they span pages.
+    let signal = test_signal(100_000);
+    let data = encode_verbatim(&signal, 40_000, Some(1));
+    let ogg_data = encode_ogg_flac(&data);
+
+    // The native reader rejects the Ogg stream.
+    assert!(claxon::FlacReader::new(io::Cursor::new(&ogg_data[..])).is_err());
+
+    let mut reader = claxon::ogg::OggFlacReader::new(io::Cursor::new(&ogg_data[..])).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(100_000));
+    assert_eq!(reader.streaminfo().max_block_size, 40_000);
+    assert_eq!(reader.metadata().count(), 2);
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+
+    // A damaged page fails its CRC check.
+    let mut damaged = ogg_data.clone();
+    let len = damaged.len();
+    damaged[len / 2] ^= 0x10;
+    let mut reader = claxon::ogg::OggFlacReader::new(io::Cursor::new(damaged)).unwrap();
+    assert!(reader.samples().any(|s| s.is_err()));
+
+    // Input that is not Ogg FLAC is an error.
+    assert!(claxon::ogg::OggFlacReader::new(io::Cursor::new(&data[..])).is_err());
+}
+
+#[test]
+fn next_stream_reads_chained_streams() {
+    let first = test_signal(200);
+    let second: Vec<i16> = test_signal(300).iter().map(|&x| x / 2).collect();
+    let mut data = encode_verbatim(&first, 64, None);
+    data.extend_from_slice(&encode_verbatim(&second, 100, Some(2)));
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(200));
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(decoded, first.iter().map(|&x| x as i32).collect::<Vec<_>>());
+
+    let options = claxon::FlacReaderOptions::default();
+    let mut reader = reader.next_stream(options).unwrap().unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(300));
+    assert_eq!(reader.streaminfo().max_block_size, 100);
+    assert!(reader.has_seektable());
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(decoded, second.iter().map(|&x| x as i32).collect::<Vec<_>>());
+    assert!(reader.next_stream(options).unwrap().is_none());
+
+    // The rest of the current stream is skipped.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    assert!(reader.samples().next().is_some());
+    let reader = reader.next_stream(options).unwrap().unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(300));
+}
+
+#[test]
+fn new_headerless_resyncs_on_bare_frames() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let mut streaminfo = reader.streaminfo();
+    let frames_offset = reader.byte_position() as usize;
+    streaminfo.samples = None;
+    let options = claxon::FlacReaderOptions::default();
+
+    // Bare frames, starting at a frame boundary.
+    let frames = &data[frames_offset..];
+    let mut reader = claxon::FlacReader::new_headerless(frames, streaminfo, options).unwrap();
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+
+    // Joining a live stream in the middle of the first frame.
+    let mut reader = claxon::FlacReader::new_headerless(&frames[10..], streaminfo, options).unwrap();
+    let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(decoded, &expected[64..]);
+}
+
+#[test]
+fn new_headerless_rejects_invalid_streaminfo() {
+    let data = encode_verbatim(&test_signal(200), 64, None);
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let valid = reader.streaminfo();
+    let options = claxon::FlacReaderOptions::default();
+
+    let mut invalid = Vec::new();
+    for &sample_rate in &[0, 655351] {
+        invalid.push(claxon::metadata::StreamInfo { sample_rate: sample_rate, ..valid });
+    }
+    for &channels in &[0, 9] {
+        invalid.push(claxon::metadata::StreamInfo { channels: channels, ..valid });
+    }
+    for &bits_per_sample in &[0, 3, 33] {
+        invalid.push(claxon::metadata::StreamInfo { bits_per_sample: bits_per_sample, ..valid });
+    }
+    invalid.push(claxon::metadata::StreamInfo { max_block_size: 15, ..valid });
+
+    for &streaminfo in &invalid {
+        match claxon::FlacReader::new_headerless(&data[..], streaminfo, options) {
+            Err(claxon::Error::FormatError { .. }) => {}
+            _ => panic!("expected {:?} to be rejected", streaminfo),
+        }
+    }
+}
+
+/// Pushes `data` into a push decoder in chunks, and returns the decoded samples.
+fn decode_pushed(data: &[u8], chunk_len: usize) -> claxon::Result<(usize, Vec<i32>)> {
+    use claxon::push::{Event, PushDecoder};
+
+    let mut decoder = PushDecoder::new();
+    let mut chunks = data.chunks(chunk_len);
+    let mut n_metadata = 0;
+    let mut samples = Vec::new();
+    loop {
+        match try!(decoder.poll()) {
+            Event::StreamInfo(..) | Event::MetadataBlock(..) => n_metadata += 1,
+            Event::Block(block) => {
+                for i in 0..block.duration() {
+                    samples.push(block.sample(0, i));
+                }
+                decoder.recycle(block.into_buffer());
+            }
+            Event::NeedMoreData => match chunks.next() {
+                Some(chunk) => decoder.push(chunk),
+                None => decoder.finish(),
+            },
+            Event::End => return Ok((n_metadata, samples)),
+        }
+    }
+}
+
+#[test]
+fn push_decoder_decodes_chunked_input() {
+    let signal = test_signal(1000);
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    let data = encode_verbatim(&signal, 64, Some(4));
+
+    for &chunk_len in &[1, 7, 100, 4096] {
+        let (n_metadata, samples) = decode_pushed(&data, chunk_len).unwrap();
+        assert_eq!(n_metadata, 2);
+        assert_eq!(samples, expected);
+    }
+
+    // A stream that is cut off is an error once the input is finished.
+    assert!(decode_pushed(&data[..data.len() - 1], 100).is_err());
+    assert!(decode_pushed(&data[..20], 100).is_err());
+}
+
+#[test]
+fn push_decoder_enforces_metadata_limits() {
+    use claxon::push::{Event, PushDecoder};
+
+    let data = flac_with_tags(&["ARTIST=Queen", "TITLE=Bohemian Rhapsody"]);
+    let limits = claxon::metadata::MetadataLimits {
+        max_vorbis_comments: 1,
+        .. claxon::metadata::MetadataLimits::default()
+    };
+    let options = claxon::FlacReaderOptions {
+        metadata_limits: limits,
+        .. claxon::FlacReaderOptions::default()
+    };
+    let mut decoder = PushDecoder::new_ext(options);
+    decoder.push(&data);
+    decoder.finish();
+    match decoder.poll().unwrap() {
+        Event::StreamInfo(..) => {}
+        event => panic!("expected the streaminfo, got {:?}", event),
+    }
+    match decoder.poll() {
+        Err(claxon::Error::Unsupported { .. }) => {}
+        _ => panic!("expected the comment count limit to be enforced"),
+    }
+
+    // With the default limits, the comments are fine.
+    let mut decoder = PushDecoder::new();
+    decoder.push(&data);
+    decoder.finish();
+    decoder.poll().unwrap();
+    match decoder.poll().unwrap() {
+        Event::MetadataBlock(claxon::metadata::MetadataBlock::VorbisComment(..)) => {}
+        event => panic!("expected the Vorbis comment, got {:?}", event),
+    }
+}
+
+#[test]
+fn read_next_into_decodes_into_fixed_buffer() {
+    let signal = test_signal(200);
+    let data = encode_verbatim(&signal, 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let mut blocks = reader.blocks();
+
+    let mut buffer = [0; 64];
+    let mut decoded = Vec::new();
+    while let Some((info, n)) = blocks.read_next_into(&mut buffer).unwrap() {
+        assert_eq!(info.first_sample, decoded.len() as u64);
+        assert_eq!(n, info.block_size as u32);
+        decoded.extend_from_slice(&buffer[..n as usize]);
+    }
+    let expected: Vec<i32> = signal.iter().map(|&x| x as i32).collect();
+    assert_eq!(decoded, expected);
+
+    // A block that does not fit is an error, which tells the required length.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let mut small = [0; 63];
+    match reader.blocks().read_next_into(&mut small) {
+        Err(claxon::Error::BufferTooSmall { required }) => assert_eq!(required, 64),
+        _ => panic!("expected an error for a buffer that is too small"),
+    }
+}
+
+#[test]
+fn push_decoder_decodes_planar_f32() {
+    let signal = test_signal(1000);
+    let data = encode_verbatim(&signal, 64, Some(4));
+
+    let mut decoder = claxon::push::PushDecoder::new();
+    let mut planar = Vec::new();
+    let mut n = 0;
+    for chunk in data.chunks(333) {
+        decoder.push(chunk);
+        n += decoder.decode_planar_f32(&mut planar).unwrap();
+    }
+    decoder.finish();
+    n += decoder.decode_planar_f32(&mut planar).unwrap();
+
+    assert_eq!(n, 1000);
+    assert_eq!(planar.len(), 1);
+    let expected: Vec<f32> = signal.iter().map(|&x| x as f32 / 32768.0).collect();
+    assert_eq!(planar[0], expected);
+}
+
+#[test]
+fn read_samples_into_matches_samples() {
+    // Chunk sizes that do and do not line up with blocks and channels.
+    for fname in &["testsamples/short.flac", "testsamples/wasted_bits.flac"] {
+        let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+            .samples().map(|s| s.unwrap()).collect();
+        for &chunk_len in &[1, 3, 7, 1000, 4096, 10000] {
+            let mut reader = claxon::FlacReader::open(fname).unwrap();
+            let mut samples = reader.samples();
+            let mut buffer = vec![0; chunk_len];
+            let mut decoded = Vec::new();
+            loop {
+                let n = samples.read_samples_into(&mut buffer).unwrap();
+                decoded.extend_from_slice(&buffer[..n]);
+                if n < chunk_len {
+                    break
+                }
+            }
+            assert_eq!(samples.read_samples_into(&mut buffer).unwrap(), 0);
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    // It mixes with iteration, and continues at the seek target.
+    let fname = "testsamples/wasted_bits.flac";
+    let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    reader.seek_to_sample(10).unwrap();
+    let mut samples = reader.into_samples();
+    assert_eq!(samples.next().unwrap().unwrap(), expected[10]);
+    let mut buffer = vec![0; 4000];
+    assert_eq!(samples.read_samples_into(&mut buffer).unwrap(), 4000);
+    assert_eq!(&buffer[..], &expected[11..4011]);
+    assert_eq!(samples.next().unwrap().unwrap(), expected[4011]);
+}
+
+#[test]
+fn decode_all_matches_samples() {
+    for fname in &["testsamples/short.flac", "testsamples/wasted_bits.flac"] {
+        let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+            .samples().map(|s| s.unwrap()).collect();
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let (streaminfo, samples) = reader.decode_all().unwrap();
+        assert_eq!(streaminfo.md5sum, claxon::FlacReader::open(fname).unwrap().streaminfo().md5sum);
+        assert_eq!(samples.len() as u64,
+                   streaminfo.samples.unwrap() * streaminfo.channels as u64);
+        assert_eq!(samples, expected);
+    }
+}
+
+/// Encodes a 16-bit stereo stream of independent channels in verbatim subframes.
+fn encode_verbatim_stereo(left: &[i16], right: &[i16], block_size: u16) -> Vec<u8> {
+    let n = left.len() as u64;
+    let mut data = b"fLaC".to_vec();
+    data.extend_from_slice(&[0x80, 0, 0, 34]);
+    data.extend_from_slice(&[(block_size >> 8) as u8, block_size as u8,
+                             (block_size >> 8) as u8, block_size as u8]);
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    // 44100 Hz, 2 channels, 16 bits per sample, and the number of samples.
+    data.extend_from_slice(&[0x0a, 0xc4, 0x42, 0xf0 | (n >> 32) as u8,
+                             (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+    data.extend_from_slice(&[0; 16]);
+
+    let blocks = left.chunks(block_size as usize).zip(right.chunks(block_size as usize));
+    for (i, (block_left, block_right)) in blocks.enumerate() {
+        // Block size stored in 16 bits, 44.1 kHz, independent stereo, 16 bits
+        // per sample.
+        let mut frame = vec![0xff, 0xf8, 0x79, 0x18, i as u8];
+        let bs = block_left.len() - 1;
+        frame.extend_from_slice(&[(bs >> 8) as u8, bs as u8]);
+        let crc = crc8(&frame);
+        frame.push(crc);
+
+        for channel in &[block_left, block_right] {
+            frame.push(0x02);
+            for &x in channel.iter() {
+                frame.extend_from_slice(&[(x >> 8) as u8, x as u8]);
+            }
+        }
+        let crc = crc16(&frame);
+        frame.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+        data.extend_from_slice(&frame);
+    }
+    data
+}
+
+#[test]
+fn channels_yield_deinterleaved_samples() {
+    let signal_left = test_signal(300);
+    let signal_right: Vec<i16> = signal_left.iter().map(|&x| x / 3 - 7).collect();
+    let data = encode_verbatim_stereo(&signal_left, &signal_right, 128);
+    let expected: Vec<i32> = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    assert_eq!(expected.len(), 600);
+
+    // Zipped, the channels interleave to the samples again.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let mut channels = reader.channels();
+    assert_eq!(channels.len(), 2);
+    let right = channels.pop().unwrap();
+    let left = channels.pop().unwrap();
+    let mut zipped = Vec::new();
+    for (l, r) in left.zip(right) {
+        zipped.push(l.unwrap());
+        zipped.push(r.unwrap());
+    }
+    assert_eq!(zipped, expected);
+
+    // One channel can be consumed before the other.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    let mut channels = reader.channels();
+    let right: Vec<i32> = channels.pop().unwrap().map(|s| s.unwrap()).collect();
+    let left: Vec<i32> = channels.pop().unwrap().map(|s| s.unwrap()).collect();
+    assert_eq!(left, expected.chunks(2).map(|s| s[0]).collect::<Vec<i32>>());
+    assert_eq!(right, expected.chunks(2).map(|s| s[1]).collect::<Vec<i32>>());
+
+    // After a seek, all channels continue at the target.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..])).unwrap();
+    reader.seek_to_sample(200).unwrap();
+    let mut channels = reader.channels();
+    assert_eq!(channels[1].next().unwrap().unwrap(), signal_right[200] as i32);
+    assert_eq!(channels[0].next().unwrap().unwrap(), signal_left[200] as i32);
+}
+
+#[test]
+fn samples_after_dropped_iterators_are_correct() {
+    // The first iterator takes the buffer that the reader allocated, and the
+    // reader gets it back from `into_reader()`. Stale samples in it must not
+    // leak into the next block.
+    let fname = "testsamples/wasted_bits.flac";
+    let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    for &target in &[0, 4100, 17, 4409] {
+        reader.seek_to_sample(target).unwrap();
+        let samples: Vec<i32> = reader.samples().take(5).map(|s| s.unwrap()).collect();
+        let end = cmp::min(target as usize + 5, expected.len());
+        assert_eq!(&samples[..], &expected[target as usize..end]);
+    }
+
+    reader.seek_to_sample(4000).unwrap();
+    let channel: Vec<i32> = reader.channels().remove(0).map(|s| s.unwrap()).collect();
+    assert_eq!(&channel[..], &expected[4000..]);
+    reader.seek_to_sample(1).unwrap();
+    let mut samples = reader.into_samples();
+    assert_eq!(samples.next().unwrap().unwrap(), expected[1]);
+    let mut reader = samples.into_reader();
+    assert_eq!(reader.samples().next().unwrap().unwrap(), expected[4096]);
+}
+
+#[test]
+fn background_decoder_yields_blocks_in_order() {
+    let fname = "testsamples/wasted_bits.flac";
+    let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let mut decoder = claxon::background::BackgroundDecoder::new(reader, 1);
+    assert_eq!(decoder.streaminfo().samples, Some(4410));
+    let mut samples = Vec::new();
+    while let Some(block) = decoder.next_block().unwrap() {
+        samples.extend_from_slice(block.channel(0));
+        decoder.recycle(block.into_buffer());
+    }
+    assert_eq!(samples, expected);
+    assert!(decoder.next_block().unwrap().is_none());
+
+    // Polling without waiting eventually yields the same blocks.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    reader.seek_to_sample(4100).unwrap();
+    let mut decoder = claxon::background::BackgroundDecoder::new(reader, 4);
+    let mut samples = Vec::new();
+    loop {
+        match decoder.try_next_block().unwrap() {
+            claxon::background::TryNext::Block(block) => {
+                samples.extend_from_slice(block.channel(0))
+            }
+            claxon::background::TryNext::NotReady => thread::yield_now(),
+            claxon::background::TryNext::End => break,
+        }
+    }
+    assert_eq!(&samples[..], &expected[4096..]);
+
+    // Dropping the decoder early stops the worker.
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let mut decoder = claxon::background::BackgroundDecoder::new(reader, 1);
+    assert!(decoder.next_block().unwrap().is_some());
+}
+
+/// A reader that returns little data per read, and panics past a given offset.
+struct PanickingReader {
+    inner: io::Cursor<Vec<u8>>,
+    panic_at: u64,
+}
+
+impl io::Read for PanickingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        assert!(self.inner.position() < self.panic_at, "reader panicked");
+        let len = std::cmp::min(buf.len(), 64);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn background_decoder_propagates_worker_panic() {
+    let data = fs::read("testsamples/wasted_bits.flac").unwrap();
+    let input = PanickingReader { inner: io::Cursor::new(data), panic_at: 512 };
+    let reader = claxon::FlacReader::new(input).unwrap();
+    let mut decoder = claxon::background::BackgroundDecoder::new(reader, 1);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        while decoder.next_block().unwrap().is_some() {}
+    }));
+    assert_eq!(*result.unwrap_err().downcast::<&str>().unwrap(), "reader panicked");
+}
+
+#[test]
+fn decode_files_decodes_every_file_in_parallel() {
+    use std::sync::{Arc, Mutex};
+
+    let paths = ["testsamples/short.flac", "testsamples/wasted_bits.flac",
+                 "testsamples/does_not_exist.flac", "testsamples/short.flac"];
+    let counts = Arc::new(Mutex::new(vec![0u64; paths.len()]));
+    let counts_worker = counts.clone();
+    let results = claxon::batch::decode_files(&paths, 3, move |index, _, block| {
+        counts_worker.lock().unwrap()[index] += block.duration() as u64;
+    });
+
+    assert_eq!(results.len(), 4);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert!(results[2].is_err());
+    assert!(results[3].is_ok());
+    assert_eq!(&counts.lock().unwrap()[..], &[4, 4410, 0, 4]);
+}
+
+#[test]
+fn frame_report_describes_subframes() {
+    use claxon::subframe::SubframeKind;
+
+    let fname = "testsamples/wasted_bits.flac";
+    let file_len = std::fs::metadata(fname).unwrap().len();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut frames_len = 0;
+    let mut samples = Vec::new();
+    {
+        let mut blocks = reader.blocks();
+        let frames_offset = blocks.get_ref().position();
+        while let Some((report, block)) = blocks.read_next_with_report_or_eof(Vec::new()).unwrap() {
+            assert_eq!(report.info.first_sample, block.time());
+            assert_eq!(report.subframes.len(), 1);
+            assert_eq!(report.subframes[0].wasted_bits, block.wasted_bits(0));
+            if block.time() == 0 {
+                // The first frame is predicted with a fixed predictor of
+                // order 2, and has a residual of two Rice partitions.
+                let subframe = &report.subframes[0];
+                assert_eq!(subframe.kind, SubframeKind::Fixed);
+                assert_eq!(subframe.order, 2);
+                assert_eq!(subframe.wasted_bits, 8);
+                assert_eq!(subframe.partition_order, 1);
+                assert_eq!(subframe.rice_parameters, vec![0, 0]);
+                assert!(subframe.coefficients.is_empty());
+                assert_eq!(report.len, 977);
+
+                // The residual plus the prediction gives the samples, before
+                // they are shifted by the wasted bits.
+                let s: Vec<i32> = block.channel(0).iter().map(|&x| x >> 8).collect();
+                assert_eq!(subframe.residual.len(), s.len() - 2);
+                for (i, &r) in subframe.residual.iter().enumerate() {
+                    assert_eq!(s[i + 2], 2 * s[i + 1] - s[i] + r);
+                }
+            }
+            frames_len += report.len as u64;
+            samples.extend_from_slice(block.channel(0));
+        }
+        assert_eq!(frames_offset + frames_len, file_len);
+    }
+
+    // The report does not affect decoding.
+    let expected: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Verbatim frames have no residual.
+    let data = encode_verbatim(&test_signal(100), 64, None);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let mut blocks = reader.blocks();
+    let (report, _) = blocks.read_next_with_report_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(report.subframes[0].kind, SubframeKind::Verbatim);
+    assert_eq!(report.subframes[0].partition_order, 0);
+    assert!(report.subframes[0].rice_parameters.is_empty());
+}
+
+#[test]
+fn bitrate_is_reported_per_frame_and_on_average() {
+    // This file has frames of 977 and 104 bytes, with 4096 and 314 samples at 44.1 kHz.
+    let mut reader = claxon::FlacReader::open("testsamples/wasted_bits.flac").unwrap();
+    assert_eq!(reader.frame_bitrate(), None);
+    assert_eq!(reader.average_bitrate(), None);
+
+    {
+        let mut samples = reader.samples();
+        for _ in 0..4096 {
+            samples.next().unwrap().unwrap();
+        }
+    }
+    assert_eq!(reader.frame_bitrate(), Some(977 * 8 * 44100 / 4096));
+    assert_eq!(reader.average_bitrate(), Some(977 * 8 * 44100 / 4096));
+
+    for sample in reader.samples() {
+        sample.unwrap();
+    }
+    assert_eq!(reader.frame_bitrate(), Some(104 * 8 * 44100 / 314));
+    assert_eq!(reader.average_bitrate(), Some((977 + 104) * 8 * 44100 / 4410));
+}
+
+#[test]
+fn measure_levels_matches_samples() {
+    let fname = "testsamples/wasted_bits.flac";
+    let samples: Vec<i32> = claxon::FlacReader::open(fname).unwrap()
+        .samples().map(|s| s.unwrap()).collect();
+    let scale = 1.0 / 32768.0;
+    let peak = samples.iter().map(|&s| (s as f64 * scale).abs()).fold(0.0, f64::max);
+    let sum_squares: f64 = samples.iter().map(|&s| (s as f64 * scale).powi(2)).sum();
+    let rms = (sum_squares / samples.len() as f64).sqrt();
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let levels = claxon::levels::measure(&mut reader).unwrap();
+    assert_eq!(levels.len(), 1);
+    assert_eq!(levels[0].peak, peak);
+    assert!((levels[0].rms - rms).abs() < 1e-12);
+    assert!(levels[0].true_peak >= peak);
+}
+
+#[test]
+fn compare_tolerates_block_sizes_and_finds_differences() {
+    use claxon::Comparison;
+
+    fn compare(a: Vec<u8>, b: Vec<u8>) -> Comparison {
+        let mut a = claxon::FlacReader::new(io::Cursor::new(a)).unwrap();
+        let mut b = claxon::FlacReader::new(io::Cursor::new(b)).unwrap();
+        claxon::compare(&mut a, &mut b).unwrap()
+    }
+
+    let signal = test_signal(1000);
+    assert_eq!(compare(encode_verbatim(&signal, 64, None), encode_verbatim(&signal, 100, None)),
+               Comparison::Equal);
+
+    let mut changed = signal.clone();
+    changed[777] ^= 1;
+    assert_eq!(compare(encode_verbatim(&signal, 64, None), encode_verbatim(&changed, 100, None)),
+               Comparison::SampleDiffers { sample: 777, channel: 0 });
+
+    assert_eq!(compare(encode_verbatim(&signal[..900], 64, None),
+                       encode_verbatim(&signal, 100, None)),
+               Comparison::LengthDiffers(900));
+
+    // The earliest difference is reported, even if a later channel has it.
+    let mut left = signal.clone();
+    let mut right = signal.clone();
+    left[9] ^= 1;
+    right[5] ^= 1;
+    assert_eq!(compare(encode_verbatim_stereo(&signal, &signal, 64),
+                       encode_verbatim_stereo(&left, &right, 48)),
+               Comparison::SampleDiffers { sample: 5, channel: 1 });
+    assert_eq!(compare(encode_verbatim_stereo(&signal, &signal, 64),
+                       encode_verbatim(&signal, 64, None)),
+               Comparison::FormatDiffers);
+}
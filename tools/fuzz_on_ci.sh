@@ -41,6 +41,17 @@ cargo fuzz run decode_full -- \
   -print_final_stats=1 \
   -detect_leaks=0
 
+# The other targets start from an empty corpus. The differential fuzzer is not
+# run, because it needs libFLAC.
+for target in decode_metadata decode_frame_header decode_subframe decode_generated; do
+  echo "Running ${target} for ${FUZZ_SECONDS:-10} seconds ..."
+  cargo fuzz run ${target} -- \
+    -max_len=8192 \
+    -max_total_time=${FUZZ_SECONDS:-10} \
+    -print_final_stats=1 \
+    -detect_leaks=0
+done
+
 # Copy back any new discoveries, so Travis can cache them. This step is not
 # reached when fuzzing finds a crash, but that is ok, because in that case we
 # should reproduce manually and add a regression test anyway.